@@ -46,6 +46,7 @@ use super::listeners::ensure_endpoints_consistency;
 use super::listeners::ensure_listenaddrs_consistency;
 use super::listeners::extra_endpoints;
 use super::listeners::ListenersAndRouters;
+use super::utils::ClientCertificate;
 use super::utils::PropagatingMakeSpan;
 use super::ListenAddrAndRouter;
 use super::ENDPOINT_CALLBACK;
@@ -626,6 +627,15 @@ async fn handle_graphql(
 
     let request: router::Request = http_request.into();
     let context = request.context.clone();
+    if let Some(client_certificate) = request
+        .router_request
+        .extensions()
+        .get::<ClientCertificate>()
+    {
+        if let Err(e) = client_certificate.insert_in_context(&context) {
+            tracing::error!("could not insert the client certificate in the context: {e}");
+        }
+    }
     let accept_encoding = request
         .router_request
         .headers()
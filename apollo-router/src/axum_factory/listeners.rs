@@ -20,7 +20,9 @@ use tokio::sync::mpsc;
 use tokio::sync::Notify;
 use tower_service::Service;
 
+use crate::axum_factory::utils::ClientCertificate;
 use crate::axum_factory::utils::ConnectionInfo;
+use crate::axum_factory::utils::InjectClientCertificate;
 use crate::axum_factory::utils::InjectConnectionInfo;
 use crate::axum_factory::ENDPOINT_CALLBACK;
 use crate::configuration::Configuration;
@@ -318,6 +320,18 @@ pub(super) fn serve_router_on_listen_addr(
                                     },
                                     NetworkStream::Tls(stream) => {
                                         let received_first_request = Arc::new(AtomicBool::new(false));
+                                        // with client authentication enabled, the certificate was verified during the handshake
+                                        let client_certificate = stream.get_ref().1
+                                            .peer_certificates()
+                                            .and_then(|certificates| certificates.first())
+                                            .and_then(|certificate| match ClientCertificate::from_der(&certificate.0) {
+                                                Ok(client_certificate) => Some(client_certificate),
+                                                Err(e) => {
+                                                    tracing::error!("could not parse the client certificate: {e}");
+                                                    None
+                                                }
+                                            });
+                                        let app = InjectClientCertificate::new(app, client_certificate);
                                         let app = IdleConnectionChecker::new(received_first_request.clone(), app);
 
                                         stream.get_ref().0
@@ -55,11 +55,18 @@ use tower::ServiceExt;
 
 pub(crate) use super::axum_http_server_factory::make_axum_router;
 use super::*;
+use crate::axum_factory::utils::CLIENT_CERTIFICATE_FINGERPRINT;
 use crate::configuration::cors::Cors;
+use crate::configuration::load_certs;
+use crate::configuration::load_key;
+use crate::configuration::ClientAuthMode;
 use crate::configuration::HealthCheck;
 use crate::configuration::Homepage;
 use crate::configuration::Sandbox;
 use crate::configuration::Supergraph;
+use crate::configuration::Tls;
+use crate::configuration::TlsSupergraph;
+use crate::configuration::TlsSupergraphClientAuth;
 use crate::graphql;
 use crate::http_server_factory::HttpServerFactory;
 use crate::http_server_factory::HttpServerHandle;
@@ -2397,3 +2404,94 @@ async fn test_supergraph_timeout() {
         })
     );
 }
+
+#[tokio::test]
+async fn it_verifies_client_certificates() {
+    const SERVER_CERT: &str = include_str!("../services/http/testdata/server.crt");
+    const SERVER_KEY: &str = include_str!("../services/http/testdata/server.key");
+    const CA_CERT: &str = include_str!("../services/http/testdata/CA/ca.crt");
+    const CLIENT_CERT: &str = include_str!("../services/http/testdata/client.crt");
+    const CLIENT_KEY: &str = include_str!("../services/http/testdata/client.key");
+    const SELF_SIGNED_CERT: &str = include_str!("../services/http/testdata/client_self_signed.crt");
+    const SELF_SIGNED_KEY: &str = include_str!("../services/http/testdata/client_self_signed.key");
+
+    let tls = Tls {
+        supergraph: Some(TlsSupergraph {
+            certificate: load_certs(SERVER_CERT).unwrap().remove(0),
+            key: load_key(SERVER_KEY).unwrap(),
+            certificate_chain: Vec::new(),
+            client_authentication: Some(TlsSupergraphClientAuth {
+                certificate_authorities: load_certs(CA_CERT).unwrap(),
+                mode: ClientAuthMode::Required,
+            }),
+        }),
+        subgraph: Default::default(),
+    };
+    let conf = Configuration::fake_builder().tls(tls).build().unwrap();
+
+    // answers with the fingerprint of the client certificate found in the context
+    let router_service = service_fn(|request: router::Request| {
+        Box::pin(async move {
+            let fingerprint = request
+                .context
+                .get::<_, String>(CLIENT_CERTIFICATE_FINGERPRINT)
+                .unwrap()
+                .unwrap_or_default();
+            Ok(router::Response {
+                response: http::Response::builder().body(fingerprint.into()).unwrap(),
+                context: request.context,
+            })
+        }) as BoxFuture<'static, router::ServiceResult>
+    });
+    let (server, _) = init_with_config(router_service, Arc::new(conf), MultiMap::new())
+        .await
+        .unwrap();
+    let port = match server.graphql_listen_address().as_ref().unwrap() {
+        ListenAddr::SocketAddr(addr) => addr.port(),
+        #[cfg(unix)]
+        ListenAddr::UnixSocket(_) => unreachable!(),
+    };
+    // the server certificate is issued for localhost
+    let url = format!("https://localhost:{port}/");
+    let client = |identity: Option<(&str, &str)>| {
+        let mut builder = reqwest::Client::builder()
+            .add_root_certificate(reqwest::Certificate::from_pem(CA_CERT.as_bytes()).unwrap());
+        if let Some((certificate, key)) = identity {
+            builder = builder.identity(
+                reqwest::Identity::from_pem(format!("{certificate}{key}").as_bytes()).unwrap(),
+            );
+        }
+        builder.build().unwrap()
+    };
+
+    // a certificate issued by the certificate authority is accepted and exposed in the context
+    let response = client(Some((CLIENT_CERT, CLIENT_KEY)))
+        .post(&url)
+        .body(r#"{ "query": "{ me }" }"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "14163883e27d29ba6c37de3ab761af895aedab73b85cfc7a00a5ca97700e67ba"
+    );
+
+    // a self signed certificate is rejected during the handshake
+    assert!(client(Some((SELF_SIGNED_CERT, SELF_SIGNED_KEY)))
+        .post(&url)
+        .body(r#"{ "query": "{ me }" }"#)
+        .send()
+        .await
+        .is_err());
+
+    // so is a connection without a certificate
+    assert!(client(None)
+        .post(&url)
+        .body(r#"{ "query": "{ me }" }"#)
+        .send()
+        .await
+        .is_err());
+
+    server.shutdown().await.unwrap();
+}
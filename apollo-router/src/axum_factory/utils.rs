@@ -1,18 +1,24 @@
 //! Utilities used for [`super::AxumHttpServerFactory`]
 
+use std::net::IpAddr;
 use std::net::SocketAddr;
 
 use opentelemetry::global;
 use opentelemetry::trace::TraceContextExt;
+use sha2::Digest;
+use sha2::Sha256;
+use tower::BoxError;
 use tower_http::trace::MakeSpan;
 use tower_service::Service;
 use tracing::Span;
+use x509_parser::extensions::GeneralName;
 
 use crate::plugins::telemetry::consts::OTEL_STATUS_CODE;
 use crate::plugins::telemetry::consts::OTEL_STATUS_CODE_ERROR;
 use crate::plugins::telemetry::SpanMode;
 use crate::uplink::license_enforcement::LicenseState;
 use crate::uplink::license_enforcement::LICENSE_EXPIRED_SHORT_MESSAGE;
+use crate::Context;
 
 #[derive(Clone, Default)]
 pub(crate) struct PropagatingMakeSpan {
@@ -62,6 +68,72 @@ impl<B> MakeSpan<B> for PropagatingMakeSpan {
     }
 }
 
+pub(crate) const CLIENT_CERTIFICATE_SUBJECT: &str = "apollo_tls::client_certificate::subject";
+pub(crate) const CLIENT_CERTIFICATE_SUBJECT_ALT_NAMES: &str =
+    "apollo_tls::client_certificate::subject_alt_names";
+pub(crate) const CLIENT_CERTIFICATE_FINGERPRINT: &str =
+    "apollo_tls::client_certificate::fingerprint";
+
+/// The certificate presented by the client, verified during the TLS handshake
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ClientCertificate {
+    pub(crate) subject: String,
+    pub(crate) subject_alt_names: Vec<String>,
+    /// SHA-256 of the DER encoded certificate, hex encoded
+    pub(crate) fingerprint: String,
+}
+
+impl ClientCertificate {
+    pub(crate) fn from_der(der: &[u8]) -> Result<Self, BoxError> {
+        let (_, certificate) = x509_parser::parse_x509_certificate(der)?;
+
+        let subject_alt_names = certificate
+            .subject_alternative_name()?
+            .map(|extension| {
+                extension
+                    .value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| match name {
+                        GeneralName::DNSName(name) => Some(format!("DNS:{name}")),
+                        GeneralName::RFC822Name(name) => Some(format!("email:{name}")),
+                        GeneralName::URI(uri) => Some(format!("URI:{uri}")),
+                        GeneralName::IPAddress(bytes) => {
+                            ip_address(bytes).map(|ip| format!("IP:{ip}"))
+                        }
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            subject: certificate.subject().to_string(),
+            subject_alt_names,
+            fingerprint: hex::encode(Sha256::digest(der)),
+        })
+    }
+
+    /// Exposes the certificate to plugins, header rules and telemetry selectors
+    pub(crate) fn insert_in_context(&self, context: &Context) -> Result<(), BoxError> {
+        context.insert(CLIENT_CERTIFICATE_SUBJECT, self.subject.clone())?;
+        context.insert(
+            CLIENT_CERTIFICATE_SUBJECT_ALT_NAMES,
+            self.subject_alt_names.clone(),
+        )?;
+        context.insert(CLIENT_CERTIFICATE_FINGERPRINT, self.fingerprint.clone())?;
+        Ok(())
+    }
+}
+
+fn ip_address(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
+
 pub(crate) struct InjectConnectionInfo<S> {
     inner: S,
     connection_info: ConnectionInfo,
@@ -104,3 +176,73 @@ where
         self.inner.call(req)
     }
 }
+
+/// Adds the certificate verified during the TLS handshake to the requests of the connection
+pub(crate) struct InjectClientCertificate<S> {
+    inner: S,
+    client_certificate: Option<ClientCertificate>,
+}
+
+impl<S> InjectClientCertificate<S> {
+    pub(crate) fn new(service: S, client_certificate: Option<ClientCertificate>) -> Self {
+        InjectClientCertificate {
+            inner: service,
+            client_certificate,
+        }
+    }
+}
+
+impl<S, B> Service<http::Request<B>> for InjectClientCertificate<S>
+where
+    S: Service<http::Request<B>>,
+{
+    type Response = <S as Service<http::Request<B>>>::Response;
+
+    type Error = <S as Service<http::Request<B>>>::Error;
+
+    type Future = <S as Service<http::Request<B>>>::Future;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<std::result::Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        if let Some(client_certificate) = &self.client_certificate {
+            req.extensions_mut().insert(client_certificate.clone());
+        }
+        self.inner.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::load_certs;
+
+    #[test]
+    fn client_certificate_from_der() {
+        let certificates =
+            load_certs(include_str!("../services/http/testdata/client.crt")).unwrap();
+        let certificate = ClientCertificate::from_der(&certificates[0].0).unwrap();
+
+        assert_eq!(certificate.subject, "C=FR, O=Apollo GraphQL, CN=router");
+        assert!(certificate.subject_alt_names.is_empty());
+        assert_eq!(
+            certificate.fingerprint,
+            "14163883e27d29ba6c37de3ab761af895aedab73b85cfc7a00a5ca97700e67ba"
+        );
+
+        let context = Context::new();
+        certificate.insert_in_context(&context).unwrap();
+        assert_eq!(
+            context
+                .get::<_, String>(CLIENT_CERTIFICATE_FINGERPRINT)
+                .unwrap()
+                .as_deref(),
+            Some(certificate.fingerprint.as_str())
+        );
+    }
+}
@@ -5,6 +5,7 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use fred::interfaces::EventInterface;
+use fred::interfaces::LuaInterface;
 #[cfg(test)]
 use fred::mocks::Mocks;
 use fred::prelude::ClientLike;
@@ -40,6 +41,16 @@ const SUPPORTED_REDIS_SCHEMES: [&str; 6] = [
     "rediss-sentinel",
 ];
 
+/// Increments a key and sets its expiration if it does not have one yet.
+/// KEYS[1]: the key, ARGV[1]: the increment, ARGV[2]: the expiration in seconds
+const INCR_WITH_EXPIRATION_SCRIPT: &str = r#"
+local value = redis.call('INCRBY', KEYS[1], ARGV[1])
+if redis.call('TTL', KEYS[1]) == -1 then
+    redis.call('EXPIRE', KEYS[1], ARGV[2])
+end
+return value
+"#;
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub(crate) struct RedisKey<K>(pub(crate) K)
 where
@@ -530,6 +541,35 @@ impl RedisCacheStorage {
         tracing::trace!("insert result {:?}", r);
     }
 
+    /// Atomically adds `delta` to an integer value, starting from 0 if the key does not exist.
+    ///
+    /// The expiration is only set on keys that do not have one yet, so that a counter expires at
+    /// the end of the window started by its first increment.
+    pub(crate) async fn incr<K: KeyType>(
+        &self,
+        key: RedisKey<K>,
+        delta: i64,
+        ttl: Option<Duration>,
+    ) -> Result<i64, RedisError> {
+        let key = self.make_key(key);
+        let value: i64 = match ttl.or(self.ttl) {
+            // the script runs atomically, another increment cannot see the key without its
+            // expiration
+            Some(ttl) => {
+                self.inner
+                    .eval(
+                        INCR_WITH_EXPIRATION_SCRIPT,
+                        key.as_str(),
+                        vec![delta, ttl.as_secs().max(1) as i64],
+                    )
+                    .await?
+            }
+            None => self.inner.incr_by(key.as_str(), delta).await?,
+        };
+        tracing::trace!("incremented redis key {:?} to {}", key, value);
+        Ok(value)
+    }
+
     pub(crate) async fn insert_multiple<K: KeyType, V: ValueType>(
         &self,
         data: &[(RedisKey<K>, RedisValue<V>)],
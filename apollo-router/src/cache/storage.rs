@@ -326,6 +326,12 @@ impl ValueType for usize {
     }
 }
 
+impl ValueType for serde_json::Value {
+    fn estimated_size(&self) -> Option<usize> {
+        None
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::num::NonZeroUsize;
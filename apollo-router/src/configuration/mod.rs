@@ -18,8 +18,11 @@ pub(crate) use persisted_queries::PersistedQueries;
 #[cfg(test)]
 pub(crate) use persisted_queries::PersistedQueriesSafelist;
 use regex::Regex;
+use rustls::server::AllowAnyAnonymousOrAuthenticatedClient;
+use rustls::server::AllowAnyAuthenticatedClient;
 use rustls::Certificate;
 use rustls::PrivateKey;
+use rustls::RootCertStore;
 use rustls::ServerConfig;
 use rustls_pemfile::certs;
 use rustls_pemfile::read_one;
@@ -1286,6 +1289,33 @@ pub(crate) struct TlsSupergraph {
     #[serde(deserialize_with = "deserialize_certificate_chain", skip_serializing)]
     #[schemars(with = "String")]
     pub(crate) certificate_chain: Vec<Certificate>,
+    /// client certificate authentication (mTLS)
+    #[serde(default)]
+    pub(crate) client_authentication: Option<TlsSupergraphClientAuth>,
+}
+
+/// Client certificate authentication on the supergraph listener
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TlsSupergraphClientAuth {
+    /// list of certificate authorities used to verify client certificates, in PEM format
+    #[serde(deserialize_with = "deserialize_certificate_chain", skip_serializing)]
+    #[schemars(with = "String")]
+    pub(crate) certificate_authorities: Vec<Certificate>,
+    /// whether clients must present a certificate
+    #[serde(default)]
+    pub(crate) mode: ClientAuthMode,
+}
+
+/// Client certificate verification mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ClientAuthMode {
+    /// connections without a valid client certificate are rejected
+    #[default]
+    Required,
+    /// clients can connect without a certificate, but a certificate they present must be valid
+    Optional,
 }
 
 impl TlsSupergraph {
@@ -1293,9 +1323,25 @@ impl TlsSupergraph {
         let mut certificates = vec![self.certificate.clone()];
         certificates.extend(self.certificate_chain.iter().cloned());
 
-        let mut config = ServerConfig::builder()
-            .with_safe_defaults()
-            .with_no_client_auth()
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &self.client_authentication {
+            None => builder.with_no_client_auth(),
+            Some(client_authentication) => {
+                let mut roots = RootCertStore::empty();
+                for certificate in &client_authentication.certificate_authorities {
+                    roots.add(certificate).map_err(ApolloRouterError::Rustls)?;
+                }
+                let verifier = match client_authentication.mode {
+                    ClientAuthMode::Required => AllowAnyAuthenticatedClient::new(roots).boxed(),
+                    ClientAuthMode::Optional => {
+                        AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed()
+                    }
+                };
+                builder.with_client_cert_verifier(verifier)
+            }
+        };
+
+        let mut config = builder
             .with_single_cert(certificates, self.key.clone())
             .map_err(ApolloRouterError::Rustls)?;
         config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
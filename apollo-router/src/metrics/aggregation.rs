@@ -27,6 +27,9 @@ use opentelemetry_api::metrics::CallbackRegistration;
 use opentelemetry_api::metrics::Observer;
 
 use crate::metrics::filter::FilterMeterProvider;
+use crate::plugins::telemetry::metrics::exemplars;
+use crate::plugins::telemetry::metrics::exemplars::ExemplarValue;
+use crate::plugins::telemetry::metrics::exemplars::Reservoir;
 
 // This meter provider enables us to combine multiple meter providers. The reasons we need this are:
 // 1. Prometheus meters are special. To dispose a meter is to dispose the entire registry. This means we need to make a best effort to keep them around.
@@ -102,6 +105,9 @@ pub(crate) enum InstrumentWrapper {
     U64Gauge {
         _keep_alive: Arc<ObservableGauge<u64>>,
     },
+    F64Gauge {
+        _keep_alive: Arc<ObservableGauge<f64>>,
+    },
 }
 
 #[derive(Eq, PartialEq, Hash)]
@@ -241,6 +247,12 @@ pub(crate) struct AggregateCounter<T> {
     delegates: Vec<Counter<T>>,
 }
 
+impl<T> AggregateCounter<T> {
+    fn new(_name: Cow<'static, str>, delegates: Vec<Counter<T>>) -> Self {
+        Self { delegates }
+    }
+}
+
 impl<T: Copy> SyncCounter<T> for AggregateCounter<T> {
     fn add(&self, value: T, attributes: &[KeyValue]) {
         for counter in &self.delegates {
@@ -267,13 +279,24 @@ impl<T: Copy> AsyncInstrument<T> for AggregateObservableCounter<T> {
 
 pub(crate) struct AggregateHistogram<T> {
     delegates: Vec<Histogram<T>>,
+    exemplars: Arc<Reservoir>,
 }
 
-impl<T: Copy> SyncHistogram<T> for AggregateHistogram<T> {
+impl<T> AggregateHistogram<T> {
+    fn new(name: Cow<'static, str>, delegates: Vec<Histogram<T>>) -> Self {
+        Self {
+            delegates,
+            exemplars: exemplars::reservoir(&name),
+        }
+    }
+}
+
+impl<T: ExemplarValue> SyncHistogram<T> for AggregateHistogram<T> {
     fn record(&self, value: T, attributes: &[KeyValue]) {
         for histogram in &self.delegates {
             histogram.record(value, attributes)
         }
+        self.exemplars.record(value, attributes);
     }
 }
 
@@ -281,6 +304,12 @@ pub(crate) struct AggregateUpDownCounter<T> {
     delegates: Vec<UpDownCounter<T>>,
 }
 
+impl<T> AggregateUpDownCounter<T> {
+    fn new(_name: Cow<'static, str>, delegates: Vec<UpDownCounter<T>>) -> Self {
+        Self { delegates }
+    }
+}
+
 impl<T: Copy> SyncUpDownCounter<T> for AggregateUpDownCounter<T> {
     fn add(&self, value: T, attributes: &[KeyValue]) {
         for counter in &self.delegates {
@@ -382,7 +411,7 @@ macro_rules! aggregate_instrument_fn {
             description: Option<Cow<'static, str>>,
             unit: Option<Unit>,
         ) -> opentelemetry::metrics::Result<$wrapper<$ty>> {
-            let delegates = self
+            let delegates: Vec<$wrapper<$ty>> = self
                 .meters
                 .iter()
                 .map(|p| {
@@ -396,7 +425,9 @@ macro_rules! aggregate_instrument_fn {
                     b.try_init()
                 })
                 .try_collect()?;
-            Ok($wrapper::new(Arc::new($implementation { delegates })))
+            Ok($wrapper::new(Arc::new(<$implementation<$ty>>::new(
+                name, delegates,
+            ))))
         }
     };
 }
@@ -48,6 +48,7 @@ use crate::notification::Notify;
 use crate::query_planner::fetch::SubgraphSchemas;
 use crate::router_factory::Endpoint;
 use crate::services::execution;
+use crate::services::query_planner;
 use crate::services::router;
 use crate::services::subgraph;
 use crate::services::supergraph;
@@ -604,6 +605,16 @@ pub(crate) trait PluginPrivate: Send + Sync + 'static {
         service
     }
 
+    /// This service handles query planning, around the query plan cache: it is called for every
+    /// operation, whether its plan was found in the cache or not. It is built once for each
+    /// supergraph and handles all its operations, so it must not be single use.
+    fn query_planner_service(
+        &self,
+        service: query_planner::CachingBoxService,
+    ) -> query_planner::CachingBoxService {
+        service
+    }
+
     /// Return the name of the plugin.
     fn name(&self) -> &'static str
     where
@@ -709,6 +720,12 @@ pub(crate) trait DynPlugin: Send + Sync + 'static {
         service: crate::services::http::BoxService,
     ) -> crate::services::http::BoxService;
 
+    /// This service handles query planning, around the query plan cache
+    fn query_planner_service(
+        &self,
+        service: query_planner::CachingBoxService,
+    ) -> query_planner::CachingBoxService;
+
     /// Return the name of the plugin.
     fn name(&self) -> &'static str;
 
@@ -754,6 +771,13 @@ where
         self.http_client_service(name, service)
     }
 
+    fn query_planner_service(
+        &self,
+        service: query_planner::CachingBoxService,
+    ) -> query_planner::CachingBoxService {
+        self.query_planner_service(service)
+    }
+
     fn name(&self) -> &'static str {
         self.name()
     }
@@ -27,6 +27,7 @@ use jsonwebtoken::Algorithm;
 use jsonwebtoken::DecodingKey;
 use jsonwebtoken::TokenData;
 use jsonwebtoken::Validation;
+use multimap::MultiMap;
 use once_cell::sync::Lazy;
 use reqwest::Client;
 use schemars::JsonSchema;
@@ -42,6 +43,7 @@ use self::jwks::JwksManager;
 use self::subgraph::SigningParams;
 use self::subgraph::SigningParamsConfig;
 use self::subgraph::SubgraphAuth;
+use self::subgraph_jwt::TokenSigner;
 use crate::graphql;
 use crate::layers::ServiceBuilderExt;
 use crate::plugin::serde::deserialize_header_name;
@@ -51,12 +53,16 @@ use crate::plugin::PluginInit;
 use crate::plugins::authentication::jwks::JwkSetInfo;
 use crate::plugins::authentication::jwks::JwksConfig;
 use crate::register_plugin;
+use crate::router_factory::Endpoint;
 use crate::services::router;
 use crate::services::APPLICATION_JSON_HEADER_VALUE;
 use crate::Context;
+use crate::ListenAddr;
 
 mod jwks;
+mod oauth2;
 pub(crate) mod subgraph;
+mod subgraph_jwt;
 
 #[cfg(test)]
 mod tests;
@@ -123,6 +129,7 @@ struct Router {
 struct AuthenticationPlugin {
     router: Option<Router>,
     subgraph: Option<SubgraphAuth>,
+    endpoints: MultiMap<ListenAddr, Endpoint>,
 }
 
 #[derive(Clone, Debug, Deserialize, JsonSchema, serde_derive_default::Default)]
@@ -413,10 +420,20 @@ impl Plugin for AuthenticationPlugin {
     type Config = Conf;
 
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let mut endpoints = MultiMap::new();
         let subgraph = if let Some(config) = init.config.subgraph {
+            let token_signer = if let Some(config) = &config.jwt_signing {
+                let token_signer = TokenSigner::new(config)?;
+                let (listen, endpoint) = token_signer.jwks_endpoint()?;
+                endpoints.insert(listen, endpoint);
+                Some(Arc::new(token_signer))
+            } else {
+                None
+            };
+
             let all = if let Some(config) = &config.all {
                 Some(Arc::new(
-                    subgraph::make_signing_params(config, "all").await?,
+                    subgraph::make_signing_params(config, "all", token_signer.as_ref()).await?,
                 ))
             } else {
                 None
@@ -426,7 +443,14 @@ impl Plugin for AuthenticationPlugin {
             for (subgraph_name, config) in &config.subgraphs {
                 subgraphs.insert(
                     subgraph_name.clone(),
-                    Arc::new(subgraph::make_signing_params(config, subgraph_name.as_str()).await?),
+                    Arc::new(
+                        subgraph::make_signing_params(
+                            config,
+                            subgraph_name.as_str(),
+                            token_signer.as_ref(),
+                        )
+                        .await?,
+                    ),
                 );
             }
 
@@ -491,7 +515,11 @@ impl Plugin for AuthenticationPlugin {
             None
         };
 
-        Ok(Self { router, subgraph })
+        Ok(Self {
+            router,
+            subgraph,
+            endpoints,
+        })
     }
 
     fn router_service(&self, service: router::BoxService) -> router::BoxService {
@@ -532,6 +560,10 @@ impl Plugin for AuthenticationPlugin {
             service
         }
     }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        self.endpoints.clone()
+    }
 }
 
 fn authenticate(
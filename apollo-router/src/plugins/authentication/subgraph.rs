@@ -16,6 +16,7 @@ use aws_smithy_runtime_api::client::identity::Identity;
 use aws_types::region::Region;
 use aws_types::sdk_config::SharedCredentialsProvider;
 use http::HeaderMap;
+use http::HeaderValue;
 use http::Request;
 use schemars::JsonSchema;
 use serde::Deserialize;
@@ -25,9 +26,16 @@ use tower::BoxError;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 
+use super::oauth2::OAuth2Config;
+use super::oauth2::OAuth2TokenProvider;
+use super::subgraph_jwt::SubgraphJwtConfig;
+use super::subgraph_jwt::SubgraphTokenMinter;
+use super::subgraph_jwt::TokenSigner;
+use super::subgraph_jwt::TokenSigningConfig;
 use crate::services::router::body::get_body_bytes;
 use crate::services::router::body::RouterBody;
 use crate::services::SubgraphRequest;
+use crate::Context;
 
 /// Hardcoded Config using access_key and secret.
 /// Prefer using DefaultChain instead.
@@ -175,6 +183,12 @@ impl AWSSigV4Config {
 pub(crate) enum AuthConfig {
     #[serde(rename = "aws_sig_v4")]
     AWSSigV4(AWSSigV4Config),
+    /// Configure OAuth2 client credentials authentication
+    #[serde(rename = "oauth2")]
+    OAuth2(OAuth2Config),
+    /// Send a JWT minted by the router, signed with the `jwt_signing` keys
+    #[serde(rename = "jwt")]
+    Jwt(SubgraphJwtConfig),
 }
 
 /// Configure subgraph authentication
@@ -187,6 +201,9 @@ pub(crate) struct Config {
     #[serde(default)]
     /// Create a configuration that will apply only to a specific subgraph.
     pub(crate) subgraphs: HashMap<String, AuthConfig>,
+    /// Keys used to sign the tokens of the `jwt` authentication method
+    #[serde(default)]
+    pub(crate) jwt_signing: Option<TokenSigningConfig>,
 }
 
 #[allow(dead_code)]
@@ -197,7 +214,14 @@ pub(crate) struct SigningParams {
 }
 
 #[derive(Clone)]
-pub(crate) struct SigningParamsConfig {
+pub(crate) enum SigningParamsConfig {
+    AWSSigV4(AWSSigV4SigningParamsConfig),
+    OAuth2(OAuth2TokenProvider),
+    Jwt(SubgraphTokenMinter),
+}
+
+#[derive(Clone)]
+pub(crate) struct AWSSigV4SigningParamsConfig {
     credentials_provider: CredentialsProvider,
     region: Region,
     service_name: String,
@@ -303,6 +327,46 @@ impl ProvideCredentials for CredentialsProvider {
 
 impl SigningParamsConfig {
     pub(crate) async fn sign(
+        &self,
+        req: Request<RouterBody>,
+        subgraph_name: &str,
+        context: &Context,
+    ) -> Result<Request<RouterBody>, BoxError> {
+        match self {
+            Self::AWSSigV4(params) => params.sign(req, subgraph_name).await,
+            Self::OAuth2(provider) => provider.authorize(req, subgraph_name).await,
+            Self::Jwt(minter) => minter.authorize(req, subgraph_name, context),
+        }
+    }
+
+    pub(crate) async fn sign_empty(
+        &self,
+        req: Request<()>,
+        subgraph_name: &str,
+        context: &Context,
+    ) -> Result<Request<()>, BoxError> {
+        match self {
+            Self::AWSSigV4(params) => params.sign_empty(req, subgraph_name).await,
+            Self::OAuth2(provider) => provider.authorize(req, subgraph_name).await,
+            Self::Jwt(minter) => minter.authorize(req, subgraph_name, context),
+        }
+    }
+
+    /// Returns `true` if a request rejected with a 401 status can be sent again with new credentials
+    pub(crate) fn retry_on_unauthorized(&self) -> bool {
+        matches!(self, Self::OAuth2(_))
+    }
+
+    /// Discards the cached credentials rejected by a subgraph before retrying a request
+    pub(crate) async fn invalidate(&self, rejected: Option<&HeaderValue>) {
+        if let Self::OAuth2(provider) = self {
+            provider.invalidate(rejected).await;
+        }
+    }
+}
+
+impl AWSSigV4SigningParamsConfig {
+    async fn sign(
         &self,
         mut req: Request<RouterBody>,
         subgraph_name: &str,
@@ -342,7 +406,7 @@ impl SigningParamsConfig {
     }
 
     // This function is the same as above, except it's a new one because () doesn't implement HttpBody`
-    pub(crate) async fn sign_empty(
+    async fn sign_empty(
         &self,
         mut req: Request<()>,
         subgraph_name: &str,
@@ -426,11 +490,12 @@ fn increment_failure_counter(subgraph_name: &str) {
 pub(super) async fn make_signing_params(
     config: &AuthConfig,
     subgraph_name: &str,
+    token_signer: Option<&Arc<TokenSigner>>,
 ) -> Result<SigningParamsConfig, BoxError> {
     match config {
         AuthConfig::AWSSigV4(config) => {
             let credentials_provider = config.get_credentials_provider().await;
-            Ok(SigningParamsConfig {
+            Ok(SigningParamsConfig::AWSSigV4(AWSSigV4SigningParamsConfig {
                 region: config.region(),
                 service_name: config.service_name(),
                 credentials_provider: CredentialsProvider::from_provide_credentials(
@@ -439,14 +504,26 @@ pub(super) async fn make_signing_params(
                 .await
                 .map_err(BoxError::from)?,
                 subgraph_name: subgraph_name.to_string(),
-            })
+            }))
+        }
+        AuthConfig::OAuth2(config) => Ok(SigningParamsConfig::OAuth2(OAuth2TokenProvider::new(
+            config.clone(),
+        ))),
+        AuthConfig::Jwt(config) => {
+            let token_signer = token_signer.ok_or_else(|| {
+                format!("subgraph {subgraph_name} uses the jwt authentication method, but jwt_signing is not configured")
+            })?;
+            Ok(SigningParamsConfig::Jwt(SubgraphTokenMinter::new(
+                token_signer.clone(),
+                config.clone(),
+            )))
         }
     }
 }
 
 /// There are three possible cases
 /// https://github.com/awslabs/aws-sdk-rust/blob/9c3168dafa4fd8885ce4e1fd41cec55ce982a33c/sdk/aws-sigv4/src/http_request/sign.rs#L264C1-L271C6
-fn get_signing_settings(signing_params: &SigningParamsConfig) -> SigningSettings {
+fn get_signing_settings(signing_params: &AWSSigV4SigningParamsConfig) -> SigningSettings {
     let mut settings = SigningSettings::default();
     settings.payload_checksum_kind = match signing_params.service_name.as_str() {
         "appsync" | "s3" | "vpc-lattice-svcs" => PayloadChecksumKind::XAmzSha256,
@@ -513,7 +590,7 @@ mod test {
     use crate::Context;
 
     async fn test_signing_settings(service_name: &str) -> SigningSettings {
-        let params: SigningParamsConfig = make_signing_params(
+        let params = make_signing_params(
             &AuthConfig::AWSSigV4(AWSSigV4Config::Hardcoded(AWSSigV4HardcodedConfig {
                 access_key_id: "id".to_string(),
                 secret_access_key: "secret".to_string(),
@@ -522,10 +599,14 @@ mod test {
                 assume_role: None,
             })),
             "all",
+            None,
         )
         .await
         .unwrap();
-        get_signing_settings(&params)
+        match params {
+            SigningParamsConfig::AWSSigV4(params) => get_signing_settings(&params),
+            _ => panic!("expected AWS SigV4 signing parameters"),
+        }
     }
 
     #[tokio::test]
@@ -604,6 +685,25 @@ mod test {
         .unwrap();
     }
 
+    #[test]
+    fn test_subgraph_oauth2_config() {
+        serde_yaml::from_str::<Config>(
+            r#"
+        subgraphs:
+          products:
+            oauth2:
+              token_endpoint: "https://auth.example.com/oauth/token"
+              client_id: "router"
+              client_secret: "secret"
+              client_authentication: post
+              scopes: ["products:read"]
+              audience: "https://products.example.com"
+              refresh_before: 2m
+        "#,
+        )
+        .unwrap();
+    }
+
     #[tokio::test]
     async fn test_lattice_body_payload_should_be_unsigned() -> Result<(), BoxError> {
         let subgraph_request = example_request();
@@ -637,6 +737,7 @@ mod test {
                         assume_role: None,
                     })),
                     "all",
+                    None,
                 )
                 .await
                 .ok()
@@ -690,6 +791,7 @@ mod test {
                         assume_role: None,
                     })),
                     "all",
+                    None,
                 )
                 .await
                 .ok()
@@ -857,7 +959,7 @@ mod test {
             let rt = tokio::runtime::Runtime::new().unwrap();
             rt.block_on(async {
                 signing_params
-                    .sign(http_request, service_name.as_str())
+                    .sign(http_request, service_name.as_str(), &Context::new())
                     .await
                     .unwrap()
             })
@@ -0,0 +1,522 @@
+//! Authorization audit log
+//!
+//! Authorization decisions are recorded as structured events in a dedicated sink, separate from
+//! the router's logs: a JSON lines file, or an OTLP logs exporter.
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::TrySendError;
+use std::time::SystemTime;
+
+use opentelemetry::logs::AnyValue;
+use opentelemetry::logs::LogRecord;
+use opentelemetry::logs::Logger as _;
+use opentelemetry::logs::LoggerProvider as _;
+use opentelemetry::logs::Severity;
+use opentelemetry::sdk::logs::BatchLogProcessor;
+use opentelemetry::sdk::logs::Logger;
+use opentelemetry::sdk::logs::LoggerProvider;
+use opentelemetry::Key;
+use opentelemetry_otlp::LogExporterBuilder;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::Value;
+use tokio::runtime::Handle;
+use tower::BoxError;
+
+use super::request_scopes;
+use super::ScopesRequirements;
+use super::REQUIRED_POLICIES_KEY;
+use crate::axum_factory::utils::CLIENT_CERTIFICATE_SUBJECT;
+use crate::json_ext::Path;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::plugins::telemetry::config_new::logging::Rollover;
+use crate::plugins::telemetry::logging::rolling_file::RollingFileWriter;
+use crate::plugins::telemetry::otlp;
+use crate::plugins::telemetry::otlp::TelemetryDataKind;
+use crate::tracer::TraceId;
+use crate::Context;
+
+/// Authorization audit log
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AuditConfig {
+    /// also record requests that were authorized without filtering anything
+    #[serde(default)]
+    include_allowed: bool,
+    /// where the audit events are written
+    sink: AuditSinkConfig,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+enum AuditSinkConfig {
+    /// write the events as JSON lines to a file
+    File {
+        /// path of the audit log file
+        path: PathBuf,
+        /// the period to rollover the file
+        #[serde(default)]
+        rollover: Rollover,
+        /// number of rolled over files to keep, all files are kept if not set
+        max_files: Option<usize>,
+    },
+    /// export the events as OTLP log records
+    Otlp(otlp::Config),
+}
+
+/// The outcome of an authorization decision
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Decision {
+    Allow,
+    Deny,
+}
+
+/// What made the decision
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AuditSource {
+    /// `require_authentication` option
+    RequireAuthentication,
+    /// `@authenticated`, `@requiresScopes` and `@policy` directives
+    Directives,
+    /// response row filters
+    RowFilters,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct AuditEvent {
+    pub(crate) timestamp: String,
+    pub(crate) decision: Decision,
+    pub(crate) source: AuditSource,
+    /// `true` if the whole request was rejected, `false` if only parts of it were removed
+    pub(crate) rejected: bool,
+    pub(crate) subject: Option<String>,
+    pub(crate) operation_name: Option<String>,
+    pub(crate) trace_id: Option<String>,
+    pub(crate) denied_paths: Vec<String>,
+    /// for each `@requiresScopes` requirement that the request does not satisfy, the sets of
+    /// scopes that would satisfy it, without the scopes the request already has
+    pub(crate) missing_scopes: Vec<Vec<Vec<String>>>,
+    pub(crate) failed_policies: Vec<String>,
+}
+
+impl AuditEvent {
+    pub(crate) fn new(
+        context: &Context,
+        source: AuditSource,
+        operation_name: Option<String>,
+        denied_paths: &[Path],
+        rejected: bool,
+    ) -> Self {
+        let decision = if denied_paths.is_empty() && !rejected {
+            Decision::Allow
+        } else {
+            Decision::Deny
+        };
+        let (missing_scopes, failed_policies) = match (decision, source) {
+            (Decision::Deny, AuditSource::Directives) => {
+                (missing_scopes(context), failed_policies(context))
+            }
+            _ => (Vec::new(), Vec::new()),
+        };
+
+        AuditEvent {
+            timestamp: humantime::format_rfc3339_millis(SystemTime::now()).to_string(),
+            decision,
+            source,
+            rejected,
+            subject: subject(context),
+            operation_name,
+            trace_id: TraceId::maybe_new().map(|trace_id| trace_id.to_string()),
+            denied_paths: denied_paths.iter().map(|path| path.to_string()).collect(),
+            missing_scopes,
+            failed_policies,
+        }
+    }
+}
+
+/// The `sub` claim of the JWT, or the subject of the client certificate
+fn subject(context: &Context) -> Option<String> {
+    context
+        .get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS)
+        .and_then(|claims| {
+            claims
+                .as_object()
+                .and_then(|claims| claims.get("sub"))
+                .and_then(|sub| sub.as_str())
+                .map(|sub| sub.to_string())
+        })
+        .or_else(|| {
+            context
+                .get::<_, String>(CLIENT_CERTIFICATE_SUBJECT)
+                .ok()
+                .flatten()
+        })
+}
+
+/// `@requiresScopes` requirements of the query that the request does not satisfy.
+///
+/// A requirement is satisfied if the request has all the scopes of one of its sets, so for each
+/// unsatisfied requirement, the scopes still missing from each set are reported.
+fn missing_scopes(context: &Context) -> Vec<Vec<Vec<String>>> {
+    let request_scopes = request_scopes(context).unwrap_or_default();
+
+    let requirements = context
+        .extensions()
+        .with_lock(|lock| lock.get::<ScopesRequirements>().cloned())
+        .unwrap_or_default();
+    requirements
+        .0
+        .into_iter()
+        .filter(|requirement| {
+            !requirement
+                .iter()
+                .any(|scopes_set| scopes_set.iter().all(|s| request_scopes.contains(s)))
+        })
+        .map(|requirement| {
+            requirement
+                .into_iter()
+                .map(|scopes_set| {
+                    scopes_set
+                        .into_iter()
+                        .filter(|scope| !request_scopes.contains(scope))
+                        .collect()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Policies required by the query that were not validated
+fn failed_policies(context: &Context) -> Vec<String> {
+    let mut policies = context
+        .get_json_value(REQUIRED_POLICIES_KEY)
+        .and_then(|policies| {
+            policies.as_object().map(|policies| {
+                policies
+                    .iter()
+                    .filter(|(_, result)| !matches!(result, Value::Bool(true)))
+                    .map(|(policy, _)| policy.as_str().to_string())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .unwrap_or_default();
+    policies.sort();
+    policies
+}
+
+/// Number of events waiting to be written to the audit log file. When the file writer falls
+/// behind, new events are dropped and counted instead of delaying the requests.
+const FILE_SINK_BUFFER_SIZE: usize = 10_000;
+
+enum AuditSink {
+    /// lines sent to the thread writing the file, which stops when the sender is dropped
+    File(SyncSender<Vec<u8>>),
+    Otlp {
+        logger: Logger,
+        // the logger only holds a weak reference to its provider
+        provider: Option<LoggerProvider>,
+    },
+    Disabled,
+}
+
+impl Drop for AuditSink {
+    fn drop(&mut self) {
+        // shutting down the provider flushes the pending records and blocks, and the sink is
+        // dropped when the plugin is replaced on reload
+        if let AuditSink::Otlp { provider, .. } = self {
+            if let Some(provider) = provider.take() {
+                match Handle::try_current() {
+                    Ok(handle) => {
+                        handle.spawn_blocking(move || drop(provider));
+                    }
+                    Err(_) => drop(provider),
+                }
+            }
+        }
+    }
+}
+
+pub(crate) struct AuditLogger {
+    include_allowed: bool,
+    sink: AuditSink,
+}
+
+impl AuditLogger {
+    pub(crate) fn new(config: &AuditConfig) -> Result<Self, BoxError> {
+        let sink = match &config.sink {
+            AuditSinkConfig::File {
+                path,
+                rollover,
+                max_files,
+            } => {
+                let mut writer = RollingFileWriter::new(path, rollover.clone(), *max_files)?;
+                let (sender, receiver) =
+                    std::sync::mpsc::sync_channel::<Vec<u8>>(FILE_SINK_BUFFER_SIZE);
+                std::thread::Builder::new()
+                    .name("authorization-audit".to_string())
+                    .spawn(move || {
+                        for line in receiver {
+                            if let Err(e) = writer.write_all(&line) {
+                                tracing::error!(
+                                    "could not write the authorization audit event: {e}"
+                                );
+                            }
+                        }
+                    })?;
+                AuditSink::File(sender)
+            }
+            AuditSinkConfig::Otlp(otlp) if otlp.enabled => {
+                let exporter = otlp
+                    .exporter::<LogExporterBuilder>(TelemetryDataKind::Logs)?
+                    .build_log_exporter()?;
+                let batch_processor = &otlp.batch_processor;
+                let processor = BatchLogProcessor::builder(exporter, opentelemetry::runtime::Tokio)
+                    .with_scheduled_delay(batch_processor.scheduled_delay)
+                    .with_max_queue_size(batch_processor.max_queue_size)
+                    .with_max_export_batch_size(batch_processor.max_export_batch_size)
+                    .with_max_timeout(batch_processor.max_export_timeout)
+                    .build();
+                let provider = LoggerProvider::builder()
+                    .with_log_processor(processor)
+                    .build();
+                let logger = provider.versioned_logger(
+                    "apollo-router-authorization-audit",
+                    Some(env!("CARGO_PKG_VERSION").into()),
+                    None,
+                    None,
+                );
+                AuditSink::Otlp {
+                    logger,
+                    provider: Some(provider),
+                }
+            }
+            AuditSinkConfig::Otlp(_) => AuditSink::Disabled,
+        };
+
+        Ok(Self {
+            include_allowed: config.include_allowed,
+            sink,
+        })
+    }
+
+    pub(crate) fn log(&self, event: AuditEvent) {
+        if event.decision == Decision::Allow && !self.include_allowed {
+            return;
+        }
+
+        match &self.sink {
+            AuditSink::File(sender) => {
+                let mut line = match serde_json::to_vec(&event) {
+                    Ok(line) => line,
+                    Err(e) => {
+                        tracing::error!("could not serialize the authorization audit event: {e}");
+                        return;
+                    }
+                };
+                line.push(b'\n');
+                match sender.try_send(line) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        u64_counter!(
+                            "apollo.router.authorization.audit.dropped",
+                            "Number of authorization audit events dropped because the audit log file writer fell behind",
+                            1
+                        );
+                    }
+                    Err(TrySendError::Disconnected(_)) => {
+                        tracing::error!("the authorization audit log writer stopped");
+                    }
+                }
+            }
+            AuditSink::Otlp { logger, .. } => logger.emit(event.to_log_record()),
+            AuditSink::Disabled => {}
+        }
+    }
+}
+
+impl AuditEvent {
+    fn to_log_record(&self) -> LogRecord {
+        let list = |values: &[String]| {
+            AnyValue::ListAny(values.iter().map(|v| v.clone().into()).collect())
+        };
+        let missing_scopes = AnyValue::ListAny(
+            self.missing_scopes
+                .iter()
+                .map(|requirement| {
+                    AnyValue::ListAny(requirement.iter().map(|set| list(set)).collect())
+                })
+                .collect(),
+        );
+        let mut attributes = vec![
+            (
+                Key::from_static_str("authorization.decision"),
+                AnyValue::from(match self.decision {
+                    Decision::Allow => "allow",
+                    Decision::Deny => "deny",
+                }),
+            ),
+            (
+                Key::from_static_str("authorization.source"),
+                AnyValue::from(match self.source {
+                    AuditSource::RequireAuthentication => "require_authentication",
+                    AuditSource::Directives => "directives",
+                    AuditSource::RowFilters => "row_filters",
+                }),
+            ),
+            (
+                Key::from_static_str("authorization.rejected"),
+                AnyValue::Boolean(self.rejected),
+            ),
+            (
+                Key::from_static_str("authorization.denied_paths"),
+                list(&self.denied_paths),
+            ),
+            (
+                Key::from_static_str("authorization.missing_scopes"),
+                missing_scopes,
+            ),
+            (
+                Key::from_static_str("authorization.failed_policies"),
+                list(&self.failed_policies),
+            ),
+        ];
+        if let Some(subject) = &self.subject {
+            attributes.push((Key::from_static_str("enduser.id"), subject.clone().into()));
+        }
+        if let Some(operation_name) = &self.operation_name {
+            attributes.push((
+                Key::from_static_str("graphql.operation.name"),
+                operation_name.clone().into(),
+            ));
+        }
+        if let Some(trace_id) = &self.trace_id {
+            attributes.push((Key::from_static_str("trace_id"), trace_id.clone().into()));
+        }
+
+        LogRecord::builder()
+            .with_timestamp(SystemTime::now())
+            .with_severity_number(match self.decision {
+                Decision::Allow => Severity::Info,
+                Decision::Deny => Severity::Warn,
+            })
+            .with_body(AnyValue::from("authorization decision"))
+            .with_attributes(attributes)
+            .build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    #[test]
+    fn deny_event_lists_failed_requirements() {
+        let context = Context::new();
+        context
+            .insert(
+                APOLLO_AUTHENTICATION_JWT_CLAIMS,
+                json!({ "sub": "user-1", "scope": "read:user" }),
+            )
+            .unwrap();
+        let scopes = |sets: &[&[&str]]| {
+            sets.iter()
+                .map(|set| {
+                    set.iter()
+                        .map(|scope| scope.to_string())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+        context.extensions().with_lock(|mut lock| {
+            lock.insert(ScopesRequirements(vec![
+                scopes(&[&["admin"], &["read:user", "write:user"]]),
+                scopes(&[&["other"], &["read:user"]]),
+                scopes(&[&["read:email"]]),
+            ]))
+        });
+        context
+            .insert(
+                REQUIRED_POLICIES_KEY,
+                json!({ "admin": false, "internal": null, "user": true }),
+            )
+            .unwrap();
+
+        let event = AuditEvent::new(
+            &context,
+            AuditSource::Directives,
+            Some("GetUser".to_string()),
+            &[Path::from("user/email")],
+            false,
+        );
+        assert_eq!(event.decision, Decision::Deny);
+        assert_eq!(event.subject.as_deref(), Some("user-1"));
+        assert_eq!(event.denied_paths, vec!["/user/email".to_string()]);
+        // the second requirement is satisfied by `read:user`
+        assert_eq!(
+            event.missing_scopes,
+            vec![
+                scopes(&[&["admin"], &["write:user"]]),
+                scopes(&[&["read:email"]])
+            ]
+        );
+        assert_eq!(
+            event.failed_policies,
+            vec!["admin".to_string(), "internal".to_string()]
+        );
+
+        let event = AuditEvent::new(&context, AuditSource::Directives, None, &[], false);
+        assert_eq!(event.decision, Decision::Allow);
+        assert!(event.missing_scopes.is_empty());
+    }
+
+    #[test]
+    fn file_sink() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("audit.log");
+        let logger = AuditLogger::new(
+            &serde_json::from_value(serde_json::json!({
+                "sink": { "file": { "path": path } }
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+
+        let context = Context::new();
+        logger.log(AuditEvent::new(
+            &context,
+            AuditSource::Directives,
+            None,
+            &[],
+            false,
+        ));
+        logger.log(AuditEvent::new(
+            &context,
+            AuditSource::RequireAuthentication,
+            None,
+            &[],
+            true,
+        ));
+
+        // the file is written in the background
+        let mut content = String::new();
+        for _ in 0..100 {
+            content = std::fs::read_to_string(&path).unwrap();
+            if !content.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1);
+        let event: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(event["decision"], "deny");
+        assert_eq!(event["source"], "require_authentication");
+        assert_eq!(event["rejected"], true);
+    }
+}
@@ -3,8 +3,10 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::ops::ControlFlow;
+use std::sync::Arc;
 
 use apollo_compiler::ast;
+use apollo_compiler::validation::Valid;
 use apollo_compiler::ExecutableDocument;
 use http::StatusCode;
 use schemars::JsonSchema;
@@ -15,6 +17,10 @@ use tower::BoxError;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 
+use self::audit::AuditConfig;
+use self::audit::AuditEvent;
+use self::audit::AuditLogger;
+use self::audit::AuditSource;
 use self::authenticated::AuthenticatedCheckVisitor;
 use self::authenticated::AuthenticatedVisitor;
 use self::authenticated::AUTHENTICATED_SPEC_BASE_URL;
@@ -23,10 +29,14 @@ use self::policy::PolicyExtractionVisitor;
 use self::policy::PolicyFilteringVisitor;
 use self::policy::POLICY_SPEC_BASE_URL;
 use self::policy::POLICY_SPEC_VERSION_RANGE;
+use self::row_filter::RowFilter;
+use self::row_filter::RowFilteringVisitor;
 use self::scopes::ScopeExtractionVisitor;
 use self::scopes::ScopeFilteringVisitor;
 use self::scopes::REQUIRES_SCOPES_SPEC_BASE_URL;
 use self::scopes::REQUIRES_SCOPES_SPEC_VERSION_RANGE;
+use crate::axum_factory::utils::CLIENT_CERTIFICATE_FINGERPRINT;
+use crate::axum_factory::utils::CLIENT_CERTIFICATE_SUBJECT_ALT_NAMES;
 use crate::error::QueryPlannerError;
 use crate::error::ServiceBuildError;
 use crate::graphql;
@@ -43,19 +53,70 @@ use crate::services::layers::query_analysis::ParsedDocumentInner;
 use crate::services::supergraph;
 use crate::spec::query::transform;
 use crate::spec::query::traverse;
+use crate::spec::Query;
 use crate::spec::Schema;
 use crate::spec::SpecError;
 use crate::Configuration;
 use crate::Context;
 
+pub(crate) mod audit;
 pub(crate) mod authenticated;
 pub(crate) mod policy;
+pub(crate) mod row_filter;
 pub(crate) mod scopes;
 
 const AUTHENTICATED_KEY: &str = "apollo_authorization::authenticated::required";
 const REQUIRED_SCOPES_KEY: &str = "apollo_authorization::scopes::required";
 const REQUIRED_POLICIES_KEY: &str = "apollo_authorization::policies::required";
 
+/// What the row filters need from the execution request to filter its responses: the query, the
+/// operation name, the JWT claims, the filters and the context
+type RowFilteringData = (
+    Arc<Query>,
+    Option<String>,
+    Option<Value>,
+    Arc<Vec<RowFilter>>,
+    Context,
+);
+
+/// A request is authenticated by a JWT, or by a client certificate verified during the TLS
+/// handshake
+fn is_authenticated(context: &Context) -> bool {
+    context.contains_key(APOLLO_AUTHENTICATION_JWT_CLAIMS)
+        || context.contains_key(CLIENT_CERTIFICATE_FINGERPRINT)
+}
+
+/// The scopes of the request: those of the `scope` claim of the JWT, and the subject alternative
+/// names of the client certificate, like `DNS:client.example.com`
+fn request_scopes(context: &Context) -> Option<HashSet<String>> {
+    let jwt_scopes = context
+        .get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS)
+        .and_then(|value| {
+            value.as_object().and_then(|object| {
+                object.get("scope").and_then(|v| {
+                    v.as_str()
+                        .map(|s| s.split(' ').map(|s| s.to_string()).collect::<HashSet<_>>())
+                })
+            })
+        });
+    let certificate_scopes = context
+        .get::<_, Vec<String>>(CLIENT_CERTIFICATE_SUBJECT_ALT_NAMES)
+        .ok()
+        .flatten()
+        .map(|names| names.into_iter().collect::<HashSet<_>>());
+
+    match (jwt_scopes, certificate_scopes) {
+        (None, None) => None,
+        (jwt_scopes, certificate_scopes) => Some(
+            jwt_scopes
+                .into_iter()
+                .chain(certificate_scopes)
+                .flatten()
+                .collect(),
+        ),
+    }
+}
+
 #[derive(Clone, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct CacheKeyMetadata {
     pub(crate) is_authenticated: bool,
@@ -63,6 +124,11 @@ pub(crate) struct CacheKeyMetadata {
     pub(crate) policies: Vec<String>,
 }
 
+/// The `@requiresScopes` requirements of the query, each one satisfied by any of its sets of
+/// scopes. Stored in the context extensions for the audit log.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ScopesRequirements(pub(crate) Vec<Vec<Vec<String>>>);
+
 /// Authorization plugin
 #[derive(Clone, Debug, serde_derive_default::Default, Deserialize, JsonSchema)]
 #[allow(dead_code)]
@@ -73,6 +139,11 @@ pub(crate) struct Conf {
     /// `@authenticated`, `@requiresScopes` and `@policy` directives
     #[serde(default)]
     directives: Directives,
+    /// filters removing objects from the response depending on their fields and the request's claims
+    #[serde(default)]
+    row_filters: Vec<RowFilter>,
+    /// structured audit log of the authorization decisions
+    audit: Option<AuditConfig>,
 }
 
 #[derive(Clone, Debug, serde_derive_default::Default, Deserialize, JsonSchema)]
@@ -133,8 +204,15 @@ fn default_enable_directives() -> bool {
 
 pub(crate) struct AuthorizationPlugin {
     require_authentication: bool,
+    row_filters: Arc<Vec<RowFilter>>,
+    errors: ErrorConfig,
+    schema: Arc<Valid<apollo_compiler::Schema>>,
+    audit: Option<Arc<AuditLogger>>,
 }
 
+/// Marks requests for which the directives decision was recorded in the audit log
+struct DirectivesAudited;
+
 impl AuthorizationPlugin {
     pub(crate) fn enable_directives(
         configuration: &Configuration,
@@ -180,11 +258,14 @@ impl AuthorizationPlugin {
         schema: &Schema,
         context: &Context,
     ) {
-        let CacheKeyMetadata {
-            is_authenticated,
-            scopes,
-            policies,
-        } = Self::generate_cache_metadata(
+        let (
+            CacheKeyMetadata {
+                is_authenticated,
+                scopes,
+                policies,
+            },
+            scopes_requirements,
+        ) = Self::extract_metadata(
             &doc.executable,
             operation_name,
             schema.supergraph_schema(),
@@ -196,6 +277,9 @@ impl AuthorizationPlugin {
 
         if !scopes.is_empty() {
             context.insert(REQUIRED_SCOPES_KEY, scopes).unwrap();
+            context
+                .extensions()
+                .with_lock(|mut lock| lock.insert(ScopesRequirements(scopes_requirements)));
         }
 
         if !policies.is_empty() {
@@ -211,6 +295,15 @@ impl AuthorizationPlugin {
         schema: &apollo_compiler::Schema,
         entity_query: bool,
     ) -> CacheKeyMetadata {
+        Self::extract_metadata(document, operation_name, schema, entity_query).0
+    }
+
+    fn extract_metadata(
+        document: &ExecutableDocument,
+        operation_name: Option<&str>,
+        schema: &apollo_compiler::Schema,
+        entity_query: bool,
+    ) -> (CacheKeyMetadata, Vec<Vec<Vec<String>>>) {
         let mut is_authenticated = false;
         if let Some(mut visitor) = AuthenticatedCheckVisitor::new(schema, document, entity_query) {
             // if this fails, the query is invalid and will fail at the query planning phase.
@@ -222,12 +315,15 @@ impl AuthorizationPlugin {
         }
 
         let mut scopes = Vec::new();
+        let mut scopes_requirements = Vec::new();
         if let Some(mut visitor) = ScopeExtractionVisitor::new(schema, document, entity_query) {
             // if this fails, the query is invalid and will fail at the query planning phase.
             // We do not return validation errors here for now because that would imply a huge
             // refactoring of telemetry and tests
             if traverse::document(&mut visitor, document, operation_name).is_ok() {
                 scopes = visitor.extracted_scopes.into_iter().collect();
+                scopes_requirements = visitor.extracted_requirements.into_iter().collect();
+                scopes_requirements.sort();
             }
         }
 
@@ -241,26 +337,20 @@ impl AuthorizationPlugin {
             }
         }
 
-        CacheKeyMetadata {
-            is_authenticated,
-            scopes,
-            policies,
-        }
+        (
+            CacheKeyMetadata {
+                is_authenticated,
+                scopes,
+                policies,
+            },
+            scopes_requirements,
+        )
     }
 
     pub(crate) fn update_cache_key(context: &Context) {
-        let is_authenticated = context.contains_key(APOLLO_AUTHENTICATION_JWT_CLAIMS);
-
-        let request_scopes = context
-            .get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS)
-            .and_then(|value| {
-                value.as_object().and_then(|object| {
-                    object.get("scope").and_then(|v| {
-                        v.as_str()
-                            .map(|s| s.split(' ').map(|s| s.to_string()).collect::<HashSet<_>>())
-                    })
-                })
-            });
+        let is_authenticated = is_authenticated(context);
+
+        let request_scopes = request_scopes(context);
         let query_scopes = context.get_json_value(REQUIRED_SCOPES_KEY).and_then(|v| {
             v.as_array().map(|v| {
                 v.iter()
@@ -545,17 +635,79 @@ impl Plugin for AuthorizationPlugin {
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
         Ok(AuthorizationPlugin {
             require_authentication: init.config.require_authentication,
+            row_filters: Arc::new(init.config.row_filters),
+            errors: init.config.directives.errors,
+            schema: init.supergraph_schema,
+            audit: init
+                .config
+                .audit
+                .as_ref()
+                .map(AuditLogger::new)
+                .transpose()?
+                .map(Arc::new),
         })
     }
 
     fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        let service = match self.audit.clone() {
+            // queries rejected by the directives do not reach the execution service, they are
+            // recorded from the response
+            Some(audit) => ServiceBuilder::new()
+                .map_future_with_request_data(
+                    |request: &supergraph::Request| {
+                        (
+                            request.context.clone(),
+                            request.supergraph_request.body().operation_name.clone(),
+                        )
+                    },
+                    move |(context, operation_name): (Context, Option<String>), fut| {
+                        let audit = audit.clone();
+                        async move {
+                            let response: supergraph::Response = fut.await?;
+                            if context
+                                .extensions()
+                                .with_lock(|lock| lock.contains_key::<DirectivesAudited>())
+                            {
+                                return Ok(response);
+                            }
+
+                            let mut first = true;
+                            Ok::<_, BoxError>(response.map_stream(move |response| {
+                                if std::mem::take(&mut first) {
+                                    let rejected_paths = response
+                                        .errors
+                                        .iter()
+                                        .filter(|error| {
+                                            error.extensions.get("code").and_then(|c| c.as_str())
+                                                == Some("UNAUTHORIZED_FIELD_OR_TYPE")
+                                        })
+                                        .filter_map(|error| error.path.clone())
+                                        .collect::<Vec<_>>();
+                                    if !rejected_paths.is_empty() {
+                                        audit.log(AuditEvent::new(
+                                            &context,
+                                            AuditSource::Directives,
+                                            operation_name.clone(),
+                                            &rejected_paths,
+                                            true,
+                                        ));
+                                    }
+                                }
+                                response
+                            }))
+                        }
+                    },
+                )
+                .service(service)
+                .boxed(),
+            None => service,
+        };
+
         if self.require_authentication {
+            let audit = self.audit.clone();
             ServiceBuilder::new()
                 .checkpoint(move |request: supergraph::Request| {
-                    if request
-                        .context
-                        .contains_key(APOLLO_AUTHENTICATION_JWT_CLAIMS)
-                    {
+                    if is_authenticated(&request.context) {
                         Ok(ControlFlow::Continue(request))
                     } else {
                         // This is a metric and will not appear in the logs
@@ -563,6 +715,15 @@ impl Plugin for AuthorizationPlugin {
                             monotonic_counter.apollo_require_authentication_failure_count = 1u64,
                         );
                         tracing::error!("rejecting unauthenticated request");
+                        if let Some(audit) = &audit {
+                            audit.log(AuditEvent::new(
+                                &request.context,
+                                AuditSource::RequireAuthentication,
+                                request.supergraph_request.body().operation_name.clone(),
+                                &[],
+                                true,
+                            ));
+                        }
                         let response = supergraph::Response::error_builder()
                             .error(
                                 graphql::Error::builder()
@@ -584,8 +745,14 @@ impl Plugin for AuthorizationPlugin {
     }
 
     fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        let row_filters = self.row_filters.clone();
+        let errors = self.errors.clone();
+        let schema = self.schema.clone();
+        let audit = self.audit.clone();
+        let row_filters_audit = self.audit.clone();
+
         ServiceBuilder::new()
-            .map_request(|request: execution::Request| {
+            .map_request(move |request: execution::Request| {
                 let filtered = !request.query_plan.query.unauthorized.paths.is_empty();
                 let needs_authenticated = request.context.contains_key(AUTHENTICATED_KEY);
                 let needs_requires_scopes = request.context.contains_key(REQUIRED_SCOPES_KEY);
@@ -599,13 +766,136 @@ impl Plugin for AuthorizationPlugin {
                     );
                 }
 
+                if let Some(audit) = &audit {
+                    request
+                        .context
+                        .extensions()
+                        .with_lock(|mut lock| lock.insert(DirectivesAudited));
+                    if needs_authenticated
+                        || needs_requires_scopes
+                        || request.context.contains_key(REQUIRED_POLICIES_KEY)
+                    {
+                        audit.log(AuditEvent::new(
+                            &request.context,
+                            AuditSource::Directives,
+                            request.supergraph_request.body().operation_name.clone(),
+                            &request.query_plan.query.unauthorized.paths,
+                            false,
+                        ));
+                    }
+                }
+
                 request
             })
+            .map_future_with_request_data(
+                move |request: &execution::Request| {
+                    if row_filters.is_empty() {
+                        None
+                    } else {
+                        Some((
+                            request.query_plan.query.clone(),
+                            request.supergraph_request.body().operation_name.clone(),
+                            request
+                                .context
+                                .get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS),
+                            row_filters.clone(),
+                            request.context.clone(),
+                        ))
+                    }
+                },
+                move |data: Option<RowFilteringData>, fut| {
+                    let errors = errors.clone();
+                    let schema = schema.clone();
+                    let audit = row_filters_audit.clone();
+                    async move {
+                        let response: execution::Response = fut.await?;
+                        let (query, operation_name, claims, row_filters, context) = match data {
+                            Some(data) => data,
+                            None => return Ok(response),
+                        };
+
+                        Ok::<_, BoxError>(response.map_stream(move |mut response| {
+                            let mut visitor = RowFilteringVisitor::new(
+                                &schema,
+                                &row_filters,
+                                claims.as_ref(),
+                                &query,
+                            );
+                            if let Some(data) = response.data.as_mut() {
+                                visitor.filter_response(
+                                    operation_name.as_deref(),
+                                    response.path.as_ref(),
+                                    data,
+                                );
+                            }
+                            let unauthorized_paths = visitor.unauthorized_paths;
+                            if let Some(audit) = &audit {
+                                if !unauthorized_paths.is_empty() {
+                                    audit.log(AuditEvent::new(
+                                        &context,
+                                        AuditSource::RowFilters,
+                                        operation_name.clone(),
+                                        &unauthorized_paths,
+                                        false,
+                                    ));
+                                }
+                            }
+                            Self::add_row_filter_errors(&errors, unauthorized_paths, &mut response);
+                            response
+                        }))
+                    }
+                },
+            )
             .service(service)
             .boxed()
     }
 }
 
+impl AuthorizationPlugin {
+    fn add_row_filter_errors(
+        errors: &ErrorConfig,
+        unauthorized_paths: Vec<Path>,
+        response: &mut graphql::Response,
+    ) {
+        if unauthorized_paths.is_empty() {
+            return;
+        }
+
+        if errors.log {
+            let unauthorized_paths = unauthorized_paths
+                .iter()
+                .map(|path| path.to_string())
+                .collect::<Vec<_>>();
+            tracing::error!(unauthorized_response_paths = ?unauthorized_paths, "Authorization error");
+        }
+
+        let errors_iter = unauthorized_paths.into_iter().map(|path| {
+            graphql::Error::builder()
+                .message("Unauthorized object")
+                .path(path)
+                .extension_code("UNAUTHORIZED_OBJECT")
+                .build()
+        });
+        match errors.response {
+            ErrorLocation::Errors => response.errors.extend(errors_iter),
+            ErrorLocation::Extensions => {
+                let mut authorization_errors =
+                    match response.extensions.remove("authorizationErrors") {
+                        Some(Value::Array(v)) => v,
+                        _ => vec![],
+                    };
+                authorization_errors.extend(errors_iter.map(|error| {
+                    serde_json_bytes::to_value(error).expect("error serialization should not fail")
+                }));
+                response
+                    .extensions
+                    .insert("authorizationErrors", Value::Array(authorization_errors));
+            }
+            ErrorLocation::Disabled => {}
+        }
+    }
+}
+
 // This macro allows us to use it in our plugin registry!
 // register_plugin takes a group name, and a plugin name.
 //
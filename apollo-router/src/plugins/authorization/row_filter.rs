@@ -0,0 +1,363 @@
+//! Authorization filtering applied on response data
+//!
+//! The directives based filtering removes fields from the query before it is planned, but it
+//! cannot decide on the data itself. Row filters look at the objects returned for a type, and
+//! evaluate a condition on their fields and on the claims of the request to decide if the client
+//! can see them. Objects failing the condition are removed from their list, or nulled.
+use apollo_compiler::schema;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json_bytes::Value;
+
+use crate::json_ext::Object;
+use crate::json_ext::Path;
+use crate::json_ext::PathElement;
+use crate::spec::query::Query;
+use crate::spec::Selection;
+use crate::spec::TYPENAME;
+
+/// Filter applied on the objects of a type in the response
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RowFilter {
+    /// name of the type (object, interface or union) the filter applies to
+    #[serde(rename = "type")]
+    pub(crate) type_name: String,
+    /// the object is kept in the response only if this condition is true
+    pub(crate) condition: RowCondition,
+    /// what to do with objects that do not satisfy the condition
+    #[serde(default)]
+    pub(crate) action: RowAction,
+}
+
+/// Condition evaluated on an object of the response and the claims of the request
+///
+/// `field` and `claim` accept a dot separated path to reach nested values. If the field was not
+/// requested by the client or the claim is not present, the condition is false.
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum RowCondition {
+    /// the field of the object is equal to the claim
+    Eq { field: String, claim: String },
+    /// the field of the object is one of the values of the claim, which can be an array or a
+    /// space separated string (like the `scope` claim)
+    In { field: String, claim: String },
+    /// all the conditions are true
+    All(Vec<RowCondition>),
+    /// at least one of the conditions is true
+    Any(Vec<RowCondition>),
+    /// the condition is false
+    Not(Box<RowCondition>),
+}
+
+/// What to do with unauthorized objects
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RowAction {
+    /// remove the object from its list, or replace it with null if it is not in a list
+    #[default]
+    Remove,
+    /// replace the object with null
+    Null,
+}
+
+impl RowCondition {
+    fn evaluate(&self, object: &Object, claims: Option<&Value>) -> bool {
+        match self {
+            RowCondition::Eq { field, claim } => {
+                match (get_path(object, field), get_claim(claims, claim)) {
+                    (Some(field), Some(claim)) => !field.is_null() && field == claim,
+                    _ => false,
+                }
+            }
+            RowCondition::In { field, claim } => {
+                match (get_path(object, field), get_claim(claims, claim)) {
+                    (Some(field), Some(Value::Array(values))) => {
+                        !field.is_null() && values.contains(field)
+                    }
+                    (Some(Value::String(field)), Some(Value::String(values))) => values
+                        .as_str()
+                        .split(' ')
+                        .any(|value| value == field.as_str()),
+                    _ => false,
+                }
+            }
+            RowCondition::All(conditions) => conditions
+                .iter()
+                .all(|condition| condition.evaluate(object, claims)),
+            RowCondition::Any(conditions) => conditions
+                .iter()
+                .any(|condition| condition.evaluate(object, claims)),
+            RowCondition::Not(condition) => !condition.evaluate(object, claims),
+        }
+    }
+}
+
+fn get_path<'a>(object: &'a Object, path: &str) -> Option<&'a Value> {
+    let mut segments = path.split('.');
+    let mut current = object.get(segments.next()?)?;
+    for segment in segments {
+        current = current.as_object()?.get(segment)?;
+    }
+    Some(current)
+}
+
+fn get_claim<'a>(claims: Option<&'a Value>, path: &str) -> Option<&'a Value> {
+    get_path(claims?.as_object()?, path)
+}
+
+/// Applies the row filters on a response
+pub(crate) struct RowFilteringVisitor<'a> {
+    schema: &'a schema::Schema,
+    filters: &'a [RowFilter],
+    claims: Option<&'a Value>,
+    query: &'a Query,
+    pub(crate) unauthorized_paths: Vec<Path>,
+}
+
+impl<'a> RowFilteringVisitor<'a> {
+    pub(crate) fn new(
+        schema: &'a schema::Schema,
+        filters: &'a [RowFilter],
+        claims: Option<&'a Value>,
+        query: &'a Query,
+    ) -> Self {
+        Self {
+            schema,
+            filters,
+            claims,
+            query,
+            unauthorized_paths: Vec::new(),
+        }
+    }
+
+    /// Filters the data of a primary or deferred response.
+    ///
+    /// `path` is the location of `data` in the complete response, it is empty for the
+    /// primary response.
+    pub(crate) fn filter_response(
+        &mut self,
+        operation_name: Option<&str>,
+        path: Option<&Path>,
+        data: &mut Value,
+    ) {
+        let query = self.query;
+        let operation = match query.operation(operation_name) {
+            Some(operation) => operation,
+            None => return,
+        };
+        let mut selections: Vec<&'a Selection> = operation.selection_set.iter().collect();
+        let mut current_type: Option<&'a schema::Type> = None;
+        let mut current_path = Path::default();
+
+        // deferred responses are located deeper in the query: follow the path to find the
+        // selections that apply to their data
+        for element in path.iter().flat_map(|path| path.iter()) {
+            current_path.push(element.clone());
+            if let PathElement::Key(key, _) = element {
+                let mut next = Vec::new();
+                for (field_type, selection_set) in self.fields_for_key(&selections, key) {
+                    current_type = Some(field_type);
+                    next.extend(selection_set);
+                }
+                selections = next;
+            }
+        }
+
+        // the root of the primary response cannot be removed, but deferred data is an object
+        // of the type found at the end of the path
+        let nulled = match data {
+            Value::Object(object) => match current_type {
+                Some(field_type)
+                    if self.denied(object, field_type.inner_named_type()).is_some() =>
+                {
+                    self.unauthorized_paths.push(current_path);
+                    true
+                }
+                _ => self.filter_selection_set(object, &selections, &mut current_path),
+            },
+            _ => false,
+        };
+        if nulled {
+            *data = Value::Null;
+        }
+    }
+
+    /// Returns `true` if a non nullable field of the object was nulled, so the object must be
+    /// nulled too.
+    fn filter_selection_set(
+        &mut self,
+        object: &mut Object,
+        selections: &[&'a Selection],
+        path: &mut Path,
+    ) -> bool {
+        let typename = object
+            .get(TYPENAME)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        let mut keys = Vec::new();
+        self.collect_fields(selections, typename.as_deref(), &mut keys);
+
+        for (key, field_type, selection_set) in keys {
+            if let Some(value) = object.get_mut(key.as_str()) {
+                path.push(PathElement::Key(key.clone(), None));
+                let nulled = self.filter_value(value, field_type, &selection_set, path);
+                path.pop();
+
+                if nulled {
+                    if field_type.is_non_null() {
+                        return true;
+                    }
+                    *value = Value::Null;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Returns `true` if the value must be nulled
+    fn filter_value(
+        &mut self,
+        value: &mut Value,
+        field_type: &schema::Type,
+        selections: &[&'a Selection],
+        path: &mut Path,
+    ) -> bool {
+        match value {
+            Value::Array(items) if field_type.is_list() => {
+                let item_type = field_type.item_type();
+                let mut filtered = Vec::with_capacity(items.len());
+                for (index, mut item) in std::mem::take(items).into_iter().enumerate() {
+                    path.push(PathElement::Index(index));
+                    let action = item
+                        .as_object()
+                        .and_then(|object| self.denied(object, item_type.inner_named_type()));
+
+                    let nulled = match action {
+                        Some(action) => {
+                            self.unauthorized_paths.push(path.clone());
+                            if action == RowAction::Remove {
+                                path.pop();
+                                continue;
+                            }
+                            true
+                        }
+                        None => self.filter_value(&mut item, item_type, selections, path),
+                    };
+                    path.pop();
+
+                    if nulled {
+                        if item_type.is_non_null() {
+                            return true;
+                        }
+                        item = Value::Null;
+                    }
+                    filtered.push(item);
+                }
+                *items = filtered;
+                false
+            }
+            Value::Object(object) => {
+                if self.denied(object, field_type.inner_named_type()).is_some() {
+                    self.unauthorized_paths.push(path.clone());
+                    return true;
+                }
+                self.filter_selection_set(object, selections, path)
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the action to apply if one of the filters rejects the object
+    fn denied(&self, object: &Object, declared_type: &str) -> Option<RowAction> {
+        let typename = object.get(TYPENAME).and_then(|v| v.as_str());
+        self.filters
+            .iter()
+            .filter(|filter| {
+                filter.type_name == declared_type
+                    || typename.map_or(false, |typename| {
+                        filter.type_name == typename
+                            || self.schema.is_subtype(&filter.type_name, typename)
+                    })
+            })
+            .find(|filter| !filter.condition.evaluate(object, self.claims))
+            .map(|filter| filter.action)
+    }
+
+    fn collect_fields(
+        &self,
+        selections: &[&'a Selection],
+        typename: Option<&str>,
+        fields: &mut Vec<(String, &'a schema::Type, Vec<&'a Selection>)>,
+    ) {
+        for selection in selections.iter().copied() {
+            match selection {
+                Selection::Field {
+                    name,
+                    alias,
+                    selection_set: Some(selection_set),
+                    field_type,
+                    ..
+                } => {
+                    let key = alias.as_ref().unwrap_or(name).as_str();
+                    match fields.iter_mut().find(|(k, _, _)| k == key) {
+                        Some((_, _, existing)) => existing.extend(selection_set.iter()),
+                        None => fields.push((
+                            key.to_string(),
+                            &field_type.0,
+                            selection_set.iter().collect(),
+                        )),
+                    }
+                }
+                Selection::Field { .. } => {}
+                Selection::InlineFragment {
+                    type_condition,
+                    selection_set,
+                    ..
+                } => {
+                    if self.applies(type_condition, typename) {
+                        let selection_set: Vec<&'a Selection> = selection_set.iter().collect();
+                        self.collect_fields(&selection_set, typename, fields);
+                    }
+                }
+                Selection::FragmentSpread { name, .. } => {
+                    let query = self.query;
+                    if let Some(fragment) = query.fragments.get(name) {
+                        if self.applies(&fragment.type_condition, typename) {
+                            let selection_set: Vec<&'a Selection> =
+                                fragment.selection_set.iter().collect();
+                            self.collect_fields(&selection_set, typename, fields);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn fields_for_key(
+        &self,
+        selections: &[&'a Selection],
+        key: &str,
+    ) -> Vec<(&'a schema::Type, Vec<&'a Selection>)> {
+        let mut fields = Vec::new();
+        self.collect_fields(selections, None, &mut fields);
+        fields
+            .into_iter()
+            .filter(|(k, _, _)| k == key)
+            .map(|(_, field_type, selection_set)| (field_type, selection_set))
+            .collect()
+    }
+
+    /// Without `__typename` in the response we cannot know which fragments apply, so they
+    /// are all visited
+    fn applies(&self, type_condition: &str, typename: Option<&str>) -> bool {
+        match typename {
+            Some(typename) => {
+                type_condition == typename || self.schema.is_subtype(type_condition, typename)
+            }
+            None => true,
+        }
+    }
+}
@@ -28,6 +28,8 @@ pub(crate) struct ScopeExtractionVisitor<'a> {
     schema: &'a schema::Schema,
     fragments: HashMap<&'a Name, &'a Node<executable::Fragment>>,
     pub(crate) extracted_scopes: HashSet<String>,
+    /// each requirement is satisfied by any of its sets of scopes
+    pub(crate) extracted_requirements: HashSet<Vec<Vec<String>>>,
     requires_scopes_directive_name: String,
     entity_query: bool,
 }
@@ -48,6 +50,7 @@ impl<'a> ScopeExtractionVisitor<'a> {
             entity_query,
             fragments: executable.fragments.iter().collect(),
             extracted_scopes: HashSet::new(),
+            extracted_requirements: HashSet::new(),
             requires_scopes_directive_name: Schema::directive_name(
                 schema,
                 REQUIRES_SCOPES_SPEC_BASE_URL,
@@ -58,9 +61,10 @@ impl<'a> ScopeExtractionVisitor<'a> {
     }
 
     fn scopes_from_field(&mut self, field: &schema::FieldDefinition) {
-        self.extracted_scopes.extend(scopes_argument(
-            field.directives.get(&self.requires_scopes_directive_name),
-        ));
+        let directive = field.directives.get(&self.requires_scopes_directive_name);
+        self.extracted_scopes.extend(scopes_argument(directive));
+        self.extracted_requirements
+            .extend(scopes_requirement(directive));
 
         if let Some(ty) = self.schema.types.get(field.ty.inner_named_type()) {
             self.scopes_from_type(ty)
@@ -68,9 +72,10 @@ impl<'a> ScopeExtractionVisitor<'a> {
     }
 
     fn scopes_from_type(&mut self, ty: &schema::ExtendedType) {
-        self.extracted_scopes.extend(scopes_argument(
-            ty.directives().get(&self.requires_scopes_directive_name),
-        ));
+        let directive = ty.directives().get(&self.requires_scopes_directive_name);
+        self.extracted_scopes.extend(scopes_argument(directive));
+        self.extracted_requirements
+            .extend(scopes_requirement(directive));
     }
 
     fn entities_operation(&mut self, node: &executable::Operation) -> Result<(), BoxError> {
@@ -121,12 +126,30 @@ fn scopes_argument(
         .filter_map(|value| value.as_str().map(str::to_owned))
 }
 
+/// The sets of scopes of the directive, sorted so that identical requirements are deduplicated.
+/// A directive without any set does not require anything.
+fn scopes_requirement(
+    opt_directive: Option<&impl AsRef<ast::Directive>>,
+) -> Option<Vec<Vec<String>>> {
+    let mut requirement = opt_directive
+        .map(|directive| {
+            scopes_sets_argument(directive.as_ref())
+                .map(|scopes_set| {
+                    let mut scopes = scopes_set.into_iter().collect::<Vec<_>>();
+                    scopes.sort();
+                    scopes
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|requirement| !requirement.is_empty())?;
+    requirement.sort();
+    Some(requirement)
+}
+
 impl<'a> traverse::Visitor for ScopeExtractionVisitor<'a> {
     fn operation(&mut self, root_type: &str, node: &executable::Operation) -> Result<(), BoxError> {
         if let Some(ty) = self.schema.types.get(root_type) {
-            self.extracted_scopes.extend(scopes_argument(
-                ty.directives().get(&self.requires_scopes_directive_name),
-            ));
+            self.scopes_from_type(ty);
         }
 
         if !self.entity_query {
@@ -741,6 +764,50 @@ mod tests {
         insta::assert_debug_snapshot!(doc);
     }
 
+    #[test]
+    fn extract_requirements() {
+        static QUERY: &str = r#"
+        {
+            topProducts {
+                type
+                internal
+            }
+
+            me {
+                name
+            }
+        }
+        "#;
+
+        let schema = Schema::parse_and_validate(BASIC_SCHEMA, "schema.graphql").unwrap();
+        let exec = Document::parse(QUERY, "query.graphql")
+            .unwrap()
+            .to_executable_validate(&schema)
+            .unwrap();
+        let mut visitor = ScopeExtractionVisitor::new(&schema, &exec, false).unwrap();
+        traverse::document(&mut visitor, &exec, None).unwrap();
+
+        let requirements = visitor
+            .extracted_requirements
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        let expected = [
+            vec![vec!["internal", "test"]],
+            vec![vec!["profile"]],
+            vec![vec!["read:user"]],
+            vec![vec!["read:username"]],
+        ]
+        .iter()
+        .map(|requirement| {
+            requirement
+                .iter()
+                .map(|set| set.iter().map(|scope| scope.to_string()).collect())
+                .collect()
+        })
+        .collect::<BTreeSet<Vec<Vec<String>>>>();
+        assert_eq!(requirements, expected);
+    }
+
     #[track_caller]
     fn filter(schema: &str, query: &str, scopes: HashSet<String>) -> (Document, Vec<Path>) {
         let schema = Schema::parse_and_validate(schema, "schema.graphql").unwrap();
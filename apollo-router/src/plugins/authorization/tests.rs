@@ -7,7 +7,9 @@ use tower::ServiceExt;
 use crate::graphql;
 use crate::plugin::test::MockSubgraph;
 use crate::plugin::test::MockSubgraphService;
+use crate::plugins::authorization::AuthorizationPlugin;
 use crate::plugins::authorization::CacheKeyMetadata;
+use crate::plugins::authorization::REQUIRED_SCOPES_KEY;
 use crate::services::router;
 use crate::services::subgraph;
 use crate::services::supergraph;
@@ -1157,3 +1159,137 @@ async fn cache_key_metadata() {
 
     insta::assert_json_snapshot!(response);
 }
+
+#[test]
+fn row_filters() {
+    use crate::json_ext::Path;
+    use crate::plugins::authorization::row_filter::RowFilter;
+    use crate::plugins::authorization::row_filter::RowFilteringVisitor;
+    use crate::spec::Query;
+    use crate::spec::Schema;
+
+    let schema = Schema::parse(SCHEMA, &Default::default()).unwrap();
+    let query = Query::parse(
+        "query { orga(id: 1) { id suborga { id name } creatorUser { id name } } }",
+        None,
+        &schema,
+        &Default::default(),
+    )
+    .unwrap();
+
+    let filters: Vec<RowFilter> = serde_json::from_value(serde_json::json!([
+        {
+            "type": "Organization",
+            "condition": { "in": { "field": "id", "claim": "organizations" } }
+        },
+        {
+            "type": "User",
+            "action": "null",
+            "condition": { "eq": { "field": "id", "claim": "sub" } }
+        }
+    ]))
+    .unwrap();
+    let claims = json!({ "sub": "1", "organizations": ["1", "3"] });
+
+    let mut data = json!({
+        "orga": {
+            "id": "1",
+            "suborga": [
+                { "id": "2", "name": "B" },
+                { "id": "3", "name": "C" },
+                { "name": "D" }
+            ],
+            "creatorUser": { "id": "0", "name": "Ada" }
+        }
+    });
+
+    let mut visitor =
+        RowFilteringVisitor::new(schema.supergraph_schema(), &filters, Some(&claims), &query);
+    visitor.filter_response(None, None, &mut data);
+
+    assert_eq!(
+        data,
+        json!({
+            "orga": {
+                "id": "1",
+                "suborga": [
+                    { "id": "3", "name": "C" }
+                ],
+                "creatorUser": null
+            }
+        })
+    );
+    assert_eq!(
+        visitor.unauthorized_paths,
+        vec![
+            Path::from("orga/suborga/0"),
+            Path::from("orga/suborga/2"),
+            Path::from("orga/creatorUser"),
+        ]
+    );
+
+    // without claims, everything is filtered
+    let mut data = json!({ "orga": { "id": "1", "suborga": [] } });
+    let mut visitor = RowFilteringVisitor::new(schema.supergraph_schema(), &filters, None, &query);
+    visitor.filter_response(None, None, &mut data);
+    assert_eq!(data, json!({ "orga": null }));
+}
+
+#[test]
+fn client_certificate_authenticates_and_adds_scopes() {
+    let context = Context::new();
+    context
+        .insert(
+            REQUIRED_SCOPES_KEY,
+            vec![
+                "URI:spiffe://example.com/client".to_string(),
+                "user:read".to_string(),
+                "admin".to_string(),
+            ],
+        )
+        .unwrap();
+    context
+        .insert(
+            "apollo_tls::client_certificate::subject_alt_names",
+            vec!["URI:spiffe://example.com/client".to_string()],
+        )
+        .unwrap();
+    context
+        .insert(
+            "apollo_tls::client_certificate::fingerprint",
+            "ab12cd34".to_string(),
+        )
+        .unwrap();
+    AuthorizationPlugin::update_cache_key(&context);
+    let metadata = context
+        .extensions()
+        .with_lock(|lock| lock.get::<CacheKeyMetadata>().cloned())
+        .unwrap();
+    assert!(metadata.is_authenticated);
+    assert_eq!(metadata.scopes, vec!["URI:spiffe://example.com/client"]);
+
+    // the scopes of the JWT are added to those of the certificate
+    context
+        .insert(
+            "apollo_authentication::JWT::claims",
+            json! {{ "scope": "user:read" }},
+        )
+        .unwrap();
+    AuthorizationPlugin::update_cache_key(&context);
+    let metadata = context
+        .extensions()
+        .with_lock(|lock| lock.get::<CacheKeyMetadata>().cloned())
+        .unwrap();
+    assert_eq!(
+        metadata.scopes,
+        vec!["URI:spiffe://example.com/client", "user:read"]
+    );
+
+    let context = Context::new();
+    AuthorizationPlugin::update_cache_key(&context);
+    let metadata = context
+        .extensions()
+        .with_lock(|lock| lock.get::<CacheKeyMetadata>().cloned())
+        .unwrap();
+    assert!(!metadata.is_authenticated);
+}
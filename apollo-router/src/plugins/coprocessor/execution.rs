@@ -8,7 +8,6 @@ use serde::Deserialize;
 use serde::Serialize;
 use tower::BoxError;
 use tower::ServiceBuilder;
-use tower_service::Service;
 
 use super::externalize_header_map;
 use super::*;
@@ -34,6 +33,11 @@ pub(super) struct ExecutionRequestConf {
     pub(super) method: bool,
     /// Send the query plan
     pub(super) query_plan: bool,
+    /// What to do when the coprocessor call fails
+    pub(super) on_error: OnError,
+    /// Send the payload without waiting for the coprocessor, which cannot modify the stage
+    #[serde(rename = "async")]
+    pub(super) asynchronous: bool,
 }
 
 /// What information is passed to a router request/response stage
@@ -50,6 +54,11 @@ pub(super) struct ExecutionResponseConf {
     pub(super) sdl: bool,
     /// Send the HTTP status
     pub(super) status_code: bool,
+    /// What to do when the coprocessor call fails
+    pub(super) on_error: OnError,
+    /// Send the payload without waiting for the coprocessor, which cannot modify the stage
+    #[serde(rename = "async")]
+    pub(super) asynchronous: bool,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
@@ -70,15 +79,7 @@ impl ExecutionStage {
         sdl: Arc<String>,
     ) -> execution::BoxService
     where
-        C: Service<
-                http::Request<RouterBody>,
-                Response = http::Response<RouterBody>,
-                Error = BoxError,
-            > + Clone
-            + Send
-            + Sync
-            + 'static,
-        <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+        C: CoprocessorTransport,
     {
         let request_layer = (self.request != Default::default()).then_some({
             let request_config = self.request.clone();
@@ -190,12 +191,7 @@ async fn process_execution_request_stage<C>(
     request_config: ExecutionRequestConf,
 ) -> Result<ControlFlow<execution::Response, execution::Request>, BoxError>
 where
-    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
-        + Clone
-        + Send
-        + Sync
-        + 'static,
-    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+    C: CoprocessorTransport,
 {
     // Call into our out of process processor with a body of our body
     // First, extract the data we need from our request and prepare our
@@ -234,7 +230,15 @@ where
     tracing::debug!(?payload, "externalized output");
     let guard = request.context.enter_active_request();
     let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
+    let co_processor_result = call_coprocessor(
+        http_client,
+        &coprocessor_url,
+        payload,
+        PipelineStep::ExecutionRequest,
+        &request_config.on_error,
+        request_config.asynchronous,
+    )
+    .await;
     let duration = start.elapsed().as_secs_f64();
     drop(guard);
     tracing::info!(
@@ -244,8 +248,7 @@ where
 
     tracing::debug!(?co_processor_result, "co-processor returned");
     let co_processor_output = co_processor_result?;
-    validate_coprocessor_output(&co_processor_output, PipelineStep::ExecutionRequest)?;
-    // unwrap is safe here because validate_coprocessor_output made sure control is available
+    // unwrap is safe here because call_coprocessor validated that control is available
     let control = co_processor_output.control.expect("validated above; qed");
 
     // Thirdly, we need to interpret the control flow which may have been
@@ -332,12 +335,7 @@ async fn process_execution_response_stage<C>(
     response_config: ExecutionResponseConf,
 ) -> Result<execution::Response, BoxError>
 where
-    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
-        + Clone
-        + Send
-        + Sync
-        + 'static,
-    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+    C: CoprocessorTransport,
 {
     // split the response into parts + body
     let (mut parts, body) = response.response.into_parts();
@@ -380,7 +378,15 @@ where
     tracing::debug!(?payload, "externalized output");
     let guard = response.context.enter_active_request();
     let start = Instant::now();
-    let co_processor_result = payload.call(http_client.clone(), &coprocessor_url).await;
+    let co_processor_result = call_coprocessor(
+        http_client.clone(),
+        &coprocessor_url,
+        payload,
+        PipelineStep::ExecutionResponse,
+        &response_config.on_error,
+        response_config.asynchronous,
+    )
+    .await;
     let duration = start.elapsed().as_secs_f64();
     drop(guard);
     tracing::info!(
@@ -391,8 +397,6 @@ where
     tracing::debug!(?co_processor_result, "co-processor returned");
     let co_processor_output = co_processor_result?;
 
-    validate_coprocessor_output(&co_processor_output, PipelineStep::ExecutionResponse)?;
-
     // Third, process our reply and act on the contents. Our processing logic is
     // that we replace "bits" of our incoming response with the updated bits if they
     // are present in our co_processor_output. If they aren't present, just use the
@@ -451,15 +455,19 @@ where
                 // Second, call our co-processor and get a reply.
                 tracing::debug!(?payload, "externalized output");
                 let guard = generator_map_context.enter_active_request();
-                let co_processor_result = payload
-                    .call(generator_client, &generator_coprocessor_url)
-                    .await;
+                let co_processor_result = call_coprocessor(
+                    generator_client,
+                    &generator_coprocessor_url,
+                    payload,
+                    PipelineStep::ExecutionResponse,
+                    &response_config.on_error,
+                    response_config.asynchronous,
+                )
+                .await;
                 drop(guard);
                 tracing::debug!(?co_processor_result, "co-processor returned");
                 let co_processor_output = co_processor_result?;
 
-                validate_coprocessor_output(&co_processor_output, PipelineStep::ExecutionResponse)?;
-
                 // Third, process our reply and act on the contents. Our processing logic is
                 // that we replace "bits" of our incoming response with the updated bits if they
                 // are present in our co_processor_output. If they aren't present, just use the
@@ -576,6 +584,8 @@ mod tests {
                 sdl: false,
                 method: false,
                 query_plan: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -710,6 +720,8 @@ mod tests {
                 sdl: false,
                 method: false,
                 query_plan: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -781,6 +793,8 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             request: Default::default(),
         };
@@ -913,6 +927,8 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             request: Default::default(),
         };
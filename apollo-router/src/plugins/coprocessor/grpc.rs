@@ -0,0 +1,574 @@
+//! gRPC transport for the coprocessor protocol
+//!
+//! The payloads of the coprocessor stages are converted to the protobuf schema in
+//! `proto/coprocessor.proto` and sent over pooled HTTP/2 connections, with TCP or unix sockets.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use http::uri::PathAndQuery;
+use http::uri::Scheme;
+use http::HeaderMap;
+use opentelemetry::global::get_text_map_propagator;
+use prost::Message;
+use prost_types::value::Kind;
+use schemars::JsonSchema;
+use serde::de::value::StrDeserializer;
+use serde::de::DeserializeOwned;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::mpsc::Sender;
+use tonic::client::Grpc;
+use tonic::codec::Codec;
+use tonic::codec::DecodeBuf;
+use tonic::codec::Decoder;
+use tonic::codec::EncodeBuf;
+use tonic::codec::Encoder;
+use tonic::metadata::MetadataMap;
+use tonic::transport::Channel;
+use tonic::transport::ClientTlsConfig;
+use tonic::transport::Endpoint;
+use tonic::Status;
+use tower::discover::Change;
+use tower::BoxError;
+
+use crate::plugins::telemetry::otel::OpenTelemetrySpanExt;
+use crate::plugins::telemetry::reload::prepare_context;
+use crate::services::external::Control;
+use crate::services::external::Externalizable;
+use crate::services::external::PipelineStep;
+use crate::Context;
+
+pub(crate) mod proto {
+    #![allow(clippy::derive_partial_eq_without_eq)]
+    #![allow(unreachable_pub)]
+    tonic::include_proto!("coprocessor");
+}
+
+const PROCESS_PATH: &str = "/coprocessor.Coprocessor/Process";
+// the integers above 2^53 cannot be represented exactly by a double
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Configures the gRPC client used to call the coprocessor
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(super) struct GrpcConf {
+    /// The number of HTTP/2 connections opened to the coprocessor, requests are balanced over them.
+    /// Unix sockets use a single connection.
+    pub(super) pool_size: usize,
+    /// The interval of the HTTP/2 and TCP keepalive pings
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    pub(super) keepalive_interval: Duration,
+    /// How long to wait for the acknowledgement of a keepalive ping before closing the connection
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    pub(super) keepalive_timeout: Duration,
+    /// The timeout for establishing a connection
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    pub(super) connect_timeout: Duration,
+}
+
+impl Default for GrpcConf {
+    fn default() -> Self {
+        Self {
+            pool_size: 4,
+            keepalive_interval: Duration::from_secs(30),
+            keepalive_timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Sends the coprocessor payloads as protobuf messages to a gRPC coprocessor
+#[derive(Clone, Debug)]
+pub(super) struct GrpcClientService {
+    channel: Channel,
+    // the pool connections are closed when the sender is dropped, unix sockets use a single
+    // connection
+    _pool: Option<Sender<Change<usize, Endpoint>>>,
+}
+
+impl GrpcClientService {
+    pub(super) fn new(url: &str, timeout: Duration, config: &GrpcConf) -> Result<Self, BoxError> {
+        let socket_path = url.strip_prefix("unix://");
+        // the connector of unix sockets ignores the endpoint URL
+        let endpoint_url = match socket_path {
+            Some(_) => "http://[::]:50051".to_string(),
+            None => url.to_string(),
+        };
+        let mut endpoint = Endpoint::from_shared(endpoint_url)?
+            .timeout(timeout)
+            .connect_timeout(config.connect_timeout)
+            .tcp_nodelay(true)
+            .tcp_keepalive(Some(config.keepalive_interval))
+            .http2_keep_alive_interval(config.keepalive_interval)
+            .keep_alive_timeout(config.keepalive_timeout)
+            .keep_alive_while_idle(true);
+        if endpoint.uri().scheme() == Some(&Scheme::HTTPS) {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new())?;
+        }
+
+        match socket_path {
+            // the balanced channel only opens connections with the default connector
+            Some(path) => Ok(Self {
+                channel: connect_unix(&endpoint, path)?,
+                _pool: None,
+            }),
+            None => {
+                let pool_size = config.pool_size.max(1);
+                let (channel, pool) = Channel::balance_channel(pool_size);
+                for connection in 0..pool_size {
+                    pool.try_send(Change::Insert(connection, endpoint.clone()))
+                        .map_err(|_| "could not open the coprocessor connection pool")?;
+                }
+                Ok(Self {
+                    channel,
+                    _pool: Some(pool),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn connect_unix(endpoint: &Endpoint, path: &str) -> Result<Channel, BoxError> {
+    let path = std::path::PathBuf::from(path);
+    Ok(
+        endpoint.connect_with_connector_lazy(tower::service_fn(move |_: http::Uri| {
+            tokio::net::UnixStream::connect(path.clone())
+        })),
+    )
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_endpoint: &Endpoint, _path: &str) -> Result<Channel, BoxError> {
+    Err("unix sockets are not supported on this platform".into())
+}
+
+impl GrpcClientService {
+    pub(super) fn process<T>(
+        &self,
+        payload: Externalizable<T>,
+    ) -> BoxFuture<'static, Result<Externalizable<T>, BoxError>>
+    where
+        T: Debug + DeserializeOwned + Serialize + Send + Sync + 'static,
+    {
+        let mut grpc = Grpc::new(self.channel.clone());
+
+        // The metadata carries the trace propagation
+        let mut headers = HeaderMap::new();
+        get_text_map_propagator(|propagator| {
+            propagator.inject_context(
+                &prepare_context(tracing::span::Span::current().context()),
+                &mut opentelemetry_http::HeaderInjector(&mut headers),
+            );
+        });
+
+        Box::pin(async move {
+            let request = tonic::Request::from_parts(
+                MetadataMap::from_headers(headers),
+                tonic::Extensions::default(),
+                proto::Externalizable::try_from(payload)?,
+            );
+            grpc.ready().await?;
+            let response = grpc
+                .unary(
+                    request,
+                    PathAndQuery::from_static(PROCESS_PATH),
+                    ProtobufCodec,
+                )
+                .await?;
+            Externalizable::try_from(response.into_inner())
+        })
+    }
+}
+
+/// Encodes the coprocessor messages with the prost version used by the router, which is not the
+/// one of the tonic codec.
+#[derive(Clone, Copy, Debug, Default)]
+struct ProtobufCodec;
+
+impl Codec for ProtobufCodec {
+    type Encode = proto::Externalizable;
+    type Decode = proto::Externalizable;
+    type Encoder = ProtobufCodec;
+    type Decoder = ProtobufCodec;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        ProtobufCodec
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        ProtobufCodec
+    }
+}
+
+impl Encoder for ProtobufCodec {
+    type Item = proto::Externalizable;
+    type Error = Status;
+
+    fn encode(&mut self, item: Self::Item, dst: &mut EncodeBuf<'_>) -> Result<(), Self::Error> {
+        item.encode(dst)
+            .map_err(|error| Status::internal(error.to_string()))
+    }
+}
+
+impl Decoder for ProtobufCodec {
+    type Item = proto::Externalizable;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Self::Item>, Self::Error> {
+        proto::Externalizable::decode(src)
+            .map(Some)
+            .map_err(|error| Status::internal(error.to_string()))
+    }
+}
+
+impl From<PipelineStep> for proto::Stage {
+    fn from(step: PipelineStep) -> Self {
+        match step {
+            PipelineStep::RouterRequest => proto::Stage::RouterRequest,
+            PipelineStep::RouterResponse => proto::Stage::RouterResponse,
+            PipelineStep::SupergraphRequest => proto::Stage::SupergraphRequest,
+            PipelineStep::SupergraphResponse => proto::Stage::SupergraphResponse,
+            PipelineStep::ExecutionRequest => proto::Stage::ExecutionRequest,
+            PipelineStep::ExecutionResponse => proto::Stage::ExecutionResponse,
+            PipelineStep::SubgraphRequest => proto::Stage::SubgraphRequest,
+            PipelineStep::SubgraphResponse => proto::Stage::SubgraphResponse,
+            PipelineStep::QueryPlannerRequest => proto::Stage::QueryPlannerRequest,
+            PipelineStep::QueryPlannerResponse => proto::Stage::QueryPlannerResponse,
+        }
+    }
+}
+
+impl TryFrom<proto::Stage> for PipelineStep {
+    type Error = BoxError;
+
+    fn try_from(stage: proto::Stage) -> Result<Self, Self::Error> {
+        Ok(match stage {
+            proto::Stage::Unspecified => return Err("unspecified coprocessor stage".into()),
+            proto::Stage::RouterRequest => PipelineStep::RouterRequest,
+            proto::Stage::RouterResponse => PipelineStep::RouterResponse,
+            proto::Stage::SupergraphRequest => PipelineStep::SupergraphRequest,
+            proto::Stage::SupergraphResponse => PipelineStep::SupergraphResponse,
+            proto::Stage::ExecutionRequest => PipelineStep::ExecutionRequest,
+            proto::Stage::ExecutionResponse => PipelineStep::ExecutionResponse,
+            proto::Stage::SubgraphRequest => PipelineStep::SubgraphRequest,
+            proto::Stage::SubgraphResponse => PipelineStep::SubgraphResponse,
+            proto::Stage::QueryPlannerRequest => PipelineStep::QueryPlannerRequest,
+            proto::Stage::QueryPlannerResponse => PipelineStep::QueryPlannerResponse,
+        })
+    }
+}
+
+impl From<Control> for proto::Control {
+    fn from(control: Control) -> Self {
+        let action = match control {
+            Control::Continue => proto::control::Action::Continue(true),
+            Control::Break(status) => proto::control::Action::Break(status.into()),
+        };
+        proto::Control {
+            action: Some(action),
+        }
+    }
+}
+
+impl TryFrom<proto::Control> for Control {
+    type Error = BoxError;
+
+    fn try_from(control: proto::Control) -> Result<Self, Self::Error> {
+        Ok(match control.action {
+            None | Some(proto::control::Action::Continue(_)) => Control::Continue,
+            Some(proto::control::Action::Break(status)) => Control::Break(status.try_into()?),
+        })
+    }
+}
+
+impl<T: Serialize> TryFrom<Externalizable<T>> for proto::Externalizable {
+    type Error = BoxError;
+
+    fn try_from(payload: Externalizable<T>) -> Result<Self, Self::Error> {
+        let deserializer: StrDeserializer<serde::de::value::Error> =
+            payload.stage.as_str().into_deserializer();
+        let stage = PipelineStep::deserialize(deserializer)?;
+        Ok(proto::Externalizable {
+            version: payload.version.into(),
+            stage: proto::Stage::from(stage).into(),
+            control: payload.control.map(Into::into),
+            id: payload.id,
+            headers: payload.headers.map(|headers| proto::Headers {
+                entries: headers
+                    .into_iter()
+                    .map(|(name, values)| (name, proto::HeaderValues { values }))
+                    .collect(),
+            }),
+            body: payload.body.map(to_value).transpose()?,
+            context: payload
+                .context
+                .map(|context| to_entries(&context))
+                .transpose()?
+                .map(|entries| proto::Context { entries }),
+            sdl: payload.sdl,
+            uri: payload.uri,
+            method: payload.method,
+            path: payload.path,
+            service_name: payload.service_name,
+            status_code: payload.status_code.map(Into::into),
+            has_next: payload.has_next,
+            query_plan: payload.query_plan.map(to_value).transpose()?,
+        })
+    }
+}
+
+impl<T: DeserializeOwned> TryFrom<proto::Externalizable> for Externalizable<T> {
+    type Error = BoxError;
+
+    fn try_from(message: proto::Externalizable) -> Result<Self, Self::Error> {
+        let stage = proto::Stage::try_from(message.stage)
+            .map_err(|_| format!("invalid coprocessor stage {}", message.stage))?;
+        Ok(Externalizable {
+            version: message.version.try_into()?,
+            stage: PipelineStep::try_from(stage)?.to_string(),
+            control: message.control.map(Control::try_from).transpose()?,
+            id: message.id,
+            headers: message.headers.map(|headers| {
+                headers
+                    .entries
+                    .into_iter()
+                    .map(|(name, values)| (name, values.values))
+                    .collect::<HashMap<_, _>>()
+            }),
+            body: message.body.map(from_value).transpose()?,
+            context: message
+                .context
+                .map(|context| from_entries(context.entries))
+                .transpose()?,
+            sdl: message.sdl,
+            uri: message.uri,
+            method: message.method,
+            path: message.path,
+            service_name: message.service_name,
+            status_code: message.status_code.map(u16::try_from).transpose()?,
+            has_next: message.has_next,
+            query_plan: message.query_plan.map(from_value).transpose()?,
+        })
+    }
+}
+
+/// The body and query plan are converted to `google.protobuf.Value`, which represents every number
+/// as a double
+fn to_value<T: Serialize>(value: T) -> Result<prost_types::Value, BoxError> {
+    Ok(json_to_proto(serde_json::to_value(value)?))
+}
+
+fn from_value<T: DeserializeOwned>(value: prost_types::Value) -> Result<T, BoxError> {
+    Ok(serde_json::from_value(proto_to_json(value))?)
+}
+
+fn to_entries(context: &Context) -> Result<HashMap<String, prost_types::Value>, BoxError> {
+    match serde_json::to_value(context)? {
+        serde_json::Value::Object(mut context) => match context.remove("entries") {
+            Some(serde_json::Value::Object(entries)) => Ok(entries
+                .into_iter()
+                .map(|(key, value)| (key, json_to_proto(value)))
+                .collect()),
+            _ => Err("the context has no entries".into()),
+        },
+        _ => Err("the context is not an object".into()),
+    }
+}
+
+fn from_entries(entries: HashMap<String, prost_types::Value>) -> Result<Context, BoxError> {
+    let entries: serde_json::Map<String, serde_json::Value> = entries
+        .into_iter()
+        .map(|(key, value)| (key, proto_to_json(value)))
+        .collect();
+    Ok(serde_json::from_value(
+        serde_json::json!({ "entries": entries }),
+    )?)
+}
+
+fn json_to_proto(value: serde_json::Value) -> prost_types::Value {
+    let kind = match value {
+        serde_json::Value::Null => Kind::NullValue(prost_types::NullValue::NullValue.into()),
+        serde_json::Value::Bool(value) => Kind::BoolValue(value),
+        serde_json::Value::Number(value) => Kind::NumberValue(value.as_f64().unwrap_or_default()),
+        serde_json::Value::String(value) => Kind::StringValue(value),
+        serde_json::Value::Array(values) => Kind::ListValue(prost_types::ListValue {
+            values: values.into_iter().map(json_to_proto).collect(),
+        }),
+        serde_json::Value::Object(fields) => Kind::StructValue(prost_types::Struct {
+            fields: fields
+                .into_iter()
+                .map(|(key, value)| (key, json_to_proto(value)))
+                .collect(),
+        }),
+    };
+    prost_types::Value { kind: Some(kind) }
+}
+
+fn proto_to_json(value: prost_types::Value) -> serde_json::Value {
+    match value.kind {
+        None | Some(Kind::NullValue(_)) => serde_json::Value::Null,
+        Some(Kind::BoolValue(value)) => serde_json::Value::Bool(value),
+        // integral doubles are converted back to integers, the router expects integers for
+        // values like the status codes and the query plan indices
+        Some(Kind::NumberValue(value))
+            if value.fract() == 0.0 && value.abs() < MAX_SAFE_INTEGER =>
+        {
+            serde_json::Value::from(value as i64)
+        }
+        Some(Kind::NumberValue(value)) => serde_json::Number::from_f64(value)
+            .map(serde_json::Value::Number)
+            .unwrap_or_default(),
+        Some(Kind::StringValue(value)) => serde_json::Value::String(value),
+        Some(Kind::ListValue(list)) => {
+            serde_json::Value::Array(list.values.into_iter().map(proto_to_json).collect())
+        }
+        Some(Kind::StructValue(object)) => serde_json::Value::Object(
+            object
+                .fields
+                .into_iter()
+                .map(|(key, value)| (key, proto_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use serde_json::Value;
+
+    use super::*;
+
+    fn subgraph_request() -> Externalizable<Value> {
+        let context = Context::new();
+        context.insert("accepts-json", true).unwrap();
+        context.insert("retries", 3).unwrap();
+        Externalizable::subgraph_builder()
+            .stage(PipelineStep::SubgraphRequest)
+            .control(Control::Break(401))
+            .id("1b19c05fdafc521016df33148ad63c1b".to_string())
+            .headers(HashMap::from([(
+                "x-custom".to_string(),
+                vec!["a".to_string(), "b".to_string()],
+            )]))
+            .body(json!({ "query": "{ me { name } }", "variables": { "first": 10, "ratio": 0.5 } }))
+            .context(context)
+            .method("POST".to_string())
+            .service_name("accounts".to_string())
+            .uri("http://accounts:4001/graphql".to_string())
+            .build()
+    }
+
+    #[test]
+    fn protobuf_round_trip() {
+        let payload = subgraph_request();
+        let expected = serde_json::to_value(&payload).unwrap();
+
+        let message = proto::Externalizable::try_from(payload).unwrap();
+        assert_eq!(message.stage, proto::Stage::SubgraphRequest as i32);
+        assert_eq!(
+            message.control.clone().unwrap().action,
+            Some(proto::control::Action::Break(401))
+        );
+        assert!(message.sdl.is_none());
+        assert_eq!(
+            message.context.as_ref().unwrap().entries["retries"].kind,
+            Some(Kind::NumberValue(3.0))
+        );
+
+        let decoded = proto::Externalizable::decode(message.encode_to_vec().as_slice()).unwrap();
+        let payload = Externalizable::<Value>::try_from(decoded).unwrap();
+        assert_eq!(serde_json::to_value(&payload).unwrap(), expected);
+    }
+
+    #[test]
+    fn continue_control_and_string_body() {
+        let payload = Externalizable::router_builder()
+            .stage(PipelineStep::RouterRequest)
+            .control(Control::Continue)
+            .id("1b19c05fdafc521016df33148ad63c1b".to_string())
+            .body("{\"query\":\"{ me { name } }\"}".to_string())
+            .build();
+
+        let message = proto::Externalizable::try_from(payload).unwrap();
+        assert_eq!(
+            message.control.clone().unwrap().action,
+            Some(proto::control::Action::Continue(true))
+        );
+        assert!(message.headers.is_none());
+        let payload = Externalizable::<String>::try_from(message).unwrap();
+        assert_eq!(payload.stage, "RouterRequest");
+        assert_eq!(payload.body.unwrap(), "{\"query\":\"{ me { name } }\"}");
+    }
+
+    #[tokio::test]
+    async fn it_calls_a_grpc_coprocessor() {
+        // a gRPC coprocessor adding a header to the subgraph requests
+        let process = tower::service_fn(
+            |request: tonic::Request<proto::Externalizable>| async move {
+                let mut message = request.into_inner();
+                assert_eq!(message.stage, proto::Stage::SubgraphRequest as i32);
+                message
+                    .headers
+                    .get_or_insert_with(Default::default)
+                    .entries
+                    .insert(
+                        "x-from-coprocessor".to_string(),
+                        proto::HeaderValues {
+                            values: vec!["true".to_string()],
+                        },
+                    );
+                message.control = Some(Control::Continue.into());
+                Ok::<_, Status>(tonic::Response::new(message))
+            },
+        );
+        let make_service = hyper::service::make_service_fn(move |_| async move {
+            Ok::<_, hyper::Error>(hyper::service::service_fn(
+                move |request: http::Request<hyper::Body>| async move {
+                    assert_eq!(request.uri().path(), PROCESS_PATH);
+                    Ok::<_, hyper::Error>(
+                        tonic::server::Grpc::new(ProtobufCodec)
+                            .unary(process, request)
+                            .await,
+                    )
+                },
+            ))
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = hyper::Server::from_tcp(listener)
+            .unwrap()
+            .http2_only(true)
+            .serve(make_service);
+        tokio::task::spawn(server);
+
+        let client = GrpcClientService::new(
+            &format!("http://{address}"),
+            Duration::from_secs(5),
+            &GrpcConf::default(),
+        )
+        .unwrap();
+        let output = client.process(subgraph_request()).await.unwrap();
+
+        assert_eq!(output.stage, "SubgraphRequest");
+        assert!(matches!(output.control, Some(Control::Continue)));
+        let headers = output.headers.unwrap();
+        assert_eq!(headers["x-from-coprocessor"], vec!["true".to_string()]);
+        assert_eq!(headers["x-custom"], vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(
+            output.body.unwrap(),
+            json!({ "query": "{ me { name } }", "variables": { "first": 10, "ratio": 0.5 } })
+        );
+        let context = output.context.unwrap();
+        assert_eq!(context.get::<_, bool>("accepts-json").unwrap(), Some(true));
+        assert_eq!(context.get::<_, i64>("retries").unwrap(), Some(3));
+    }
+}
@@ -9,6 +9,7 @@ use std::time::Instant;
 
 use bytes::Bytes;
 use futures::future::ready;
+use futures::future::BoxFuture;
 use futures::stream::once;
 use futures::StreamExt;
 use futures::TryStreamExt;
@@ -19,9 +20,13 @@ use http::HeaderValue;
 use hyper::client::HttpConnector;
 use hyper_rustls::ConfigBuilderExt;
 use hyper_rustls::HttpsConnector;
+#[cfg(unix)]
+use hyperlocal::UnixConnector;
+use once_cell::sync::Lazy;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
+use tokio::sync::Semaphore;
 use tower::timeout::TimeoutLayer;
 use tower::util::MapFutureLayer;
 use tower::BoxError;
@@ -34,13 +39,18 @@ use crate::error::Error;
 use crate::graphql;
 use crate::layers::async_checkpoint::OneShotAsyncCheckpointLayer;
 use crate::layers::ServiceBuilderExt;
-use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugin::PluginPrivate;
+use crate::plugins::coprocessor::grpc::GrpcClientService;
+use crate::plugins::coprocessor::grpc::GrpcConf;
+#[cfg(feature = "wasm")]
+use crate::plugins::coprocessor::wasm::WasmClientService;
+use crate::plugins::coprocessor::wasm::WasmConf;
 use crate::plugins::telemetry::config_new::conditions::Condition;
 use crate::plugins::telemetry::config_new::selectors::RouterSelector;
 use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
 use crate::plugins::traffic_shaping::Http2Config;
-use crate::register_plugin;
+use crate::register_private_plugin;
 use crate::services;
 use crate::services::external::externalize_header_map;
 use crate::services::external::Control;
@@ -60,7 +70,10 @@ use crate::services::trust_dns_connector::AsyncHyperResolver;
 mod test;
 
 mod execution;
+mod grpc;
+mod query_planner;
 mod supergraph;
+mod wasm;
 
 pub(crate) const EXTERNAL_SPAN_NAME: &str = "external_plugin";
 const POOL_IDLE_TIMEOUT_DURATION: Option<Duration> = Some(Duration::from_secs(5));
@@ -73,11 +86,124 @@ type HTTPClientService = RouterBodyConverter<
     >,
 >;
 
-#[async_trait::async_trait]
-impl Plugin for CoprocessorPlugin<HTTPClientService> {
-    type Config = Conf;
+#[cfg(unix)]
+type UnixHTTPClientService =
+    RouterBodyConverter<tower::timeout::Timeout<hyper::Client<UnixConnector, RouterBody>>>;
+
+/// The client used to call the coprocessor, depending on the configured transport
+#[derive(Clone)]
+enum CoprocessorClient {
+    Http(HTTPClientService),
+    #[cfg(unix)]
+    Unix(UnixHTTPClientService),
+    Grpc(GrpcClientService),
+    #[cfg(feature = "wasm")]
+    Wasm(WasmClientService),
+}
+
+impl CoprocessorTransport for CoprocessorClient {
+    fn process<T>(
+        &self,
+        payload: Externalizable<T>,
+        coprocessor_url: &str,
+    ) -> BoxFuture<'static, Result<Externalizable<T>, BoxError>>
+    where
+        T: std::fmt::Debug + serde::de::DeserializeOwned + Serialize + Send + Sync + 'static,
+    {
+        match self {
+            CoprocessorClient::Http(client) => client.process(payload, coprocessor_url),
+            #[cfg(unix)]
+            CoprocessorClient::Unix(client) => client.process(payload, coprocessor_url),
+            // the gRPC client converts the payload to protobuf messages itself
+            CoprocessorClient::Grpc(client) => client.process(payload),
+            // the WebAssembly client calls the component loaded from the file:// URL
+            #[cfg(feature = "wasm")]
+            CoprocessorClient::Wasm(client) => client.process(payload),
+        }
+    }
+}
+
+/// Sends the payloads of the stages to the coprocessor and returns its output
+pub(crate) trait CoprocessorTransport: Clone + Send + Sync + 'static {
+    fn process<T>(
+        &self,
+        payload: Externalizable<T>,
+        coprocessor_url: &str,
+    ) -> BoxFuture<'static, Result<Externalizable<T>, BoxError>>
+    where
+        T: std::fmt::Debug + serde::de::DeserializeOwned + Serialize + Send + Sync + 'static;
+}
+
+/// HTTP clients send the payloads as JSON
+impl<C> CoprocessorTransport for C
+where
+    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+{
+    fn process<T>(
+        &self,
+        payload: Externalizable<T>,
+        coprocessor_url: &str,
+    ) -> BoxFuture<'static, Result<Externalizable<T>, BoxError>>
+    where
+        T: std::fmt::Debug + serde::de::DeserializeOwned + Serialize + Send + Sync + 'static,
+    {
+        let client = self.clone();
+        let coprocessor_url = coprocessor_url.to_string();
+        Box::pin(async move { payload.call(client, &coprocessor_url).await })
+    }
+}
+
+impl CoprocessorClient {
+    fn new(config: &Conf) -> Result<Self, BoxError> {
+        if let Some(path) = config.url.strip_prefix("file://") {
+            #[cfg(feature = "wasm")]
+            return Ok(CoprocessorClient::Wasm(WasmClientService::new(
+                path,
+                &config.wasm.clone().unwrap_or_default(),
+                config.timeout,
+            )?));
+            #[cfg(not(feature = "wasm"))]
+            return Err(format!(
+                "cannot load the coprocessor component {path}: the router was built without the `wasm` feature"
+            )
+            .into());
+        }
+
+        if let Some(grpc) = &config.grpc {
+            return Ok(CoprocessorClient::Grpc(GrpcClientService::new(
+                &config.url,
+                config.timeout,
+                grpc,
+            )?));
+        }
+
+        let http2_only = config
+            .client
+            .as_ref()
+            .is_some_and(|client| client.experimental_http2 == Some(Http2Config::Http2Only));
+
+        if config.url.starts_with("unix://") {
+            // there is no protocol negotiation without TLS, HTTP/2 is only used with prior knowledge
+            #[cfg(unix)]
+            return Ok(CoprocessorClient::Unix(RouterBodyConverter {
+                inner: ServiceBuilder::new()
+                    .layer(TimeoutLayer::new(config.timeout))
+                    .service(
+                        hyper::Client::builder()
+                            .http2_only(http2_only)
+                            .pool_idle_timeout(POOL_IDLE_TIMEOUT_DURATION)
+                            .build(UnixConnector),
+                    ),
+            }));
+            #[cfg(not(unix))]
+            return Err("unix sockets are not supported on this platform".into());
+        }
 
-    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
         let mut http_connector = new_async_http_connector()?;
         http_connector.set_nodelay(true);
         http_connector.set_keepalive(Some(std::time::Duration::from_secs(60)));
@@ -93,8 +219,8 @@ impl Plugin for CoprocessorPlugin<HTTPClientService> {
             .https_or_http()
             .enable_http1();
 
-        let connector = if init.config.client.is_none()
-            || init.config.client.as_ref().unwrap().experimental_http2 != Some(Http2Config::Disable)
+        let connector = if config.client.is_none()
+            || config.client.as_ref().unwrap().experimental_http2 != Some(Http2Config::Disable)
         {
             builder.enable_http2().wrap_connector(http_connector)
         } else {
@@ -103,55 +229,109 @@ impl Plugin for CoprocessorPlugin<HTTPClientService> {
 
         let http_client = RouterBodyConverter {
             inner: ServiceBuilder::new()
-                .layer(TimeoutLayer::new(init.config.timeout))
+                .layer(TimeoutLayer::new(config.timeout))
                 .service(
                     hyper::Client::builder()
-                        .http2_only(
-                            init.config.client.is_some()
-                                && init.config.client.as_ref().unwrap().experimental_http2
-                                    == Some(Http2Config::Http2Only),
-                        )
+                        .http2_only(http2_only)
                         .pool_idle_timeout(POOL_IDLE_TIMEOUT_DURATION)
                         .build(connector),
                 ),
         };
 
-        CoprocessorPlugin::new(http_client, init.config, init.supergraph_sdl)
+        Ok(CoprocessorClient::Http(http_client))
     }
+}
+
+/// The configured coprocessors, in the order they are called
+struct Coprocessors {
+    coprocessors: Vec<CoprocessorPlugin<CoprocessorClient>>,
+}
+
+#[async_trait::async_trait]
+impl PluginPrivate for Coprocessors {
+    type Config = Confs;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let coprocessors = init
+            .config
+            .into_vec()
+            .into_iter()
+            .map(|config| {
+                CoprocessorPlugin::new(
+                    CoprocessorClient::new(&config)?,
+                    config,
+                    init.supergraph_sdl.clone(),
+                )
+            })
+            .collect::<Result<_, BoxError>>()?;
+
+        Ok(Coprocessors { coprocessors })
+    }
+
+    // The first coprocessor is the outermost layer: it sees the requests first and the responses
+    // last, and each coprocessor sees the changes made by the previous ones.
 
     fn router_service(&self, service: router::BoxService) -> router::BoxService {
-        self.router_service(service)
+        self.coprocessors
+            .iter()
+            .rev()
+            .fold(service, |service, coprocessor| {
+                coprocessor.router_service(service)
+            })
     }
 
     fn supergraph_service(
         &self,
         service: services::supergraph::BoxService,
     ) -> services::supergraph::BoxService {
-        self.supergraph_service(service)
+        self.coprocessors
+            .iter()
+            .rev()
+            .fold(service, |service, coprocessor| {
+                coprocessor.supergraph_service(service)
+            })
     }
 
     fn execution_service(
         &self,
         service: services::execution::BoxService,
     ) -> services::execution::BoxService {
-        self.execution_service(service)
+        self.coprocessors
+            .iter()
+            .rev()
+            .fold(service, |service, coprocessor| {
+                coprocessor.execution_service(service)
+            })
     }
 
     fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
-        self.subgraph_service(name, service)
+        self.coprocessors
+            .iter()
+            .rev()
+            .fold(service, |service, coprocessor| {
+                coprocessor.subgraph_service(name, service)
+            })
+    }
+
+    fn query_planner_service(
+        &self,
+        service: services::query_planner::CachingBoxService,
+    ) -> services::query_planner::CachingBoxService {
+        self.coprocessors
+            .iter()
+            .rev()
+            .fold(service, |service, coprocessor| {
+                coprocessor.query_planner_service(service)
+            })
     }
 }
 
 // This macro allows us to use it in our plugin registry!
-// register_plugin takes a group name, and a plugin name.
+// register_private_plugin takes a group name, and a plugin name.
 //
 // In order to keep the plugin names consistent,
 // we use using the `Reverse domain name notation`
-register_plugin!(
-    "apollo",
-    "coprocessor",
-    CoprocessorPlugin<HTTPClientService>
-);
+register_private_plugin!("apollo", "coprocessor", Coprocessors);
 
 // -------------------------------------------------------------------------------------------------------
 
@@ -162,12 +342,7 @@ register_plugin!(
 #[derive(Debug)]
 struct CoprocessorPlugin<C>
 where
-    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
-        + Clone
-        + Send
-        + Sync
-        + 'static,
-    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+    C: CoprocessorTransport,
 {
     http_client: C,
     configuration: Conf,
@@ -176,14 +351,17 @@ where
 
 impl<C> CoprocessorPlugin<C>
 where
-    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
-        + Clone
-        + Send
-        + Sync
-        + 'static,
-    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+    C: CoprocessorTransport,
 {
-    fn new(http_client: C, configuration: Conf, sdl: Arc<String>) -> Result<Self, BoxError> {
+    fn new(http_client: C, mut configuration: Conf, sdl: Arc<String>) -> Result<Self, BoxError> {
+        #[cfg(unix)]
+        if let Some(path) = configuration.url.strip_prefix("unix://") {
+            // there is no specified format for unix socket URLs (cf https://github.com/whatwg/url/issues/577)
+            // so a unix:// URL will not be parsed by http::Uri
+            // hyperlocal hides the socket path in a hex encoded authority that its connector decodes,
+            // and the gRPC client already knows the socket path
+            configuration.url = http::Uri::from(hyperlocal::Uri::new(path, "/")).to_string();
+        }
         Ok(Self {
             http_client,
             configuration,
@@ -232,6 +410,18 @@ where
             name.to_string(),
         )
     }
+
+    fn query_planner_service(
+        &self,
+        service: services::query_planner::CachingBoxService,
+    ) -> services::query_planner::CachingBoxService {
+        self.configuration.query_planner.as_service(
+            self.http_client.clone(),
+            service,
+            self.configuration.url.clone(),
+            self.sdl.clone(),
+        )
+    }
 }
 /// What information is passed to a router request/response stage
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
@@ -252,6 +442,11 @@ pub(super) struct RouterRequestConf {
     pub(super) path: bool,
     /// Send the method
     pub(super) method: bool,
+    /// What to do when the coprocessor call fails
+    pub(super) on_error: OnError,
+    /// Send the payload without waiting for the coprocessor, which cannot modify the stage
+    #[serde(rename = "async")]
+    pub(super) asynchronous: bool,
 }
 
 /// What information is passed to a router request/response stage
@@ -271,6 +466,11 @@ pub(super) struct RouterResponseConf {
     pub(super) sdl: bool,
     /// Send the HTTP status
     pub(super) status_code: bool,
+    /// What to do when the coprocessor call fails
+    pub(super) on_error: OnError,
+    /// Send the payload without waiting for the coprocessor, which cannot modify the stage
+    #[serde(rename = "async")]
+    pub(super) asynchronous: bool,
 }
 /// What information is passed to a subgraph request/response stage
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
@@ -291,6 +491,11 @@ pub(super) struct SubgraphRequestConf {
     pub(super) method: bool,
     /// Send the service name
     pub(super) service_name: bool,
+    /// What to do when the coprocessor call fails
+    pub(super) on_error: OnError,
+    /// Send the payload without waiting for the coprocessor, which cannot modify the stage
+    #[serde(rename = "async")]
+    pub(super) asynchronous: bool,
 }
 
 /// What information is passed to a subgraph request/response stage
@@ -310,15 +515,77 @@ pub(super) struct SubgraphResponseConf {
     pub(super) service_name: bool,
     /// Send the http status
     pub(super) status_code: bool,
+    /// What to do when the coprocessor call fails
+    pub(super) on_error: OnError,
+    /// Send the payload without waiting for the coprocessor, which cannot modify the stage
+    #[serde(rename = "async")]
+    pub(super) asynchronous: bool,
+}
+
+/// Configures the externalization plugin, with one or several coprocessors
+#[derive(Clone, Debug, JsonSchema)]
+#[serde(untagged, deny_unknown_fields)]
+enum Confs {
+    /// A single coprocessor
+    Single(Box<Conf>),
+    /// Several coprocessors
+    Multiple {
+        /// Coprocessors called in order. The requests go through them in the order of the list,
+        /// and the responses in the reverse order.
+        coprocessors: Vec<Conf>,
+    },
+}
+
+impl Confs {
+    fn into_vec(self) -> Vec<Conf> {
+        match self {
+            Confs::Single(conf) => vec![*conf],
+            Confs::Multiple { coprocessors } => coprocessors,
+        }
+    }
+}
+
+// The configuration is either a list of coprocessors under the `coprocessors` key or a single
+// coprocessor. The form is chosen from the keys, so that the errors point at the invalid field
+// instead of reporting that no variant matched.
+impl<'de> Deserialize<'de> for Confs {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        let mut map = serde_json::Map::deserialize(deserializer)?;
+        match map.remove("coprocessors") {
+            Some(coprocessors) => {
+                if let Some(key) = map.keys().next() {
+                    return Err(D::Error::custom(format!(
+                        "unknown field `{key}`, the coprocessors must be configured in the `coprocessors` list"
+                    )));
+                }
+                Ok(Confs::Multiple {
+                    coprocessors: serde_json::from_value(coprocessors).map_err(D::Error::custom)?,
+                })
+            }
+            None => Ok(Confs::Single(Box::new(
+                serde_json::from_value(serde_json::Value::Object(map)).map_err(D::Error::custom)?,
+            ))),
+        }
+    }
 }
 
-/// Configures the externalization plugin
+/// Configures a coprocessor
 #[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 struct Conf {
     /// The url you'd like to offload processing to
     url: String,
     client: Option<Client>,
+    /// Call the coprocessor with gRPC instead of sending JSON payloads over HTTP
+    grpc: Option<GrpcConf>,
+    /// The limits of WebAssembly coprocessors, loaded from `file://` URLs
+    #[cfg_attr(not(feature = "wasm"), allow(dead_code))]
+    wasm: Option<WasmConf>,
     /// The timeout for external requests
     #[serde(deserialize_with = "humantime_serde::deserialize")]
     #[schemars(with = "String", default = "default_timeout")]
@@ -330,6 +597,9 @@ struct Conf {
     /// The supergraph stage request/response configuration
     #[serde(default)]
     supergraph: supergraph::SupergraphStage,
+    /// The query planner stage request/response configuration
+    #[serde(default)]
+    query_planner: query_planner::QueryPlannerStage,
     /// The execution stage request/response configuration
     #[serde(default)]
     execution: execution::ExecutionStage,
@@ -360,15 +630,7 @@ impl RouterStage {
         sdl: Arc<String>,
     ) -> router::BoxService
     where
-        C: Service<
-                http::Request<RouterBody>,
-                Response = http::Response<RouterBody>,
-                Error = BoxError,
-            > + Clone
-            + Send
-            + Sync
-            + 'static,
-        <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+        C: CoprocessorTransport,
     {
         let request_layer = (self.request != Default::default()).then_some({
             let request_config = self.request.clone();
@@ -498,15 +760,7 @@ impl SubgraphStage {
         service_name: String,
     ) -> subgraph::BoxService
     where
-        C: Service<
-                http::Request<RouterBody>,
-                Response = http::Response<RouterBody>,
-                Error = BoxError,
-            > + Clone
-            + Send
-            + Sync
-            + 'static,
-        <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+        C: CoprocessorTransport,
     {
         let request_layer = (self.request != Default::default()).then_some({
             let request_config = self.request.clone();
@@ -616,12 +870,7 @@ async fn process_router_request_stage<C>(
     mut request_config: RouterRequestConf,
 ) -> Result<ControlFlow<router::Response, router::Request>, BoxError>
 where
-    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
-        + Clone
-        + Send
-        + Sync
-        + 'static,
-    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+    C: CoprocessorTransport,
 {
     let should_be_executed = request_config
         .condition
@@ -669,7 +918,15 @@ where
     tracing::debug!(?payload, "externalized output");
     let guard = request.context.enter_active_request();
     let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
+    let co_processor_result = call_coprocessor(
+        http_client,
+        &coprocessor_url,
+        payload,
+        PipelineStep::RouterRequest,
+        &request_config.on_error,
+        request_config.asynchronous,
+    )
+    .await;
     let duration = start.elapsed().as_secs_f64();
     drop(guard);
     tracing::info!(
@@ -680,8 +937,7 @@ where
     tracing::debug!(?co_processor_result, "co-processor returned");
     let mut co_processor_output = co_processor_result?;
 
-    validate_coprocessor_output(&co_processor_output, PipelineStep::RouterRequest)?;
-    // unwrap is safe here because validate_coprocessor_output made sure control is available
+    // unwrap is safe here because call_coprocessor validated that control is available
     let control = co_processor_output.control.expect("validated above; qed");
 
     // Thirdly, we need to interpret the control flow which may have been
@@ -777,12 +1033,7 @@ async fn process_router_response_stage<C>(
     response_config: RouterResponseConf,
 ) -> Result<router::Response, BoxError>
 where
-    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
-        + Clone
-        + Send
-        + Sync
-        + 'static,
-    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+    C: CoprocessorTransport,
 {
     let should_be_executed = response_config
         .condition
@@ -844,7 +1095,15 @@ where
     tracing::debug!(?payload, "externalized output");
     let guard = response.context.enter_active_request();
     let start = Instant::now();
-    let co_processor_result = payload.call(http_client.clone(), &coprocessor_url).await;
+    let co_processor_result = call_coprocessor(
+        http_client.clone(),
+        &coprocessor_url,
+        payload,
+        PipelineStep::RouterResponse,
+        &response_config.on_error,
+        response_config.asynchronous,
+    )
+    .await;
     let duration = start.elapsed().as_secs_f64();
     drop(guard);
     tracing::info!(
@@ -855,8 +1114,6 @@ where
     tracing::debug!(?co_processor_result, "co-processor returned");
     let co_processor_output = co_processor_result?;
 
-    validate_coprocessor_output(&co_processor_output, PipelineStep::RouterResponse)?;
-
     // Third, process our reply and act on the contents. Our processing logic is
     // that we replace "bits" of our incoming response with the updated bits if they
     // are present in our co_processor_output. If they aren't present, just use the
@@ -926,15 +1183,19 @@ where
                 // Second, call our co-processor and get a reply.
                 tracing::debug!(?payload, "externalized output");
                 let guard = generator_map_context.enter_active_request();
-                let co_processor_result = payload
-                    .call(generator_client, &generator_coprocessor_url)
-                    .await;
+                let co_processor_result = call_coprocessor(
+                    generator_client,
+                    &generator_coprocessor_url,
+                    payload,
+                    PipelineStep::RouterResponse,
+                    &response_config.on_error,
+                    response_config.asynchronous,
+                )
+                .await;
                 drop(guard);
                 tracing::debug!(?co_processor_result, "co-processor returned");
                 let co_processor_output = co_processor_result?;
 
-                validate_coprocessor_output(&co_processor_output, PipelineStep::RouterResponse)?;
-
                 // Third, process our reply and act on the contents. Our processing logic is
                 // that we replace "bits" of our incoming response with the updated bits if they
                 // are present in our co_processor_output. If they aren't present, just use the
@@ -979,12 +1240,7 @@ async fn process_subgraph_request_stage<C>(
     mut request_config: SubgraphRequestConf,
 ) -> Result<ControlFlow<subgraph::Response, subgraph::Request>, BoxError>
 where
-    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
-        + Clone
-        + Send
-        + Sync
-        + 'static,
-    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+    C: CoprocessorTransport,
 {
     let should_be_executed = request_config
         .condition
@@ -1028,7 +1284,15 @@ where
     tracing::debug!(?payload, "externalized output");
     let guard = request.context.enter_active_request();
     let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
+    let co_processor_result = call_coprocessor(
+        http_client,
+        &coprocessor_url,
+        payload,
+        PipelineStep::SubgraphRequest,
+        &request_config.on_error,
+        request_config.asynchronous,
+    )
+    .await;
     let duration = start.elapsed().as_secs_f64();
     drop(guard);
     tracing::info!(
@@ -1038,8 +1302,7 @@ where
 
     tracing::debug!(?co_processor_result, "co-processor returned");
     let co_processor_output = co_processor_result?;
-    validate_coprocessor_output(&co_processor_output, PipelineStep::SubgraphRequest)?;
-    // unwrap is safe here because validate_coprocessor_output made sure control is available
+    // unwrap is safe here because call_coprocessor validated that control is available
     let control = co_processor_output.control.expect("validated above; qed");
 
     // Thirdly, we need to interpret the control flow which may have been
@@ -1134,12 +1397,7 @@ async fn process_subgraph_response_stage<C>(
     response_config: SubgraphResponseConf,
 ) -> Result<subgraph::Response, BoxError>
 where
-    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
-        + Clone
-        + Send
-        + Sync
-        + 'static,
-    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+    C: CoprocessorTransport,
 {
     let should_be_executed = response_config
         .condition
@@ -1182,7 +1440,15 @@ where
     tracing::debug!(?payload, "externalized output");
     let guard = response.context.enter_active_request();
     let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
+    let co_processor_result = call_coprocessor(
+        http_client,
+        &coprocessor_url,
+        payload,
+        PipelineStep::SubgraphResponse,
+        &response_config.on_error,
+        response_config.asynchronous,
+    )
+    .await;
     let duration = start.elapsed().as_secs_f64();
     drop(guard);
     tracing::info!(
@@ -1193,8 +1459,6 @@ where
     tracing::debug!(?co_processor_result, "co-processor returned");
     let co_processor_output = co_processor_result?;
 
-    validate_coprocessor_output(&co_processor_output, PipelineStep::SubgraphResponse)?;
-
     // Third, process our reply and act on the contents. Our processing logic is
     // that we replace "bits" of our incoming response with the updated bits if they
     // are present in our co_processor_output. If they aren't present, just use the
@@ -1226,6 +1490,127 @@ where
 
 // -----------------------------------------------------------------------------------------
 
+/// What to do when the coprocessor call of a stage fails
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum OnError {
+    /// Fail the client request
+    #[default]
+    FailClosed,
+    /// Continue with the unmodified request or response
+    FailOpen,
+}
+
+impl OnError {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OnError::FailClosed => "fail_closed",
+            OnError::FailOpen => "fail_open",
+        }
+    }
+}
+
+/// Calls the coprocessor and validates its output, following the error policy of the stage.
+///
+/// When the stage is asynchronous, or when the call failed and the stage fails open, the returned
+/// output leaves the request or response unmodified.
+pub(super) async fn call_coprocessor<C, T>(
+    http_client: C,
+    coprocessor_url: &str,
+    payload: Externalizable<T>,
+    stage: PipelineStep,
+    on_error: &OnError,
+    asynchronous: bool,
+) -> Result<Externalizable<T>, BoxError>
+where
+    C: CoprocessorTransport,
+    T: std::fmt::Debug + serde::de::DeserializeOwned + Serialize + Send + Sync + 'static,
+{
+    let unmodified = Externalizable::unmodified(stage.clone(), payload.id.clone());
+
+    if asynchronous {
+        let coprocessor_url = coprocessor_url.to_string();
+        spawn_async_call(&ASYNC_CALLS, stage.clone(), async move {
+            let result = http_client
+                .process(payload, &coprocessor_url)
+                .await
+                .and_then(|output| validate_coprocessor_output(&output, stage.clone()));
+            if let Err(error) = result {
+                // nothing waits for asynchronous stages, so they always fail open
+                record_coprocessor_error(stage, OnError::FailOpen, &error);
+            }
+        });
+        return Ok(unmodified);
+    }
+
+    let result = http_client
+        .process(payload, coprocessor_url)
+        .await
+        .and_then(|output| {
+            validate_coprocessor_output(&output, stage.clone())?;
+            Ok(output)
+        });
+    match result {
+        Ok(output) => Ok(output),
+        Err(error) => {
+            record_coprocessor_error(stage, *on_error, &error);
+            match on_error {
+                OnError::FailClosed => Err(error),
+                OnError::FailOpen => Ok(unmodified),
+            }
+        }
+    }
+}
+
+/// The maximum number of asynchronous coprocessor calls in flight. When the coprocessors are slower
+/// than the traffic, the calls beyond it are dropped instead of piling up in memory.
+const MAX_PENDING_ASYNC_CALLS: usize = 1024;
+
+static ASYNC_CALLS: Lazy<Arc<Semaphore>> =
+    Lazy::new(|| Arc::new(Semaphore::new(MAX_PENDING_ASYNC_CALLS)));
+
+/// Runs the call of an asynchronous stage in the background if a permit is available, otherwise
+/// drops it and counts it.
+fn spawn_async_call<F>(permits: &Arc<Semaphore>, stage: PipelineStep, call: F)
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    match permits.clone().try_acquire_owned() {
+        Ok(permit) => {
+            tokio::task::spawn(tracing::Instrument::in_current_span(async move {
+                call.await;
+                drop(permit);
+            }));
+        }
+        Err(_) => {
+            tracing::debug!(
+                "external extensibility: too many pending asynchronous calls, the {stage} stage call was dropped"
+            );
+            u64_counter!(
+                "apollo.router.operations.coprocessor.async.dropped",
+                "Asynchronous coprocessor calls dropped because too many calls were pending",
+                1,
+                "coprocessor.stage" = stage
+            );
+        }
+    }
+}
+
+fn record_coprocessor_error(stage: PipelineStep, on_error: OnError, error: &BoxError) {
+    if on_error == OnError::FailOpen {
+        tracing::warn!(
+            "external extensibility: {stage} stage error, continuing without the coprocessor: {error}"
+        );
+    }
+    u64_counter!(
+        "apollo.router.operations.coprocessor.errors",
+        "Coprocessor calls that failed or returned an invalid payload",
+        1,
+        "coprocessor.stage" = stage,
+        "coprocessor.on_error" = on_error.as_str()
+    );
+}
+
 fn validate_coprocessor_output<T>(
     co_processor_output: &Externalizable<T>,
     expected_step: PipelineStep,
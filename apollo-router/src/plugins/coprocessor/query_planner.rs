@@ -0,0 +1,561 @@
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+
+use super::*;
+use crate::graphql;
+use crate::layers::async_checkpoint::AsyncCheckpointLayer;
+use crate::layers::map_future_with_request_data::MapFutureWithRequestDataLayer;
+use crate::layers::ServiceBuilderExt;
+use crate::plugins::coprocessor::EXTERNAL_SPAN_NAME;
+use crate::services::query_planner;
+use crate::services::QueryPlannerContent;
+
+/// What information is passed to a query planner request stage
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct QueryPlannerRequestConf {
+    /// Send the context
+    pub(super) context: bool,
+    /// Send the body (the operation and its name)
+    pub(super) body: bool,
+    /// Send the SDL
+    pub(super) sdl: bool,
+    /// What to do when the coprocessor call fails
+    pub(super) on_error: OnError,
+    /// Send the payload without waiting for the coprocessor, which cannot modify the stage
+    #[serde(rename = "async")]
+    pub(super) asynchronous: bool,
+}
+
+/// What information is passed to a query planner response stage
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct QueryPlannerResponseConf {
+    /// Send the context
+    pub(super) context: bool,
+    /// Send the body (the operation and its name)
+    pub(super) body: bool,
+    /// Send the SDL
+    pub(super) sdl: bool,
+    /// Send the query plan
+    pub(super) query_plan: bool,
+    /// What to do when the coprocessor call fails
+    pub(super) on_error: OnError,
+    /// Send the payload without waiting for the coprocessor, which cannot modify the stage
+    #[serde(rename = "async")]
+    pub(super) asynchronous: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[serde(default)]
+pub(super) struct QueryPlannerStage {
+    /// The request configuration
+    pub(super) request: QueryPlannerRequestConf,
+    /// The response configuration
+    pub(super) response: QueryPlannerResponseConf,
+}
+
+impl QueryPlannerStage {
+    pub(crate) fn as_service<C>(
+        &self,
+        http_client: C,
+        service: query_planner::CachingBoxService,
+        coprocessor_url: String,
+        sdl: Arc<String>,
+    ) -> query_planner::CachingBoxService
+    where
+        C: CoprocessorTransport,
+    {
+        let request_layer = (self.request != Default::default()).then_some({
+            let request_config = self.request.clone();
+            let coprocessor_url = coprocessor_url.clone();
+            let http_client = http_client.clone();
+            let sdl = sdl.clone();
+
+            AsyncCheckpointLayer::new(move |request: query_planner::CachingRequest| {
+                let request_config = request_config.clone();
+                let coprocessor_url = coprocessor_url.clone();
+                let http_client = http_client.clone();
+                let sdl = sdl.clone();
+
+                async move {
+                    let mut succeeded = true;
+                    let result = process_query_planner_request_stage(
+                        http_client,
+                        coprocessor_url,
+                        sdl,
+                        request,
+                        request_config,
+                    )
+                    .await
+                    .map_err(|error| {
+                        succeeded = false;
+                        tracing::error!(
+                            "external extensibility: query planner request stage error: {error}"
+                        );
+                        error
+                    });
+
+                    u64_counter!(
+                        "apollo.router.operations.coprocessor",
+                        "Total operations with co-processors enabled",
+                        1,
+                        "coprocessor.stage" = PipelineStep::QueryPlannerRequest,
+                        "coprocessor.succeeded" = succeeded
+                    );
+                    result
+                }
+            })
+        });
+
+        let response_layer = (self.response != Default::default()).then_some({
+            let response_config = self.response.clone();
+
+            // the response stage needs the operation, which is not part of the query planner response
+            MapFutureWithRequestDataLayer::new(
+                operation_body,
+                move |operation: serde_json::Value, fut| {
+                    let coprocessor_url = coprocessor_url.clone();
+                    let sdl: Arc<String> = sdl.clone();
+                    let http_client = http_client.clone();
+                    let response_config = response_config.clone();
+
+                    async move {
+                        let response: query_planner::Response = fut.await?;
+
+                        let mut succeeded = true;
+                        let result = process_query_planner_response_stage(
+                            http_client,
+                            coprocessor_url,
+                            sdl,
+                            operation,
+                            response,
+                            response_config,
+                        )
+                        .await
+                        .map_err(|error| {
+                            succeeded = false;
+                            tracing::error!(
+                                "external extensibility: query planner response stage error: {error}"
+                            );
+                            error
+                        });
+
+                        u64_counter!(
+                            "apollo.router.operations.coprocessor",
+                            "Total operations with co-processors enabled",
+                            1,
+                            "coprocessor.stage" = PipelineStep::QueryPlannerResponse,
+                            "coprocessor.succeeded" = succeeded
+                        );
+                        result
+                    }
+                },
+            )
+        });
+
+        fn external_service_span(
+        ) -> impl Fn(&query_planner::CachingRequest) -> tracing::Span + Clone {
+            move |_request: &query_planner::CachingRequest| {
+                tracing::info_span!(
+                    EXTERNAL_SPAN_NAME,
+                    "external service" = stringify!(query_planner::CachingRequest),
+                    "otel.kind" = "INTERNAL"
+                )
+            }
+        }
+
+        // unlike the other stages, the query planner stage is built once and handles every
+        // operation, so the checkpoint calls a clone of the buffered inner service
+        ServiceBuilder::new()
+            .instrument(external_service_span())
+            .option_layer(request_layer)
+            .option_layer(response_layer)
+            .buffered()
+            .service(service)
+            .boxed()
+    }
+}
+
+/// The body of the query planner stages: the operation to plan
+fn operation_body(request: &query_planner::CachingRequest) -> serde_json::Value {
+    serde_json::json!({
+        "query": request.query,
+        "operationName": request.operation_name,
+    })
+}
+
+/// Rejects the operation with the errors from the body of the coprocessor output
+fn rejection(
+    status_code: StatusCode,
+    body: Option<serde_json::Value>,
+    context: crate::Context,
+) -> query_planner::Response {
+    let mut errors =
+        serde_json::from_value::<graphql::Response>(body.unwrap_or(serde_json::Value::Null))
+            .map(|response| response.errors)
+            .unwrap_or_else(|error| {
+                vec![Error::builder()
+                    .message(format!(
+                        "couldn't deserialize coprocessor output body: {error}"
+                    ))
+                    .extension_code(COPROCESSOR_DESERIALIZATION_ERROR_EXTENSION)
+                    .build()]
+            });
+    if errors.is_empty() {
+        errors.push(
+            Error::builder()
+                .message("the operation was rejected by the coprocessor")
+                .extension_code(COPROCESSOR_ERROR_EXTENSION)
+                .build(),
+        );
+    }
+    query_planner::Response::builder()
+        .errors(errors)
+        .context(context)
+        .status_code(status_code)
+        .build()
+}
+
+fn merge_context(context: &crate::Context, update: Option<crate::Context>) -> Result<(), BoxError> {
+    if let Some(update) = update {
+        for (key, value) in update.try_into_iter()? {
+            context.upsert_json_value(key, move |_current| value);
+        }
+    }
+    Ok(())
+}
+
+async fn process_query_planner_request_stage<C>(
+    http_client: C,
+    coprocessor_url: String,
+    sdl: Arc<String>,
+    request: query_planner::CachingRequest,
+    request_config: QueryPlannerRequestConf,
+) -> Result<ControlFlow<query_planner::Response, query_planner::CachingRequest>, BoxError>
+where
+    C: CoprocessorTransport,
+{
+    let body_to_send = request_config.body.then(|| operation_body(&request));
+    let context_to_send = request_config.context.then(|| request.context.clone());
+    let sdl_to_send = request_config.sdl.then(|| sdl.clone().to_string());
+
+    let payload = Externalizable::query_planner_builder()
+        .stage(PipelineStep::QueryPlannerRequest)
+        .control(Control::default())
+        .id(request.context.id.clone())
+        .and_body(body_to_send)
+        .and_context(context_to_send)
+        .and_sdl(sdl_to_send)
+        .build()?;
+
+    tracing::debug!(?payload, "externalized output");
+    let guard = request.context.enter_active_request();
+    let start = Instant::now();
+    let co_processor_result = call_coprocessor(
+        http_client,
+        &coprocessor_url,
+        payload,
+        PipelineStep::QueryPlannerRequest,
+        &request_config.on_error,
+        request_config.asynchronous,
+    )
+    .await;
+    let duration = start.elapsed().as_secs_f64();
+    drop(guard);
+    tracing::info!(
+        histogram.apollo.router.operations.coprocessor.duration = duration,
+        coprocessor.stage = %PipelineStep::QueryPlannerRequest,
+    );
+
+    tracing::debug!(?co_processor_result, "co-processor returned");
+    let co_processor_output = co_processor_result?;
+    // unwrap is safe here because call_coprocessor validated that control is available
+    let control = co_processor_output.control.expect("validated above; qed");
+
+    // The operation was already parsed and validated, so the coprocessor cannot modify it: it can
+    // only reject it or update the context.
+    if matches!(control, Control::Break(_)) {
+        // Ensure the code is a valid http status code
+        let status_code = control.get_http_status()?;
+        let response = rejection(status_code, co_processor_output.body, request.context);
+        merge_context(&response.context, co_processor_output.context)?;
+        return Ok(ControlFlow::Break(response));
+    }
+
+    merge_context(&request.context, co_processor_output.context)?;
+
+    Ok(ControlFlow::Continue(request))
+}
+
+async fn process_query_planner_response_stage<C>(
+    http_client: C,
+    coprocessor_url: String,
+    sdl: Arc<String>,
+    operation: serde_json::Value,
+    response: query_planner::Response,
+    response_config: QueryPlannerResponseConf,
+) -> Result<query_planner::Response, BoxError>
+where
+    C: CoprocessorTransport,
+{
+    let body_to_send = response_config.body.then_some(operation);
+    let context_to_send = response_config.context.then(|| response.context.clone());
+    let sdl_to_send = response_config.sdl.then(|| sdl.clone().to_string());
+    let query_plan = match &response.content {
+        Some(QueryPlannerContent::Plan { plan }) if response_config.query_plan => {
+            Some(plan.clone())
+        }
+        _ => None,
+    };
+
+    let payload = Externalizable::query_planner_builder()
+        .stage(PipelineStep::QueryPlannerResponse)
+        .id(response.context.id.clone())
+        .and_body(body_to_send)
+        .and_context(context_to_send)
+        .and_sdl(sdl_to_send)
+        .and_query_plan(query_plan)
+        .build()?;
+
+    tracing::debug!(?payload, "externalized output");
+    let guard = response.context.enter_active_request();
+    let start = Instant::now();
+    let co_processor_result = call_coprocessor(
+        http_client,
+        &coprocessor_url,
+        payload,
+        PipelineStep::QueryPlannerResponse,
+        &response_config.on_error,
+        response_config.asynchronous,
+    )
+    .await;
+    let duration = start.elapsed().as_secs_f64();
+    drop(guard);
+    tracing::info!(
+        histogram.apollo.router.operations.coprocessor.duration = duration,
+        coprocessor.stage = %PipelineStep::QueryPlannerResponse,
+    );
+
+    tracing::debug!(?co_processor_result, "co-processor returned");
+    let co_processor_output = co_processor_result?;
+
+    // The query plan cannot be modified, but the coprocessor can reject it, for example because it
+    // makes too many fetches to a subgraph.
+    if let Some(control @ Control::Break(_)) = co_processor_output.control {
+        // Ensure the code is a valid http status code
+        let status_code = control.get_http_status()?;
+        let rejected = rejection(status_code, co_processor_output.body, response.context);
+        merge_context(&rejected.context, co_processor_output.context)?;
+        return Ok(rejected);
+    }
+
+    merge_context(&response.context, co_processor_output.context)?;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::BoxFuture;
+    use serde_json_bytes::json as bjson;
+    use tower::Service;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::plugin::test::MockInternalHttpClientService;
+    use crate::services::router::body::get_body_bytes;
+    use crate::services::router::body::RouterBody;
+
+    #[allow(clippy::type_complexity)]
+    fn mock_with_callback(
+        callback: fn(
+            http::Request<RouterBody>,
+        ) -> BoxFuture<'static, Result<http::Response<RouterBody>, BoxError>>,
+    ) -> MockInternalHttpClientService {
+        let mut mock_http_client = MockInternalHttpClientService::new();
+        mock_http_client.expect_clone().returning(move || {
+            let mut mock_http_client = MockInternalHttpClientService::new();
+            mock_http_client.expect_clone().returning(move || {
+                let mut mock_http_client = MockInternalHttpClientService::new();
+                mock_http_client.expect_call().returning(callback);
+                mock_http_client
+            });
+            mock_http_client
+        });
+
+        mock_http_client
+    }
+
+    fn planner_service() -> query_planner::CachingBoxService {
+        tower::service_fn(|request: query_planner::CachingRequest| async move {
+            Ok(query_planner::Response::builder()
+                .content(QueryPlannerContent::Plan {
+                    plan: Arc::new(crate::query_planner::QueryPlan::fake_builder().build()),
+                })
+                .context(request.context)
+                .build())
+        })
+        .boxed()
+    }
+
+    fn request() -> query_planner::CachingRequest {
+        query_planner::CachingRequest::builder()
+            .query("query Me { me { name } }")
+            .operation_name("Me")
+            .context(crate::Context::new())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn query_planner_request_annotates_context() {
+        let stage = QueryPlannerStage {
+            request: QueryPlannerRequestConf {
+                body: true,
+                ..Default::default()
+            },
+            response: Default::default(),
+        };
+
+        let mock_http_client = mock_with_callback(move |req: http::Request<RouterBody>| {
+            Box::pin(async {
+                let input: serde_json::Value =
+                    serde_json::from_slice(&get_body_bytes(req.into_body()).await.unwrap())
+                        .unwrap();
+                assert_eq!(input["stage"], "QueryPlannerRequest");
+                assert_eq!(input["body"]["operationName"], "Me");
+                assert_eq!(input["body"]["query"], "query Me { me { name } }");
+
+                let output = serde_json::json!({
+                    "version": 1,
+                    "stage": "QueryPlannerRequest",
+                    "control": "continue",
+                    "context": { "entries": { "planned_by": "coprocessor" } }
+                });
+                Ok(http::Response::builder()
+                    .body(RouterBody::from(serde_json::to_string(&output).unwrap()))
+                    .unwrap())
+            })
+        });
+
+        let service = stage.as_service(
+            mock_http_client,
+            planner_service(),
+            "http://test".to_string(),
+            Arc::new("".to_string()),
+        );
+
+        let response = service.oneshot(request()).await.unwrap();
+        assert!(response.errors.is_empty());
+        assert!(matches!(
+            response.content,
+            Some(QueryPlannerContent::Plan { .. })
+        ));
+        assert_eq!(
+            response.context.get_json_value("planned_by"),
+            Some(bjson!("coprocessor"))
+        );
+    }
+
+    #[tokio::test]
+    async fn query_planner_response_rejects_plan() {
+        let stage = QueryPlannerStage {
+            request: Default::default(),
+            response: QueryPlannerResponseConf {
+                query_plan: true,
+                ..Default::default()
+            },
+        };
+
+        let mock_http_client = mock_with_callback(move |req: http::Request<RouterBody>| {
+            Box::pin(async {
+                let input: serde_json::Value =
+                    serde_json::from_slice(&get_body_bytes(req.into_body()).await.unwrap())
+                        .unwrap();
+                assert_eq!(input["stage"], "QueryPlannerResponse");
+                assert!(input.get("queryPlan").is_some());
+
+                let output = serde_json::json!({
+                    "version": 1,
+                    "stage": "QueryPlannerResponse",
+                    "control": { "break": 403 },
+                    "body": { "errors": [{ "message": "too many fetches" }] }
+                });
+                Ok(http::Response::builder()
+                    .body(RouterBody::from(serde_json::to_string(&output).unwrap()))
+                    .unwrap())
+            })
+        });
+
+        let service = stage.as_service(
+            mock_http_client,
+            planner_service(),
+            "http://test".to_string(),
+            Arc::new("".to_string()),
+        );
+
+        let response = service.oneshot(request()).await.unwrap();
+        assert!(response.content.is_none());
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(response.errors[0].message, "too many fetches");
+        assert_eq!(response.status_code, Some(http::StatusCode::FORBIDDEN));
+    }
+
+    #[tokio::test]
+    async fn query_planner_stage_handles_every_operation() {
+        let stage = QueryPlannerStage {
+            request: QueryPlannerRequestConf {
+                context: true,
+                ..Default::default()
+            },
+            response: Default::default(),
+        };
+
+        let mock_http_client = mock_with_callback(move |_: http::Request<RouterBody>| {
+            Box::pin(async {
+                let output = serde_json::json!({
+                    "version": 1,
+                    "stage": "QueryPlannerRequest",
+                    "control": "continue",
+                    "context": { "entries": { "planned_by": "coprocessor" } }
+                });
+                Ok(http::Response::builder()
+                    .body(RouterBody::from(serde_json::to_string(&output).unwrap()))
+                    .unwrap())
+            })
+        });
+
+        // the stage is built once by the supergraph service and called for every operation
+        let mut service = stage.as_service(
+            mock_http_client,
+            planner_service(),
+            "http://test".to_string(),
+            Arc::new("".to_string()),
+        );
+
+        for _ in 0..2 {
+            let response = service
+                .ready()
+                .await
+                .unwrap()
+                .call(request())
+                .await
+                .unwrap();
+            assert!(matches!(
+                response.content,
+                Some(QueryPlannerContent::Plan { .. })
+            ));
+            assert_eq!(
+                response.context.get_json_value("planned_by"),
+                Some(bjson!("coprocessor"))
+            );
+        }
+    }
+}
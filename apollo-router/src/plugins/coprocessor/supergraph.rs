@@ -8,7 +8,6 @@ use serde::Deserialize;
 use serde::Serialize;
 use tower::BoxError;
 use tower::ServiceBuilder;
-use tower_service::Service;
 
 use super::externalize_header_map;
 use super::*;
@@ -37,6 +36,11 @@ pub(super) struct SupergraphRequestConf {
     pub(super) sdl: bool,
     /// Send the method
     pub(super) method: bool,
+    /// What to do when the coprocessor call fails
+    pub(super) on_error: OnError,
+    /// Send the payload without waiting for the coprocessor, which cannot modify the stage
+    #[serde(rename = "async")]
+    pub(super) asynchronous: bool,
 }
 
 /// What information is passed to a router request/response stage
@@ -56,6 +60,11 @@ pub(super) struct SupergraphResponseConf {
     pub(super) sdl: bool,
     /// Send the HTTP status
     pub(super) status_code: bool,
+    /// What to do when the coprocessor call fails
+    pub(super) on_error: OnError,
+    /// Send the payload without waiting for the coprocessor, which cannot modify the stage
+    #[serde(rename = "async")]
+    pub(super) asynchronous: bool,
 }
 
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
@@ -76,15 +85,7 @@ impl SupergraphStage {
         sdl: Arc<String>,
     ) -> supergraph::BoxService
     where
-        C: Service<
-                http::Request<RouterBody>,
-                Response = http::Response<RouterBody>,
-                Error = BoxError,
-            > + Clone
-            + Send
-            + Sync
-            + 'static,
-        <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+        C: CoprocessorTransport,
     {
         let request_layer = (self.request != Default::default()).then_some({
             let request_config = self.request.clone();
@@ -194,12 +195,7 @@ async fn process_supergraph_request_stage<C>(
     mut request_config: SupergraphRequestConf,
 ) -> Result<ControlFlow<supergraph::Response, supergraph::Request>, BoxError>
 where
-    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
-        + Clone
-        + Send
-        + Sync
-        + 'static,
-    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+    C: CoprocessorTransport,
 {
     let should_be_executed = request_config
         .condition
@@ -242,7 +238,15 @@ where
     tracing::debug!(?payload, "externalized output");
     let guard = request.context.enter_active_request();
     let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
+    let co_processor_result = call_coprocessor(
+        http_client,
+        &coprocessor_url,
+        payload,
+        PipelineStep::SupergraphRequest,
+        &request_config.on_error,
+        request_config.asynchronous,
+    )
+    .await;
     let duration = start.elapsed().as_secs_f64();
     drop(guard);
     tracing::info!(
@@ -252,8 +256,7 @@ where
 
     tracing::debug!(?co_processor_result, "co-processor returned");
     let co_processor_output = co_processor_result?;
-    validate_coprocessor_output(&co_processor_output, PipelineStep::SupergraphRequest)?;
-    // unwrap is safe here because validate_coprocessor_output made sure control is available
+    // unwrap is safe here because call_coprocessor validated that control is available
     let control = co_processor_output.control.expect("validated above; qed");
 
     // Thirdly, we need to interpret the control flow which may have been
@@ -340,12 +343,7 @@ async fn process_supergraph_response_stage<C>(
     response_config: SupergraphResponseConf,
 ) -> Result<supergraph::Response, BoxError>
 where
-    C: Service<http::Request<RouterBody>, Response = http::Response<RouterBody>, Error = BoxError>
-        + Clone
-        + Send
-        + Sync
-        + 'static,
-    <C as tower::Service<http::Request<RouterBody>>>::Future: Send + 'static,
+    C: CoprocessorTransport,
 {
     let should_be_executed = response_config
         .condition
@@ -396,7 +394,15 @@ where
     tracing::debug!(?payload, "externalized output");
     let guard = response.context.enter_active_request();
     let start = Instant::now();
-    let co_processor_result = payload.call(http_client.clone(), &coprocessor_url).await;
+    let co_processor_result = call_coprocessor(
+        http_client.clone(),
+        &coprocessor_url,
+        payload,
+        PipelineStep::SupergraphResponse,
+        &response_config.on_error,
+        response_config.asynchronous,
+    )
+    .await;
     let duration = start.elapsed().as_secs_f64();
     drop(guard);
     tracing::info!(
@@ -407,8 +413,6 @@ where
     tracing::debug!(?co_processor_result, "co-processor returned");
     let co_processor_output = co_processor_result?;
 
-    validate_coprocessor_output(&co_processor_output, PipelineStep::SupergraphResponse)?;
-
     // Third, process our reply and act on the contents. Our processing logic is
     // that we replace "bits" of our incoming response with the updated bits if they
     // are present in our co_processor_output. If they aren't present, just use the
@@ -475,18 +479,19 @@ where
                 // Second, call our co-processor and get a reply.
                 tracing::debug!(?payload, "externalized output");
                 let guard = generator_map_context.enter_active_request();
-                let co_processor_result = payload
-                    .call(generator_client, &generator_coprocessor_url)
-                    .await;
+                let co_processor_result = call_coprocessor(
+                    generator_client,
+                    &generator_coprocessor_url,
+                    payload,
+                    PipelineStep::SupergraphResponse,
+                    &response_config.on_error,
+                    response_config.asynchronous,
+                )
+                .await;
                 drop(guard);
                 tracing::debug!(?co_processor_result, "co-processor returned");
                 let co_processor_output = co_processor_result?;
 
-                validate_coprocessor_output(
-                    &co_processor_output,
-                    PipelineStep::SupergraphResponse,
-                )?;
-
                 // Third, process our reply and act on the contents. Our processing logic is
                 // that we replace "bits" of our incoming response with the updated bits if they
                 // are present in our co_processor_output. If they aren't present, just use the
@@ -603,6 +608,8 @@ mod tests {
                 body: true,
                 sdl: false,
                 method: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -745,6 +752,8 @@ mod tests {
                 body: true,
                 sdl: false,
                 method: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -880,6 +889,8 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             request: Default::default(),
         };
@@ -1012,6 +1023,8 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             request: Default::default(),
         };
@@ -1130,6 +1143,8 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             request: Default::default(),
         };
@@ -19,6 +19,7 @@ mod tests {
     use tower::ServiceExt;
 
     use super::super::*;
+    use crate::metrics::FutureMetricsExt;
     use crate::plugin::test::MockInternalHttpClientService;
     use crate::plugin::test::MockRouterService;
     use crate::plugin::test::MockSubgraphService;
@@ -70,6 +71,233 @@ mod tests {
             .is_err());
     }
 
+    #[tokio::test]
+    async fn load_multiple_coprocessors() {
+        let config = json!({
+            "coprocessor": {
+                "coprocessors": [
+                    {
+                        "url": "http://127.0.0.1:8081",
+                        "router": {
+                            "request": {
+                                "headers": true
+                            }
+                        }
+                    },
+                    {
+                        "url": "http://127.0.0.1:8082",
+                        "timeout": "2s",
+                        "router": {
+                            "response": {
+                                "body": true
+                            }
+                        }
+                    }
+                ]
+            }
+        });
+        let _test_harness = crate::TestHarness::builder()
+            .configuration_json(config)
+            .unwrap()
+            .build_router()
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn coprocessors_keep_their_order() {
+        let confs: Confs = serde_json::from_value(json!({
+            "coprocessors": [
+                { "url": "http://auth:8081" },
+                { "url": "http://audit:8082", "timeout": "2s" }
+            ]
+        }))
+        .unwrap();
+        let confs = confs.into_vec();
+        assert_eq!(confs.len(), 2);
+        assert_eq!(confs[0].url, "http://auth:8081");
+        assert_eq!(confs[1].url, "http://audit:8082");
+        assert_eq!(confs[1].timeout, std::time::Duration::from_secs(2));
+
+        let single: Confs = serde_json::from_value(json!({ "url": "http://auth:8081" })).unwrap();
+        assert_eq!(single.into_vec().len(), 1);
+    }
+
+    #[test]
+    fn coprocessors_list_is_exclusive() {
+        let error = serde_json::from_value::<Confs>(json!({
+            "url": "http://auth:8081",
+            "coprocessors": [{ "url": "http://audit:8082" }]
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("unknown field `url`"), "{error}");
+
+        // the errors of a single coprocessor point at the invalid field
+        let error = serde_json::from_value::<Confs>(json!({
+            "url": "http://auth:8081",
+            "timeuot": "2s"
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("timeuot"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn chained_coprocessors_see_the_previous_changes() {
+        let router_stage = RouterStage {
+            request: RouterRequestConf {
+                headers: true,
+                context: true,
+                ..Default::default()
+            },
+            response: Default::default(),
+        };
+
+        // the first coprocessor adds a header and a context entry
+        let first = mock_with_callback(move |req: http::Request<RouterBody>| {
+            Box::pin(async {
+                let mut payload: serde_json::Value =
+                    serde_json::from_slice(&hyper::body::to_bytes(req.into_body()).await.unwrap())
+                        .unwrap();
+                payload["control"] = json!("continue");
+                payload["headers"]["x-authenticated"] = json!(["true"]);
+                payload["context"]["entries"]["user"] = json!("alice");
+                Ok(http::Response::builder()
+                    .body(RouterBody::from(serde_json::to_string(&payload).unwrap()))
+                    .unwrap())
+            })
+        });
+
+        // the second coprocessor is called with the changes of the first one
+        let second = mock_with_callback(move |req: http::Request<RouterBody>| {
+            Box::pin(async {
+                let mut payload: serde_json::Value =
+                    serde_json::from_slice(&hyper::body::to_bytes(req.into_body()).await.unwrap())
+                        .unwrap();
+                assert_eq!(payload["headers"]["x-authenticated"], json!(["true"]));
+                assert_eq!(payload["context"]["entries"]["user"], json!("alice"));
+                payload["control"] = json!("continue");
+                payload["headers"]["x-audited"] = json!(["true"]);
+                Ok(http::Response::builder()
+                    .body(RouterBody::from(serde_json::to_string(&payload).unwrap()))
+                    .unwrap())
+            })
+        });
+
+        let mock_router_service = router::service::from_supergraph_mock_callback(move |req| {
+            let headers = req.supergraph_request.headers();
+            assert_eq!(headers.get("x-authenticated").unwrap(), "true");
+            assert_eq!(headers.get("x-audited").unwrap(), "true");
+            assert_eq!(
+                req.context.get::<_, String>("user").unwrap().as_deref(),
+                Some("alice")
+            );
+
+            Ok(supergraph::Response::builder()
+                .data(json!({ "test": 1234_u32 }))
+                .context(req.context)
+                .build()
+                .unwrap())
+        })
+        .await;
+
+        // the coprocessors are applied in the same way as in `Coprocessors::router_service`
+        let service = router_stage.as_service(
+            second,
+            mock_router_service.boxed(),
+            "http://audit".to_string(),
+            Arc::new("".to_string()),
+        );
+        let service = router_stage.as_service(
+            first,
+            service,
+            "http://auth".to_string(),
+            Arc::new("".to_string()),
+        );
+
+        let request = supergraph::Request::canned_builder().build().unwrap();
+        let response = service.oneshot(request.try_into().unwrap()).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[cfg(unix)]
+    async fn coprocessor_over_unix_socket() {
+        call_unix_socket_coprocessor(false, json!(null)).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[cfg(unix)]
+    async fn coprocessor_over_unix_socket_with_http2() {
+        // without TLS, HTTP/2 needs prior knowledge
+        call_unix_socket_coprocessor(true, json!({ "experimental_http2": "http2only" })).await;
+    }
+
+    #[cfg(unix)]
+    async fn call_unix_socket_coprocessor(http2_only: bool, client: serde_json::Value) {
+        use hyperlocal::UnixServerExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("coprocessor.sock");
+
+        let make_service = hyper::service::make_service_fn(|_| async {
+            Ok::<_, hyper::Error>(hyper::service::service_fn(
+                |_: http::Request<hyper::Body>| async {
+                    Ok::<_, hyper::Error>(http::Response::new(hyper::Body::from(
+                        r#"{
+                            "version": 1,
+                            "stage": "RouterRequest",
+                            "control": "continue",
+                            "context": { "entries": { "from-unix-socket": true } }
+                        }"#,
+                    )))
+                },
+            ))
+        });
+        let server = hyper::Server::bind_unix(&path)
+            .unwrap()
+            .http2_only(http2_only)
+            .serve(make_service);
+        tokio::task::spawn(server);
+
+        let config: Conf = serde_json::from_value(json!({
+            "url": format!("unix://{}", path.display()),
+            "client": client,
+            "router": {
+                "request": {
+                    "context": true
+                }
+            }
+        }))
+        .unwrap();
+        let plugin = CoprocessorPlugin::new(
+            CoprocessorClient::new(&config).unwrap(),
+            config,
+            Default::default(),
+        )
+        .unwrap();
+
+        let mock_router_service = router::service::from_supergraph_mock_callback(move |req| {
+            assert!(req
+                .context
+                .get::<&str, bool>("from-unix-socket")
+                .unwrap()
+                .unwrap());
+            Ok(supergraph::Response::builder()
+                .data(json!({ "test": 1234_u32 }))
+                .context(req.context)
+                .build()
+                .unwrap())
+        })
+        .await;
+
+        let request = supergraph::Request::canned_builder().build().unwrap();
+        plugin
+            .router_service(mock_router_service.boxed())
+            .oneshot(request.try_into().unwrap())
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn external_plugin_with_stages_wont_load_without_graph_ref() {
         let config = json!({
@@ -106,6 +334,8 @@ mod tests {
                 sdl: true,
                 path: false,
                 method: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -166,6 +396,8 @@ mod tests {
                 sdl: true,
                 path: false,
                 method: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -226,6 +458,8 @@ mod tests {
                 sdl: true,
                 path: false,
                 method: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -285,6 +519,8 @@ mod tests {
                 uri: false,
                 method: false,
                 service_name: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -348,6 +584,8 @@ mod tests {
                 uri: false,
                 method: false,
                 service_name: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -486,6 +724,8 @@ mod tests {
                 uri: false,
                 method: false,
                 service_name: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -549,6 +789,211 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn external_plugin_subgraph_request_fail_open() {
+        let subgraph_stage = SubgraphStage {
+            request: SubgraphRequestConf {
+                condition: Default::default(),
+                headers: false,
+                context: false,
+                body: true,
+                uri: false,
+                method: false,
+                service_name: false,
+                on_error: OnError::FailOpen,
+                asynchronous: false,
+            },
+            response: Default::default(),
+        };
+
+        let mut mock_subgraph_service = MockSubgraphService::new();
+
+        mock_subgraph_service
+            .expect_call()
+            .returning(|req: subgraph::Request| {
+                // The request is unmodified
+                assert_eq!("/", req.subgraph_request.uri().to_string());
+                Ok(subgraph::Response::builder()
+                    .data(json!({ "test": 1234_u32 }))
+                    .errors(Vec::new())
+                    .extensions(crate::json_ext::Object::new())
+                    .context(req.context)
+                    .build())
+            });
+
+        let mock_http_client = mock_with_callback(move |_: http::Request<RouterBody>| {
+            Box::pin(async { Err(BoxError::from("connection refused")) })
+        });
+
+        let service = subgraph_stage.as_service(
+            mock_http_client,
+            mock_subgraph_service.boxed(),
+            "http://test".to_string(),
+            "my_subgraph_service_name".to_string(),
+        );
+
+        let request = subgraph::Request::fake_builder().build();
+
+        assert_eq!(
+            serde_json_bytes::json!({ "test": 1234_u32 }),
+            service
+                .oneshot(request)
+                .await
+                .unwrap()
+                .response
+                .into_body()
+                .data
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn external_plugin_subgraph_request_async() {
+        let subgraph_stage = SubgraphStage {
+            request: SubgraphRequestConf {
+                condition: Default::default(),
+                headers: false,
+                context: false,
+                body: true,
+                uri: true,
+                method: false,
+                service_name: false,
+                on_error: Default::default(),
+                asynchronous: true,
+            },
+            response: Default::default(),
+        };
+
+        let mut mock_subgraph_service = MockSubgraphService::new();
+
+        mock_subgraph_service
+            .expect_call()
+            .returning(|req: subgraph::Request| {
+                // The coprocessor cannot modify the request of an asynchronous stage
+                assert_eq!("/", req.subgraph_request.uri().to_string());
+                Ok(subgraph::Response::builder()
+                    .data(json!({ "test": 1234_u32 }))
+                    .errors(Vec::new())
+                    .extensions(crate::json_ext::Object::new())
+                    .context(req.context)
+                    .build())
+            });
+
+        let mock_http_client = mock_with_callback(move |_: http::Request<RouterBody>| {
+            Box::pin(async {
+                Ok(http::Response::builder()
+                    .body(RouterBody::from(
+                        r#"{
+                                "version": 1,
+                                "stage": "SubgraphRequest",
+                                "control": "continue",
+                                "uri": "http://thisurihaschanged"
+                            }"#,
+                    ))
+                    .unwrap())
+            })
+        });
+
+        let service = subgraph_stage.as_service(
+            mock_http_client,
+            mock_subgraph_service.boxed(),
+            "http://test".to_string(),
+            "my_subgraph_service_name".to_string(),
+        );
+
+        let request = subgraph::Request::fake_builder().build();
+
+        assert!(service.oneshot(request).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn external_plugin_async_stage_does_not_delay_the_response() {
+        let subgraph_stage = SubgraphStage {
+            request: SubgraphRequestConf {
+                body: true,
+                asynchronous: true,
+                ..Default::default()
+            },
+            response: Default::default(),
+        };
+
+        let mut mock_subgraph_service = MockSubgraphService::new();
+        mock_subgraph_service
+            .expect_call()
+            .returning(|req: subgraph::Request| {
+                Ok(subgraph::Response::builder()
+                    .data(json!({ "test": 1234_u32 }))
+                    .errors(Vec::new())
+                    .extensions(crate::json_ext::Object::new())
+                    .context(req.context)
+                    .build())
+            });
+
+        // the coprocessor answers long after the client got its response
+        let mock_http_client = mock_with_callback(move |_: http::Request<RouterBody>| {
+            Box::pin(async {
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                Ok(http::Response::builder()
+                    .body(RouterBody::from(
+                        r#"{ "version": 1, "stage": "SubgraphRequest", "control": "continue" }"#,
+                    ))
+                    .unwrap())
+            })
+        });
+
+        let service = subgraph_stage.as_service(
+            mock_http_client,
+            mock_subgraph_service.boxed(),
+            "http://test".to_string(),
+            "my_subgraph_service_name".to_string(),
+        );
+
+        let request = subgraph::Request::fake_builder().build();
+        let response =
+            tokio::time::timeout(std::time::Duration::from_secs(5), service.oneshot(request))
+                .await
+                .expect("the response should not wait for the coprocessor")
+                .unwrap();
+        assert_eq!(
+            response.response.into_body().data,
+            Some(serde_json_bytes::json!({ "test": 1234_u32 }))
+        );
+    }
+
+    #[tokio::test]
+    async fn async_calls_are_dropped_when_saturated() {
+        async {
+            let permits = Arc::new(tokio::sync::Semaphore::new(1));
+            let (release, released) = tokio::sync::oneshot::channel::<()>();
+            spawn_async_call(&permits, PipelineStep::SubgraphRequest, async move {
+                let _ = released.await;
+            });
+
+            // the only permit is taken by the pending call
+            let (called, mut dropped_call) = tokio::sync::oneshot::channel::<()>();
+            spawn_async_call(&permits, PipelineStep::SubgraphRequest, async move {
+                let _ = called.send(());
+            });
+            assert!(dropped_call.try_recv().is_err());
+            assert_counter!(
+                "apollo.router.operations.coprocessor.async.dropped",
+                1,
+                "coprocessor.stage" = "SubgraphRequest"
+            );
+
+            // the permit is available again once the pending call is done
+            release.send(()).unwrap();
+            drop(permits.acquire().await.unwrap());
+            let (called, accepted_call) = tokio::sync::oneshot::channel::<()>();
+            spawn_async_call(&permits, PipelineStep::SubgraphRequest, async move {
+                let _ = called.send(());
+            });
+            accepted_call.await.expect("the call should run");
+        }
+        .with_metrics()
+        .await;
+    }
+
     #[tokio::test]
     async fn external_plugin_subgraph_request_controlflow_break() {
         let subgraph_stage = SubgraphStage {
@@ -560,6 +1005,8 @@ mod tests {
                 uri: false,
                 method: false,
                 service_name: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -630,6 +1077,8 @@ mod tests {
                 uri: false,
                 method: false,
                 service_name: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -694,6 +1143,8 @@ mod tests {
                 body: true,
                 service_name: false,
                 status_code: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
         };
 
@@ -812,6 +1263,8 @@ mod tests {
                 body: true,
                 service_name: false,
                 status_code: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
         };
 
@@ -927,6 +1380,8 @@ mod tests {
                 body: true,
                 status_code: false,
                 sdl: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
         };
 
@@ -989,6 +1444,8 @@ mod tests {
                 sdl: true,
                 path: true,
                 method: true,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -1115,6 +1572,8 @@ mod tests {
                 sdl: true,
                 path: true,
                 method: true,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -1220,6 +1679,8 @@ mod tests {
                 sdl: true,
                 path: true,
                 method: true,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -1349,6 +1810,8 @@ mod tests {
                 sdl: true,
                 path: true,
                 method: true,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -1438,6 +1901,8 @@ mod tests {
                 sdl: true,
                 path: true,
                 method: true,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             response: Default::default(),
         };
@@ -1517,6 +1982,8 @@ mod tests {
                 body: true,
                 sdl: true,
                 status_code: false,
+                on_error: Default::default(),
+                asynchronous: false,
             },
             request: Default::default(),
         };
@@ -0,0 +1,42 @@
+//! WebAssembly transport for the coprocessor protocol
+//!
+//! The coprocessor is a WebAssembly component, loaded from a `file://` URL, that exports the
+//! `process` function of `wit/coprocessor.wit`. It receives the same JSON payloads as HTTP
+//! coprocessors and returns them, modified or not. Each call runs in a new instance of the
+//! component, with limited fuel, memory and time, and the component is reloaded when its file
+//! changes.
+//!
+//! The runtime is only built with the `wasm` feature.
+
+use bytesize::ByteSize;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+#[cfg(feature = "wasm")]
+mod client;
+
+#[cfg(feature = "wasm")]
+pub(super) use client::WasmClientService;
+
+/// Configures the runtime of WebAssembly coprocessors
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+#[cfg_attr(not(feature = "wasm"), allow(dead_code))]
+pub(super) struct WasmConf {
+    /// The fuel of each call, consumed by the executed instructions. Calls running out of fuel
+    /// fail (default: 1000000000)
+    pub(super) fuel: u64,
+    /// The maximum memory of the component for each call (default: 64MB)
+    #[serde(deserialize_with = "bytesize::ByteSize::deserialize")]
+    #[schemars(with = "String")]
+    pub(super) max_memory: ByteSize,
+}
+
+impl Default for WasmConf {
+    fn default() -> Self {
+        Self {
+            fuel: 1_000_000_000,
+            max_memory: ByteSize::mb(64),
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use std::fmt::Debug;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tower::BoxError;
+use wasmtime::component::Component;
+use wasmtime::component::InstancePre;
+use wasmtime::component::Linker;
+use wasmtime::component::ResourceTable;
+use wasmtime::Engine;
+use wasmtime::Store;
+use wasmtime::StoreLimits;
+use wasmtime::StoreLimitsBuilder;
+use wasmtime::Trap;
+use wasmtime_wasi::WasiCtx;
+use wasmtime_wasi::WasiCtxBuilder;
+use wasmtime_wasi::WasiView;
+
+use super::WasmConf;
+use crate::services::external::Externalizable;
+
+const PROCESS_FUNCTION: &str = "process";
+/// The interval at which the engine epoch is incremented, to interrupt the calls running longer
+/// than the timeout
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// Calls the `process` function of a WebAssembly component with the coprocessor JSON payloads
+#[derive(Clone)]
+pub(crate) struct WasmClientService {
+    runtime: Arc<Runtime>,
+    _tasks: Arc<BackgroundTasks>,
+}
+
+struct Runtime {
+    engine: Engine,
+    path: PathBuf,
+    config: WasmConf,
+    /// The number of epoch ticks after which a call is interrupted
+    deadline: u64,
+    component: ArcSwap<InstancePre<State>>,
+}
+
+/// Stops watching the component file and ticking the epoch when the client is dropped
+struct BackgroundTasks(Vec<JoinHandle<()>>);
+
+impl Drop for BackgroundTasks {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+/// The data of each call: the component can only write to stderr, without any other access to
+/// the host
+struct State {
+    wasi: WasiCtx,
+    table: ResourceTable,
+    limits: StoreLimits,
+}
+
+impl WasiView for State {
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+}
+
+impl WasmClientService {
+    pub(crate) fn new(path: &str, config: &WasmConf, timeout: Duration) -> Result<Self, BoxError> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config
+            .wasm_component_model(true)
+            .consume_fuel(true)
+            .epoch_interruption(true);
+        let engine = Engine::new(&engine_config)?;
+        let path = PathBuf::from(path);
+        let component = load(&engine, &path)?;
+
+        let runtime = Arc::new(Runtime {
+            engine,
+            path,
+            config: config.clone(),
+            deadline: (timeout.as_millis() / EPOCH_TICK.as_millis()).max(1) as u64,
+            component: ArcSwap::from_pointee(component),
+        });
+
+        let ticked = runtime.clone();
+        let epoch = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EPOCH_TICK);
+            loop {
+                interval.tick().await;
+                ticked.engine.increment_epoch();
+            }
+        });
+
+        let watched = runtime.clone();
+        let reload = tokio::spawn(async move {
+            let mut changes = Box::pin(crate::files::watch(&watched.path));
+            while changes.next().await.is_some() {
+                let runtime = watched.clone();
+                // compiling the component is CPU intensive
+                let loaded =
+                    tokio::task::spawn_blocking(move || load(&runtime.engine, &runtime.path))
+                        .await
+                        .map_err(BoxError::from)
+                        .and_then(|loaded| loaded);
+                match loaded {
+                    Ok(component) => {
+                        tracing::info!(
+                            "reloaded the coprocessor component {}",
+                            watched.path.display()
+                        );
+                        watched.component.store(Arc::new(component));
+                    }
+                    Err(error) => tracing::warn!(
+                        "could not reload the coprocessor component {}: {}",
+                        watched.path.display(),
+                        error
+                    ),
+                }
+            }
+        });
+
+        Ok(Self {
+            runtime,
+            _tasks: Arc::new(BackgroundTasks(vec![epoch, reload])),
+        })
+    }
+
+    pub(crate) fn process<T>(
+        &self,
+        payload: Externalizable<T>,
+    ) -> BoxFuture<'static, Result<Externalizable<T>, BoxError>>
+    where
+        T: Debug + DeserializeOwned + Serialize + Send + Sync + 'static,
+    {
+        let runtime = self.runtime.clone();
+        Box::pin(async move {
+            let payload = serde_json::to_string(&payload)?;
+            tracing::debug!("forwarding json: {}", payload);
+            // the guest code runs until it returns, runs out of fuel or times out
+            let output = tokio::task::spawn_blocking(move || runtime.process(&payload)).await??;
+            Ok(serde_json::from_str(&output)?)
+        })
+    }
+}
+
+fn load(engine: &Engine, path: &Path) -> Result<InstancePre<State>, BoxError> {
+    let component = Component::from_file(engine, path)
+        .map_err(|error| format!("could not load {}: {error}", path.display()))?;
+    let mut linker = Linker::new(engine);
+    wasmtime_wasi::add_to_linker_sync(&mut linker)?;
+    Ok(linker.instantiate_pre(&component)?)
+}
+
+impl Runtime {
+    fn process(&self, payload: &str) -> Result<String, BoxError> {
+        let component = self.component.load_full();
+        let mut store = Store::new(
+            &self.engine,
+            State {
+                wasi: WasiCtxBuilder::new().inherit_stderr().build(),
+                table: ResourceTable::new(),
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.config.max_memory.as_u64() as usize)
+                    .build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.config.fuel)?;
+        store.set_epoch_deadline(self.deadline);
+
+        let result = component.instantiate(&mut store).and_then(|instance| {
+            let process = instance.get_typed_func::<(&str,), (Result<String, String>,)>(
+                &mut store,
+                PROCESS_FUNCTION,
+            )?;
+            let (result,) = process.call(&mut store, (payload,))?;
+            process.post_return(&mut store)?;
+            Ok(result)
+        });
+        match result {
+            Ok(result) => {
+                result.map_err(|error| format!("the coprocessor component failed: {error}").into())
+            }
+            Err(error) if error.downcast_ref::<Trap>() == Some(&Trap::Interrupt) => {
+                Err("the coprocessor component timed out".into())
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::services::external::PipelineStep;
+
+    // returns the payload unchanged
+    const ECHO_COMPONENT: &str = r#"
+    (component
+      (core module $m
+        (memory (export "memory") 1)
+        (global $heap (mut i32) (i32.const 1024))
+        (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+          (local $ptr i32)
+          (local.set $ptr (global.get $heap))
+          (global.set $heap (i32.add (global.get $heap) (local.get 3)))
+          (local.get $ptr))
+        (func (export "process") (param $ptr i32) (param $len i32) (result i32)
+          (i32.store8 (i32.const 16) (i32.const 0))
+          (i32.store (i32.const 20) (local.get $ptr))
+          (i32.store (i32.const 24) (local.get $len))
+          (i32.const 16)))
+      (core instance $i (instantiate $m))
+      (func (export "process") (param "payload" string) (result (result string (error string)))
+        (canon lift (core func $i "process") (memory $i "memory") (realloc (func $i "realloc"))))
+    )
+    "#;
+
+    // never returns
+    const LOOP_COMPONENT: &str = r#"
+    (component
+      (core module $m
+        (memory (export "memory") 1)
+        (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+          (i32.const 1024))
+        (func (export "process") (param i32 i32) (result i32)
+          (loop $forever (br $forever))
+          (unreachable)))
+      (core instance $i (instantiate $m))
+      (func (export "process") (param "payload" string) (result (result string (error string)))
+        (canon lift (core func $i "process") (memory $i "memory") (realloc (func $i "realloc"))))
+    )
+    "#;
+
+    // always fails with the `reloaded` error
+    const FAILING_COMPONENT: &str = r#"
+    (component
+      (core module $m
+        (memory (export "memory") 1)
+        (data (i32.const 64) "reloaded")
+        (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+          (i32.const 1024))
+        (func (export "process") (param i32 i32) (result i32)
+          (i32.store8 (i32.const 16) (i32.const 1))
+          (i32.store (i32.const 20) (i32.const 64))
+          (i32.store (i32.const 24) (i32.const 8))
+          (i32.const 16)))
+      (core instance $i (instantiate $m))
+      (func (export "process") (param "payload" string) (result (result string (error string)))
+        (canon lift (core func $i "process") (memory $i "memory") (realloc (func $i "realloc"))))
+    )
+    "#;
+
+    fn component_file(component: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(component.as_bytes()).unwrap();
+        file
+    }
+
+    fn payload() -> Externalizable<String> {
+        Externalizable::router_builder()
+            .stage(PipelineStep::RouterRequest)
+            .id("1b19c05fdafc521016df33148ad63c1b".to_string())
+            .body(r#"{"query":"{ me { name } }"}"#.to_string())
+            .build()
+    }
+
+    #[tokio::test]
+    async fn it_calls_the_component() {
+        let file = component_file(ECHO_COMPONENT);
+        let client = WasmClientService::new(
+            file.path().to_str().unwrap(),
+            &Default::default(),
+            Duration::from_secs(1),
+        )
+        .unwrap();
+
+        let output = client.process(payload()).await.unwrap();
+        assert_eq!(
+            serde_json::to_value(output).unwrap(),
+            serde_json::to_value(payload()).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_stops_components_out_of_fuel() {
+        let file = component_file(LOOP_COMPONENT);
+        let client = WasmClientService::new(
+            file.path().to_str().unwrap(),
+            &WasmConf {
+                fuel: 10_000,
+                ..Default::default()
+            },
+            Duration::from_secs(60),
+        )
+        .unwrap();
+
+        assert!(client.process(payload()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_stops_components_after_the_timeout() {
+        let file = component_file(LOOP_COMPONENT);
+        let client = WasmClientService::new(
+            file.path().to_str().unwrap(),
+            &WasmConf {
+                fuel: u64::MAX,
+                ..Default::default()
+            },
+            Duration::from_millis(100),
+        )
+        .unwrap();
+
+        let error = tokio::time::timeout(Duration::from_secs(5), client.process(payload()))
+            .await
+            .expect("the call should be interrupted")
+            .unwrap_err();
+        assert_eq!(error.to_string(), "the coprocessor component timed out");
+    }
+
+    #[tokio::test]
+    async fn it_reloads_the_component_when_the_file_changes() {
+        let file = component_file(ECHO_COMPONENT);
+        let client = WasmClientService::new(
+            file.path().to_str().unwrap(),
+            &Default::default(),
+            Duration::from_secs(1),
+        )
+        .unwrap();
+        assert!(client.process(payload()).await.is_ok());
+
+        // let the watcher read the initial content of the file before replacing it
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        std::fs::write(file.path(), FAILING_COMPONENT).unwrap();
+
+        let error = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                match client.process(payload()).await {
+                    Ok(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+                    Err(error) => break error,
+                }
+            }
+        })
+        .await
+        .expect("the component should be reloaded");
+        assert_eq!(
+            error.to_string(),
+            "the coprocessor component failed: reloaded"
+        );
+    }
+}
@@ -35,9 +35,14 @@ use tower::BoxError;
 use uuid::Uuid;
 
 use super::execution;
+use super::http_client::HttpClient;
+use super::kv_store::KvStore;
+use super::query_planner;
 use super::router;
 use super::subgraph;
 use super::supergraph;
+use super::telemetry::span_module;
+use super::telemetry::Instruments;
 use super::Rhai;
 use super::ServiceStep;
 use crate::configuration::expansion;
@@ -48,6 +53,7 @@ use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
 use crate::plugins::cache::entity::CONTEXT_CACHE_KEY;
 use crate::plugins::subscription::SUBSCRIPTION_WS_CUSTOM_CONNECTION_PARAMS;
 use crate::query_planner::APOLLO_OPERATION_ID;
+use crate::services::QueryPlannerContent;
 use crate::Context;
 
 const CANNOT_ACCESS_HEADERS_ON_A_DEFERRED_RESPONSE: &str =
@@ -1273,6 +1279,77 @@ mod router_plugin {
                 .unwrap_or_default()
         })
     }
+
+    // Query planner requests and responses
+    #[rhai_fn(get = "context", pure, return_raw)]
+    pub(crate) fn query_planner_request_context_get(
+        obj: &mut SharedMut<query_planner::Request>,
+    ) -> Result<Context, Box<EvalAltResult>> {
+        Ok(obj.with_mut(|request| request.context.clone()))
+    }
+    #[rhai_fn(set = "context", return_raw)]
+    pub(crate) fn query_planner_request_context_set(
+        obj: &mut SharedMut<query_planner::Request>,
+        context: Context,
+    ) -> Result<(), Box<EvalAltResult>> {
+        obj.with_mut(|request| request.context = context);
+        Ok(())
+    }
+    #[rhai_fn(get = "id", pure)]
+    pub(crate) fn query_planner_request_id_get(
+        obj: &mut SharedMut<query_planner::Request>,
+    ) -> String {
+        obj.with_mut(|request| request.context.id.clone())
+    }
+    #[rhai_fn(get = "query", pure)]
+    pub(crate) fn query_planner_request_query_get(
+        obj: &mut SharedMut<query_planner::Request>,
+    ) -> String {
+        obj.with_mut(|request| request.query.clone())
+    }
+    #[rhai_fn(get = "operation_name", pure)]
+    pub(crate) fn query_planner_request_operation_name_get(
+        obj: &mut SharedMut<query_planner::Request>,
+    ) -> Dynamic {
+        obj.with_mut(|request| request.operation_name.clone())
+            .map(Dynamic::from)
+            .unwrap_or_default()
+    }
+
+    #[rhai_fn(get = "context", pure, return_raw)]
+    pub(crate) fn query_planner_response_context_get(
+        obj: &mut SharedMut<query_planner::Response>,
+    ) -> Result<Context, Box<EvalAltResult>> {
+        Ok(obj.with_mut(|response| response.context.clone()))
+    }
+    #[rhai_fn(set = "context", return_raw)]
+    pub(crate) fn query_planner_response_context_set(
+        obj: &mut SharedMut<query_planner::Response>,
+        context: Context,
+    ) -> Result<(), Box<EvalAltResult>> {
+        obj.with_mut(|response| response.context = context);
+        Ok(())
+    }
+    #[rhai_fn(get = "id", pure)]
+    pub(crate) fn query_planner_response_id_get(
+        obj: &mut SharedMut<query_planner::Response>,
+    ) -> String {
+        obj.with_mut(|response| response.context.id.clone())
+    }
+    // The query plan is empty when the operation was answered without planning (introspection)
+    #[rhai_fn(get = "query_plan", pure)]
+    pub(crate) fn query_planner_response_query_plan_get(
+        obj: &mut SharedMut<query_planner::Response>,
+    ) -> String {
+        obj.with_mut(|response| match &response.content {
+            Some(QueryPlannerContent::Plan { plan }) => plan
+                .formatted_query_plan
+                .as_deref()
+                .cloned()
+                .unwrap_or_default(),
+            _ => String::new(),
+        })
+    }
 }
 
 #[derive(Default)]
@@ -1687,7 +1764,14 @@ impl Rhai {
         Ok(())
     }
 
-    pub(super) fn new_rhai_engine(path: Option<PathBuf>, sdl: String, main: PathBuf) -> Engine {
+    pub(super) fn new_rhai_engine(
+        path: Option<PathBuf>,
+        sdl: String,
+        main: PathBuf,
+        http_client: &Arc<HttpClient>,
+        kv_store: &Arc<KvStore>,
+        instruments: &Arc<Instruments>,
+    ) -> Engine {
         let mut engine = Engine::new();
         // If we pass in a path, use it to configure our engine
         // with a FileModuleResolver which allows import to work
@@ -1738,6 +1822,13 @@ impl Rhai {
             // Register our expansion module (not global)
             // Hide the fact that it is an expansion module by calling it "env"
             .register_static_module("env", expansion_module.into())
+            // Register our http client module (not global)
+            .register_static_module("http", http_client.module().into())
+            // Register our key-value store module (not global)
+            .register_static_module("kv", kv_store.module().into())
+            // Register our metrics and span modules (not global)
+            .register_static_module("metrics", instruments.module().into())
+            .register_static_module("span", span_module().into())
             // Register HeaderMap as an iterator so we can loop over contents
             .register_iterator::<HeaderMap>()
             // Register a series of logging functions
@@ -0,0 +1,353 @@
+//! Outbound HTTP calls from Rhai scripts.
+
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use http::header::HeaderName;
+use http::HeaderValue;
+use http::Method;
+use http::Uri;
+use hyper::client::HttpConnector;
+use hyper_rustls::ConfigBuilderExt;
+use hyper_rustls::HttpsConnector;
+use lru::LruCache;
+use rhai::Dynamic;
+use rhai::EvalAltResult;
+use rhai::Map;
+use rhai::Module;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tokio::runtime::Handle;
+use tokio::runtime::RuntimeFlavor;
+use tokio::sync::Semaphore;
+use tower::BoxError;
+
+use crate::services::trust_dns_connector::new_async_http_connector;
+use crate::services::trust_dns_connector::AsyncHyperResolver;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_CACHE_CAPACITY: usize = 512;
+const DEFAULT_MAX_CONCURRENT_CALLS: usize = 64;
+
+/// Outbound HTTP calls from Rhai scripts
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct HttpConf {
+    /// The hosts that scripts can call, calls to other hosts fail. A `*.` prefix allows all the
+    /// subdomains of a domain. No calls are allowed by default
+    allowed_hosts: Vec<String>,
+    /// The timeout of each call (default: 1s)
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    timeout: Duration,
+    /// How long successful responses to GET requests are cached. Responses are not cached by default
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "Option<String>")]
+    cache_ttl: Option<Duration>,
+    /// The maximum number of cached responses (default: 512)
+    cache_capacity: NonZeroUsize,
+    /// The maximum number of calls in flight, further calls fail (default: 64). Each call blocks a
+    /// thread while it waits for the response
+    max_concurrent_calls: NonZeroUsize,
+}
+
+impl Default for HttpConf {
+    fn default() -> Self {
+        Self {
+            allowed_hosts: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            cache_ttl: None,
+            cache_capacity: NonZeroUsize::new(DEFAULT_CACHE_CAPACITY).expect("not zero; qed"),
+            max_concurrent_calls: NonZeroUsize::new(DEFAULT_MAX_CONCURRENT_CALLS)
+                .expect("not zero; qed"),
+        }
+    }
+}
+
+struct CachedResponse {
+    expires_at: Instant,
+    response: Map,
+}
+
+/// The HTTP client shared by all the versions of the scripts
+pub(crate) struct HttpClient {
+    // only created when some hosts are allowed
+    client: Option<hyper::Client<HttpsConnector<HttpConnector<AsyncHyperResolver>>>>,
+    allowed_hosts: Vec<String>,
+    timeout: Duration,
+    cache_ttl: Option<Duration>,
+    cache: Mutex<LruCache<String, CachedResponse>>,
+    // bounds the threads blocked by the calls
+    in_flight: Semaphore,
+}
+
+impl HttpClient {
+    pub(crate) fn new(config: HttpConf) -> Result<Self, BoxError> {
+        let client = if config.allowed_hosts.is_empty() {
+            None
+        } else {
+            Some(Self::new_client()?)
+        };
+
+        Ok(Self {
+            client,
+            allowed_hosts: config.allowed_hosts,
+            timeout: config.timeout,
+            cache_ttl: config.cache_ttl,
+            cache: Mutex::new(LruCache::new(config.cache_capacity)),
+            in_flight: Semaphore::new(config.max_concurrent_calls.get()),
+        })
+    }
+
+    fn new_client(
+    ) -> Result<hyper::Client<HttpsConnector<HttpConnector<AsyncHyperResolver>>>, BoxError> {
+        let mut http_connector = new_async_http_connector()?;
+        http_connector.set_nodelay(true);
+        http_connector.enforce_http(false);
+
+        let tls_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_native_roots()
+            .with_no_client_auth();
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_tls_config(tls_config)
+            .https_or_http()
+            .enable_http1()
+            .enable_http2()
+            .wrap_connector(http_connector);
+
+        Ok(hyper::Client::builder().build(connector))
+    }
+
+    /// The `http` module, with the functions calling this client
+    pub(crate) fn module(self: &Arc<Self>) -> Module {
+        let mut module = Module::new();
+
+        let client = self.clone();
+        module.set_native_fn("get", move |url: &str| {
+            client.call(Method::GET, url, Map::new(), None)
+        });
+        let client = self.clone();
+        module.set_native_fn("get", move |url: &str, headers: Map| {
+            client.call(Method::GET, url, headers, None)
+        });
+        let client = self.clone();
+        module.set_native_fn("post", move |url: &str, body: &str| {
+            client.call(Method::POST, url, Map::new(), Some(body.to_string()))
+        });
+        let client = self.clone();
+        module.set_native_fn("post", move |url: &str, body: &str, headers: Map| {
+            client.call(Method::POST, url, headers, Some(body.to_string()))
+        });
+
+        module
+    }
+
+    /// Calls a service and returns its response as a map with the `status`, `headers` and `body`
+    /// properties. Errors, timeouts and the calls over the in-flight limit are thrown in the script.
+    ///
+    /// Rhai functions are synchronous, so the calling thread blocks until the response is read.
+    fn call(
+        &self,
+        method: Method,
+        url: &str,
+        headers: Map,
+        body: Option<String>,
+    ) -> Result<Map, Box<EvalAltResult>> {
+        let uri: Uri = url
+            .parse()
+            .map_err(|error| format!("invalid url '{url}': {error}"))?;
+        self.check_allowed(&uri)?;
+
+        let mut request = http::Request::builder().method(method.clone()).uri(uri);
+        let mut cache_key =
+            (method == Method::GET && self.cache_ttl.is_some()).then(|| url.to_string());
+        let mut headers: Vec<(String, String)> = headers
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        headers.sort();
+        for (name, value) in headers {
+            if let Some(key) = &mut cache_key {
+                key.push_str(&format!("\n{name}: {value}"));
+            }
+            let name = HeaderName::try_from(name.as_str())
+                .map_err(|error| format!("invalid header name '{name}': {error}"))?;
+            let value = HeaderValue::try_from(value.as_str())
+                .map_err(|error| format!("invalid header value '{value}': {error}"))?;
+            request = request.header(name, value);
+        }
+        let request = request
+            .body(
+                body.map(hyper::Body::from)
+                    .unwrap_or_else(hyper::Body::empty),
+            )
+            .map_err(|error| error.to_string())?;
+
+        if let Some(key) = &cache_key {
+            // expired entries are replaced by the next successful response
+            let cached = self
+                .cache
+                .lock()
+                .expect("poisoned mutex")
+                .get(key)
+                .filter(|cached| cached.expires_at > Instant::now())
+                .map(|cached| cached.response.clone());
+            if let Some(response) = cached {
+                return Ok(response);
+            }
+        }
+
+        let client = self
+            .client
+            .clone()
+            .ok_or("the rhai http client is not configured")?;
+        // the permit is held until the response is read
+        let _permit = self.in_flight.try_acquire().map_err(|_| {
+            format!("http call to '{url}' failed: too many calls in flight from the rhai scripts")
+        })?;
+        let timeout = self.timeout;
+        let response = block_on(async move {
+            tokio::time::timeout(timeout, async move {
+                let response = client.request(request).await?;
+                let (parts, body) = response.into_parts();
+                let body = hyper::body::to_bytes(body).await?;
+                Ok::<_, BoxError>((parts, body))
+            })
+            .await
+            .map_err(|_| BoxError::from(format!("request timed out after {timeout:?}")))?
+        })
+        .and_then(|result| result)
+        .map_err(|error| format!("http call to '{url}' failed: {error}"))?;
+
+        let (parts, body) = response;
+        let mut response_headers = Map::new();
+        for (name, value) in &parts.headers {
+            let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+            response_headers
+                .entry(name.as_str().into())
+                .and_modify(|existing: &mut Dynamic| {
+                    *existing = format!("{existing}, {value}").into();
+                })
+                .or_insert_with(|| value.clone().into());
+        }
+        let mut response = Map::new();
+        response.insert("status".into(), (parts.status.as_u16() as i64).into());
+        response.insert("headers".into(), response_headers.into());
+        response.insert(
+            "body".into(),
+            String::from_utf8_lossy(&body).to_string().into(),
+        );
+
+        if let (Some(key), Some(ttl)) = (cache_key, self.cache_ttl) {
+            if parts.status.is_success() {
+                self.cache.lock().expect("poisoned mutex").put(
+                    key,
+                    CachedResponse {
+                        expires_at: Instant::now() + ttl,
+                        response: response.clone(),
+                    },
+                );
+            }
+        }
+
+        Ok(response)
+    }
+
+    fn check_allowed(&self, uri: &Uri) -> Result<(), Box<EvalAltResult>> {
+        if !matches!(uri.scheme_str(), Some("http") | Some("https")) {
+            return Err(format!("unsupported url scheme in '{uri}'").into());
+        }
+        let host = uri.host().unwrap_or_default();
+        let allowed = self
+            .allowed_hosts
+            .iter()
+            .any(|allowed| match allowed.strip_prefix("*.") {
+                Some(domain) => host
+                    .strip_suffix(domain)
+                    .map_or(false, |subdomain| subdomain.ends_with('.')),
+                None => allowed == host,
+            });
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!("host '{host}' is not in the allowed hosts of the rhai http client").into())
+        }
+    }
+}
+
+/// Waits for a future from the synchronous Rhai callbacks.
+///
+/// On the multi threaded runtime, the worker thread hands its other tasks over while it waits.
+/// A current thread runtime cannot drive the future while its only thread is blocked, so the
+/// future runs on a separate thread instead.
+pub(super) fn block_on<F>(future: F) -> Result<F::Output, BoxError>
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            Ok(tokio::task::block_in_place(move || handle.block_on(future)))
+        }
+        _ => std::thread::scope(|scope| {
+            scope
+                .spawn(move || {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map(|runtime| runtime.block_on(future))
+                })
+                .join()
+                .map_err(|_| BoxError::from("rhai blocking call panicked"))?
+                .map_err(BoxError::from)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(allowed_hosts: &[&str]) -> HttpClient {
+        HttpClient::new(HttpConf {
+            allowed_hosts: allowed_hosts.iter().map(|host| host.to_string()).collect(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn allowed_hosts() {
+        let client = client(&["flags.example.com", "*.internal.example.com"]);
+        let allowed = |url: &str| client.check_allowed(&url.parse().unwrap()).is_ok();
+
+        assert!(allowed("https://flags.example.com/flags"));
+        assert!(allowed("http://a.internal.example.com:8080/"));
+        assert!(allowed("http://a.b.internal.example.com/"));
+        assert!(!allowed("http://internal.example.com/"));
+        assert!(!allowed("http://evilinternal.example.com/"));
+        assert!(!allowed("https://example.com/"));
+        assert!(!allowed("ftp://flags.example.com/"));
+    }
+
+    #[tokio::test]
+    async fn calls_over_the_in_flight_limit_fail() {
+        let client = HttpClient::new(HttpConf {
+            allowed_hosts: vec!["127.0.0.1".to_string()],
+            max_concurrent_calls: NonZeroUsize::new(1).unwrap(),
+            ..Default::default()
+        })
+        .unwrap();
+        let _permit = client.in_flight.try_acquire().unwrap();
+
+        let error = client
+            .call(Method::GET, "http://127.0.0.1/", Map::new(), None)
+            .unwrap_err();
+        assert!(error.to_string().contains("too many calls in flight"));
+    }
+}
@@ -0,0 +1,303 @@
+//! Key-value store shared by the Rhai scripts across requests.
+
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use dashmap::DashMap;
+use rhai::Dynamic;
+use rhai::EvalAltResult;
+use rhai::Module;
+use rhai::INT;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+
+use super::http_client::block_on;
+use crate::cache::redis::RedisCacheStorage;
+use crate::cache::redis::RedisKey;
+use crate::cache::redis::RedisValue;
+use crate::configuration::RedisCache;
+
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Key-value store shared by the scripts across requests
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct KvConf {
+    /// The maximum number of keys in the in-memory store (default: 10000)
+    capacity: NonZeroUsize,
+    /// Stores the values in Redis instead of the router's memory, so that they are shared by all
+    /// the router instances
+    redis: Option<RedisCache>,
+}
+
+impl Default for KvConf {
+    fn default() -> Self {
+        Self {
+            capacity: NonZeroUsize::new(DEFAULT_CAPACITY).expect("not zero; qed"),
+            redis: None,
+        }
+    }
+}
+
+struct Entry {
+    value: serde_json::Value,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at
+            .map_or(false, |expires_at| expires_at <= now)
+    }
+}
+
+enum Backend {
+    Memory {
+        entries: DashMap<String, Entry>,
+        capacity: usize,
+    },
+    Redis(RedisCacheStorage),
+}
+
+/// The store shared by all the versions of the scripts
+pub(crate) struct KvStore {
+    backend: Backend,
+}
+
+impl KvStore {
+    pub(crate) async fn new(config: KvConf) -> Result<Self, BoxError> {
+        let backend = match config.redis {
+            Some(redis) => Backend::Redis(RedisCacheStorage::new(redis).await?),
+            None => Backend::Memory {
+                entries: DashMap::new(),
+                capacity: config.capacity.get(),
+            },
+        };
+        Ok(Self { backend })
+    }
+
+    /// The `kv` module, with the functions accessing this store
+    pub(crate) fn module(self: &Arc<Self>) -> Module {
+        let mut module = Module::new();
+
+        let store = self.clone();
+        module.set_native_fn("get", move |key: &str| store.get(key));
+        let store = self.clone();
+        module.set_native_fn("set", move |key: &str, value: Dynamic| {
+            store.set(key, value, None)
+        });
+        let store = self.clone();
+        module.set_native_fn("set", move |key: &str, value: Dynamic, ttl: INT| {
+            store.set(key, value, Some(ttl_seconds(ttl)?))
+        });
+        let store = self.clone();
+        module.set_native_fn("incr", move |key: &str| store.incr(key, 1, None));
+        let store = self.clone();
+        module.set_native_fn("incr", move |key: &str, delta: INT| {
+            store.incr(key, delta, None)
+        });
+        let store = self.clone();
+        module.set_native_fn("incr", move |key: &str, delta: INT, ttl: INT| {
+            store.incr(key, delta, Some(ttl_seconds(ttl)?))
+        });
+        let store = self.clone();
+        module.set_native_fn("remove", move |key: &str| store.remove(key));
+
+        module
+    }
+
+    /// Returns the value of a key, or `()` if it is not set
+    fn get(&self, key: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+        let value = match &self.backend {
+            Backend::Memory { entries, .. } => entries
+                .get(key)
+                .filter(|entry| !entry.is_expired(Instant::now()))
+                .map(|entry| entry.value.clone()),
+            Backend::Redis(storage) => {
+                let key = RedisKey(key.to_string());
+                block_on(storage.get::<String, serde_json::Value>(key))
+                    .map_err(|error| format!("kv store error: {error}"))?
+                    .map(|value| value.0)
+            }
+        };
+        match value {
+            Some(value) => rhai::serde::to_dynamic(value),
+            None => Ok(Dynamic::UNIT),
+        }
+    }
+
+    /// Sets the value of a key, replacing its previous value and expiration
+    fn set(
+        &self,
+        key: &str,
+        value: Dynamic,
+        ttl: Option<Duration>,
+    ) -> Result<(), Box<EvalAltResult>> {
+        let value: serde_json::Value = rhai::serde::from_dynamic(&value)?;
+        match &self.backend {
+            Backend::Memory { entries, capacity } => {
+                make_room(entries, *capacity, key)?;
+                entries.insert(
+                    key.to_string(),
+                    Entry {
+                        value,
+                        expires_at: ttl.map(|ttl| Instant::now() + ttl),
+                    },
+                );
+            }
+            Backend::Redis(storage) => {
+                let key = RedisKey(key.to_string());
+                block_on(storage.insert(key, RedisValue(value), ttl))
+                    .map_err(|error| format!("kv store error: {error}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Atomically adds `delta` to an integer value, starting from 0 if the key is not set.
+    /// The expiration is only set when the key does not have one yet.
+    fn incr(
+        &self,
+        key: &str,
+        delta: INT,
+        ttl: Option<Duration>,
+    ) -> Result<INT, Box<EvalAltResult>> {
+        match &self.backend {
+            Backend::Memory { entries, capacity } => {
+                make_room(entries, *capacity, key)?;
+                let now = Instant::now();
+                let mut entry = entries.entry(key.to_string()).or_insert(Entry {
+                    value: 0.into(),
+                    expires_at: None,
+                });
+                if entry.is_expired(now) {
+                    entry.value = 0.into();
+                    entry.expires_at = None;
+                }
+                let value = entry
+                    .value
+                    .as_i64()
+                    .ok_or_else(|| format!("the value of '{key}' is not an integer"))?
+                    .checked_add(delta)
+                    .ok_or_else(|| format!("the value of '{key}' overflowed"))?;
+                entry.value = value.into();
+                if entry.expires_at.is_none() {
+                    entry.expires_at = ttl.map(|ttl| now + ttl);
+                }
+                Ok(value)
+            }
+            Backend::Redis(storage) => {
+                let key = RedisKey(key.to_string());
+                block_on(storage.incr(key, delta, ttl))
+                    .map_err(|error| format!("kv store error: {error}"))?
+                    .map_err(|error| format!("kv store error: {error}").into())
+            }
+        }
+    }
+
+    /// Removes a key
+    fn remove(&self, key: &str) -> Result<(), Box<EvalAltResult>> {
+        match &self.backend {
+            Backend::Memory { entries, .. } => {
+                entries.remove(key);
+            }
+            Backend::Redis(storage) => {
+                let key = RedisKey(key.to_string());
+                block_on(storage.delete(vec![key]))
+                    .map_err(|error| format!("kv store error: {error}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Purges the expired entries when a new key would exceed the capacity of the store
+fn make_room(
+    entries: &DashMap<String, Entry>,
+    capacity: usize,
+    key: &str,
+) -> Result<(), Box<EvalAltResult>> {
+    if entries.len() < capacity || entries.contains_key(key) {
+        return Ok(());
+    }
+    let now = Instant::now();
+    entries.retain(|_, entry| !entry.is_expired(now));
+    if entries.len() < capacity {
+        Ok(())
+    } else {
+        Err(format!("the kv store is full ({capacity} keys)").into())
+    }
+}
+
+fn ttl_seconds(ttl: INT) -> Result<Duration, Box<EvalAltResult>> {
+    u64::try_from(ttl)
+        .ok()
+        .filter(|ttl| *ttl > 0)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("invalid ttl {ttl}, it must be a positive number of seconds").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn store(capacity: usize) -> Arc<KvStore> {
+        Arc::new(
+            KvStore::new(KvConf {
+                capacity: NonZeroUsize::new(capacity).unwrap(),
+                redis: None,
+            })
+            .await
+            .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn memory_store() {
+        let store = store(2).await;
+        let mut engine = rhai::Engine::new();
+        engine.register_static_module("kv", store.module().into());
+
+        let result: rhai::Map = engine
+            .eval(
+                r#"
+                kv::set("flags", #{ checkout: true });
+                kv::incr("quota:alice");
+                #{
+                    flags: kv::get("flags"),
+                    quota: kv::incr("quota:alice", 2, 60),
+                    missing: kv::get("missing"),
+                }
+                "#,
+            )
+            .unwrap();
+        assert_eq!(result["quota"].as_int().unwrap(), 3);
+        assert!(result["missing"].is_unit());
+        let flags = result["flags"].clone().try_cast::<rhai::Map>().unwrap();
+        assert!(flags["checkout"].as_bool().unwrap());
+
+        // the store is full
+        assert!(engine.run(r#"kv::set("other", 1)"#).is_err());
+        assert!(engine.run(r#"kv::incr("flags")"#).is_err());
+        assert!(engine.run(r#"kv::set("flags", 1, 0)"#).is_err());
+        engine
+            .run(r#"kv::remove("flags"); kv::set("other", 1, 60);"#)
+            .unwrap();
+        assert_eq!(engine.eval::<INT>(r#"kv::get("other")"#).unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn expired_entries() {
+        let store = store(1).await;
+        store
+            .set("counter", Dynamic::from_int(41), Some(Duration::ZERO))
+            .unwrap();
+        assert!(store.get("counter").unwrap().is_unit());
+        // the expired entry is replaced
+        assert_eq!(store.incr("counter", 1, None).unwrap(), 1);
+        assert_eq!(store.incr("counter", 1, None).unwrap(), 2);
+    }
+}
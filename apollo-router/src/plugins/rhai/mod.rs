@@ -40,21 +40,30 @@ use tower::ServiceExt;
 
 use self::engine::RhaiService;
 use self::engine::SharedMut;
+use self::http_client::HttpClient;
+use self::http_client::HttpConf;
+use self::kv_store::KvConf;
+use self::kv_store::KvStore;
+use self::telemetry::Instruments;
 use crate::error::Error;
 use crate::layers::ServiceBuilderExt;
-use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugin::PluginPrivate;
 use crate::plugins::rhai::engine::OptionDance;
-use crate::register_plugin;
+use crate::register_private_plugin;
 
 mod engine;
 
 pub(crate) const RHAI_SPAN_NAME: &str = "rhai_plugin";
 
 mod execution;
+mod http_client;
+mod kv_store;
+mod query_planner;
 mod router;
 mod subgraph;
 mod supergraph;
+mod telemetry;
 
 struct EngineBlock {
     ast: AST,
@@ -67,11 +76,17 @@ impl EngineBlock {
         scripts: Option<PathBuf>,
         main: PathBuf,
         sdl: Arc<String>,
+        http_client: &Arc<HttpClient>,
+        kv_store: &Arc<KvStore>,
+        instruments: &Arc<Instruments>,
     ) -> Result<Self, BoxError> {
         let engine = Arc::new(Rhai::new_rhai_engine(
             scripts,
             sdl.to_string(),
             main.clone(),
+            http_client,
+            kv_store,
+            instruments,
         ));
         let ast = engine
             .compile_file(main.clone())
@@ -101,7 +116,7 @@ impl EngineBlock {
 /// the engine block will be infrequent in relation to the accesses of it.
 /// We'd love to use AtomicArc if such a thing existed, but since it doesn't
 /// we'll use ArcSwap to accomplish our goal.
-struct Rhai {
+pub(crate) struct Rhai {
     block: Arc<ArcSwap<EngineBlock>>,
     park_flag: Arc<AtomicBool>,
     watcher_handle: Option<std::thread::JoinHandle<()>>,
@@ -115,10 +130,16 @@ pub(crate) struct Conf {
     scripts: Option<PathBuf>,
     /// The main entry point for Rhai script evaluation
     main: Option<String>,
+    /// Outbound HTTP calls from scripts
+    #[serde(default)]
+    http: HttpConf,
+    /// Key-value store shared by the scripts across requests
+    #[serde(default)]
+    kv: KvConf,
 }
 
 #[async_trait::async_trait]
-impl Plugin for Rhai {
+impl PluginPrivate for Rhai {
     type Config = Conf;
 
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
@@ -139,10 +160,21 @@ impl Plugin for Rhai {
         let watched_main = main.clone();
         let watched_sdl = sdl.clone();
 
+        // the client, its cache, the store and the instruments are kept when the scripts are reloaded
+        let http_client = Arc::new(HttpClient::new(init.config.http)?);
+        let watched_http_client = http_client.clone();
+        let kv_store = Arc::new(KvStore::new(init.config.kv).await?);
+        let watched_kv_store = kv_store.clone();
+        let instruments = Arc::new(Instruments::default());
+        let watched_instruments = instruments.clone();
+
         let block = Arc::new(ArcSwap::from_pointee(EngineBlock::try_new(
             Some(scripts_path),
             main,
             sdl,
+            &http_client,
+            &kv_store,
+            &instruments,
         )?));
         let watched_block = block.clone();
 
@@ -182,6 +214,9 @@ impl Plugin for Rhai {
                                         Some(watching_path.clone()),
                                         watched_main.clone(),
                                         watched_sdl.clone(),
+                                        &watched_http_client,
+                                        &watched_kv_store,
+                                        &watched_instruments,
                                     ) {
                                         Ok(eb) => {
                                             tracing::info!("updating rhai execution engine");
@@ -293,6 +328,27 @@ impl Plugin for Rhai {
         }
         shared_service.take_unwrap()
     }
+
+    fn query_planner_service(
+        &self,
+        service: query_planner::BoxService,
+    ) -> query_planner::BoxService {
+        const FUNCTION_NAME_SERVICE: &str = "query_planner_service";
+        if !self.ast_has_function(FUNCTION_NAME_SERVICE) {
+            return service;
+        }
+        tracing::debug!("query_planner_service function found");
+        let shared_service = Arc::new(Mutex::new(Some(service)));
+        if let Err(error) = self.run_rhai_service(
+            FUNCTION_NAME_SERVICE,
+            None,
+            ServiceStep::QueryPlanner(shared_service.clone()),
+            self.block.load().scope.clone(),
+        ) {
+            tracing::error!("service callback failed: {error}");
+        }
+        shared_service.take_unwrap()
+    }
 }
 
 impl Drop for Rhai {
@@ -311,6 +367,7 @@ pub(crate) enum ServiceStep {
     Supergraph(SharedMut<supergraph::BoxService>),
     Execution(SharedMut<execution::BoxService>),
     Subgraph(SharedMut<subgraph::BoxService>),
+    QueryPlanner(SharedMut<query_planner::BoxService>),
 }
 
 // Actually use the checkpoint function so that we can shortcut requests which fail
@@ -717,6 +774,9 @@ impl ServiceStep {
             ServiceStep::Subgraph(service) => {
                 gen_map_request!(subgraph, service, rhai_service, callback);
             }
+            ServiceStep::QueryPlanner(service) => {
+                gen_map_request!(query_planner, service, rhai_service, callback);
+            }
         }
     }
 
@@ -734,6 +794,9 @@ impl ServiceStep {
             ServiceStep::Subgraph(service) => {
                 gen_map_response!(subgraph, service, rhai_service, callback);
             }
+            ServiceStep::QueryPlanner(service) => {
+                gen_map_response!(query_planner, service, rhai_service, callback);
+            }
         }
     }
 }
@@ -815,14 +878,19 @@ fn execute(
     if callback.is_curried() {
         callback.call(&rhai_service.engine, &rhai_service.ast, args)
     } else {
-        let mut guard = rhai_service.scope.lock().unwrap();
+        // the scope is only locked while it is copied: the callbacks can wait on the `http` and
+        // `kv` functions, which must not hold back the callbacks of the other requests. The
+        // writes of a callback to the copy are dropped, which is what the callbacks on the shared
+        // scope did too: the function calls rewind the scope, and the top level statements that
+        // run before them only update the variables they declare again.
+        let mut scope = rhai_service.scope.lock().unwrap().clone();
         rhai_service
             .engine
-            .call_fn(&mut guard, &rhai_service.ast, callback.fn_name(), args)
+            .call_fn(&mut scope, &rhai_service.ast, callback.fn_name(), args)
     }
 }
 
-register_plugin!("apollo", "rhai", Rhai);
+register_private_plugin!("apollo", "rhai", Rhai);
 
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,34 @@
+//! query_planner module
+
+use std::ops::ControlFlow;
+
+use tower::BoxError;
+
+use super::ErrorDetails;
+use crate::graphql::Error;
+pub(crate) use crate::services::query_planner::CachingBoxService as BoxService;
+pub(crate) use crate::services::query_planner::CachingRequest as Request;
+pub(crate) use crate::services::query_planner::Response;
+use crate::Context;
+
+pub(super) fn request_failure(
+    context: Context,
+    error_details: ErrorDetails,
+) -> Result<ControlFlow<Response, Request>, BoxError> {
+    Ok(ControlFlow::Break(response_failure(context, error_details)))
+}
+
+pub(super) fn response_failure(context: Context, error_details: ErrorDetails) -> Response {
+    let errors = match error_details.body {
+        Some(body) if !body.errors.is_empty() => body.errors,
+        _ => vec![Error {
+            message: error_details.message.unwrap_or_default(),
+            ..Default::default()
+        }],
+    };
+    Response::builder()
+        .errors(errors)
+        .context(context)
+        .status_code(error_details.status)
+        .build()
+}
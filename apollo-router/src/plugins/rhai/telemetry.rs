@@ -0,0 +1,377 @@
+//! Custom metrics and span data from Rhai scripts.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+
+use opentelemetry::metrics::Counter;
+use opentelemetry::metrics::Histogram;
+use opentelemetry::metrics::ObservableGauge;
+use opentelemetry::Key;
+use opentelemetry::KeyValue;
+use opentelemetry::Value;
+use rhai::Dynamic;
+use rhai::EvalAltResult;
+use rhai::Map;
+use rhai::Module;
+use tracing::Span;
+
+use crate::metrics::aggregation::InstrumentWrapper;
+use crate::metrics::meter_provider;
+use crate::plugins::telemetry::dynamic_attribute::SpanDynAttribute;
+use crate::plugins::telemetry::otel::OpenTelemetrySpanExt;
+
+const METER_NAME: &str = "apollo/router";
+/// The number of attribute sets an instrument reports, which bounds the cardinality of the
+/// script metrics. Gauges also keep a value for each of them, as they are reported until replaced
+const MAX_SERIES: usize = 1000;
+
+type GaugeValues = Mutex<HashMap<String, (Vec<KeyValue>, f64)>>;
+
+struct Recorded<T> {
+    instrument: Weak<T>,
+    // keyed by the sorted attributes, since the instrument was created
+    series: HashSet<String>,
+}
+
+struct Gauge {
+    // keyed by the sorted attributes
+    values: Arc<GaugeValues>,
+    instrument: Weak<ObservableGauge<f64>>,
+}
+
+/// The instruments created by the scripts, kept when the scripts are reloaded.
+///
+/// Like the instruments of the `metric!` macros, they are registered with the meter provider,
+/// which drops them when the telemetry configuration changes, and they are created again on the
+/// next call.
+#[derive(Default)]
+pub(crate) struct Instruments {
+    counters: Mutex<HashMap<String, Recorded<Counter<f64>>>>,
+    histograms: Mutex<HashMap<String, Recorded<Histogram<f64>>>>,
+    gauges: Mutex<HashMap<String, Gauge>>,
+}
+
+impl Instruments {
+    /// The `metrics` module, with the functions recording measurements on these instruments
+    pub(crate) fn module(self: &Arc<Self>) -> Module {
+        let mut module = Module::new();
+
+        let instruments = self.clone();
+        module.set_native_fn("counter_add", move |name: &str, value: Dynamic| {
+            instruments.counter_add(name, value, Map::new())
+        });
+        let instruments = self.clone();
+        module.set_native_fn(
+            "counter_add",
+            move |name: &str, value: Dynamic, attributes: Map| {
+                instruments.counter_add(name, value, attributes)
+            },
+        );
+        let instruments = self.clone();
+        module.set_native_fn("histogram_record", move |name: &str, value: Dynamic| {
+            instruments.histogram_record(name, value, Map::new())
+        });
+        let instruments = self.clone();
+        module.set_native_fn(
+            "histogram_record",
+            move |name: &str, value: Dynamic, attributes: Map| {
+                instruments.histogram_record(name, value, attributes)
+            },
+        );
+        let instruments = self.clone();
+        module.set_native_fn("gauge_set", move |name: &str, value: Dynamic| {
+            instruments.gauge_set(name, value, Map::new())
+        });
+        let instruments = self.clone();
+        module.set_native_fn(
+            "gauge_set",
+            move |name: &str, value: Dynamic, attributes: Map| {
+                instruments.gauge_set(name, value, attributes)
+            },
+        );
+
+        module
+    }
+
+    fn counter_add(
+        &self,
+        name: &str,
+        value: Dynamic,
+        attributes: Map,
+    ) -> Result<(), Box<EvalAltResult>> {
+        check_name(name)?;
+        let value = to_f64(value)?;
+        if value < 0.0 {
+            return Err(format!("counter '{name}' cannot be decreased").into());
+        }
+        let attributes = to_attributes(attributes);
+        let counter = registered_instrument(&self.counters, name, &attributes, |meter| {
+            meter.f64_counter(name.to_string()).init()
+        })?;
+        counter.add(value, &attributes);
+        Ok(())
+    }
+
+    fn histogram_record(
+        &self,
+        name: &str,
+        value: Dynamic,
+        attributes: Map,
+    ) -> Result<(), Box<EvalAltResult>> {
+        check_name(name)?;
+        let value = to_f64(value)?;
+        let attributes = to_attributes(attributes);
+        let histogram = registered_instrument(&self.histograms, name, &attributes, |meter| {
+            meter.f64_histogram(name.to_string()).init()
+        })?;
+        histogram.record(value, &attributes);
+        Ok(())
+    }
+
+    /// Sets the value reported by a gauge for a set of attributes, until the next call
+    fn gauge_set(
+        &self,
+        name: &str,
+        value: Dynamic,
+        attributes: Map,
+    ) -> Result<(), Box<EvalAltResult>> {
+        check_name(name)?;
+        let value = to_f64(value)?;
+        let attributes = to_attributes(attributes);
+
+        let mut gauges = self.gauges.lock().expect("lock poisoned");
+        let gauge = gauges.entry(name.to_string()).or_insert_with(|| Gauge {
+            values: Default::default(),
+            instrument: Weak::new(),
+        });
+        if gauge.instrument.upgrade().is_none() {
+            // the callback stops reporting when the scripts' instruments are dropped
+            let values = Arc::downgrade(&gauge.values);
+            let instrument = meter_provider().create_registered_instrument(|provider| {
+                let values = values.clone();
+                provider
+                    .meter(METER_NAME)
+                    .f64_observable_gauge(name.to_string())
+                    .with_callback(move |observer| {
+                        if let Some(values) = values.upgrade() {
+                            for (attributes, value) in
+                                values.lock().expect("lock poisoned").values()
+                            {
+                                observer.observe(*value, attributes);
+                            }
+                        }
+                    })
+                    .init()
+            });
+            gauge.instrument = Arc::downgrade(&instrument);
+        }
+        let mut values = gauge.values.lock().expect("lock poisoned");
+        let series = series_key(&attributes);
+        if values.len() >= MAX_SERIES && !values.contains_key(&series) {
+            return Err(too_many_series(name));
+        }
+        values.insert(series, (attributes, value));
+        Ok(())
+    }
+}
+
+/// Returns the instrument, if it can record the attributes
+fn registered_instrument<T>(
+    cache: &Mutex<HashMap<String, Recorded<T>>>,
+    name: &str,
+    attributes: &[KeyValue],
+    create: impl Fn(&opentelemetry::metrics::Meter) -> T,
+) -> Result<Arc<T>, Box<EvalAltResult>>
+where
+    Arc<T>: Into<InstrumentWrapper>,
+{
+    let mut cache = cache.lock().expect("lock poisoned");
+    let recorded = cache.entry(name.to_string()).or_insert_with(|| Recorded {
+        instrument: Weak::new(),
+        series: HashSet::new(),
+    });
+    let instrument = match recorded.instrument.upgrade() {
+        Some(instrument) => instrument,
+        None => {
+            // a new instrument starts without series
+            let instrument = meter_provider()
+                .create_registered_instrument(|provider| create(&provider.meter(METER_NAME)));
+            recorded.instrument = Arc::downgrade(&instrument);
+            recorded.series.clear();
+            instrument
+        }
+    };
+    let series = series_key(attributes);
+    if recorded.series.len() >= MAX_SERIES && !recorded.series.contains(&series) {
+        return Err(too_many_series(name));
+    }
+    recorded.series.insert(series);
+    Ok(instrument)
+}
+
+/// Identifies a set of attributes, whatever their order
+fn series_key(attributes: &[KeyValue]) -> String {
+    let mut attributes: Vec<_> = attributes.iter().collect();
+    attributes.sort_by(|a, b| a.key.as_str().cmp(b.key.as_str()));
+    format!("{attributes:?}")
+}
+
+fn too_many_series(name: &str) -> Box<EvalAltResult> {
+    format!("metric '{name}' cannot report more than {MAX_SERIES} sets of attributes").into()
+}
+
+/// The `span` module, with the functions adding data to the current span
+pub(crate) fn span_module() -> Module {
+    let mut module = Module::new();
+
+    module.set_native_fn("set_attribute", |key: &str, value: Dynamic| {
+        Span::current().set_span_dyn_attribute(Key::new(key.to_string()), to_value(value));
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    module.set_native_fn("add_event", |name: &str| {
+        Span::current().add_event(name.to_string(), Vec::new());
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+    module.set_native_fn("add_event", |name: &str, attributes: Map| {
+        Span::current().add_event(name.to_string(), to_attributes(attributes));
+        Ok::<_, Box<EvalAltResult>>(())
+    });
+
+    module
+}
+
+// the scripts cannot report the router's own metrics
+fn check_name(name: &str) -> Result<(), Box<EvalAltResult>> {
+    if name.is_empty() || name.starts_with("apollo.") || name.starts_with("apollo_") {
+        Err(format!("invalid metric name '{name}'").into())
+    } else {
+        Ok(())
+    }
+}
+
+fn to_f64(value: Dynamic) -> Result<f64, Box<EvalAltResult>> {
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|value| value as f64))
+        .map_err(|type_name| format!("expected a number, found {type_name}").into())
+}
+
+fn to_attributes(attributes: Map) -> Vec<KeyValue> {
+    attributes
+        .into_iter()
+        .map(|(key, value)| KeyValue::new(key.to_string(), to_value(value)))
+        .collect()
+}
+
+fn to_value(value: Dynamic) -> Value {
+    if let Ok(value) = value.as_bool() {
+        value.into()
+    } else if let Ok(value) = value.as_int() {
+        value.into()
+    } else if let Ok(value) = value.as_float() {
+        value.into()
+    } else {
+        value.to_string().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> rhai::Engine {
+        let mut engine = rhai::Engine::new();
+        engine.register_static_module("metrics", Arc::new(Instruments::default()).module().into());
+        engine
+    }
+
+    #[test]
+    fn custom_metrics() {
+        let engine = engine();
+        engine
+            .run(
+                r#"
+                metrics::counter_add("checkout.count", 1, #{ currency: "EUR" });
+                metrics::counter_add("checkout.count", 2.5, #{ currency: "EUR" });
+                metrics::histogram_record("cart.size", 3);
+                metrics::gauge_set("queue.depth", 10, #{ queue: "orders" });
+                metrics::gauge_set("queue.depth", 4, #{ queue: "orders" });
+                "#,
+            )
+            .unwrap();
+
+        assert_counter!("checkout.count", 3.5, "currency" = "EUR");
+        assert_histogram_sum!("cart.size", 3);
+        assert_gauge!("queue.depth", 4, "queue" = "orders");
+    }
+
+    #[test]
+    fn invalid_measurements() {
+        let engine = engine();
+        assert!(engine.run(r#"metrics::counter_add("count", -1)"#).is_err());
+        assert!(engine.run(r#"metrics::counter_add("count", "1")"#).is_err());
+        assert!(engine
+            .run(r#"metrics::histogram_record("apollo.router.operations", 1)"#)
+            .is_err());
+    }
+
+    #[test]
+    fn gauge_series_are_capped() {
+        let engine = engine();
+        engine
+            .run(&format!(
+                r#"
+                for i in 0..{MAX_SERIES} {{
+                    metrics::gauge_set("connections", i, #{{ client: i.to_string() }});
+                }}
+                "#
+            ))
+            .unwrap();
+        // already reported attributes can still be updated
+        engine
+            .run(r#"metrics::gauge_set("connections", 10, #{ client: "0" })"#)
+            .unwrap();
+        assert!(engine
+            .run(&format!(
+                r#"metrics::gauge_set("connections", 1, #{{ client: "{MAX_SERIES}" }})"#
+            ))
+            .is_err());
+        assert_gauge!("connections", 10, "client" = "0");
+    }
+
+    #[test]
+    fn counter_and_histogram_series_are_capped() {
+        let engine = engine();
+        engine
+            .run(&format!(
+                r#"
+                for i in 0..{MAX_SERIES} {{
+                    metrics::counter_add("requests", 1, #{{ client: i.to_string() }});
+                    metrics::histogram_record("latency", 1, #{{ client: i.to_string() }});
+                }}
+                "#
+            ))
+            .unwrap();
+        // already recorded attributes can still be recorded
+        engine
+            .run(
+                r#"
+                metrics::counter_add("requests", 1, #{ client: "0" });
+                metrics::histogram_record("latency", 1, #{ client: "0" });
+                "#,
+            )
+            .unwrap();
+        for function in ["counter_add(\"requests\"", "histogram_record(\"latency\""] {
+            assert!(engine
+                .run(&format!(
+                    r#"metrics::{function}, 1, #{{ client: "{MAX_SERIES}" }})"#
+                ))
+                .is_err());
+        }
+        assert_counter!("requests", 2, "client" = "0");
+        assert_histogram_sum!("latency", 2, "client" = "0");
+    }
+}
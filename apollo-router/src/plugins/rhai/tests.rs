@@ -1,6 +1,8 @@
 //! Rhai module tests.
 
 use std::str::FromStr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::SystemTime;
@@ -18,8 +20,11 @@ use tower::Service;
 use tower::ServiceExt;
 use uuid::Uuid;
 
+use super::http_client::HttpClient;
+use super::kv_store::KvStore;
 use super::process_error;
 use super::subgraph;
+use super::telemetry::Instruments;
 use super::PathBuf;
 use super::Rhai;
 use crate::graphql;
@@ -35,6 +40,7 @@ use crate::plugins::rhai::engine::RhaiRouterChunkedResponse;
 use crate::plugins::rhai::engine::RhaiRouterResponse;
 use crate::plugins::rhai::engine::RhaiSupergraphDeferredResponse;
 use crate::plugins::rhai::engine::RhaiSupergraphResponse;
+use crate::services::query_planner;
 use crate::services::ExecutionRequest;
 use crate::services::SubgraphRequest;
 use crate::services::SupergraphRequest;
@@ -167,6 +173,57 @@ async fn rhai_plugin_supergraph_service() -> Result<(), BoxError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn rhai_plugin_query_planner_service() -> Result<(), BoxError> {
+    let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+        .find(|factory| factory.name == "apollo.rhai")
+        .expect("Plugin not found")
+        .create_instance_without_schema(
+            &Value::from_str(r#"{"scripts":"tests/fixtures", "main":"test.rhai"}"#).unwrap(),
+        )
+        .await
+        .unwrap();
+    let planner = BoxService::new(tower::service_fn(
+        |request: query_planner::CachingRequest| async move {
+            Ok::<_, BoxError>(
+                query_planner::Response::builder()
+                    .context(request.context)
+                    .build(),
+            )
+        },
+    ));
+    let mut query_planner_service = dyn_plugin.query_planner_service(planner);
+
+    let request = query_planner::CachingRequest::builder()
+        .query("query Me { me { name } }")
+        .operation_name("Me")
+        .context(Context::new())
+        .build();
+    let response = query_planner_service.ready().await?.call(request).await?;
+    assert!(response.errors.is_empty());
+    assert_eq!(
+        response
+            .context
+            .get::<_, String>("planned_operation")
+            .unwrap()
+            .unwrap(),
+        "Me"
+    );
+
+    let context = Context::new();
+    context.insert("reject_plan", true).unwrap();
+    let request = query_planner::CachingRequest::builder()
+        .query("query Me { me { name } }")
+        .operation_name("Me")
+        .context(context)
+        .build();
+    let response = query_planner_service.ready().await?.call(request).await?;
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(response.errors[0].message, "query plan rejected");
+    assert_eq!(response.status_code, Some(StatusCode::FORBIDDEN));
+    Ok(())
+}
+
 #[tokio::test]
 async fn rhai_plugin_execution_service_error() -> Result<(), BoxError> {
     let mut mock_service = MockExecutionService::new();
@@ -232,7 +289,19 @@ async fn rhai_plugin_execution_service_error() -> Result<(), BoxError> {
 // A Rhai engine suitable for minimal testing. There are no scripts and the SDL is an empty
 // string.
 fn new_rhai_test_engine() -> Engine {
-    Rhai::new_rhai_engine(None, "".to_string(), PathBuf::new())
+    Rhai::new_rhai_engine(
+        None,
+        "".to_string(),
+        PathBuf::new(),
+        &Arc::new(HttpClient::new(Default::default()).unwrap()),
+        &new_kv_test_store(),
+        &Arc::new(Instruments::default()),
+    )
+}
+
+// The in-memory store is created without awaiting anything.
+fn new_kv_test_store() -> Arc<KvStore> {
+    Arc::new(futures::executor::block_on(KvStore::new(Default::default())).unwrap())
 }
 
 // Some of these tests rely extensively on internal implementation details of the tracing_test crate.
@@ -785,3 +854,168 @@ async fn test_router_service_adds_timestamp_header() -> Result<(), BoxError> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn it_calls_allowed_hosts_over_http() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let server_calls = calls.clone();
+    let make_service = hyper::service::make_service_fn(move |_| {
+        let calls = server_calls.clone();
+        async move {
+            Ok::<_, hyper::Error>(hyper::service::service_fn(
+                move |request: http::Request<hyper::Body>| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    let body = format!("flag for {}", request.uri().path());
+                    async move { Ok::<_, hyper::Error>(http::Response::new(hyper::Body::from(body))) }
+                },
+            ))
+        }
+    });
+    let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+    let address = server.local_addr();
+    tokio::spawn(server);
+
+    let config = serde_json::from_value(serde_json::json!({
+        "allowed_hosts": ["127.0.0.1"],
+        "cache_ttl": "1m"
+    }))
+    .unwrap();
+    let engine = Rhai::new_rhai_engine(
+        None,
+        "".to_string(),
+        PathBuf::new(),
+        &Arc::new(HttpClient::new(config).unwrap()),
+        &new_kv_test_store(),
+        &Arc::new(Instruments::default()),
+    );
+
+    let script = format!(
+        r#"
+        let response = http::get("http://{address}/new-checkout");
+        response.status.to_string() + " " + response.body
+        "#
+    );
+    for _ in 0..2 {
+        let result: String = engine.eval(&script).unwrap();
+        assert_eq!(result, "200 flag for /new-checkout");
+    }
+    // the second response came from the cache
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    let error = engine
+        .eval::<rhai::Map>(r#"http::get("http://example.com/")"#)
+        .unwrap_err();
+    assert!(error.to_string().contains("not in the allowed hosts"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn it_does_not_hold_back_concurrent_http_calls() -> Result<(), BoxError> {
+    // the server only answers once both calls are in flight, so the callbacks must not wait for
+    // each other
+    let barrier = Arc::new(tokio::sync::Barrier::new(2));
+    let make_service = hyper::service::make_service_fn(move |_| {
+        let barrier = barrier.clone();
+        async move {
+            Ok::<_, hyper::Error>(hyper::service::service_fn(
+                move |_: http::Request<hyper::Body>| {
+                    let barrier = barrier.clone();
+                    async move {
+                        barrier.wait().await;
+                        Ok::<_, hyper::Error>(http::Response::new(hyper::Body::from("enabled")))
+                    }
+                },
+            ))
+        }
+    });
+    let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+    let address = server.local_addr();
+    tokio::spawn(server);
+
+    let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+        .find(|factory| factory.name == "apollo.rhai")
+        .expect("Plugin not found")
+        .create_instance_without_schema(&serde_json::json!({
+            "scripts": "tests/fixtures",
+            "main": "http_call.rhai",
+            "http": {
+                "allowed_hosts": ["127.0.0.1"],
+                "timeout": "10s"
+            }
+        }))
+        .await
+        .unwrap();
+
+    let calls = (0..2)
+        .map(|_| {
+            let mut mock_service = MockSupergraphService::new();
+            mock_service
+                .expect_call()
+                .returning(|req: SupergraphRequest| {
+                    Ok(SupergraphResponse::fake_builder()
+                        .context(req.context)
+                        .build()
+                        .unwrap())
+                });
+            let service = dyn_plugin.supergraph_service(BoxService::new(mock_service));
+            let request = SupergraphRequest::fake_builder()
+                .header("x-flag-url", format!("http://{address}/flag"))
+                .build()
+                .unwrap();
+            // the callbacks block their thread, each request gets its own task
+            tokio::spawn(service.oneshot(request))
+        })
+        .collect::<Vec<_>>();
+
+    let responses = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        futures::future::join_all(calls),
+    )
+    .await
+    .expect("the http calls of the callbacks should run concurrently");
+    for response in responses {
+        let response = response??;
+        assert_eq!(
+            response.context.get::<_, String>("flag")?.as_deref(),
+            Some("enabled")
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_does_not_persist_callback_writes_to_the_scope() -> Result<(), BoxError> {
+    let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+        .find(|factory| factory.name == "apollo.rhai")
+        .expect("Plugin not found")
+        .create_instance_without_schema(
+            &Value::from_str(r#"{"scripts":"tests/fixtures", "main":"callback_scope.rhai"}"#)
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // every callback starts from the variables of the loaded script, so the top level statements
+    // increment the same value each time
+    for _ in 0..3 {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .returning(|req: SupergraphRequest| {
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .build()
+                    .unwrap())
+            });
+        let service = dyn_plugin.supergraph_service(BoxService::new(mock_service));
+        let response = service
+            .oneshot(SupergraphRequest::fake_builder().build().unwrap())
+            .await?;
+        assert_eq!(response.context.get::<_, i64>("loads")?, Some(2));
+    }
+
+    let it: &dyn std::any::Any = dyn_plugin.as_any();
+    let rhai_instance: &Rhai = it.downcast_ref::<Rhai>().expect("downcast");
+    let scope = rhai_instance.block.load().scope.clone();
+    assert_eq!(scope.lock().unwrap().get_value::<i64>("loads"), Some(1));
+    Ok(())
+}
@@ -24,6 +24,9 @@ use super::*;
 use crate::plugin::serde::deserialize_option_header_name;
 use crate::plugins::telemetry::metrics;
 use crate::plugins::telemetry::resource::ConfigResource;
+use crate::plugins::telemetry::tracing::sampling::RuleSampler;
+use crate::plugins::telemetry::tracing::sampling::SamplingRule;
+use crate::plugins::telemetry::tracing::sampling::TailSampling;
 use crate::Configuration;
 
 #[derive(thiserror::Error, Debug)]
@@ -104,6 +107,8 @@ pub(crate) struct Metrics {
     pub(crate) otlp: otlp::Config,
     /// Prometheus exporter configuration
     pub(crate) prometheus: metrics::prometheus::Config,
+    /// StatsD and DogStatsD exporter configuration
+    pub(crate) statsd: metrics::statsd::Config,
 }
 
 #[derive(Clone, Debug, Deserialize, JsonSchema)]
@@ -121,6 +126,8 @@ pub(crate) struct MetricsCommon {
     pub(crate) buckets: Vec<f64>,
     /// Views applied on metrics
     pub(crate) views: Vec<MetricView>,
+    /// Attach the trace and span ids of sampled traces as exemplars on histograms, for the OTLP and Prometheus exporters
+    pub(crate) exemplars: bool,
 }
 
 impl Default for MetricsCommon {
@@ -131,6 +138,7 @@ impl Default for MetricsCommon {
             service_namespace: None,
             resource: BTreeMap::new(),
             views: Vec::with_capacity(0),
+            exemplars: false,
             buckets: vec![
                 0.001, 0.005, 0.015, 0.05, 0.1, 0.2, 0.3, 0.4, 0.5, 1.0, 5.0, 10.0,
             ],
@@ -143,6 +151,8 @@ impl Default for MetricsCommon {
 pub(crate) struct MetricView {
     /// The instrument name you're targeting
     pub(crate) name: String,
+    /// New name to set to the instrument, it cannot be used with wildcards in `name`
+    pub(crate) rename: Option<String>,
     /// New description to set to the instrument
     pub(crate) description: Option<String>,
     /// New unit to set to the instrument
@@ -168,8 +178,17 @@ impl TryInto<Box<dyn View>> for MetricView {
             },
             MetricAggregation::Drop => Aggregation::Drop,
         });
-        let instrument = Instrument::new().name(self.name);
         let mut mask = Stream::new();
+        if let Some(rename) = self.rename {
+            if self.name.contains(['*', '?']) {
+                return Err(MetricsError::Config(format!(
+                    "the metric view for '{}' cannot rename several instruments",
+                    self.name
+                )));
+            }
+            mask = mask.name(rename);
+        }
+        let instrument = Instrument::new().name(self.name);
         if let Some(desc) = self.description {
             mask = mask.description(desc);
         }
@@ -341,6 +360,12 @@ pub(crate) struct TracingCommon {
     pub(crate) sampler: SamplerOption,
     /// Whether to use parent based sampling
     pub(crate) parent_based_sampler: bool,
+    /// Sampling rules, evaluated when a trace starts. The first rule matching its root span sets
+    /// its sampler, and the traces matching no rule use the sampler
+    pub(crate) sampling_rules: Vec<SamplingRule>,
+    /// Tail sampling, keeping the traces with errors, slow traces or traces with specific
+    /// attributes
+    pub(crate) tail_sampling: TailSampling,
     /// The maximum events per span before discarding
     pub(crate) max_events_per_span: u32,
     /// The maximum attributes per span before discarding
@@ -394,6 +419,8 @@ impl Default for TracingCommon {
             service_namespace: Default::default(),
             sampler: default_sampler(),
             parent_based_sampler: default_parent_based_sampler(),
+            sampling_rules: Default::default(),
+            tail_sampling: Default::default(),
             max_events_per_span: default_max_events_per_span(),
             max_attributes_per_span: default_max_attributes_per_span(),
             max_links_per_span: default_max_links_per_span(),
@@ -656,12 +683,15 @@ impl From<&TracingCommon> for opentelemetry::sdk::trace::Config {
     fn from(config: &TracingCommon) -> Self {
         let mut common = opentelemetry::sdk::trace::config();
 
-        let mut sampler: opentelemetry::sdk::trace::Sampler = config.sampler.clone().into();
-        if config.parent_based_sampler {
-            sampler = parent_based(sampler);
+        if config.has_sampling_rules() {
+            common = common.with_sampler(RuleSampler::new(config));
+        } else {
+            let mut sampler: opentelemetry::sdk::trace::Sampler = config.sampler.clone().into();
+            if config.parent_based_sampler {
+                sampler = parent_based(sampler);
+            }
+            common = common.with_sampler(sampler);
         }
-
-        common = common.with_sampler(sampler);
         common = common.with_max_events_per_span(config.max_events_per_span);
         common = common.with_max_attributes_per_span(config.max_attributes_per_span);
         common = common.with_max_links_per_span(config.max_links_per_span);
@@ -815,4 +845,21 @@ mod tests {
         AttributeValue::try_from(json!([1.1, true])).expect_err("mixed conversion must fail");
         AttributeValue::try_from(json!([true, "bar"])).expect_err("mixed conversion must fail");
     }
+
+    #[test]
+    fn test_metric_view_rename() {
+        let view: MetricView = serde_json::from_value(json!({
+            "name": "http.server.request.duration",
+            "rename": "graphql.request.duration"
+        }))
+        .unwrap();
+        assert!(TryInto::<Box<dyn View>>::try_into(view).is_ok());
+
+        let view: MetricView = serde_json::from_value(json!({
+            "name": "http.*",
+            "rename": "graphql.request.duration"
+        }))
+        .unwrap();
+        assert!(TryInto::<Box<dyn View>>::try_into(view).is_err());
+    }
 }
@@ -397,6 +397,7 @@ impl DefaultForLevel for HttpCommonAttributes {
                             self.network_protocol_version = Some(true);
                         }
                     }
+                    TelemetryDataKind::Logs => {}
                 }
             }
             DefaultAttributeRequirementLevel::None => {}
@@ -557,6 +558,7 @@ impl DefaultForLevel for HttpServerAttributes {
                         self.server_port = Some(true);
                     }
                 }
+                TelemetryDataKind::Logs => {}
             },
             DefaultAttributeRequirementLevel::Recommended => match kind {
                 TelemetryDataKind::Traces => {
@@ -573,7 +575,7 @@ impl DefaultForLevel for HttpServerAttributes {
                         self.user_agent_original = Some(true);
                     }
                 }
-                TelemetryDataKind::Metrics => {}
+                TelemetryDataKind::Metrics | TelemetryDataKind::Logs => {}
             },
             DefaultAttributeRequirementLevel::None => {}
         }
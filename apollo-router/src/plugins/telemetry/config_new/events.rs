@@ -18,11 +18,14 @@ use crate::plugins::telemetry::config_new::attributes::RouterAttributes;
 use crate::plugins::telemetry::config_new::attributes::SubgraphAttributes;
 use crate::plugins::telemetry::config_new::attributes::SupergraphAttributes;
 use crate::plugins::telemetry::config_new::conditions::Condition;
+use crate::plugins::telemetry::config_new::execution::attributes::ExecutionAttributes;
+use crate::plugins::telemetry::config_new::execution::selectors::ExecutionSelector;
 use crate::plugins::telemetry::config_new::extendable::Extendable;
 use crate::plugins::telemetry::config_new::selectors::RouterSelector;
 use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
 use crate::plugins::telemetry::config_new::selectors::SupergraphSelector;
 use crate::plugins::telemetry::dynamic_attribute::EventDynAttribute;
+use crate::services::execution;
 use crate::services::router;
 use crate::services::subgraph;
 use crate::services::supergraph;
@@ -36,6 +39,8 @@ pub(crate) struct Events {
     router: Extendable<RouterEventsConfig, Event<RouterAttributes, RouterSelector>>,
     /// Subgraph service events
     supergraph: Extendable<SupergraphEventsConfig, Event<SupergraphAttributes, SupergraphSelector>>,
+    /// Execution service events
+    execution: Extendable<ExecutionEventsConfig, Event<ExecutionAttributes, ExecutionSelector>>,
     /// Supergraph service events
     subgraph: Extendable<SubgraphEventsConfig, Event<SubgraphAttributes, SubgraphSelector>>,
 }
@@ -99,6 +104,35 @@ impl Events {
         }
     }
 
+    pub(crate) fn new_execution_events(&self) -> ExecutionEvents {
+        let custom_events = self
+            .execution
+            .custom
+            .iter()
+            .filter_map(|(event_name, event_cfg)| match &event_cfg.level {
+                EventLevel::Off => None,
+                _ => Some(CustomEvent {
+                    inner: Mutex::new(CustomEventInner {
+                        name: event_name.clone(),
+                        level: event_cfg.level,
+                        event_on: event_cfg.on,
+                        message: event_cfg.message.clone(),
+                        selectors: event_cfg.attributes.clone().into(),
+                        condition: event_cfg.condition.clone(),
+                        attributes: Vec::new(),
+                    }),
+                }),
+            })
+            .collect();
+
+        ExecutionEvents {
+            request: self.execution.attributes.request.clone().into(),
+            response: self.execution.attributes.response.clone().into(),
+            error: self.execution.attributes.error.clone().into(),
+            custom: custom_events,
+        }
+    }
+
     pub(crate) fn new_subgraph_events(&self) -> SubgraphEvents {
         let custom_events = self
             .subgraph
@@ -139,6 +173,9 @@ pub(crate) type SupergraphEvents = CustomEvents<
     SupergraphSelector,
 >;
 
+pub(crate) type ExecutionEvents =
+    CustomEvents<execution::Request, execution::Response, ExecutionAttributes, ExecutionSelector>;
+
 pub(crate) type SubgraphEvents =
     CustomEvents<subgraph::Request, subgraph::Response, SubgraphAttributes, SubgraphSelector>;
 
@@ -387,6 +424,104 @@ impl Instrumented
     }
 }
 
+impl Instrumented
+    for CustomEvents<
+        execution::Request,
+        execution::Response,
+        ExecutionAttributes,
+        ExecutionSelector,
+    >
+{
+    type Request = execution::Request;
+    type Response = execution::Response;
+    type EventResponse = crate::graphql::Response;
+
+    fn on_request(&self, request: &Self::Request) {
+        if self.request.level() != EventLevel::Off {
+            if let Some(condition) = self.request.condition() {
+                if condition.lock().evaluate_request(request) != Some(true) {
+                    return;
+                }
+            }
+            let root = &request.query_plan.root;
+            let attrs = vec![
+                KeyValue::new(
+                    Key::from_static_str("query_plan.nodes"),
+                    opentelemetry::Value::I64(root.node_count() as i64),
+                ),
+                KeyValue::new(
+                    Key::from_static_str("query_plan.parallelism"),
+                    opentelemetry::Value::I64(root.parallelism() as i64),
+                ),
+                KeyValue::new(
+                    Key::from_static_str("query_plan.defer_blocks"),
+                    opentelemetry::Value::I64(root.defer_blocks() as i64),
+                ),
+            ];
+            log_event(self.request.level(), "execution.request", attrs, "");
+        }
+        for custom_event in &self.custom {
+            custom_event.on_request(request);
+        }
+    }
+
+    fn on_response(&self, response: &Self::Response) {
+        for custom_event in &self.custom {
+            custom_event.on_response(response);
+        }
+    }
+
+    fn on_response_event(&self, response: &Self::EventResponse, ctx: &Context) {
+        // The execution response is a stream, so the standard response event is logged for
+        // every chunk: the primary response and each deferred response.
+        if self.response.level() != EventLevel::Off {
+            let matches = self
+                .response
+                .condition()
+                .map(|condition| condition.lock().evaluate_event_response(response, ctx))
+                .unwrap_or(true);
+            if matches {
+                log_event(
+                    self.response.level(),
+                    "execution.response",
+                    vec![KeyValue::new(
+                        Key::from_static_str("response.body"),
+                        opentelemetry::Value::String(
+                            serde_json::to_string(response).unwrap_or_default().into(),
+                        ),
+                    )],
+                    "",
+                );
+            }
+        }
+        for custom_event in &self.custom {
+            custom_event.on_response_event(response, ctx);
+        }
+    }
+
+    fn on_error(&self, error: &BoxError, ctx: &Context) {
+        if self.error.level() != EventLevel::Off {
+            if let Some(condition) = self.error.condition() {
+                if !condition.lock().evaluate_error(error, ctx) {
+                    return;
+                }
+            }
+            log_event(
+                self.error.level(),
+                "execution.error",
+                vec![KeyValue::new(
+                    Key::from_static_str("error"),
+                    opentelemetry::Value::String(error.to_string().into()),
+                )],
+                "",
+            );
+        }
+        for custom_event in &self.custom {
+            custom_event.on_error(error, ctx);
+        }
+    }
+}
+
 impl Instrumented
     for CustomEvents<subgraph::Request, subgraph::Response, SubgraphAttributes, SubgraphSelector>
 {
@@ -475,6 +610,17 @@ struct SupergraphEventsConfig {
     error: StandardEventConfig<SupergraphSelector>,
 }
 
+#[derive(Clone, Deserialize, JsonSchema, Debug, Default)]
+#[serde(deny_unknown_fields, default)]
+struct ExecutionEventsConfig {
+    /// Log the execution request
+    request: StandardEventConfig<ExecutionSelector>,
+    /// Log every execution response
+    response: StandardEventConfig<ExecutionSelector>,
+    /// Log the execution error
+    error: StandardEventConfig<ExecutionSelector>,
+}
+
 #[derive(Clone, Deserialize, JsonSchema, Debug, Default)]
 #[serde(deny_unknown_fields, default)]
 struct SubgraphEventsConfig {
@@ -0,0 +1,98 @@
+use opentelemetry_api::KeyValue;
+use opentelemetry_semantic_conventions::trace::GRAPHQL_OPERATION_NAME;
+use opentelemetry_semantic_conventions::trace::GRAPHQL_OPERATION_TYPE;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+
+use crate::context::OPERATION_KIND;
+use crate::context::OPERATION_NAME;
+use crate::plugins::telemetry::config_new::DefaultAttributeRequirementLevel;
+use crate::plugins::telemetry::config_new::DefaultForLevel;
+use crate::plugins::telemetry::config_new::Selectors;
+use crate::plugins::telemetry::otlp::TelemetryDataKind;
+use crate::services::execution;
+use crate::Context;
+
+#[derive(Deserialize, JsonSchema, Clone, Default, Debug, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ExecutionAttributes {
+    /// The name of the operation being executed.
+    /// Examples:
+    ///
+    /// * findBookById
+    ///
+    /// Requirement level: Recommended
+    #[serde(rename = "graphql.operation.name")]
+    pub(crate) graphql_operation_name: Option<bool>,
+
+    /// The type of the operation being executed.
+    /// Examples:
+    ///
+    /// * query
+    /// * subscription
+    /// * mutation
+    ///
+    /// Requirement level: Recommended
+    #[serde(rename = "graphql.operation.type")]
+    pub(crate) graphql_operation_type: Option<bool>,
+}
+
+impl DefaultForLevel for ExecutionAttributes {
+    fn defaults_for_level(
+        &mut self,
+        requirement_level: DefaultAttributeRequirementLevel,
+        _kind: TelemetryDataKind,
+    ) {
+        match requirement_level {
+            DefaultAttributeRequirementLevel::Required => {}
+            DefaultAttributeRequirementLevel::Recommended => {
+                if self.graphql_operation_name.is_none() {
+                    self.graphql_operation_name = Some(true);
+                }
+                if self.graphql_operation_type.is_none() {
+                    self.graphql_operation_type = Some(true);
+                }
+            }
+            DefaultAttributeRequirementLevel::None => {}
+        }
+    }
+}
+
+impl Selectors for ExecutionAttributes {
+    type Request = execution::Request;
+    type Response = execution::Response;
+    type EventResponse = crate::graphql::Response;
+
+    fn on_request(&self, request: &Self::Request) -> Vec<KeyValue> {
+        let mut attrs = Vec::new();
+        if let Some(true) = &self.graphql_operation_name {
+            if let Some(operation_name) = request
+                .context
+                .get::<_, String>(OPERATION_NAME)
+                .unwrap_or_default()
+            {
+                attrs.push(KeyValue::new(GRAPHQL_OPERATION_NAME, operation_name));
+            }
+        }
+        if let Some(true) = &self.graphql_operation_type {
+            if let Some(operation_type) = request
+                .context
+                .get::<_, String>(OPERATION_KIND)
+                .unwrap_or_default()
+            {
+                attrs.push(KeyValue::new(GRAPHQL_OPERATION_TYPE, operation_type));
+            }
+        }
+
+        attrs
+    }
+
+    fn on_response(&self, _response: &Self::Response) -> Vec<KeyValue> {
+        Vec::default()
+    }
+
+    fn on_error(&self, _error: &BoxError, _ctx: &Context) -> Vec<KeyValue> {
+        Vec::default()
+    }
+}
@@ -0,0 +1,46 @@
+use tower::BoxError;
+
+use super::instruments::CustomInstruments;
+use crate::plugins::telemetry::config_new::execution::attributes::ExecutionAttributes;
+use crate::plugins::telemetry::config_new::execution::selectors::ExecutionSelector;
+use crate::plugins::telemetry::config_new::execution::selectors::ExecutionValue;
+use crate::plugins::telemetry::config_new::instruments::Instrumented;
+use crate::services::execution;
+use crate::Context;
+
+pub(crate) mod attributes;
+pub(crate) mod selectors;
+
+pub(crate) type ExecutionCustomInstruments = CustomInstruments<
+    execution::Request,
+    execution::Response,
+    ExecutionAttributes,
+    ExecutionSelector,
+    ExecutionValue,
+>;
+
+pub(crate) struct ExecutionInstruments {
+    pub(crate) custom: ExecutionCustomInstruments,
+}
+
+impl Instrumented for ExecutionInstruments {
+    type Request = execution::Request;
+    type Response = execution::Response;
+    type EventResponse = crate::graphql::Response;
+
+    fn on_request(&self, request: &Self::Request) {
+        self.custom.on_request(request);
+    }
+
+    fn on_response(&self, response: &Self::Response) {
+        self.custom.on_response(response);
+    }
+
+    fn on_response_event(&self, response: &Self::EventResponse, ctx: &Context) {
+        self.custom.on_response_event(response, ctx);
+    }
+
+    fn on_error(&self, error: &BoxError, ctx: &Context) {
+        self.custom.on_error(error, ctx);
+    }
+}
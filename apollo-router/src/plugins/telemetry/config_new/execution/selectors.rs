@@ -0,0 +1,313 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use sha2::Digest;
+use tower::BoxError;
+
+use crate::context::OPERATION_NAME;
+use crate::graphql;
+use crate::plugins::telemetry::config::AttributeValue;
+use crate::plugins::telemetry::config_new::instruments::Event;
+use crate::plugins::telemetry::config_new::instruments::InstrumentValue;
+use crate::plugins::telemetry::config_new::instruments::Standard;
+use crate::plugins::telemetry::config_new::selectors::ErrorRepr;
+use crate::plugins::telemetry::config_new::selectors::OperationName;
+use crate::plugins::telemetry::config_new::Selector;
+use crate::services::execution;
+use crate::Context;
+
+#[derive(Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum ExecutionQueryPlan {
+    /// The number of nodes in the query plan.
+    Nodes,
+    /// The highest number of subgraph fetches of the query plan that can run at the same time.
+    Parallelism,
+    /// The number of deferred blocks in the query plan.
+    DeferBlocks,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", untagged)]
+pub(crate) enum ExecutionValue {
+    Standard(Standard),
+    Event(Event<ExecutionSelector>),
+    Custom(ExecutionSelector),
+}
+
+impl From<&ExecutionValue> for InstrumentValue<ExecutionSelector> {
+    fn from(value: &ExecutionValue) -> Self {
+        match value {
+            ExecutionValue::Standard(s) => InstrumentValue::Standard(s.clone()),
+            ExecutionValue::Custom(selector) => InstrumentValue::Custom(selector.clone()),
+            ExecutionValue::Event(e) => InstrumentValue::Chunked(e.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields, untagged)]
+pub(crate) enum ExecutionSelector {
+    OperationName {
+        /// The operation name from the query.
+        operation_name: OperationName,
+        /// Optional default value.
+        default: Option<String>,
+    },
+    /// Information about the executed query plan
+    QueryPlan {
+        /// The query plan property
+        query_plan: ExecutionQueryPlan,
+    },
+    PrimaryResponse {
+        /// Boolean set to true if the response is the primary (non deferred) response
+        primary_response: bool,
+    },
+    DeferredResponse {
+        /// Boolean set to true if the response is a deferred response
+        deferred_response: bool,
+    },
+    OnGraphQLError {
+        /// Boolean set to true if the response contains graphql errors
+        on_graphql_error: bool,
+    },
+    StaticField {
+        /// A static value
+        r#static: AttributeValue,
+    },
+    Error {
+        #[allow(dead_code)]
+        /// Critical error if it happens
+        error: ErrorRepr,
+    },
+}
+
+impl Selector for ExecutionSelector {
+    type Request = execution::Request;
+    type Response = execution::Response;
+    type EventResponse = graphql::Response;
+
+    fn on_request(&self, request: &Self::Request) -> Option<opentelemetry::Value> {
+        match self {
+            ExecutionSelector::OperationName {
+                operation_name,
+                default,
+            } => operation_name_value(&request.context, operation_name, default),
+            ExecutionSelector::QueryPlan { query_plan } => {
+                let root = &request.query_plan.root;
+                Some(
+                    (match query_plan {
+                        ExecutionQueryPlan::Nodes => root.node_count(),
+                        ExecutionQueryPlan::Parallelism => root.parallelism(),
+                        ExecutionQueryPlan::DeferBlocks => root.defer_blocks(),
+                    } as i64)
+                        .into(),
+                )
+            }
+            ExecutionSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            // For response
+            _ => None,
+        }
+    }
+
+    fn on_response(&self, response: &Self::Response) -> Option<opentelemetry::Value> {
+        match self {
+            ExecutionSelector::OperationName {
+                operation_name,
+                default,
+            } => operation_name_value(&response.context, operation_name, default),
+            ExecutionSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            // For request and response events
+            _ => None,
+        }
+    }
+
+    fn on_response_event(
+        &self,
+        response: &Self::EventResponse,
+        ctx: &Context,
+    ) -> Option<opentelemetry::Value> {
+        match self {
+            ExecutionSelector::OperationName {
+                operation_name,
+                default,
+            } => operation_name_value(ctx, operation_name, default),
+            ExecutionSelector::PrimaryResponse { primary_response } if *primary_response => {
+                Some(is_primary(response).into())
+            }
+            ExecutionSelector::DeferredResponse { deferred_response } if *deferred_response => {
+                Some(is_deferred(response).into())
+            }
+            ExecutionSelector::OnGraphQLError { on_graphql_error } if *on_graphql_error => {
+                has_errors(response).then_some(true.into())
+            }
+            ExecutionSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            _ => None,
+        }
+    }
+
+    fn on_error(&self, error: &BoxError, ctx: &Context) -> Option<opentelemetry::Value> {
+        match self {
+            ExecutionSelector::OperationName {
+                operation_name,
+                default,
+            } => operation_name_value(ctx, operation_name, default),
+            ExecutionSelector::Error { .. } => Some(error.to_string().into()),
+            ExecutionSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            _ => None,
+        }
+    }
+}
+
+fn operation_name_value(
+    ctx: &Context,
+    operation_name: &OperationName,
+    default: &Option<String>,
+) -> Option<opentelemetry::Value> {
+    let op_name = ctx.get(OPERATION_NAME).ok().flatten();
+    match operation_name {
+        OperationName::String => op_name.or_else(|| default.clone()),
+        OperationName::Hash => op_name.or_else(|| default.clone()).map(|op_name| {
+            let mut hasher = sha2::Sha256::new();
+            hasher.update(op_name.as_bytes());
+            let result = hasher.finalize();
+            hex::encode(result)
+        }),
+    }
+    .map(opentelemetry::Value::from)
+}
+
+/// Deferred responses are sent as incremental patches, the primary response carries the data or
+/// errors of the non deferred part of the query. The last response of a deferred query may only
+/// contain `hasNext: false`, in which case it is neither.
+fn is_primary(response: &graphql::Response) -> bool {
+    !is_deferred(response) && (response.data.is_some() || !response.errors.is_empty())
+}
+
+fn is_deferred(response: &graphql::Response) -> bool {
+    !response.incremental.is_empty() || response.path.is_some()
+}
+
+fn has_errors(response: &graphql::Response) -> bool {
+    !response.errors.is_empty()
+        || response
+            .incremental
+            .iter()
+            .any(|incremental| !incremental.errors.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::Value;
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::graphql::IncrementalResponse;
+    use crate::query_planner::PlanNode;
+    use crate::services::execution::QueryPlan as Plan;
+
+    #[test]
+    fn query_plan() {
+        let root: PlanNode = serde_json::from_value(serde_json::json!({
+            "kind": "Defer",
+            "primary": {
+                "node": {
+                    "kind": "Parallel",
+                    "nodes": [
+                        {
+                            "kind": "Fetch",
+                            "serviceName": "products",
+                            "variableUsages": [],
+                            "operation": "{topProducts{upc}}",
+                            "operationKind": "query"
+                        },
+                        {
+                            "kind": "Fetch",
+                            "serviceName": "accounts",
+                            "variableUsages": [],
+                            "operation": "{me{id}}",
+                            "operationKind": "query"
+                        }
+                    ]
+                }
+            },
+            "deferred": [{
+                "depends": [],
+                "queryPath": [],
+                "node": {
+                    "kind": "Fetch",
+                    "serviceName": "reviews",
+                    "variableUsages": [],
+                    "operation": "{reviews{body}}",
+                    "operationKind": "query"
+                }
+            }]
+        }))
+        .unwrap();
+        let request = execution::Request::fake_builder()
+            .query_plan(Plan::fake_builder().root(root).build())
+            .build();
+
+        let value = |query_plan| ExecutionSelector::QueryPlan { query_plan }.on_request(&request);
+        assert_eq!(value(ExecutionQueryPlan::Nodes), Some(Value::I64(5)));
+        assert_eq!(value(ExecutionQueryPlan::Parallelism), Some(Value::I64(3)));
+        assert_eq!(value(ExecutionQueryPlan::DeferBlocks), Some(Value::I64(1)));
+    }
+
+    #[test]
+    fn primary_and_deferred_responses() {
+        let primary = graphql::Response::builder()
+            .data(json!({"topProducts": []}))
+            .has_next(true)
+            .build();
+        let deferred = graphql::Response::builder()
+            .incremental(vec![IncrementalResponse::builder()
+                .data(json!({"reviews": []}))
+                .path(crate::json_ext::Path::from("topProducts"))
+                .build()])
+            .has_next(false)
+            .build();
+        let ctx = Context::default();
+
+        let primary_response = ExecutionSelector::PrimaryResponse {
+            primary_response: true,
+        };
+        let deferred_response = ExecutionSelector::DeferredResponse {
+            deferred_response: true,
+        };
+        assert_eq!(
+            primary_response.on_response_event(&primary, &ctx),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            primary_response.on_response_event(&deferred, &ctx),
+            Some(Value::Bool(false))
+        );
+        assert_eq!(
+            deferred_response.on_response_event(&deferred, &ctx),
+            Some(Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn graphql_errors() {
+        let response = graphql::Response::builder()
+            .error(
+                graphql::Error::builder()
+                    .message("boom")
+                    .extension_code("BOOM")
+                    .build(),
+            )
+            .build();
+        let selector = ExecutionSelector::OnGraphQLError {
+            on_graphql_error: true,
+        };
+        assert_eq!(
+            selector.on_response_event(&response, &Context::default()),
+            Some(Value::Bool(true))
+        );
+        assert_eq!(
+            selector.on_response_event(&graphql::Response::default(), &Context::default()),
+            None
+        );
+    }
+}
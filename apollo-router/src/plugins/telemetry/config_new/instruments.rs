@@ -25,10 +25,18 @@ use super::cache::attributes::CacheAttributes;
 use super::cache::CacheInstruments;
 use super::cache::CacheInstrumentsConfig;
 use super::cache::CACHE_METRIC;
+use super::execution::attributes::ExecutionAttributes;
+use super::execution::selectors::ExecutionSelector;
+use super::execution::selectors::ExecutionValue;
+use super::execution::ExecutionInstruments;
 use super::graphql::selectors::ListLength;
 use super::graphql::GraphQLInstruments;
 use super::graphql::FIELD_EXECUTION;
 use super::graphql::FIELD_LENGTH;
+use super::query_planner::attributes::QueryPlannerAttributes;
+use super::query_planner::selectors::QueryPlannerSelector;
+use super::query_planner::selectors::QueryPlannerValue;
+use super::query_planner::QueryPlannerInstruments;
 use super::selectors::CacheKind;
 use super::DefaultForLevel;
 use super::Selector;
@@ -76,6 +84,9 @@ pub(crate) struct InstrumentsConfig {
         SupergraphInstrumentsConfig,
         Instrument<SupergraphAttributes, SupergraphSelector, SupergraphValue>,
     >,
+    /// Execution service instruments, run around the execution of the query plan. For more information see documentation on Router lifecycle.
+    pub(crate) execution:
+        HashMap<String, Instrument<ExecutionAttributes, ExecutionSelector, ExecutionValue>>,
     /// Subgraph service instruments. For more information see documentation on Router lifecycle.
     pub(crate) subgraph: Extendable<
         SubgraphInstrumentsConfig,
@@ -91,6 +102,11 @@ pub(crate) struct InstrumentsConfig {
         CacheInstrumentsConfig,
         Instrument<CacheAttributes, SubgraphSelector, SubgraphValue>,
     >,
+    /// Query planner instruments, run once per request around query planning, whether the query plan was cached or not.
+    pub(crate) query_planner: HashMap<
+        String,
+        Instrument<QueryPlannerAttributes, QueryPlannerSelector, QueryPlannerValue>,
+    >,
 }
 
 const HTTP_SERVER_REQUEST_DURATION_METRIC: &str = "http.server.request.duration";
@@ -434,6 +450,50 @@ impl InstrumentsConfig {
         }
     }
 
+    pub(crate) fn new_builtin_execution_instruments(&self) -> HashMap<String, StaticInstrument> {
+        let meter = metrics::meter_provider().meter(METER_NAME);
+        let mut static_instruments = HashMap::with_capacity(self.execution.len());
+        for (instrument_name, instrument) in &self.execution {
+            match instrument.ty {
+                InstrumentType::Counter => {
+                    static_instruments.insert(
+                        instrument_name.clone(),
+                        StaticInstrument::CounterF64(
+                            meter
+                                .f64_counter(instrument_name.clone())
+                                .with_description(instrument.description.clone())
+                                .with_unit(Unit::new(instrument.unit.clone()))
+                                .init(),
+                        ),
+                    );
+                }
+                InstrumentType::Histogram => {
+                    static_instruments.insert(
+                        instrument_name.clone(),
+                        StaticInstrument::Histogram(
+                            meter
+                                .f64_histogram(instrument_name.clone())
+                                .with_description(instrument.description.clone())
+                                .with_unit(Unit::new(instrument.unit.clone()))
+                                .init(),
+                        ),
+                    );
+                }
+            }
+        }
+
+        static_instruments
+    }
+
+    pub(crate) fn new_execution_instruments(
+        &self,
+        static_instruments: Arc<HashMap<String, StaticInstrument>>,
+    ) -> ExecutionInstruments {
+        ExecutionInstruments {
+            custom: CustomInstruments::new(&self.execution, static_instruments),
+        }
+    }
+
     pub(crate) fn new_builtin_subgraph_instruments(&self) -> HashMap<String, StaticInstrument> {
         let meter = metrics::meter_provider().meter(METER_NAME);
         let mut static_instruments = HashMap::with_capacity(self.subgraph.custom.len());
@@ -857,6 +917,52 @@ impl InstrumentsConfig {
             }),
         }
     }
+
+    pub(crate) fn new_builtin_query_planner_instruments(
+        &self,
+    ) -> HashMap<String, StaticInstrument> {
+        let meter = metrics::meter_provider().meter(METER_NAME);
+        let mut static_instruments = HashMap::with_capacity(self.query_planner.len());
+        for (instrument_name, instrument) in &self.query_planner {
+            match instrument.ty {
+                InstrumentType::Counter => {
+                    static_instruments.insert(
+                        instrument_name.clone(),
+                        StaticInstrument::CounterF64(
+                            meter
+                                .f64_counter(instrument_name.clone())
+                                .with_description(instrument.description.clone())
+                                .with_unit(Unit::new(instrument.unit.clone()))
+                                .init(),
+                        ),
+                    );
+                }
+                InstrumentType::Histogram => {
+                    static_instruments.insert(
+                        instrument_name.clone(),
+                        StaticInstrument::Histogram(
+                            meter
+                                .f64_histogram(instrument_name.clone())
+                                .with_description(instrument.description.clone())
+                                .with_unit(Unit::new(instrument.unit.clone()))
+                                .init(),
+                        ),
+                    );
+                }
+            }
+        }
+
+        static_instruments
+    }
+
+    pub(crate) fn new_query_planner_instruments(
+        &self,
+        static_instruments: Arc<HashMap<String, StaticInstrument>>,
+    ) -> QueryPlannerInstruments {
+        QueryPlannerInstruments {
+            custom: CustomInstruments::new(&self.query_planner, static_instruments),
+        }
+    }
 }
 
 #[derive(Debug)]
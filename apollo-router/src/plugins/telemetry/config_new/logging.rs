@@ -2,6 +2,7 @@ use std::collections::BTreeMap;
 use std::io::IsTerminal;
 use std::time::Duration;
 
+use bytesize::ByteSize;
 use schemars::gen::SchemaGenerator;
 use schemars::schema::InstanceType;
 use schemars::schema::Metadata;
@@ -20,6 +21,7 @@ use crate::configuration::ConfigurationError;
 use crate::plugins::telemetry::config::AttributeValue;
 use crate::plugins::telemetry::config::TraceIdFormat;
 use crate::plugins::telemetry::config_new::experimental_when_header::HeaderLoggingCondition;
+use crate::plugins::telemetry::otlp;
 use crate::plugins::telemetry::resource::ConfigResource;
 use crate::services::SupergraphRequest;
 
@@ -31,9 +33,10 @@ pub(crate) struct Logging {
     pub(crate) common: LoggingCommon,
     /// Settings for logging to stdout.
     pub(crate) stdout: StdOut,
-    #[serde(skip)]
     /// Settings for logging to a file.
     pub(crate) file: File,
+    /// Settings for exporting logs and events through OTLP.
+    pub(crate) otlp: otlp::Config,
 
     /// Log configuration to log request and response for subgraphs and supergraph
     /// Note that this will be removed when events are implemented.
@@ -147,39 +150,59 @@ impl Default for RateLimit {
 }
 
 /// Log to a file
-#[allow(dead_code)]
-#[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
 #[serde(deny_unknown_fields, default)]
 pub(crate) struct File {
     /// Set to true to log to a file.
     pub(crate) enabled: bool,
-    /// The path pattern of the file to log to.
+    /// The path of the file to log to.
     pub(crate) path: String,
     /// The format of the log file.
     pub(crate) format: Format,
     /// The period to rollover the log file.
     pub(crate) rollover: Rollover,
+    /// The maximum size of the log file before it is rolled over, for example `100MB`.
+    #[schemars(with = "Option<String>")]
+    pub(crate) max_size: Option<ByteSize>,
+    /// The maximum number of log files to keep, including the current one.
+    pub(crate) max_files: Option<usize>,
+    /// Set to true to compress the log files with gzip after they are rolled over.
+    pub(crate) compress: bool,
     /// Log rate limiting. The limit is set per type of log message
     pub(crate) rate_limit: Option<RateLimit>,
 }
 
+impl Default for File {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "router.log".to_string(),
+            format: Format::Json(JsonFormat::default()),
+            rollover: Rollover::default(),
+            max_size: None,
+            max_files: None,
+            compress: false,
+            rate_limit: None,
+        }
+    }
+}
+
 /// The format for logging.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub(crate) enum Format {
     // !!!!WARNING!!!!, if you change this enum then be sure to add the changes to the JsonSchema AND the custom deserializer.
 
     // Want to see support for these formats? Please open an issue!
-    // /// https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/CWL_AnalyzeLogData-discoverable-fields.html
-    // Aws,
-    // /// https://github.com/trentm/node-bunyan
-    // Bunyan,
-    // /// https://go2docs.graylog.org/5-0/getting_in_log_data/ingest_gelf.html#:~:text=The%20Graylog%20Extended%20Log%20Format,UDP%2C%20TCP%2C%20or%20HTTP.
-    // Gelf,
-    //
-    // /// https://cloud.google.com/logging/docs/structured-logging
-    // Google,
     // /// https://github.com/open-telemetry/opentelemetry-rust/tree/main/opentelemetry-appender-log
     // OpenTelemetry,
+    /// https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/CWL_AnalyzeLogData-discoverable-fields.html
+    Aws(AwsFormat),
+    /// https://github.com/trentm/node-bunyan
+    Bunyan(BunyanFormat),
+    /// https://go2docs.graylog.org/5-0/getting_in_log_data/ingest_gelf.html#:~:text=The%20Graylog%20Extended%20Log%20Format,UDP%2C%20TCP%2C%20or%20HTTP.
+    Gelf(GelfFormat),
+    /// https://cloud.google.com/logging/docs/structured-logging
+    Google(GoogleFormat),
     /// https://docs.rs/tracing-subscriber/latest/tracing_subscriber/fmt/format/struct.Json.html
     Json(JsonFormat),
 
@@ -198,6 +221,10 @@ impl JsonSchema for Format {
         let types = vec![
             ("json", JsonFormat::json_schema(gen), "Tracing subscriber https://docs.rs/tracing-subscriber/latest/tracing_subscriber/fmt/format/struct.Json.html"),
             ("text", TextFormat::json_schema(gen), "Tracing subscriber https://docs.rs/tracing-subscriber/latest/tracing_subscriber/fmt/format/struct.Full.html"),
+            ("aws", AwsFormat::json_schema(gen), "AWS CloudWatch https://docs.aws.amazon.com/AmazonCloudWatch/latest/logs/CWL_AnalyzeLogData-discoverable-fields.html"),
+            ("bunyan", BunyanFormat::json_schema(gen), "Bunyan https://github.com/trentm/node-bunyan"),
+            ("gelf", GelfFormat::json_schema(gen), "Graylog Extended Log Format https://go2docs.graylog.org/5-0/getting_in_log_data/ingest_gelf.html"),
+            ("google", GoogleFormat::json_schema(gen), "Google Cloud Logging https://cloud.google.com/logging/docs/structured-logging"),
         ];
 
         Schema::Object(SchemaObject {
@@ -276,6 +303,10 @@ impl<'de> Deserialize<'de> for Format {
                 match value {
                     "json" => Ok(Format::Json(JsonFormat::default())),
                     "text" => Ok(Format::Text(TextFormat::default())),
+                    "aws" => Ok(Format::Aws(AwsFormat::default())),
+                    "bunyan" => Ok(Format::Bunyan(BunyanFormat::default())),
+                    "gelf" => Ok(Format::Gelf(GelfFormat::default())),
+                    "google" => Ok(Format::Google(GoogleFormat::default())),
                     _ => Err(E::custom(format!("unknown log format: {}", value))),
                 }
             }
@@ -289,6 +320,10 @@ impl<'de> Deserialize<'de> for Format {
                 match key.as_deref() {
                     Some("json") => Ok(Format::Json(map.next_value::<JsonFormat>()?)),
                     Some("text") => Ok(Format::Text(map.next_value::<TextFormat>()?)),
+                    Some("aws") => Ok(Format::Aws(map.next_value::<AwsFormat>()?)),
+                    Some("bunyan") => Ok(Format::Bunyan(map.next_value::<BunyanFormat>()?)),
+                    Some("gelf") => Ok(Format::Gelf(map.next_value::<GelfFormat>()?)),
+                    Some("google") => Ok(Format::Google(map.next_value::<GoogleFormat>()?)),
                     Some(value) => Err(serde::de::Error::custom(format!(
                         "unknown log format: {}",
                         value
@@ -450,8 +485,35 @@ impl Default for TextFormat {
     }
 }
 
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct AwsFormat {
+    /// Include the resource with the log event. (default: false)
+    pub(crate) display_resource: bool,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct BunyanFormat {
+    /// The name of the logger. (default: the service name)
+    pub(crate) name: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct GelfFormat {
+    /// The name of the host sending the message. (default: the host name)
+    pub(crate) host: Option<String>,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default, Eq, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct GoogleFormat {
+    /// The Google Cloud project id. When set, the trace is reported as `projects/<project_id>/traces/<trace_id>`, so that log entries are linked to Cloud Trace.
+    pub(crate) project_id: Option<String>,
+}
+
 /// The period to rollover the log file.
-#[allow(dead_code)]
 #[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub(crate) enum Rollover {
@@ -471,6 +533,7 @@ mod test {
 
     use crate::plugins::telemetry::config_new::experimental_when_header::HeaderLoggingCondition;
     use crate::plugins::telemetry::config_new::logging::Format;
+    use crate::plugins::telemetry::config_new::logging::GoogleFormat;
     use crate::plugins::telemetry::config_new::logging::Logging;
     use crate::services::SupergraphRequest;
     #[test]
@@ -483,6 +546,17 @@ mod test {
         assert_eq!(format, Format::Text(Default::default()));
         let format = serde_json::from_value::<Format>(json!({"json":{}})).unwrap();
         assert_eq!(format, Format::Json(Default::default()));
+        let format = serde_json::from_value::<Format>(json!("gelf")).unwrap();
+        assert_eq!(format, Format::Gelf(Default::default()));
+        let format =
+            serde_json::from_value::<Format>(json!({"google":{"project_id": "my-project"}}))
+                .unwrap();
+        assert_eq!(
+            format,
+            Format::Google(GoogleFormat {
+                project_id: Some("my-project".to_string())
+            })
+        );
     }
 
     #[test]
@@ -0,0 +1,75 @@
+use opentelemetry_api::Key;
+use opentelemetry_api::KeyValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+
+use crate::plugins::telemetry::config_new::DefaultAttributeRequirementLevel;
+use crate::plugins::telemetry::config_new::DefaultForLevel;
+use crate::plugins::telemetry::config_new::Selectors;
+use crate::plugins::telemetry::otlp::TelemetryDataKind;
+use crate::query_planner::PlanningInfo;
+use crate::services::query_planner;
+use crate::Context;
+
+const QUERY_PLANNER_CACHE: Key = Key::from_static_str("query_planner.cache");
+const QUERY_PLANNER_MODE: Key = Key::from_static_str("query_planner.mode");
+
+#[derive(Deserialize, JsonSchema, Clone, Default, Debug, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct QueryPlannerAttributes {
+    /// The query plan cache status: `hit` or `miss`
+    #[serde(rename = "query_planner.cache")]
+    pub(crate) cache: Option<bool>,
+    /// The configured query planner mode: `legacy`, `new`, `both` or `both_best_effort`
+    #[serde(rename = "query_planner.mode")]
+    pub(crate) mode: Option<bool>,
+}
+
+impl DefaultForLevel for QueryPlannerAttributes {
+    fn defaults_for_level(
+        &mut self,
+        _requirement_level: DefaultAttributeRequirementLevel,
+        _kind: TelemetryDataKind,
+    ) {
+    }
+}
+
+impl QueryPlannerAttributes {
+    fn planning_info(&self, ctx: &Context) -> Vec<KeyValue> {
+        let mut attrs = Vec::new();
+        if let Some(info) = ctx
+            .extensions()
+            .with_lock(|lock| lock.get::<PlanningInfo>().cloned())
+        {
+            if let Some(true) = self.cache {
+                attrs.push(KeyValue::new(
+                    QUERY_PLANNER_CACHE,
+                    if info.cache_hit { "hit" } else { "miss" },
+                ));
+            }
+            if let Some(true) = self.mode {
+                attrs.push(KeyValue::new(QUERY_PLANNER_MODE, info.mode));
+            }
+        }
+        attrs
+    }
+}
+
+impl Selectors for QueryPlannerAttributes {
+    type Request = query_planner::CachingRequest;
+    type Response = query_planner::Response;
+    type EventResponse = ();
+
+    fn on_request(&self, _request: &Self::Request) -> Vec<KeyValue> {
+        Vec::default()
+    }
+
+    fn on_response(&self, response: &Self::Response) -> Vec<KeyValue> {
+        self.planning_info(&response.context)
+    }
+
+    fn on_error(&self, _error: &BoxError, ctx: &Context) -> Vec<KeyValue> {
+        self.planning_info(ctx)
+    }
+}
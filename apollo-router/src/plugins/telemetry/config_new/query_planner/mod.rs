@@ -0,0 +1,42 @@
+use tower::BoxError;
+
+use super::instruments::CustomInstruments;
+use crate::plugins::telemetry::config_new::instruments::Instrumented;
+use crate::plugins::telemetry::config_new::query_planner::attributes::QueryPlannerAttributes;
+use crate::plugins::telemetry::config_new::query_planner::selectors::QueryPlannerSelector;
+use crate::plugins::telemetry::config_new::query_planner::selectors::QueryPlannerValue;
+use crate::services::query_planner;
+use crate::Context;
+
+pub(crate) mod attributes;
+pub(crate) mod selectors;
+
+pub(crate) type QueryPlannerCustomInstruments = CustomInstruments<
+    query_planner::CachingRequest,
+    query_planner::Response,
+    QueryPlannerAttributes,
+    QueryPlannerSelector,
+    QueryPlannerValue,
+>;
+
+pub(crate) struct QueryPlannerInstruments {
+    pub(crate) custom: QueryPlannerCustomInstruments,
+}
+
+impl Instrumented for QueryPlannerInstruments {
+    type Request = query_planner::CachingRequest;
+    type Response = query_planner::Response;
+    type EventResponse = ();
+
+    fn on_request(&self, request: &Self::Request) {
+        self.custom.on_request(request);
+    }
+
+    fn on_response(&self, response: &Self::Response) {
+        self.custom.on_response(response);
+    }
+
+    fn on_error(&self, error: &BoxError, ctx: &Context) {
+        self.custom.on_error(error, ctx);
+    }
+}
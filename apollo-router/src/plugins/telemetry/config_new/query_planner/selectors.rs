@@ -0,0 +1,248 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use sha2::Digest;
+use tower::BoxError;
+
+use crate::plugins::telemetry::config::AttributeValue;
+use crate::plugins::telemetry::config_new::instruments::InstrumentValue;
+use crate::plugins::telemetry::config_new::instruments::Standard;
+use crate::plugins::telemetry::config_new::selectors::ErrorRepr;
+use crate::plugins::telemetry::config_new::selectors::OperationName;
+use crate::plugins::telemetry::config_new::Selector;
+use crate::query_planner::PlanningInfo;
+use crate::services::query_planner;
+use crate::services::query_planner::QueryPlannerContent;
+use crate::Context;
+
+#[derive(Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum QueryPlan {
+    /// The estimated size of the query plan in bytes.
+    Size,
+    /// The number of subgraph fetches in the query plan.
+    Fetches,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum QueryPlanner {
+    /// The query plan cache status: `hit` or `miss`.
+    Cache,
+    /// The configured query planner mode: `legacy`, `new`, `both` or `both_best_effort`.
+    Mode,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields, rename_all = "snake_case", untagged)]
+pub(crate) enum QueryPlannerValue {
+    Standard(Standard),
+    Custom(QueryPlannerSelector),
+}
+
+impl From<&QueryPlannerValue> for InstrumentValue<QueryPlannerSelector> {
+    fn from(value: &QueryPlannerValue) -> Self {
+        match value {
+            QueryPlannerValue::Standard(s) => InstrumentValue::Standard(s.clone()),
+            QueryPlannerValue::Custom(selector) => InstrumentValue::Custom(selector.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields, untagged)]
+pub(crate) enum QueryPlannerSelector {
+    OperationName {
+        /// The operation name from the query.
+        operation_name: OperationName,
+        /// Optional default value.
+        default: Option<String>,
+    },
+    /// Information about the query planning
+    QueryPlanner {
+        /// How the query plan was obtained
+        query_planner: QueryPlanner,
+    },
+    /// Information about the query plan
+    QueryPlan {
+        /// The query plan property
+        query_plan: QueryPlan,
+    },
+    StaticField {
+        /// A static value
+        r#static: AttributeValue,
+    },
+    Error {
+        #[allow(dead_code)]
+        /// Critical error if it happens
+        error: ErrorRepr,
+    },
+}
+
+impl Selector for QueryPlannerSelector {
+    type Request = query_planner::CachingRequest;
+    type Response = query_planner::Response;
+    type EventResponse = ();
+
+    fn on_request(&self, request: &Self::Request) -> Option<opentelemetry::Value> {
+        match self {
+            QueryPlannerSelector::OperationName {
+                operation_name,
+                default,
+            } => {
+                let op_name = request.operation_name.clone();
+                match operation_name {
+                    OperationName::String => op_name.or_else(|| default.clone()),
+                    OperationName::Hash => op_name.or_else(|| default.clone()).map(|op_name| {
+                        let mut hasher = sha2::Sha256::new();
+                        hasher.update(op_name.as_bytes());
+                        let result = hasher.finalize();
+                        hex::encode(result)
+                    }),
+                }
+                .map(opentelemetry::Value::from)
+            }
+            QueryPlannerSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            // For response
+            _ => None,
+        }
+    }
+
+    fn on_response(&self, response: &Self::Response) -> Option<opentelemetry::Value> {
+        match self {
+            QueryPlannerSelector::QueryPlanner { query_planner } => {
+                planning_info(&response.context, query_planner)
+            }
+            QueryPlannerSelector::QueryPlan { query_plan } => match &response.content {
+                Some(QueryPlannerContent::Plan { plan }) => Some(match query_plan {
+                    QueryPlan::Size => (plan.estimated_size() as i64).into(),
+                    QueryPlan::Fetches => (plan.root.subgraph_fetches() as i64).into(),
+                }),
+                _ => None,
+            },
+            QueryPlannerSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            // For request
+            _ => None,
+        }
+    }
+
+    fn on_error(&self, error: &BoxError, ctx: &Context) -> Option<opentelemetry::Value> {
+        match self {
+            QueryPlannerSelector::Error { .. } => Some(error.to_string().into()),
+            QueryPlannerSelector::QueryPlanner { query_planner } => {
+                planning_info(ctx, query_planner)
+            }
+            QueryPlannerSelector::StaticField { r#static } => Some(r#static.clone().into()),
+            _ => None,
+        }
+    }
+}
+
+fn planning_info(ctx: &Context, query_planner: &QueryPlanner) -> Option<opentelemetry::Value> {
+    let info = ctx
+        .extensions()
+        .with_lock(|lock| lock.get::<PlanningInfo>().cloned())?;
+    match query_planner {
+        QueryPlanner::Cache => Some(if info.cache_hit { "hit" } else { "miss" }.into()),
+        QueryPlanner::Mode => Some(info.mode.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use opentelemetry::Value;
+
+    use super::*;
+    use crate::query_planner::PlanNode;
+    use crate::query_planner::QueryPlan as Plan;
+
+    fn response(context: Context) -> query_planner::Response {
+        let plan = Plan::fake_builder()
+            .root(PlanNode::Parallel {
+                nodes: vec![
+                    serde_json::from_value(serde_json::json!({
+                        "kind": "Fetch",
+                        "serviceName": "products",
+                        "variableUsages": [],
+                        "operation": "{topProducts{name}}",
+                        "operationKind": "query"
+                    }))
+                    .unwrap(),
+                    serde_json::from_value(serde_json::json!({
+                        "kind": "Fetch",
+                        "serviceName": "reviews",
+                        "variableUsages": [],
+                        "operation": "{reviews{body}}",
+                        "operationKind": "query"
+                    }))
+                    .unwrap(),
+                ],
+            })
+            .build();
+        query_planner::Response::builder()
+            .content(QueryPlannerContent::Plan {
+                plan: Arc::new(plan),
+            })
+            .context(context)
+            .build()
+    }
+
+    #[test]
+    fn operation_name() {
+        let request = query_planner::CachingRequest::builder()
+            .query("query Products { topProducts { name } }")
+            .operation_name("Products")
+            .context(Context::default())
+            .build();
+        let selector = QueryPlannerSelector::OperationName {
+            operation_name: OperationName::String,
+            default: None,
+        };
+        assert_eq!(
+            selector.on_request(&request),
+            Some(Value::String("Products".into()))
+        );
+    }
+
+    #[test]
+    fn cache_and_mode() {
+        let context = Context::default();
+        context.extensions().with_lock(|mut lock| {
+            lock.insert(PlanningInfo {
+                cache_hit: false,
+                mode: "legacy",
+            })
+        });
+        let response = response(context);
+
+        let cache = QueryPlannerSelector::QueryPlanner {
+            query_planner: QueryPlanner::Cache,
+        };
+        assert_eq!(
+            cache.on_response(&response),
+            Some(Value::String("miss".into()))
+        );
+        let mode = QueryPlannerSelector::QueryPlanner {
+            query_planner: QueryPlanner::Mode,
+        };
+        assert_eq!(
+            mode.on_response(&response),
+            Some(Value::String("legacy".into()))
+        );
+    }
+
+    #[test]
+    fn query_plan() {
+        let response = response(Context::default());
+
+        let fetches = QueryPlannerSelector::QueryPlan {
+            query_plan: QueryPlan::Fetches,
+        };
+        assert_eq!(fetches.on_response(&response), Some(Value::I64(2)));
+        let size = QueryPlannerSelector::QueryPlan {
+            query_plan: QueryPlan::Size,
+        };
+        assert!(matches!(size.on_response(&response), Some(Value::I64(size)) if size > 0));
+    }
+}
@@ -7,6 +7,8 @@ use crate::plugins::telemetry::config_new::attributes::RouterAttributes;
 use crate::plugins::telemetry::config_new::attributes::SubgraphAttributes;
 use crate::plugins::telemetry::config_new::attributes::SupergraphAttributes;
 use crate::plugins::telemetry::config_new::extendable::Extendable;
+use crate::plugins::telemetry::config_new::query_planner::attributes::QueryPlannerAttributes;
+use crate::plugins::telemetry::config_new::query_planner::selectors::QueryPlannerSelector;
 use crate::plugins::telemetry::config_new::selectors::RouterSelector;
 use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
 use crate::plugins::telemetry::config_new::selectors::SupergraphSelector;
@@ -35,6 +37,10 @@ pub(crate) struct Spans {
     /// Attributes to include on the subgraph span.
     /// Subgraph spans contain information about the subgraph request and response and therefore contain subgraph specific attributes.
     pub(crate) subgraph: SubgraphSpans,
+
+    /// Attributes to include on the query planning span.
+    /// Query planning spans contain information about the query plan cache and the query plan.
+    pub(crate) query_planner: QueryPlannerSpans,
 }
 
 impl Spans {
@@ -93,6 +99,30 @@ impl DefaultForLevel for SupergraphSpans {
 pub(crate) struct SubgraphSpans {
     /// Custom attributes that are attached to the subgraph span.
     pub(crate) attributes: Extendable<SubgraphAttributes, Conditional<SubgraphSelector>>,
+
+    /// Spans created for the field resolvers of the subgraph, from its federated traces (FTV1).
+    pub(crate) field_spans: FieldSpans,
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct FieldSpans {
+    /// Request federated traces (FTV1) from subgraphs for every sampled request and create a child
+    /// span of the subgraph span for each field resolver they contain. Defaults to false.
+    pub(crate) enabled: bool,
+
+    /// The maximum number of field spans created for a subgraph response, the fields resolved
+    /// after it are not traced. Defaults to 500.
+    pub(crate) max_spans: usize,
+}
+
+impl Default for FieldSpans {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_spans: 500,
+        }
+    }
 }
 
 impl DefaultForLevel for SubgraphSpans {
@@ -105,6 +135,13 @@ impl DefaultForLevel for SubgraphSpans {
     }
 }
 
+#[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct QueryPlannerSpans {
+    /// Custom attributes that are attached to the query planning span.
+    pub(crate) attributes: Extendable<QueryPlannerAttributes, Conditional<QueryPlannerSelector>>,
+}
+
 #[cfg(test)]
 mod test {
     use std::str::FromStr;
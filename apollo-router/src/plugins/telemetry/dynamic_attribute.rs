@@ -3,6 +3,7 @@ use opentelemetry::KeyValue;
 use opentelemetry::OrderMap;
 use tracing_subscriber::layer::Context;
 use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::registry::SpanRef;
 use tracing_subscriber::Layer;
 use tracing_subscriber::Registry;
 
@@ -223,6 +224,25 @@ impl EventAttributes {
     pub(crate) fn take(&mut self) -> Vec<KeyValue> {
         std::mem::take(&mut self.attributes)
     }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &KeyValue> {
+        self.attributes.iter()
+    }
+}
+
+/// Removes the attributes of the last event raised in this span, once every logging output has
+/// seen them.
+pub(crate) fn clear_event_attributes<S>(span: &SpanRef<'_, S>)
+where
+    S: for<'lookup> LookupSpan<'lookup>,
+{
+    let mut extensions = span.extensions_mut();
+    if let Some(otel_data) = extensions.get_mut::<OtelData>() {
+        otel_data.event_attributes = None;
+    }
+    if let Some(event_attributes) = extensions.get_mut::<EventAttributes>() {
+        event_attributes.take();
+    }
 }
 
 /// To add dynamic attributes for spans
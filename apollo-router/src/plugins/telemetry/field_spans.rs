@@ -0,0 +1,346 @@
+//! Resolver level spans reconstructed from the subgraph FTV1 traces.
+use std::time::Duration;
+use std::time::SystemTime;
+
+use opentelemetry::trace::SpanBuilder;
+use opentelemetry::trace::SpanKind;
+use opentelemetry::trace::Status;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::trace::Tracer;
+use opentelemetry::Context;
+use opentelemetry::KeyValue;
+use tracing::Span;
+
+use crate::plugins::telemetry::apollo_exporter::proto::reports::trace::node::Id;
+use crate::plugins::telemetry::apollo_exporter::proto::reports::trace::Node;
+use crate::plugins::telemetry::apollo_exporter::proto::reports::Trace;
+use crate::plugins::telemetry::otel::OpenTelemetrySpanExt;
+use crate::plugins::telemetry::reload::OPENTELEMETRY_TRACER_HANDLE;
+
+/// Marker in the context extensions to request FTV1 traces from subgraphs for field spans
+#[derive(Clone)]
+pub(crate) struct EnableSubgraphFieldSpans {
+    pub(crate) max_spans: usize,
+}
+
+/// Creates a span for each resolver of the subgraph trace, as children of the current subgraph
+/// span, up to `max_spans` spans.
+pub(crate) fn record_field_spans(subgraph_name: &str, trace: &Trace, max_spans: usize) {
+    let Some(tracer) = OPENTELEMETRY_TRACER_HANDLE.get() else {
+        return;
+    };
+    let parent = Span::current().context();
+    if !parent.span().span_context().is_sampled() {
+        return;
+    }
+    record_trace(
+        tracer,
+        subgraph_name,
+        &parent,
+        SystemTime::now(),
+        trace,
+        max_spans,
+    );
+}
+
+/// The resolver timings are relative to the start of the subgraph trace, which is measured with the
+/// subgraph clock. They are anchored on the router clock so that the subgraph execution ends when
+/// the router received the response.
+fn record_trace<T>(
+    tracer: &T,
+    subgraph_name: &str,
+    parent: &Context,
+    received: SystemTime,
+    trace: &Trace,
+    max_spans: usize,
+) where
+    T: Tracer,
+    T::Span: Send + Sync + 'static,
+{
+    let Some(root) = &trace.root else {
+        return;
+    };
+    let start = received
+        .checked_sub(Duration::from_nanos(trace.duration_ns))
+        .unwrap_or(received);
+    let mut recorder = Recorder {
+        tracer,
+        subgraph_name,
+        start,
+        remaining_spans: max_spans,
+        path: Vec::new(),
+    };
+    for child in &root.child {
+        recorder.record_node(parent, child);
+    }
+    if recorder.remaining_spans == 0 {
+        tracing::debug!(
+            "the trace of subgraph '{subgraph_name}' has more than {max_spans} fields, the remaining ones were not recorded as spans"
+        );
+    }
+}
+
+struct Recorder<'a, T> {
+    tracer: &'a T,
+    subgraph_name: &'a str,
+    start: SystemTime,
+    remaining_spans: usize,
+    path: Vec<String>,
+}
+
+impl<T> Recorder<'_, T>
+where
+    T: Tracer,
+    T::Span: Send + Sync + 'static,
+{
+    fn record_node(&mut self, parent: &Context, node: &Node) {
+        match &node.id {
+            Some(Id::ResponseName(response_name)) => {
+                if self.remaining_spans == 0 {
+                    return;
+                }
+                self.remaining_spans -= 1;
+                self.path.push(response_name.clone());
+                let field_name = if node.original_field_name.is_empty() {
+                    response_name
+                } else {
+                    &node.original_field_name
+                };
+                let status = match node.error.first() {
+                    Some(error) => Status::error(error.message.clone()),
+                    None => Status::Unset,
+                };
+                let span = self.tracer.build_with_context(
+                    SpanBuilder::from_name(format!("{}.{}", node.parent_type, field_name))
+                        .with_kind(SpanKind::Internal)
+                        .with_start_time(self.start + Duration::from_nanos(node.start_time))
+                        .with_attributes([
+                            KeyValue::new("subgraph.name", self.subgraph_name.to_string()),
+                            KeyValue::new("graphql.field.name", field_name.clone()),
+                            KeyValue::new("graphql.field.type", node.r#type.clone()),
+                            KeyValue::new("graphql.type.name", node.parent_type.clone()),
+                            KeyValue::new("graphql.field.path", self.path.join(".")),
+                            KeyValue::new("graphql.error.count", node.error.len() as i64),
+                        ])
+                        .with_status(status),
+                    parent,
+                );
+                let context = parent.with_span(span);
+                for child in &node.child {
+                    self.record_node(&context, child);
+                }
+                context
+                    .span()
+                    .end_with_timestamp(self.start + Duration::from_nanos(node.end_time));
+                self.path.pop();
+            }
+            // list items are not resolved, their fields are children of the list field span
+            Some(Id::Index(index)) => {
+                self.path.push(index.to_string());
+                for child in &node.child {
+                    self.record_node(parent, child);
+                }
+                self.path.pop();
+            }
+            None => {
+                for child in &node.child {
+                    self.record_node(parent, child);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use opentelemetry::sdk::export::trace::SpanData;
+    use opentelemetry::sdk::trace::SpanProcessor;
+    use opentelemetry::sdk::trace::TracerProvider;
+    use opentelemetry::trace::TraceResult;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::Key;
+    use opentelemetry::Value;
+    use parking_lot::Mutex;
+
+    use super::*;
+    use crate::plugins::telemetry::apollo_exporter::proto::reports::trace::Error;
+    use crate::plugins::telemetry::tracing::apollo_telemetry::decode_ftv1_trace;
+    use crate::plugins::telemetry::tracing::apollo_telemetry::encode_ftv1_trace;
+
+    #[derive(Debug, Default, Clone)]
+    struct Collect(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanProcessor for Collect {
+        fn on_start(&self, _span: &mut opentelemetry::sdk::trace::Span, _cx: &Context) {}
+
+        fn on_end(&self, span: SpanData) {
+            self.0.lock().push(span);
+        }
+
+        fn force_flush(&self) -> TraceResult<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> TraceResult<()> {
+            Ok(())
+        }
+    }
+
+    fn field(response_name: &str, parent_type: &str, start: u64, end: u64) -> Node {
+        Node {
+            id: Some(Id::ResponseName(response_name.to_string())),
+            r#type: "String".to_string(),
+            parent_type: parent_type.to_string(),
+            start_time: start,
+            end_time: end,
+            ..Default::default()
+        }
+    }
+
+    /// `{ topProducts { name reviews { body } } }` with two products, the reviews of the second
+    /// one failing
+    fn ftv1_fixture() -> Trace {
+        let product = |index: u32, reviews: Node| Node {
+            id: Some(Id::Index(index)),
+            child: vec![field("name", "Product", 300, 400), reviews],
+            ..Default::default()
+        };
+        let trace = Trace {
+            duration_ns: 1_000,
+            root: Some(Node {
+                child: vec![Node {
+                    r#type: "[Product]".to_string(),
+                    child: vec![
+                        product(
+                            0,
+                            Node {
+                                r#type: "[Review]".to_string(),
+                                child: vec![Node {
+                                    id: Some(Id::Index(0)),
+                                    child: vec![field("body", "Review", 600, 700)],
+                                    ..Default::default()
+                                }],
+                                ..field("reviews", "Product", 400, 800)
+                            },
+                        ),
+                        product(
+                            1,
+                            Node {
+                                r#type: "[Review]".to_string(),
+                                error: vec![Error {
+                                    message: "reviews are unavailable".to_string(),
+                                    ..Default::default()
+                                }],
+                                ..field("reviews", "Product", 400, 500)
+                            },
+                        ),
+                    ],
+                    ..field("topProducts", "Query", 100, 900)
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // the trace goes through the FTV1 encoding, as in subgraph responses
+        decode_ftv1_trace(&encode_ftv1_trace(&trace)).expect("valid FTV1 trace")
+    }
+
+    fn record(max_spans: usize) -> (SpanData, Vec<SpanData>) {
+        let collect = Collect::default();
+        let provider = TracerProvider::builder()
+            .with_span_processor(collect.clone())
+            .build();
+        let tracer = provider.tracer("test");
+        let parent = Context::new().with_span(tracer.start("subgraph"));
+        let received = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        record_trace(
+            &tracer,
+            "products",
+            &parent,
+            received,
+            &ftv1_fixture(),
+            max_spans,
+        );
+        parent.span().end();
+
+        let mut spans = std::mem::take(&mut *collect.0.lock());
+        let subgraph = spans.pop().expect("the subgraph span ends last");
+        (subgraph, spans)
+    }
+
+    fn attribute<'a>(span: &'a SpanData, key: &'static str) -> Option<&'a Value> {
+        span.attributes.get(&Key::from_static_str(key))
+    }
+
+    fn find<'a>(spans: &'a [SpanData], path: &str) -> &'a SpanData {
+        spans
+            .iter()
+            .find(|span| {
+                attribute(span, "graphql.field.path") == Some(&Value::from(path.to_string()))
+            })
+            .unwrap_or_else(|| panic!("no span for {path}"))
+    }
+
+    #[test]
+    fn field_spans_follow_the_subgraph_trace() {
+        let (subgraph, spans) = record(500);
+        assert_eq!(spans.len(), 6);
+
+        let top_products = find(&spans, "topProducts");
+        assert_eq!(top_products.name, "Query.topProducts");
+        assert_eq!(top_products.parent_span_id, subgraph.span_context.span_id());
+        assert_eq!(
+            top_products.span_context.trace_id(),
+            subgraph.span_context.trace_id()
+        );
+        // the subgraph trace ends when the response was received
+        let start =
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_000) - Duration::from_nanos(1_000);
+        assert_eq!(top_products.start_time, start + Duration::from_nanos(100));
+        assert_eq!(top_products.end_time, start + Duration::from_nanos(900));
+        assert_eq!(
+            attribute(top_products, "subgraph.name"),
+            Some(&Value::from("products"))
+        );
+        assert_eq!(
+            attribute(top_products, "graphql.field.type"),
+            Some(&Value::from("[Product]"))
+        );
+        assert_eq!(
+            attribute(top_products, "graphql.type.name"),
+            Some(&Value::from("Query"))
+        );
+        assert_eq!(top_products.status, Status::Unset);
+
+        // list items are not spans, their fields are children of the list field
+        let name = find(&spans, "topProducts.0.name");
+        assert_eq!(name.name, "Product.name");
+        assert_eq!(name.parent_span_id, top_products.span_context.span_id());
+        let reviews = find(&spans, "topProducts.0.reviews");
+        let body = find(&spans, "topProducts.0.reviews.0.body");
+        assert_eq!(body.parent_span_id, reviews.span_context.span_id());
+        assert_eq!(body.start_time, start + Duration::from_nanos(600));
+        assert_eq!(body.end_time, start + Duration::from_nanos(700));
+
+        let failed_reviews = find(&spans, "topProducts.1.reviews");
+        assert_eq!(
+            failed_reviews.status,
+            Status::error("reviews are unavailable")
+        );
+        assert_eq!(
+            attribute(failed_reviews, "graphql.error.count"),
+            Some(&Value::I64(1))
+        );
+    }
+
+    #[test]
+    fn field_spans_are_limited() {
+        let (_, spans) = record(3);
+        assert_eq!(spans.len(), 3);
+        find(&spans, "topProducts");
+        find(&spans, "topProducts.0.name");
+        find(&spans, "topProducts.0.reviews");
+    }
+}
@@ -3,9 +3,11 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::io::IsTerminal;
 use std::marker::PhantomData;
+use std::sync::Mutex;
 
 use opentelemetry::Key;
 use opentelemetry::KeyValue;
+use tower::BoxError;
 use tracing::field;
 use tracing_core::span::Id;
 use tracing_core::span::Record;
@@ -16,75 +18,188 @@ use tracing_subscriber::layer::Context;
 use tracing_subscriber::Layer;
 
 use super::config_new::ToOtelValue;
+use super::dynamic_attribute::clear_event_attributes;
 use super::dynamic_attribute::LogAttributes;
 use super::formatters::EventFormatter;
 use super::formatters::EXCLUDED_ATTRIBUTES;
 use super::reload::IsSampled;
 use crate::plugins::telemetry::config;
 use crate::plugins::telemetry::config_new::logging::Format;
+use crate::plugins::telemetry::config_new::logging::RateLimit;
 use crate::plugins::telemetry::config_new::logging::StdOut;
+use crate::plugins::telemetry::formatters::aws::Aws;
+use crate::plugins::telemetry::formatters::bunyan::Bunyan;
 use crate::plugins::telemetry::formatters::filter_metric_events;
+use crate::plugins::telemetry::formatters::gelf::Gelf;
+use crate::plugins::telemetry::formatters::google::Google;
 use crate::plugins::telemetry::formatters::json::Json;
 use crate::plugins::telemetry::formatters::text::Text;
 use crate::plugins::telemetry::formatters::FilteringFormatter;
+use crate::plugins::telemetry::logging::otlp::OtlpLogLayer;
+use crate::plugins::telemetry::logging::rolling_file::RollingFileWriter;
 use crate::plugins::telemetry::reload::LayeredTracer;
 use crate::plugins::telemetry::resource::ConfigResource;
 
 pub(crate) fn create_fmt_layer(
     config: &config::Conf,
-) -> Box<dyn Layer<LayeredTracer> + Send + Sync> {
-    match &config.exporters.logging.stdout {
-        StdOut {
-            enabled,
-            format,
-            tty_format,
-            rate_limit,
-        } if *enabled => {
-            let format = if std::io::stdout().is_terminal() && tty_format.is_some() {
-                tty_format
-                    .as_ref()
-                    .expect("checked previously in the if; qed")
-            } else {
-                format
-            };
-            match format {
-                Format::Json(format_config) => {
-                    let format = Json::new(
-                        config.exporters.logging.common.to_resource(),
-                        format_config.clone(),
-                    );
-                    FmtLayer::new(
-                        FilteringFormatter::new(format, filter_metric_events, rate_limit),
-                        std::io::stdout,
-                    )
-                    .boxed()
-                }
+) -> Result<Box<dyn Layer<LayeredTracer> + Send + Sync>, BoxError> {
+    let logging = &config.exporters.logging;
+    let mut layers: Vec<Box<dyn Layer<LayeredTracer> + Send + Sync>> = Vec::new();
+
+    // every output must see the event attributes before they are cleared by the last fmt layer
+    if logging.otlp.enabled {
+        let otlp_layer = OtlpLogLayer::new(&logging.otlp, logging.common.to_resource())
+            .map_err(|e| format!("cannot create the otlp log exporter: {e}"))?;
+        layers.push(otlp_layer.boxed());
+    }
+    let stdout_layer = create_stdout_layer(config);
+    let mut clears_event_attributes = stdout_layer.is_some();
+    if logging.file.enabled {
+        let file_layer = create_file_layer(config, stdout_layer.is_some())
+            .map_err(|e| format!("cannot open the log file '{}': {e}", logging.file.path))?;
+        layers.push(file_layer);
+        clears_event_attributes = true;
+    }
+    layers.extend(stdout_layer);
+    if !clears_event_attributes {
+        layers.push(EventAttributesCleanup.boxed());
+    }
 
-                Format::Text(format_config) => {
-                    let format = Text::new(
-                        config.exporters.logging.common.to_resource(),
-                        format_config.clone(),
-                    );
-                    FmtLayer::new(
-                        FilteringFormatter::new(format, filter_metric_events, rate_limit),
-                        std::io::stdout,
-                    )
-                    .boxed()
-                }
-            }
-        }
-        _ => NoOpLayer.boxed(),
+    Ok(layers.boxed())
+}
+
+fn create_stdout_layer(
+    config: &config::Conf,
+) -> Option<Box<dyn Layer<LayeredTracer> + Send + Sync>> {
+    let StdOut {
+        enabled,
+        format,
+        tty_format,
+        rate_limit,
+    } = &config.exporters.logging.stdout;
+    if !enabled {
+        return None;
+    }
+    let format = if std::io::stdout().is_terminal() && tty_format.is_some() {
+        tty_format
+            .as_ref()
+            .expect("checked previously in the if; qed")
+    } else {
+        format
+    };
+    Some(create_output_layer(
+        config,
+        format,
+        rate_limit,
+        std::io::stdout,
+        false,
+    ))
+}
+
+fn create_file_layer(
+    config: &config::Conf,
+    secondary: bool,
+) -> std::io::Result<Box<dyn Layer<LayeredTracer> + Send + Sync>> {
+    let file = &config.exporters.logging.file;
+    let writer = RollingFileWriter::new(&file.path, file.rollover.clone(), file.max_files)?
+        .with_max_size(file.max_size.map(|max_size| max_size.as_u64()))
+        .with_compression(file.compress);
+    Ok(create_output_layer(
+        config,
+        &file.format,
+        &file.rate_limit.clone().unwrap_or_default(),
+        Mutex::new(writer),
+        secondary,
+    ))
+}
+
+fn create_output_layer<W>(
+    config: &config::Conf,
+    format: &Format,
+    rate_limit: &RateLimit,
+    make_writer: W,
+    secondary: bool,
+) -> Box<dyn Layer<LayeredTracer> + Send + Sync>
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let resource = config.exporters.logging.common.to_resource();
+    match format {
+        Format::Json(format_config) => output_layer(
+            Json::new(resource, format_config.clone()),
+            rate_limit,
+            make_writer,
+            secondary,
+        ),
+        Format::Text(format_config) => output_layer(
+            Text::new(resource, format_config.clone()),
+            rate_limit,
+            make_writer,
+            secondary,
+        ),
+        Format::Aws(format_config) => output_layer(
+            Aws::new(resource, format_config.clone()),
+            rate_limit,
+            make_writer,
+            secondary,
+        ),
+        Format::Bunyan(format_config) => output_layer(
+            Bunyan::new(resource, format_config.clone()),
+            rate_limit,
+            make_writer,
+            secondary,
+        ),
+        Format::Gelf(format_config) => output_layer(
+            Gelf::new(resource, format_config.clone()),
+            rate_limit,
+            make_writer,
+            secondary,
+        ),
+        Format::Google(format_config) => output_layer(
+            Google::new(resource, format_config.clone()),
+            rate_limit,
+            make_writer,
+            secondary,
+        ),
     }
 }
 
-struct NoOpLayer;
+fn output_layer<T, W>(
+    format: T,
+    rate_limit: &RateLimit,
+    make_writer: W,
+    secondary: bool,
+) -> Box<dyn Layer<LayeredTracer> + Send + Sync>
+where
+    T: EventFormatter<LayeredTracer> + Send + Sync + 'static,
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    FmtLayer::new(
+        FilteringFormatter::new(format, filter_metric_events, rate_limit),
+        make_writer,
+    )
+    .with_secondary(secondary)
+    .boxed()
+}
+
+/// Clears the event attributes when no fmt layer is there to do it
+struct EventAttributesCleanup;
 
-impl Layer<LayeredTracer> for NoOpLayer {}
+impl Layer<LayeredTracer> for EventAttributesCleanup {
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, LayeredTracer>) {
+        if let Some(span) = ctx.event_span(event) {
+            clear_event_attributes(&span);
+        }
+    }
+}
 
 pub(crate) struct FmtLayer<T, S, W> {
     fmt_event: T,
     excluded_attributes: HashSet<&'static str>,
     make_writer: W,
+    /// A secondary layer comes before another FmtLayer, which records the span attributes and
+    /// clears the event attributes for both
+    secondary: bool,
     _inner: PhantomData<S>,
 }
 
@@ -99,9 +214,15 @@ where
             fmt_event,
             excluded_attributes: EXCLUDED_ATTRIBUTES.into(),
             make_writer,
+            secondary: false,
             _inner: PhantomData,
         }
     }
+
+    pub(crate) fn with_secondary(mut self, secondary: bool) -> Self {
+        self.secondary = secondary;
+        self
+    }
 }
 
 impl<S, T, W> Layer<S> for FmtLayer<T, S, W>
@@ -116,6 +237,9 @@ where
         id: &tracing_core::span::Id,
         ctx: Context<'_, S>,
     ) {
+        if self.secondary {
+            return;
+        }
         let span = ctx.span(id).expect("Span not found, this is a bug");
         let mut visitor = FieldsVisitor::new(&self.excluded_attributes);
         // We're checking if it's sampled to not add both attributes in OtelData and our LogAttributes
@@ -145,6 +269,9 @@ where
     }
 
     fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if self.secondary {
+            return;
+        }
         let span = ctx.span(id).expect("Span not found, this is a bug");
         let mut extensions = span.extensions_mut();
         if let Some(fields) = extensions.get_mut::<LogAttributes>() {
@@ -187,6 +314,12 @@ where
             }
             buf.clear();
         });
+
+        if !self.secondary {
+            if let Some(span) = ctx.event_span(event) {
+                clear_event_attributes(&span);
+            }
+        }
     }
 }
 
@@ -197,7 +330,7 @@ pub(crate) struct FieldsVisitor<'a, 'b> {
 }
 
 impl<'a, 'b> FieldsVisitor<'a, 'b> {
-    fn new(excluded_attributes: &'b HashSet<&'static str>) -> Self {
+    pub(crate) fn new(excluded_attributes: &'b HashSet<&'static str>) -> Self {
         Self {
             values: HashMap::with_capacity(0),
             excluded_attributes,
@@ -602,6 +735,58 @@ subgraph:
         insta::assert_snapshot!(buff.to_string());
     }
 
+    #[tokio::test]
+    async fn test_secondary_layer_with_custom_events() {
+        let secondary_buff = LogBuffer::default();
+        let buff = LogBuffer::default();
+        let format = || {
+            Json::new(
+                Default::default(),
+                JsonFormat {
+                    display_resource: false,
+                    ..Default::default()
+                },
+            )
+        };
+        let secondary_layer = FmtLayer::new(
+            FilteringFormatter::new(format(), filter_metric_events, &RateLimit::default()),
+            secondary_buff.clone(),
+        )
+        .with_secondary(true)
+        .boxed();
+        let fmt_layer = FmtLayer::new(
+            FilteringFormatter::new(format(), filter_metric_events, &RateLimit::default()),
+            buff.clone(),
+        )
+        .boxed();
+
+        ::tracing::subscriber::with_default(
+            fmt::Subscriber::new()
+                .with(otel::layer().force_sampling())
+                .with(secondary_layer)
+                .with(fmt_layer),
+            || {
+                let test_span = info_span!("test");
+                let _enter = test_span.enter();
+                log_event(
+                    EventLevel::Info,
+                    "my_custom_event",
+                    vec![KeyValue::new("http.response.body.size", "125")],
+                    "my message",
+                );
+
+                error!(http.method = "GET", "Hello from test");
+            },
+        );
+
+        for logs in [secondary_buff.to_string(), buff.to_string()] {
+            let lines = logs.lines().collect::<Vec<_>>();
+            assert_eq!(lines.len(), 2);
+            assert!(lines[0].contains(r#""http.response.body.size":"125""#));
+            assert!(!lines[1].contains("http.response.body.size"));
+        }
+    }
+
     #[tokio::test]
     async fn test_json_logging_with_custom_events() {
         let buff = LogBuffer::default();
@@ -0,0 +1,128 @@
+//! AWS CloudWatch format, with the trace id in the AWS X-Ray format so that CloudWatch can link
+//! log events to traces
+use std::collections::HashSet;
+use std::fmt;
+
+use opentelemetry::sdk::Resource;
+use opentelemetry::trace::TraceId;
+use time::format_description::well_known::Rfc3339;
+use tracing_core::Event;
+use tracing_core::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use super::to_list;
+use super::EventFields;
+use super::EventFormatter;
+use super::EXCLUDED_ATTRIBUTES;
+use crate::plugins::telemetry::config_new::logging::AwsFormat;
+
+#[derive(Debug)]
+pub(crate) struct Aws {
+    config: AwsFormat,
+    service: Option<serde_json::Value>,
+    resource: Vec<(String, serde_json::Value)>,
+    excluded_attributes: HashSet<&'static str>,
+}
+
+impl Aws {
+    pub(crate) fn new(resource: Resource, config: AwsFormat) -> Self {
+        let resource = to_list(resource);
+        Self {
+            config,
+            service: resource
+                .iter()
+                .find(|(key, _)| key == "service.name")
+                .map(|(_, value)| value.clone()),
+            resource,
+            excluded_attributes: EXCLUDED_ATTRIBUTES.into(),
+        }
+    }
+}
+
+impl<S> EventFormatter<S> for Aws
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn format_event<W>(
+        &self,
+        ctx: &Context<'_, S>,
+        writer: &mut W,
+        event: &Event<'_>,
+    ) -> fmt::Result
+    where
+        W: std::fmt::Write,
+    {
+        let meta = event.metadata();
+        let EventFields {
+            timestamp,
+            message,
+            mut fields,
+            trace_and_span_id,
+            ..
+        } = EventFields::new(ctx, event, &self.excluded_attributes);
+
+        fields.insert(
+            "timestamp".to_string(),
+            timestamp.format(&Rfc3339).map_err(|_| fmt::Error)?.into(),
+        );
+        fields.insert("level".to_string(), meta.level().as_str().into());
+        fields.insert("message".to_string(), message.unwrap_or_default().into());
+        fields.insert("target".to_string(), meta.target().into());
+        if let Some(service) = &self.service {
+            fields.insert("service".to_string(), service.clone());
+        }
+        if let Some((trace_id, span_id)) = trace_and_span_id {
+            fields.insert("xray_trace_id".to_string(), xray_trace_id(trace_id).into());
+            fields.insert("span_id".to_string(), span_id.to_string().into());
+        }
+        if self.config.display_resource {
+            fields.insert(
+                "resource".to_string(),
+                serde_json::Value::Object(self.resource.iter().cloned().collect()),
+            );
+        }
+
+        writeln!(writer, "{}", serde_json::Value::Object(fields))
+    }
+}
+
+/// X-Ray trace ids are made of a version, the epoch time of the trace start in 8 hexadecimal
+/// digits, and 24 random hexadecimal digits: `1-5759e988-bd862e3fe1be46a994272793`
+fn xray_trace_id(trace_id: TraceId) -> String {
+    let trace_id = trace_id.to_string();
+    format!("1-{}-{}", &trace_id[..8], &trace_id[8..])
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry::KeyValue;
+
+    use super::*;
+    use crate::plugins::telemetry::formatters::format_test_event;
+
+    #[test]
+    fn xray_trace_ids() {
+        let trace_id = TraceId::from_hex("5759e988bd862e3fe1be46a994272793").unwrap();
+        assert_eq!(
+            xray_trace_id(trace_id),
+            "1-5759e988-bd862e3fe1be46a994272793"
+        );
+    }
+
+    #[test]
+    fn aws_format() {
+        let entry = format_test_event(Aws::new(
+            Resource::new([KeyValue::new("service.name", "my-router")]),
+            AwsFormat::default(),
+        ));
+
+        assert_eq!(entry["level"], "ERROR");
+        assert_eq!(entry["message"], "Hello from test");
+        assert_eq!(entry["http.method"], "GET");
+        assert_eq!(entry["http.response.status_code"], 200);
+        assert_eq!(entry["service"], "my-router");
+        assert!(entry["xray_trace_id"].as_str().unwrap().starts_with("1-"));
+        assert!(entry.get("resource").is_none());
+    }
+}
@@ -0,0 +1,120 @@
+//! Bunyan format, see https://github.com/trentm/node-bunyan#core-fields
+use std::collections::HashSet;
+use std::fmt;
+
+use opentelemetry::sdk::Resource;
+use time::format_description::well_known::Rfc3339;
+use tracing_core::Event;
+use tracing_core::Level;
+use tracing_core::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use super::to_list;
+use super::EventFields;
+use super::EventFormatter;
+use super::EXCLUDED_ATTRIBUTES;
+use crate::plugins::telemetry::config_new::logging::BunyanFormat;
+
+#[derive(Debug)]
+pub(crate) struct Bunyan {
+    name: String,
+    hostname: String,
+    excluded_attributes: HashSet<&'static str>,
+}
+
+impl Bunyan {
+    pub(crate) fn new(resource: Resource, config: BunyanFormat) -> Self {
+        let name = config.name.unwrap_or_else(|| {
+            to_list(resource)
+                .into_iter()
+                .find(|(key, _)| key == "service.name")
+                .and_then(|(_, value)| value.as_str().map(str::to_string))
+                .unwrap_or_else(|| "router".to_string())
+        });
+        Self {
+            name,
+            hostname: sys_info::hostname().unwrap_or_else(|_| "unknown".to_string()),
+            excluded_attributes: EXCLUDED_ATTRIBUTES.into(),
+        }
+    }
+}
+
+impl<S> EventFormatter<S> for Bunyan
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn format_event<W>(
+        &self,
+        ctx: &Context<'_, S>,
+        writer: &mut W,
+        event: &Event<'_>,
+    ) -> fmt::Result
+    where
+        W: std::fmt::Write,
+    {
+        let meta = event.metadata();
+        let EventFields {
+            timestamp,
+            message,
+            fields,
+            trace_and_span_id,
+            ..
+        } = EventFields::new(ctx, event, &self.excluded_attributes);
+
+        let mut record = serde_json::Map::from_iter([
+            ("v".to_string(), 0.into()),
+            ("level".to_string(), level(meta.level()).into()),
+            ("name".to_string(), self.name.clone().into()),
+            ("hostname".to_string(), self.hostname.clone().into()),
+            ("pid".to_string(), std::process::id().into()),
+            (
+                "time".to_string(),
+                timestamp.format(&Rfc3339).map_err(|_| fmt::Error)?.into(),
+            ),
+            ("msg".to_string(), message.unwrap_or_default().into()),
+            ("target".to_string(), meta.target().into()),
+        ]);
+        if let Some((trace_id, span_id)) = trace_and_span_id {
+            record.insert("trace_id".to_string(), trace_id.to_string().into());
+            record.insert("span_id".to_string(), span_id.to_string().into());
+        }
+        record.extend(fields);
+
+        writeln!(writer, "{}", serde_json::Value::Object(record))
+    }
+}
+
+fn level(level: &Level) -> u8 {
+    match *level {
+        Level::TRACE => 10,
+        Level::DEBUG => 20,
+        Level::INFO => 30,
+        Level::WARN => 40,
+        Level::ERROR => 50,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry::KeyValue;
+
+    use super::*;
+    use crate::plugins::telemetry::formatters::format_test_event;
+
+    #[test]
+    fn bunyan_format() {
+        let record = format_test_event(Bunyan::new(
+            Resource::new([KeyValue::new("service.name", "my-router")]),
+            BunyanFormat::default(),
+        ));
+
+        assert_eq!(record["v"], 0);
+        assert_eq!(record["level"], 50);
+        assert_eq!(record["name"], "my-router");
+        assert_eq!(record["msg"], "Hello from test");
+        assert_eq!(record["http.method"], "GET");
+        assert_eq!(record["http.response.status_code"], 200);
+        assert_eq!(record["span_id"].as_str().unwrap().len(), 16);
+    }
+}
@@ -0,0 +1,149 @@
+//! Graylog Extended Log Format, see https://go2docs.graylog.org/5-0/getting_in_log_data/gelf.html
+use std::collections::HashSet;
+use std::fmt;
+
+use opentelemetry::sdk::Resource;
+use tracing_core::Event;
+use tracing_core::Level;
+use tracing_core::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use super::to_list;
+use super::EventFields;
+use super::EventFormatter;
+use super::EXCLUDED_ATTRIBUTES;
+use crate::plugins::telemetry::config_new::logging::GelfFormat;
+
+#[derive(Debug)]
+pub(crate) struct Gelf {
+    host: String,
+    resource: Vec<(String, serde_json::Value)>,
+    excluded_attributes: HashSet<&'static str>,
+}
+
+impl Gelf {
+    pub(crate) fn new(resource: Resource, config: GelfFormat) -> Self {
+        Self {
+            host: config
+                .host
+                .unwrap_or_else(|| sys_info::hostname().unwrap_or_else(|_| "unknown".to_string())),
+            resource: to_list(resource),
+            excluded_attributes: EXCLUDED_ATTRIBUTES.into(),
+        }
+    }
+}
+
+impl<S> EventFormatter<S> for Gelf
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn format_event<W>(
+        &self,
+        ctx: &Context<'_, S>,
+        writer: &mut W,
+        event: &Event<'_>,
+    ) -> fmt::Result
+    where
+        W: std::fmt::Write,
+    {
+        let meta = event.metadata();
+        let EventFields {
+            timestamp,
+            message,
+            fields,
+            trace_and_span_id,
+            ..
+        } = EventFields::new(ctx, event, &self.excluded_attributes);
+
+        let mut message = serde_json::Map::from_iter([
+            ("version".to_string(), "1.1".into()),
+            ("host".to_string(), self.host.clone().into()),
+            (
+                "short_message".to_string(),
+                message.unwrap_or_default().into(),
+            ),
+            (
+                "timestamp".to_string(),
+                (timestamp.unix_timestamp_nanos() as f64 / 1_000_000_000.0).into(),
+            ),
+            ("level".to_string(), level(meta.level()).into()),
+            ("_target".to_string(), meta.target().into()),
+        ]);
+        if let Some((trace_id, span_id)) = trace_and_span_id {
+            message.insert("_trace_id".to_string(), trace_id.to_string().into());
+            message.insert("_span_id".to_string(), span_id.to_string().into());
+        }
+        let additional_fields = self.resource.iter().cloned().chain(fields);
+        for (key, value) in additional_fields {
+            message.insert(additional_field(&key), value);
+        }
+
+        writeln!(writer, "{}", serde_json::Value::Object(message))
+    }
+}
+
+/// Syslog severity levels
+fn level(level: &Level) -> u8 {
+    match *level {
+        Level::TRACE | Level::DEBUG => 7,
+        Level::INFO => 6,
+        Level::WARN => 4,
+        Level::ERROR => 3,
+    }
+}
+
+/// Additional fields are prefixed with an underscore, may only contain letters, numbers,
+/// underscores, dashes and dots, and `_id` is reserved
+fn additional_field(key: &str) -> String {
+    let key = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if key == "id" {
+        "__id".to_string()
+    } else {
+        format!("_{key}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry::KeyValue;
+
+    use super::*;
+    use crate::plugins::telemetry::formatters::format_test_event;
+
+    #[test]
+    fn additional_fields() {
+        assert_eq!(additional_field("http.method"), "_http.method");
+        assert_eq!(additional_field("id"), "__id");
+        assert_eq!(additional_field("my field"), "_my_field");
+    }
+
+    #[test]
+    fn gelf_format() {
+        let formatter = Gelf::new(
+            Resource::new([KeyValue::new("service.name", "my-router")]),
+            GelfFormat {
+                host: Some("router-1".to_string()),
+            },
+        );
+        let message = format_test_event(formatter);
+
+        assert_eq!(message["version"], "1.1");
+        assert_eq!(message["host"], "router-1");
+        assert_eq!(message["short_message"], "Hello from test");
+        assert_eq!(message["level"], 3);
+        assert_eq!(message["_http.method"], "GET");
+        assert_eq!(message["_http.response.status_code"], 200);
+        assert_eq!(message["_service.name"], "my-router");
+        assert_eq!(message["_trace_id"].as_str().unwrap().len(), 32);
+    }
+}
@@ -0,0 +1,137 @@
+//! Google Cloud Logging structured format, see https://cloud.google.com/logging/docs/structured-logging
+use std::collections::HashSet;
+use std::fmt;
+
+use opentelemetry::sdk::Resource;
+use serde_json::json;
+use time::format_description::well_known::Rfc3339;
+use tracing_core::Event;
+use tracing_core::Level;
+use tracing_core::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+use super::to_labels;
+use super::to_list;
+use super::EventFields;
+use super::EventFormatter;
+use super::EXCLUDED_ATTRIBUTES;
+use crate::plugins::telemetry::config_new::logging::GoogleFormat;
+
+const TRACE: &str = "logging.googleapis.com/trace";
+const SPAN_ID: &str = "logging.googleapis.com/spanId";
+const TRACE_SAMPLED: &str = "logging.googleapis.com/trace_sampled";
+const SOURCE_LOCATION: &str = "logging.googleapis.com/sourceLocation";
+const LABELS: &str = "logging.googleapis.com/labels";
+
+#[derive(Debug)]
+pub(crate) struct Google {
+    config: GoogleFormat,
+    resource: Vec<(String, serde_json::Value)>,
+    excluded_attributes: HashSet<&'static str>,
+}
+
+impl Google {
+    pub(crate) fn new(resource: Resource, config: GoogleFormat) -> Self {
+        Self {
+            config,
+            resource: to_list(resource),
+            excluded_attributes: EXCLUDED_ATTRIBUTES.into(),
+        }
+    }
+}
+
+impl<S> EventFormatter<S> for Google
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn format_event<W>(
+        &self,
+        ctx: &Context<'_, S>,
+        writer: &mut W,
+        event: &Event<'_>,
+    ) -> fmt::Result
+    where
+        W: std::fmt::Write,
+    {
+        let meta = event.metadata();
+        let EventFields {
+            timestamp,
+            message,
+            mut fields,
+            trace_and_span_id,
+            sampled,
+        } = EventFields::new(ctx, event, &self.excluded_attributes);
+
+        fields.insert("severity".to_string(), severity(meta.level()).into());
+        fields.insert("message".to_string(), message.unwrap_or_default().into());
+        fields.insert(
+            "time".to_string(),
+            timestamp.format(&Rfc3339).map_err(|_| fmt::Error)?.into(),
+        );
+        fields.insert(
+            SOURCE_LOCATION.to_string(),
+            json!({
+                "file": meta.file(),
+                "line": meta.line().map(|line| line.to_string()),
+                "function": meta.target(),
+            }),
+        );
+        if let Some((trace_id, span_id)) = trace_and_span_id {
+            let trace = match &self.config.project_id {
+                Some(project_id) => format!("projects/{project_id}/traces/{trace_id}"),
+                None => trace_id.to_string(),
+            };
+            fields.insert(TRACE.to_string(), trace.into());
+            fields.insert(SPAN_ID.to_string(), span_id.to_string().into());
+            fields.insert(TRACE_SAMPLED.to_string(), sampled.into());
+        }
+        if !self.resource.is_empty() {
+            fields.insert(LABELS.to_string(), to_labels(&self.resource));
+        }
+
+        writeln!(writer, "{}", serde_json::Value::Object(fields))
+    }
+}
+
+/// https://cloud.google.com/logging/docs/reference/v2/rest/v2/LogEntry#LogSeverity
+fn severity(level: &Level) -> &'static str {
+    match *level {
+        Level::TRACE | Level::DEBUG => "DEBUG",
+        Level::INFO => "INFO",
+        Level::WARN => "WARNING",
+        Level::ERROR => "ERROR",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry::KeyValue;
+
+    use super::*;
+    use crate::plugins::telemetry::formatters::format_test_event;
+
+    #[test]
+    fn google_format() {
+        let formatter = Google::new(
+            Resource::new([KeyValue::new("service.name", "my-router")]),
+            GoogleFormat {
+                project_id: Some("my-project".to_string()),
+            },
+        );
+        let entry = format_test_event(formatter);
+
+        assert_eq!(entry["severity"], "ERROR");
+        assert_eq!(entry["message"], "Hello from test");
+        assert_eq!(entry["time"], "1970-01-01T00:00:00Z");
+        assert_eq!(entry["http.method"], "GET");
+        assert_eq!(entry["http.response.status_code"], 200);
+        assert!(entry[TRACE]
+            .as_str()
+            .unwrap()
+            .starts_with("projects/my-project/traces/"));
+        assert_eq!(entry[SPAN_ID].as_str().unwrap().len(), 16);
+        assert_eq!(entry[TRACE_SAMPLED], true);
+        assert_eq!(entry[LABELS]["service.name"], "my-router");
+    }
+}
@@ -258,19 +258,18 @@ where
                     }
                 };
                 let event_attributes = {
-                    let mut extensions = span.extensions_mut();
-                    let otel_data = extensions.get_mut::<OtelData>();
-                    let attrs = otel_data.and_then(|od| od.event_attributes.take());
+                    let extensions = span.extensions();
+                    let otel_data = extensions.get::<OtelData>();
+                    let attrs = otel_data.and_then(|od| od.event_attributes.clone());
                     match attrs {
                         Some(attrs) => Some(attrs),
                         None => {
-                            let event_attributes = extensions.get_mut::<EventAttributes>();
+                            let event_attributes = extensions.get::<EventAttributes>();
                             event_attributes.map(|event_attributes| {
                                 OrderMap::from_iter(
                                     event_attributes
-                                        .take()
-                                        .into_iter()
-                                        .map(|kv| (kv.key, kv.value)),
+                                        .iter()
+                                        .map(|kv| (kv.key.clone(), kv.value.clone())),
                                 )
                             })
                         }
@@ -1,8 +1,13 @@
 //! Our formatters and visitors used for logging
+pub(crate) mod aws;
+pub(crate) mod bunyan;
+pub(crate) mod gelf;
+pub(crate) mod google;
 pub(crate) mod json;
 pub(crate) mod text;
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt;
 use std::time::Instant;
 
@@ -13,6 +18,7 @@ use opentelemetry_api::trace::TraceId;
 use opentelemetry_api::KeyValue;
 use parking_lot::Mutex;
 use serde_json::Number;
+use time::OffsetDateTime;
 use tracing::Subscriber;
 use tracing_core::callsite::Identifier;
 use tracing_subscriber::fmt::format::Writer;
@@ -22,8 +28,12 @@ use tracing_subscriber::layer::Context;
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::registry::SpanRef;
 
+use super::config::AttributeValue;
 use super::config_new::logging::RateLimit;
+use super::dynamic_attribute::EventAttributes;
 use super::dynamic_attribute::LogAttributes;
+use super::fmt_layer::FieldsVisitor;
+use super::reload::IsSampled;
 use super::reload::SampledSpan;
 use crate::metrics::layer::METRIC_PREFIX_COUNTER;
 use crate::metrics::layer::METRIC_PREFIX_HISTOGRAM;
@@ -325,3 +335,150 @@ where
 
     None
 }
+
+/// The parts of an event shared by the platform specific JSON formats
+pub(crate) struct EventFields {
+    pub(crate) timestamp: OffsetDateTime,
+    pub(crate) message: Option<String>,
+    /// The fields of the event, and the attributes of the custom events
+    pub(crate) fields: serde_json::Map<String, serde_json::Value>,
+    pub(crate) trace_and_span_id: Option<(TraceId, SpanId)>,
+    pub(crate) sampled: bool,
+}
+
+impl EventFields {
+    pub(crate) fn new<S>(
+        ctx: &Context<'_, S>,
+        event: &tracing::Event<'_>,
+        excluded_attributes: &HashSet<&'static str>,
+    ) -> Self
+    where
+        S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+    {
+        let mut visitor = FieldsVisitor::new(excluded_attributes);
+        event.record(&mut visitor);
+        let message = visitor
+            .values
+            .remove("message")
+            .map(|message| match message {
+                serde_json::Value::String(message) => message,
+                other => other.to_string(),
+            });
+        let mut fields = visitor
+            .values
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect::<serde_json::Map<_, _>>();
+
+        let mut trace_and_span_id = None;
+        let mut sampled = false;
+        let current_span = event
+            .parent()
+            .and_then(|id| ctx.span(id))
+            .or_else(|| ctx.lookup_current());
+        if let Some(span) = current_span {
+            trace_and_span_id = get_trace_and_span_id(&span);
+            sampled = span.is_sampled();
+
+            let extensions = span.extensions();
+            let event_attributes = match extensions
+                .get::<OtelData>()
+                .and_then(|otel_data| otel_data.event_attributes.as_ref())
+            {
+                Some(event_attributes) => event_attributes
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.clone()))
+                    .collect::<Vec<_>>(),
+                None => extensions
+                    .get::<EventAttributes>()
+                    .map(|event_attributes| {
+                        event_attributes
+                            .iter()
+                            .map(|kv| (kv.key.to_string(), kv.value.clone()))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            };
+            for (key, value) in event_attributes {
+                if key.starts_with(APOLLO_PRIVATE_PREFIX) {
+                    continue;
+                }
+                if let Ok(value) = serde_json::to_value(AttributeValue::from(value)) {
+                    fields.insert(key, value);
+                }
+            }
+        }
+
+        #[cfg(test)]
+        let timestamp = OffsetDateTime::UNIX_EPOCH;
+        #[cfg(not(test))]
+        let timestamp = OffsetDateTime::now_utc();
+
+        Self {
+            timestamp,
+            message,
+            fields,
+            trace_and_span_id,
+            sampled,
+        }
+    }
+}
+
+/// Resource values as strings, for the formats that only accept string labels
+pub(crate) fn to_labels(resource: &[(String, serde_json::Value)]) -> serde_json::Value {
+    serde_json::Value::Object(
+        resource
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    serde_json::Value::String(value) => value.clone(),
+                    other => other.to_string(),
+                };
+                (key.clone(), serde_json::Value::String(value))
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+struct TestFormatLayer<F>(F, std::sync::Arc<Mutex<String>>);
+
+#[cfg(test)]
+impl<S, F> tracing_subscriber::Layer<S> for TestFormatLayer<F>
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+    F: EventFormatter<S> + 'static,
+{
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+        let mut output = self.1.lock();
+        self.0
+            .format_event(&ctx, &mut *output, event)
+            .expect("event must be formatted");
+    }
+}
+
+/// Formats an error event, with custom event attributes, raised in a sampled span
+#[cfg(test)]
+pub(crate) fn format_test_event<F>(formatter: F) -> serde_json::Value
+where
+    F: EventFormatter<tracing_subscriber::Registry> + Send + Sync + 'static,
+{
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::dynamic_attribute::EventDynAttribute;
+
+    let output = std::sync::Arc::new(Mutex::new(String::new()));
+    tracing::subscriber::with_default(
+        tracing_subscriber::Registry::default()
+            .with(TestFormatLayer(formatter, output.clone()))
+            .with(super::otel::layer().force_sampling()),
+        || {
+            let span = tracing::info_span!("test");
+            let _guard = span.enter();
+            span.set_event_dyn_attributes([KeyValue::new("http.response.status_code", 200_i64)]);
+            tracing::error!(http.method = "GET", "Hello from test");
+        },
+    );
+    let output = output.lock();
+    serde_json::from_str(&output).expect("the output must be a JSON object")
+}
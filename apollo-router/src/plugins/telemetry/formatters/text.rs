@@ -383,19 +383,18 @@ where
             DefaultVisitor::new(writer.by_ref(), true, self.config.ansi_escape_codes);
 
         if let Some(span) = ctx.event_span(event) {
-            let mut extensions = span.extensions_mut();
-            let otel_data = extensions.get_mut::<OtelData>();
-            let attrs = otel_data.and_then(|od| od.event_attributes.take());
+            let extensions = span.extensions();
+            let otel_data = extensions.get::<OtelData>();
+            let attrs = otel_data.and_then(|od| od.event_attributes.clone());
             let event_attributes = match attrs {
                 Some(attrs) => Some(attrs),
                 None => {
-                    let event_attributes = extensions.get_mut::<EventAttributes>();
+                    let event_attributes = extensions.get::<EventAttributes>();
                     event_attributes.map(|event_attributes| {
                         OrderMap::from_iter(
                             event_attributes
-                                .take()
-                                .into_iter()
-                                .map(|kv| (kv.key, kv.value)),
+                                .iter()
+                                .map(|kv| (kv.key.clone(), kv.value.clone())),
                         )
                     })
                 }
@@ -0,0 +1,392 @@
+//! Export of the router's logs and events as OTLP log records
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+use opentelemetry::logs::AnyValue;
+use opentelemetry::logs::LogRecord;
+use opentelemetry::logs::Logger as _;
+use opentelemetry::logs::LoggerProvider as _;
+use opentelemetry::logs::Severity;
+use opentelemetry::sdk::logs::BatchLogProcessor;
+use opentelemetry::sdk::logs::Config;
+use opentelemetry::sdk::logs::Logger;
+use opentelemetry::sdk::logs::LoggerProvider;
+use opentelemetry::sdk::Resource;
+use opentelemetry::trace::SpanContext;
+use opentelemetry::trace::TraceFlags;
+use opentelemetry::trace::TraceState;
+use opentelemetry::Key;
+use opentelemetry_otlp::LogExporterBuilder;
+use tokio::runtime::Handle;
+use tower::BoxError;
+use tracing::field;
+use tracing_core::Event;
+use tracing_core::Field;
+use tracing_core::Level;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::plugins::telemetry::dynamic_attribute::EventAttributes;
+use crate::plugins::telemetry::formatters::filter_metric_events;
+use crate::plugins::telemetry::formatters::get_trace_and_span_id;
+use crate::plugins::telemetry::formatters::EXCLUDED_ATTRIBUTES;
+use crate::plugins::telemetry::otel::OtelData;
+use crate::plugins::telemetry::otlp;
+use crate::plugins::telemetry::otlp::TelemetryDataKind;
+use crate::plugins::telemetry::reload::IsSampled;
+
+/// Targets of the exporter's own logs, which must not be exported to avoid feedback loops
+const EXPORTER_TARGETS: [&str; 4] = ["opentelemetry", "tonic", "h2", "hyper"];
+
+/// Converts the tracing events to log records, exported by a batch processor
+pub(crate) struct OtlpLogLayer {
+    logger: Logger,
+    // the logger only holds a weak reference to its provider
+    provider: Option<LoggerProvider>,
+    excluded_attributes: HashSet<&'static str>,
+}
+
+impl OtlpLogLayer {
+    pub(crate) fn new(config: &otlp::Config, resource: Resource) -> Result<Self, BoxError> {
+        let exporter = config
+            .exporter::<LogExporterBuilder>(TelemetryDataKind::Logs)?
+            .build_log_exporter()?;
+        let batch_processor = &config.batch_processor;
+        let processor = BatchLogProcessor::builder(exporter, opentelemetry::runtime::Tokio)
+            .with_scheduled_delay(batch_processor.scheduled_delay)
+            .with_max_queue_size(batch_processor.max_queue_size)
+            .with_max_export_batch_size(batch_processor.max_export_batch_size)
+            .with_max_timeout(batch_processor.max_export_timeout)
+            .build();
+        let provider = LoggerProvider::builder()
+            .with_config(Config::default().with_resource(resource))
+            .with_log_processor(processor)
+            .build();
+
+        Ok(Self::with_provider(provider))
+    }
+
+    fn with_provider(provider: LoggerProvider) -> Self {
+        let logger = provider.versioned_logger(
+            "apollo-router",
+            Some(env!("CARGO_PKG_VERSION").into()),
+            None,
+            None,
+        );
+
+        Self {
+            logger,
+            provider: Some(provider),
+            excluded_attributes: EXCLUDED_ATTRIBUTES.into(),
+        }
+    }
+}
+
+impl Drop for OtlpLogLayer {
+    fn drop(&mut self) {
+        // shutting down the provider flushes the pending records and blocks, and the layer is
+        // dropped while the logging layers are reloaded
+        if let Some(provider) = self.provider.take() {
+            match Handle::try_current() {
+                Ok(handle) => {
+                    handle.spawn_blocking(move || drop(provider));
+                }
+                Err(_) => drop(provider),
+            }
+        }
+    }
+}
+
+impl<S> Layer<S> for OtlpLogLayer
+where
+    S: tracing_core::Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if !filter_metric_events(event)
+            || EXPORTER_TARGETS
+                .iter()
+                .any(|target| metadata.target().starts_with(target))
+        {
+            return;
+        }
+
+        let mut visitor = LogRecordVisitor {
+            body: None,
+            attributes: Vec::new(),
+            excluded_attributes: &self.excluded_attributes,
+        };
+        event.record(&mut visitor);
+
+        let mut builder = LogRecord::builder()
+            .with_timestamp(SystemTime::now())
+            .with_observed_timestamp(SystemTime::now())
+            .with_severity_number(severity(metadata.level()))
+            .with_severity_text(metadata.level().as_str());
+
+        if let Some(span) = ctx.event_span(event) {
+            if let Some((trace_id, span_id)) = get_trace_and_span_id(&span) {
+                let trace_flags = if span.is_sampled() {
+                    TraceFlags::SAMPLED
+                } else {
+                    TraceFlags::default()
+                };
+                builder = builder.with_span_context(&SpanContext::new(
+                    trace_id,
+                    span_id,
+                    trace_flags,
+                    false,
+                    TraceState::default(),
+                ));
+            }
+
+            // attributes of the custom events. They are cleared later by the last fmt layer
+            let extensions = span.extensions();
+            let event_attributes = match extensions
+                .get::<OtelData>()
+                .and_then(|otel_data| otel_data.event_attributes.as_ref())
+            {
+                Some(event_attributes) => event_attributes
+                    .iter()
+                    .map(|(key, value)| (key.clone(), AnyValue::from(value.clone())))
+                    .collect::<Vec<_>>(),
+                None => extensions
+                    .get::<EventAttributes>()
+                    .map(|event_attributes| {
+                        event_attributes
+                            .iter()
+                            .map(|kv| (kv.key.clone(), AnyValue::from(kv.value.clone())))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            };
+            visitor.attributes.extend(event_attributes);
+        }
+
+        if let Some(body) = visitor.body.take() {
+            builder = builder.with_body(AnyValue::from(body));
+        }
+        builder = builder
+            .with_attributes(visitor.attributes)
+            .with_attribute("code.namespace", metadata.target().to_string());
+
+        self.logger.emit(builder.build());
+    }
+}
+
+fn severity(level: &Level) -> Severity {
+    match *level {
+        Level::TRACE => Severity::Trace,
+        Level::DEBUG => Severity::Debug,
+        Level::INFO => Severity::Info,
+        Level::WARN => Severity::Warn,
+        Level::ERROR => Severity::Error,
+    }
+}
+
+struct LogRecordVisitor<'a> {
+    body: Option<String>,
+    attributes: Vec<(Key, AnyValue)>,
+    excluded_attributes: &'a HashSet<&'static str>,
+}
+
+impl<'a> LogRecordVisitor<'a> {
+    fn insert(&mut self, field: &Field, value: AnyValue) {
+        let name = field.name();
+        if name == "message" {
+            if let AnyValue::String(message) = value {
+                self.body = Some(message.to_string());
+            }
+        } else if !self.excluded_attributes.contains(name) && !name.starts_with("log.") {
+            self.attributes.push((Key::new(name), value));
+        }
+    }
+}
+
+impl<'a> field::Visit for LogRecordVisitor<'a> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, AnyValue::Double(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, AnyValue::Int(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, AnyValue::Int(value as i64));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, AnyValue::Boolean(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, AnyValue::from(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.insert(field, AnyValue::from(format!("{value:?}")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use opentelemetry::logs::LogResult;
+    use opentelemetry::sdk::export::logs::LogData;
+    use opentelemetry::sdk::export::logs::LogExporter;
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::KeyValue;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::plugins::telemetry::config_new::events::log_event;
+    use crate::plugins::telemetry::config_new::events::EventLevel;
+    use crate::plugins::telemetry::otel;
+    use crate::plugins::telemetry::otel::OpenTelemetrySpanExt;
+
+    #[test]
+    fn severities() {
+        assert_eq!(severity(&Level::TRACE), Severity::Trace);
+        assert_eq!(severity(&Level::DEBUG), Severity::Debug);
+        assert_eq!(severity(&Level::INFO), Severity::Info);
+        assert_eq!(severity(&Level::WARN), Severity::Warn);
+        assert_eq!(severity(&Level::ERROR), Severity::Error);
+    }
+
+    /// The body and attributes of the last log record
+    type Record = (Option<String>, Vec<(Key, AnyValue)>);
+
+    #[derive(Default, Clone)]
+    struct Capture(Arc<Mutex<Option<Record>>>);
+
+    impl<S: tracing_core::Subscriber> Layer<S> for Capture {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let excluded_attributes = EXCLUDED_ATTRIBUTES.into();
+            let mut visitor = LogRecordVisitor {
+                body: None,
+                attributes: Vec::new(),
+                excluded_attributes: &excluded_attributes,
+            };
+            event.record(&mut visitor);
+            *self.0.lock().unwrap() = Some((visitor.body, visitor.attributes));
+        }
+    }
+
+    #[test]
+    fn message_is_the_body() {
+        let capture = Capture::default();
+        let subscriber = tracing_subscriber::registry().with(capture.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(http.response.status_code = 200, "request received");
+        });
+
+        let (body, attributes) = capture.0.lock().unwrap().take().unwrap();
+        assert_eq!(body.as_deref(), Some("request received"));
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].0.as_str(), "http.response.status_code");
+    }
+
+    #[derive(Debug, Default, Clone)]
+    struct InMemoryExporter(Arc<Mutex<Vec<LogData>>>);
+
+    #[async_trait::async_trait]
+    impl LogExporter for InMemoryExporter {
+        async fn export(&mut self, batch: Vec<LogData>) -> LogResult<()> {
+            self.0.lock().unwrap().extend(batch);
+            Ok(())
+        }
+    }
+
+    fn attribute<'a>(data: &'a LogData, key: &str) -> Option<&'a AnyValue> {
+        data.record
+            .attributes
+            .iter()
+            .flatten()
+            .find(|(k, _)| k.as_str() == key)
+            .map(|(_, value)| value)
+    }
+
+    fn string(value: Option<&AnyValue>) -> Option<String> {
+        match value {
+            Some(AnyValue::String(value)) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn exports_records() {
+        let exporter = InMemoryExporter::default();
+        let provider = LoggerProvider::builder()
+            .with_config(
+                Config::default().with_resource(Resource::new([KeyValue::new(
+                    "service.name",
+                    "router-test",
+                )])),
+            )
+            .with_simple_exporter(exporter.clone())
+            .build();
+        // the SDK tracer generates the trace and span ids, while its provider is alive
+        let tracer_provider = opentelemetry::sdk::trace::TracerProvider::builder().build();
+        let tracer = tracer_provider.versioned_tracer("test", None::<String>, None::<String>, None);
+        let subscriber = tracing_subscriber::registry()
+            .with(otel::layer().force_sampling().with_tracer(tracer))
+            .with(OtlpLogLayer::with_provider(provider));
+
+        // dropping the subscriber shuts down the provider, which exports the pending records
+        let span_context = tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("test");
+            let _guard = span.enter();
+            tracing::info!(http.response.status_code = 200, "request received");
+            log_event(
+                EventLevel::Warn,
+                "my_event",
+                vec![KeyValue::new("custom", "value")],
+                "custom event",
+            );
+            span.context().span().span_context().clone()
+        });
+        assert!(span_context.is_valid());
+
+        let records = exporter.0.lock().unwrap();
+        assert_eq!(records.len(), 2);
+        for data in records.iter() {
+            let trace_context = data.record.trace_context.as_ref().unwrap();
+            assert_eq!(trace_context.trace_id, span_context.trace_id());
+            assert_eq!(trace_context.span_id, span_context.span_id());
+            assert_eq!(trace_context.trace_flags, Some(TraceFlags::SAMPLED));
+            assert_eq!(
+                data.resource.get(Key::from_static_str("service.name")),
+                Some("router-test".into())
+            );
+        }
+
+        let log = &records[0];
+        assert_eq!(log.record.severity_number, Some(Severity::Info));
+        assert_eq!(
+            string(log.record.body.as_ref()).as_deref(),
+            Some("request received")
+        );
+        assert!(matches!(
+            attribute(log, "http.response.status_code"),
+            Some(AnyValue::Int(200))
+        ));
+        assert!(attribute(log, "custom").is_none());
+
+        let event = &records[1];
+        assert_eq!(event.record.severity_number, Some(Severity::Warn));
+        assert_eq!(
+            string(event.record.body.as_ref()).as_deref(),
+            Some("custom event")
+        );
+        assert_eq!(
+            string(attribute(event, "kind")).as_deref(),
+            Some("my_event")
+        );
+        assert_eq!(string(attribute(event, "custom")).as_deref(), Some("value"));
+    }
+}
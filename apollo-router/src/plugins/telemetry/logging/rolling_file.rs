@@ -0,0 +1,451 @@
+//! A file writer rolling over to a new file every hour or day, or when it grows too large
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use time::OffsetDateTime;
+
+use crate::plugins::telemetry::config_new::logging::Rollover;
+
+/// Writes to `path`, suffixed with the current period (`path.2024-01-31` for daily rollover,
+/// `path.2024-01-31-13` for hourly rollover).
+///
+/// When `max_size` is set, the current file is also rolled over when it reaches that size: it is
+/// renamed with an index suffix (`path.2024-01-31.1`) and a new file is started.
+///
+/// When `compress` is set, the files that were rolled over are compressed with gzip. When
+/// `max_files` is set, the oldest files are then removed. Both happen on a dedicated thread, so
+/// that they do not block the writes.
+pub(crate) struct RollingFileWriter {
+    path: PathBuf,
+    rollover: Rollover,
+    max_files: Option<usize>,
+    max_size: Option<u64>,
+    compress: bool,
+    period: Option<String>,
+    file: File,
+    size: u64,
+    maintenance: Option<Maintenance>,
+}
+
+/// The thread compressing the files that were rolled over, then removing the oldest ones
+struct Maintenance {
+    sender: mpsc::Sender<RolledOver>,
+    /// Only joined by the tests, the thread stops on its own once the sender is dropped
+    #[cfg_attr(not(test), allow(dead_code))]
+    worker: JoinHandle<()>,
+}
+
+struct RolledOver {
+    /// the file that was rolled over
+    path: PathBuf,
+    /// the file written after the rollover, which is never removed
+    current: PathBuf,
+}
+
+impl RollingFileWriter {
+    pub(crate) fn new(
+        path: impl AsRef<Path>,
+        rollover: Rollover,
+        max_files: Option<usize>,
+    ) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let period = period(&rollover, OffsetDateTime::now_utc());
+        let file = open(&file_path(&path, period.as_deref()))?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            rollover,
+            max_files,
+            max_size: None,
+            compress: false,
+            period,
+            file,
+            size,
+            maintenance: None,
+        })
+    }
+
+    /// Rolls over the current file when writing to it would make it larger than `max_size` bytes.
+    pub(crate) fn with_max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Compresses the files that were rolled over.
+    pub(crate) fn with_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
+    fn current_path(&self) -> PathBuf {
+        file_path(&self.path, self.period.as_deref())
+    }
+
+    fn roll_over_if_needed(&mut self, len: usize) -> io::Result<()> {
+        let period = period(&self.rollover, OffsetDateTime::now_utc());
+        if period != self.period {
+            self.file.flush()?;
+            let previous = self.current_path();
+            self.period = period;
+            self.file = open(&self.current_path())?;
+            self.size = self.file.metadata()?.len();
+            self.rolled_over(previous)?;
+        } else if self
+            .max_size
+            .is_some_and(|max_size| self.size > 0 && self.size + len as u64 > max_size)
+        {
+            self.file.flush()?;
+            let current = self.current_path();
+            let rotated = rotated_path(&current);
+            std::fs::rename(&current, &rotated)?;
+            self.file = open(&current)?;
+            self.size = 0;
+            self.rolled_over(rotated)?;
+        }
+        Ok(())
+    }
+
+    fn rolled_over(&mut self, path: PathBuf) -> io::Result<()> {
+        if !self.compress && self.max_files.is_none() {
+            return Ok(());
+        }
+        let job = RolledOver {
+            path,
+            current: self.current_path(),
+        };
+        if self.maintenance.is_none() {
+            self.maintenance = Some(Maintenance::spawn(
+                self.path.clone(),
+                self.compress,
+                self.max_files,
+            )?);
+        }
+        let sent = self
+            .maintenance
+            .as_ref()
+            .is_some_and(|maintenance| maintenance.sender.send(job).is_ok());
+        if !sent {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "the log file maintenance thread stopped",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Waits for the maintenance of the files that were rolled over
+    #[cfg(test)]
+    fn finish_maintenance(&mut self) {
+        if let Some(Maintenance { sender, worker }) = self.maintenance.take() {
+            drop(sender);
+            worker.join().unwrap();
+        }
+    }
+}
+
+impl Maintenance {
+    fn spawn(path: PathBuf, compression: bool, max_files: Option<usize>) -> io::Result<Self> {
+        let (sender, receiver) = mpsc::channel::<RolledOver>();
+        let worker = std::thread::Builder::new()
+            .name("log-file-maintenance".to_string())
+            .spawn(move || {
+                let mut errors = ErrorReporter::default();
+                // stops when the writer is dropped
+                while let Ok(job) = receiver.recv() {
+                    // rollovers that happened in the meantime are handled together
+                    let jobs = std::iter::once(job)
+                        .chain(receiver.try_iter())
+                        .collect::<Vec<_>>();
+                    if compression {
+                        for job in &jobs {
+                            if job.path.exists() {
+                                if let Err(e) = compress(&job.path) {
+                                    errors.report(format!(
+                                        "could not compress log file {}: {e}",
+                                        job.path.display()
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                    if let (Some(max_files), Some(job)) = (max_files, jobs.last()) {
+                        remove_old_files(&path, &job.current, max_files, &mut errors);
+                    }
+                }
+            })?;
+        Ok(Self { sender, worker })
+    }
+}
+
+/// Logs the maintenance errors at most once per interval, as they would otherwise repeat on every
+/// rollover
+#[derive(Default)]
+struct ErrorReporter {
+    last_report: Option<Instant>,
+    suppressed: usize,
+}
+
+const ERROR_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+impl ErrorReporter {
+    fn report(&mut self, message: String) {
+        if self
+            .last_report
+            .is_some_and(|last_report| last_report.elapsed() < ERROR_REPORT_INTERVAL)
+        {
+            self.suppressed += 1;
+            return;
+        }
+        self.last_report = Some(Instant::now());
+        let suppressed = std::mem::take(&mut self.suppressed);
+        if suppressed > 0 {
+            tracing::error!("{message} ({suppressed} similar errors were not logged)");
+        } else {
+            tracing::error!("{message}");
+        }
+    }
+}
+
+/// Removes the least recently written files, keeping `max_files` including the current one
+fn remove_old_files(path: &Path, current: &Path, max_files: usize, errors: &mut ErrorReporter) {
+    let (directory, prefix) = match (path.parent(), path.file_name()) {
+        (Some(directory), Some(file_name)) => (
+            if directory.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                directory
+            },
+            format!("{}.", file_name.to_string_lossy()),
+        ),
+        _ => return,
+    };
+
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            errors.report(format!(
+                "could not list the log files in {}: {e}",
+                directory.display()
+            ));
+            return;
+        }
+    };
+    let mut files = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name() != current.file_name())
+        .map(|path| {
+            let modified = std::fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (modified, path)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let to_remove = files.len().saturating_sub(max_files.saturating_sub(1));
+    for (_, file) in files.into_iter().take(to_remove) {
+        if let Err(e) = std::fs::remove_file(&file) {
+            errors.report(format!("could not remove log file {}: {e}", file.display()));
+        }
+    }
+}
+
+impl Write for RollingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.roll_over_if_needed(buf.len())?;
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn period(rollover: &Rollover, now: OffsetDateTime) -> Option<String> {
+    match rollover {
+        Rollover::Hourly => Some(format!(
+            "{:04}-{:02}-{:02}-{:02}",
+            now.year(),
+            now.month() as u8,
+            now.day(),
+            now.hour()
+        )),
+        Rollover::Daily => Some(format!(
+            "{:04}-{:02}-{:02}",
+            now.year(),
+            now.month() as u8,
+            now.day()
+        )),
+        Rollover::Never => None,
+    }
+}
+
+fn file_path(path: &Path, period: Option<&str>) -> PathBuf {
+    match period {
+        Some(period) => {
+            let mut file_path = path.as_os_str().to_owned();
+            file_path.push(".");
+            file_path.push(period);
+            PathBuf::from(file_path)
+        }
+        None => path.to_path_buf(),
+    }
+}
+
+/// The first `path.N` that is not used yet
+fn rotated_path(path: &Path) -> PathBuf {
+    (1..)
+        .map(|index| file_path(path, Some(&index.to_string())))
+        .find(|rotated| {
+            let mut compressed = rotated.as_os_str().to_owned();
+            compressed.push(".gz");
+            !rotated.exists() && !Path::new(&compressed).exists()
+        })
+        .expect("there is always an unused index; qed")
+}
+
+fn compress(path: &Path) -> io::Result<()> {
+    let mut compressed_path = path.as_os_str().to_owned();
+    compressed_path.push(".gz");
+    let mut encoder = GzEncoder::new(File::create(compressed_path)?, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+    std::fs::remove_file(path)
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rollover_periods() {
+        // 2024-01-31 13:45 UTC
+        let now = OffsetDateTime::from_unix_timestamp(1706708700).unwrap();
+        assert_eq!(
+            period(&Rollover::Hourly, now).as_deref(),
+            Some("2024-01-31-13")
+        );
+        assert_eq!(period(&Rollover::Daily, now).as_deref(), Some("2024-01-31"));
+        assert_eq!(period(&Rollover::Never, now), None);
+        assert_eq!(
+            file_path(Path::new("/var/log/audit.log"), Some("2024-01-31")),
+            PathBuf::from("/var/log/audit.log.2024-01-31")
+        );
+    }
+
+    #[test]
+    fn old_files_are_removed() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("audit.log");
+        for day in ["2024-01-28", "2024-01-29", "2024-01-30"] {
+            std::fs::write(file_path(&path, Some(day)), "old").unwrap();
+        }
+
+        let mut writer = RollingFileWriter::new(&path, Rollover::Daily, Some(2)).unwrap();
+        writer.write_all(b"line\n").unwrap();
+        remove_old_files(
+            &path,
+            &writer.current_path(),
+            2,
+            &mut ErrorReporter::default(),
+        );
+
+        let mut remaining = std::fs::read_dir(directory.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(remaining[0], "audit.log.2024-01-30");
+    }
+
+    #[test]
+    fn size_rollover() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("router.log");
+
+        let mut writer = RollingFileWriter::new(&path, Rollover::Never, Some(2))
+            .unwrap()
+            .with_max_size(Some(10));
+        for line in ["first\n", "second\n", "third\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+        writer.finish_maintenance();
+
+        let mut remaining = std::fs::read_dir(directory.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, ["router.log", "router.log.2"]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "third\n");
+        assert_eq!(
+            std::fs::read_to_string(file_path(&path, Some("2"))).unwrap(),
+            "second\n"
+        );
+    }
+
+    #[test]
+    fn compressed_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("router.log");
+        std::fs::write(&path, "line\n").unwrap();
+
+        compress(&path).unwrap();
+
+        assert!(!path.exists());
+        let mut decoder =
+            flate2::read::GzDecoder::new(File::open(file_path(&path, Some("gz"))).unwrap());
+        let mut content = String::new();
+        io::Read::read_to_string(&mut decoder, &mut content).unwrap();
+        assert_eq!(content, "line\n");
+    }
+
+    #[test]
+    fn old_files_are_removed_after_compression() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("router.log");
+
+        let mut writer = RollingFileWriter::new(path, Rollover::Never, Some(2))
+            .unwrap()
+            .with_max_size(Some(10))
+            .with_compression(true);
+        for line in ["first\n", "second\n", "third\n"] {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        writer.finish_maintenance();
+
+        let mut remaining = std::fs::read_dir(directory.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, ["router.log", "router.log.2.gz"]);
+    }
+}
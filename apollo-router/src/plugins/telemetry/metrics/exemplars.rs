@@ -0,0 +1,476 @@
+//! Exemplars linking histogram measurements to the traces that were sampled when they were recorded.
+//!
+//! The OpenTelemetry SDK does not sample exemplars, so each histogram keeps the latest measurements
+//! of its series recorded under a sampled span in a bounded reservoir, and the OTLP and Prometheus
+//! readers attach them to the histogram buckets they export.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use opentelemetry::sdk::metrics::data::Exemplar;
+use opentelemetry::sdk::metrics::data::Histogram;
+use opentelemetry::sdk::metrics::data::ResourceMetrics;
+use opentelemetry::sdk::metrics::data::Temporality;
+use opentelemetry::sdk::metrics::exporter::PushMetricsExporter;
+use opentelemetry::sdk::metrics::reader::AggregationSelector;
+use opentelemetry::sdk::metrics::reader::TemporalitySelector;
+use opentelemetry::sdk::metrics::Aggregation;
+use opentelemetry::sdk::metrics::InstrumentKind;
+use opentelemetry::sdk::AttributeSet;
+use opentelemetry::trace::SpanId;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::trace::TraceId;
+use opentelemetry::KeyValue;
+use parking_lot::Mutex;
+use tracing::Span;
+
+use crate::plugins::telemetry::config::MetricView;
+use crate::plugins::telemetry::otel::OpenTelemetrySpanExt;
+
+/// The number of exemplars kept for each histogram series, older ones are evicted first.
+const MAX_EXEMPLARS_PER_SERIES: usize = 16;
+/// The number of series of a histogram that keep exemplars, the measurements of other series are
+/// not kept.
+const MAX_SERIES_PER_HISTOGRAM: usize = 1000;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The reservoirs of the histograms by name.
+///
+/// This is only locked when a histogram is created and when the exemplars are exported, a
+/// measurement only locks the reservoir of its histogram.
+static RESERVOIRS: Lazy<Mutex<HashMap<String, Weak<Reservoir>>>> = Lazy::new(Default::default);
+
+/// The exemplars of a histogram, by series attributes
+#[derive(Default)]
+pub(crate) struct Reservoir {
+    series: Mutex<HashMap<AttributeSet, Series>>,
+}
+
+struct Series {
+    attributes: Vec<KeyValue>,
+    exemplars: VecDeque<StoredExemplar>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct StoredExemplar {
+    pub(crate) value: f64,
+    pub(crate) time: SystemTime,
+    pub(crate) trace_id: TraceId,
+    pub(crate) span_id: SpanId,
+}
+
+/// An exemplar and the attributes of the series it was recorded on.
+#[derive(Clone, Debug)]
+pub(crate) struct SeriesExemplar {
+    pub(crate) exemplar: StoredExemplar,
+    pub(crate) attributes: Vec<KeyValue>,
+}
+
+pub(crate) fn set_enabled(enabled: bool) {
+    // the exemplars are dropped when they are disabled by a reload
+    if ENABLED.swap(enabled, Ordering::Relaxed) && !enabled {
+        for reservoir in RESERVOIRS.lock().values().filter_map(Weak::upgrade) {
+            reservoir.series.lock().clear();
+        }
+    }
+}
+
+pub(crate) fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// The reservoir of the histogram, shared by the instruments created with the same name.
+pub(crate) fn reservoir(name: &str) -> Arc<Reservoir> {
+    let mut reservoirs = RESERVOIRS.lock();
+    if let Some(reservoir) = reservoirs.get(name).and_then(Weak::upgrade) {
+        return reservoir;
+    }
+    reservoirs.retain(|_, reservoir| reservoir.strong_count() > 0);
+    let reservoir = Arc::new(Reservoir::default());
+    reservoirs.insert(name.to_string(), Arc::downgrade(&reservoir));
+    reservoir
+}
+
+/// Histogram values that can be kept as exemplars
+pub(crate) trait ExemplarValue: Copy + std::fmt::Debug + Send + Sync + 'static {
+    fn to_f64(self) -> f64;
+    fn from_f64(value: f64) -> Self;
+}
+
+impl ExemplarValue for f64 {
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+impl ExemplarValue for u64 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as u64
+    }
+}
+
+impl ExemplarValue for i64 {
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value as i64
+    }
+}
+
+impl Reservoir {
+    /// Keeps the measurement as an exemplar if the current span is sampled.
+    ///
+    /// Exemplars are samples, so the measurement is skipped rather than waiting when another
+    /// thread holds the reservoir.
+    pub(crate) fn record(&self, value: impl ExemplarValue, attributes: &[KeyValue]) {
+        if !is_enabled() {
+            return;
+        }
+        let context = Span::current().context();
+        let span = context.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() || !span_context.is_sampled() {
+            return;
+        }
+        let exemplar = StoredExemplar {
+            value: value.to_f64(),
+            time: SystemTime::now(),
+            trace_id: span_context.trace_id(),
+            span_id: span_context.span_id(),
+        };
+
+        self.keep(exemplar, attributes);
+    }
+
+    fn keep(&self, exemplar: StoredExemplar, attributes: &[KeyValue]) {
+        let Some(mut series) = self.series.try_lock() else {
+            return;
+        };
+        let series_count = series.len();
+        let series = match series.entry(AttributeSet::from(attributes)) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(_) if series_count >= MAX_SERIES_PER_HISTOGRAM => return,
+            Entry::Vacant(entry) => entry.insert(Series {
+                attributes: attributes.to_vec(),
+                exemplars: VecDeque::with_capacity(MAX_EXEMPLARS_PER_SERIES),
+            }),
+        };
+        if series.exemplars.len() == MAX_EXEMPLARS_PER_SERIES {
+            series.exemplars.pop_front();
+        }
+        series.exemplars.push_back(exemplar);
+    }
+
+    /// The latest exemplar in the `(lower, upper]` bucket, among the series whose attributes
+    /// match.
+    pub(crate) fn latest(
+        &self,
+        attributes_match: impl Fn(&AttributeSet) -> bool,
+        lower: f64,
+        upper: f64,
+    ) -> Option<SeriesExemplar> {
+        let series = self.series.lock();
+        series
+            .iter()
+            .filter(|(attributes, _)| attributes_match(attributes))
+            .flat_map(|(_, series)| {
+                series
+                    .exemplars
+                    .iter()
+                    .filter(|exemplar| exemplar.value > lower && exemplar.value <= upper)
+                    .map(move |exemplar| (exemplar, &series.attributes))
+            })
+            .max_by_key(|(exemplar, _)| exemplar.time)
+            .map(|(exemplar, attributes)| SeriesExemplar {
+                exemplar: exemplar.clone(),
+                attributes: attributes.clone(),
+            })
+    }
+}
+
+/// The instrument names of the metrics renamed by the views, by metric name
+pub(crate) fn renamed_instruments(views: &[MetricView]) -> HashMap<String, String> {
+    views
+        .iter()
+        .filter_map(|view| Some((view.rename.clone()?, view.name.clone())))
+        .collect()
+}
+
+/// Attaches the exemplars to the data points of the histograms, one for each bucket at most.
+///
+/// The reservoirs are named after the instruments, so the metrics renamed by a view are looked up
+/// with their instrument name.
+pub(crate) fn attach(metrics: &mut ResourceMetrics, renamed_instruments: &HashMap<String, String>) {
+    let reservoirs: HashMap<String, Arc<Reservoir>> = RESERVOIRS
+        .lock()
+        .iter()
+        .filter_map(|(name, reservoir)| Some((name.clone(), reservoir.upgrade()?)))
+        .collect();
+    for scope_metrics in &mut metrics.scope_metrics {
+        for metric in &mut scope_metrics.metrics {
+            let name = renamed_instruments
+                .get(metric.name.as_ref())
+                .map(String::as_str)
+                .unwrap_or(metric.name.as_ref());
+            let Some(reservoir) = reservoirs.get(name) else {
+                continue;
+            };
+            let data = metric.data.as_any();
+            if let Some(histogram) = data.downcast_ref::<Histogram<f64>>() {
+                metric.data = Box::new(with_exemplars(histogram, reservoir));
+            } else if let Some(histogram) = data.downcast_ref::<Histogram<u64>>() {
+                metric.data = Box::new(with_exemplars(histogram, reservoir));
+            } else if let Some(histogram) = data.downcast_ref::<Histogram<i64>>() {
+                metric.data = Box::new(with_exemplars(histogram, reservoir));
+            }
+        }
+    }
+}
+
+fn with_exemplars<T: ExemplarValue>(
+    histogram: &Histogram<T>,
+    reservoir: &Reservoir,
+) -> Histogram<T> {
+    let data_points = histogram
+        .data_points
+        .iter()
+        .map(|data_point| {
+            let mut data_point = data_point.clone();
+            let bounds = &data_point.bounds;
+            data_point.exemplars = (0..=bounds.len())
+                .filter_map(|bucket| {
+                    let lower = if bucket == 0 {
+                        f64::NEG_INFINITY
+                    } else {
+                        bounds[bucket - 1]
+                    };
+                    let upper = bounds.get(bucket).copied().unwrap_or(f64::INFINITY);
+                    reservoir.latest(
+                        |attributes| is_subset(&data_point.attributes, attributes),
+                        lower,
+                        upper,
+                    )
+                })
+                .map(
+                    |SeriesExemplar {
+                         exemplar,
+                         attributes,
+                     }| Exemplar {
+                        // Attributes removed from the data point by a view
+                        filtered_attributes: attributes
+                            .into_iter()
+                            .filter(|kv| {
+                                !data_point.attributes.iter().any(|(key, _)| *key == kv.key)
+                            })
+                            .collect(),
+                        time: exemplar.time,
+                        value: T::from_f64(exemplar.value),
+                        span_id: exemplar.span_id.to_bytes(),
+                        trace_id: exemplar.trace_id.to_bytes(),
+                    },
+                )
+                .collect();
+            data_point
+        })
+        .collect();
+    Histogram {
+        data_points,
+        temporality: histogram.temporality,
+    }
+}
+
+fn is_subset(subset: &AttributeSet, set: &AttributeSet) -> bool {
+    subset
+        .iter()
+        .all(|(key, value)| set.iter().any(|(k, v)| k == key && v == value))
+}
+
+/// Wraps a push exporter to attach the exemplars to the histograms it exports.
+pub(crate) struct ExemplarExporter<E> {
+    inner: E,
+    renamed_instruments: HashMap<String, String>,
+}
+
+impl<E> ExemplarExporter<E> {
+    pub(crate) fn new(inner: E, views: &[MetricView]) -> Self {
+        Self {
+            inner,
+            renamed_instruments: renamed_instruments(views),
+        }
+    }
+}
+
+impl<E: AggregationSelector> AggregationSelector for ExemplarExporter<E> {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.inner.aggregation(kind)
+    }
+}
+
+impl<E: TemporalitySelector> TemporalitySelector for ExemplarExporter<E> {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.inner.temporality(kind)
+    }
+}
+
+#[async_trait]
+impl<E: PushMetricsExporter> PushMetricsExporter for ExemplarExporter<E> {
+    async fn export(
+        &self,
+        metrics: &mut ResourceMetrics,
+    ) -> opentelemetry_api::metrics::Result<()> {
+        if is_enabled() {
+            attach(metrics, &self.renamed_instruments);
+        }
+        self.inner.export(metrics).await
+    }
+
+    async fn force_flush(&self) -> opentelemetry_api::metrics::Result<()> {
+        self.inner.force_flush().await
+    }
+
+    fn shutdown(&self) -> opentelemetry_api::metrics::Result<()> {
+        self.inner.shutdown()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use opentelemetry::metrics::MeterProvider as _;
+    use opentelemetry::sdk::metrics::reader::MetricReader;
+    use opentelemetry::sdk::metrics::MeterProvider;
+    use opentelemetry::sdk::metrics::View;
+    use opentelemetry::sdk::Resource;
+
+    use super::*;
+    use crate::metrics::test_utils::ClonableManualReader;
+
+    fn exemplar(value: f64, trace_id: u128) -> StoredExemplar {
+        StoredExemplar {
+            value,
+            time: SystemTime::now(),
+            trace_id: TraceId::from(trace_id),
+            span_id: SpanId::from(1u64),
+        }
+    }
+
+    #[test]
+    fn latest_exemplar_of_bucket() {
+        let attributes = [KeyValue::new("subgraph", "products")];
+        let reservoir = Reservoir::default();
+        reservoir.keep(exemplar(0.05, 1), &attributes);
+        reservoir.keep(exemplar(0.5, 2), &attributes);
+        reservoir.keep(exemplar(0.07, 3), &attributes);
+
+        let in_bucket = |lower, upper| {
+            reservoir
+                .latest(|_| true, lower, upper)
+                .map(|e| e.exemplar.trace_id)
+        };
+        assert_eq!(in_bucket(0.0, 0.1), Some(TraceId::from(3u128)));
+        assert_eq!(in_bucket(0.1, 1.0), Some(TraceId::from(2u128)));
+        assert_eq!(in_bucket(1.0, f64::INFINITY), None);
+
+        let other_subgraph = AttributeSet::from(&[KeyValue::new("subgraph", "reviews")][..]);
+        assert!(reservoir
+            .latest(
+                |attributes| is_subset(&other_subgraph, attributes),
+                0.0,
+                1.0
+            )
+            .is_none());
+    }
+
+    #[test]
+    fn reservoir_is_bounded() {
+        let reservoir = Reservoir::default();
+        for trace_id in 0..(MAX_EXEMPLARS_PER_SERIES as u128 * 2) {
+            reservoir.keep(exemplar(0.1, trace_id), &[]);
+        }
+        for series in 0..(MAX_SERIES_PER_HISTOGRAM * 2) {
+            reservoir.keep(exemplar(0.1, 0), &[KeyValue::new("series", series as i64)]);
+        }
+
+        let series = reservoir.series.lock();
+        assert_eq!(series.len(), MAX_SERIES_PER_HISTOGRAM);
+        let unlabeled = &series[&AttributeSet::from(&[] as &[KeyValue])];
+        assert_eq!(unlabeled.exemplars.len(), MAX_EXEMPLARS_PER_SERIES);
+        // the oldest exemplars are evicted first
+        assert_eq!(
+            unlabeled.exemplars.front().map(|e| e.trace_id),
+            Some(TraceId::from(MAX_EXEMPLARS_PER_SERIES as u128))
+        );
+    }
+
+    #[test]
+    fn histograms_with_the_same_name_share_a_reservoir() {
+        let reservoir = super::reservoir("test.exemplars.shared");
+        assert!(Arc::ptr_eq(
+            &reservoir,
+            &super::reservoir("test.exemplars.shared")
+        ));
+        drop(reservoir);
+        assert!(RESERVOIRS.lock()["test.exemplars.shared"]
+            .upgrade()
+            .is_none());
+    }
+
+    #[test]
+    fn exemplars_are_attached_to_renamed_integer_histograms() {
+        let views: Vec<MetricView> = vec![serde_json::from_value(serde_json::json!({
+            "name": "test.exemplars.renamed",
+            "rename": "test.exemplars.view"
+        }))
+        .unwrap()];
+        let reader = ClonableManualReader::default();
+        let view: Box<dyn View> = views[0].clone().try_into().unwrap();
+        let meter_provider = MeterProvider::builder()
+            .with_reader(reader.clone())
+            .with_view(view)
+            .build();
+        let attributes = [KeyValue::new("subgraph", "products")];
+        meter_provider
+            .meter("test")
+            .u64_histogram("test.exemplars.renamed")
+            .init()
+            .record(7, &attributes);
+        let reservoir = super::reservoir("test.exemplars.renamed");
+        reservoir.keep(exemplar(7.0, 1), &attributes);
+
+        let mut metrics = ResourceMetrics {
+            resource: Resource::empty(),
+            scope_metrics: Vec::new(),
+        };
+        reader.collect(&mut metrics).unwrap();
+        attach(&mut metrics, &renamed_instruments(&views));
+
+        let metric = &metrics.scope_metrics[0].metrics[0];
+        assert_eq!(metric.name, "test.exemplars.view");
+        let histogram = metric
+            .data
+            .as_any()
+            .downcast_ref::<Histogram<u64>>()
+            .unwrap();
+        let exemplars = &histogram.data_points[0].exemplars;
+        assert_eq!(exemplars.len(), 1);
+        assert_eq!(exemplars[0].value, 7);
+        assert_eq!(exemplars[0].trace_id, TraceId::from(1u128).to_bytes());
+    }
+}
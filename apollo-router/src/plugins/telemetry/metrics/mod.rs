@@ -31,10 +31,12 @@ use crate::Context;
 use crate::ListenAddr;
 
 pub(crate) mod apollo;
+pub(crate) mod exemplars;
 pub(crate) mod local_type_stats;
 pub(crate) mod otlp;
 pub(crate) mod prometheus;
 pub(crate) mod span_metrics_exporter;
+pub(crate) mod statsd;
 
 #[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
 #[serde(deny_unknown_fields, default)]
@@ -5,6 +5,7 @@ use opentelemetry_otlp::MetricsExporterBuilder;
 use tower::BoxError;
 
 use crate::plugins::telemetry::config::MetricsCommon;
+use crate::plugins::telemetry::metrics::exemplars::ExemplarExporter;
 use crate::plugins::telemetry::metrics::CustomAggregationSelector;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
@@ -34,10 +35,13 @@ impl MetricsConfigurator for super::super::otlp::Config {
         )?;
 
         builder.public_meter_provider_builder = builder.public_meter_provider_builder.with_reader(
-            PeriodicReader::builder(exporter, runtime::Tokio)
-                .with_interval(self.batch_processor.scheduled_delay)
-                .with_timeout(self.batch_processor.max_export_timeout)
-                .build(),
+            PeriodicReader::builder(
+                ExemplarExporter::new(exporter, &metrics_config.views),
+                runtime::Tokio,
+            )
+            .with_interval(self.batch_processor.scheduled_delay)
+            .with_timeout(self.batch_processor.max_export_timeout)
+            .build(),
         );
         for metric_view in metrics_config.views.clone() {
             let view: Box<dyn View> = metric_view.try_into()?;
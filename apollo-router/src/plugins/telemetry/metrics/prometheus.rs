@@ -1,13 +1,34 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::Weak;
 use std::task::Context;
 use std::task::Poll;
+use std::time::UNIX_EPOCH;
 
 use futures::future::BoxFuture;
 use http::StatusCode;
 use once_cell::sync::Lazy;
+use opentelemetry::sdk::metrics::data;
+use opentelemetry::sdk::metrics::data::ResourceMetrics;
+use opentelemetry::sdk::metrics::data::Temporality;
+use opentelemetry::sdk::metrics::reader::AggregationSelector;
+use opentelemetry::sdk::metrics::reader::MetricProducer;
+use opentelemetry::sdk::metrics::reader::MetricReader;
+use opentelemetry::sdk::metrics::reader::TemporalitySelector;
+use opentelemetry::sdk::metrics::Aggregation;
+use opentelemetry::sdk::metrics::InstrumentKind;
+use opentelemetry::sdk::metrics::ManualReader;
 use opentelemetry::sdk::metrics::MeterProvider;
+use opentelemetry::sdk::metrics::Pipeline;
 use opentelemetry::sdk::metrics::View;
 use opentelemetry::sdk::Resource;
+use opentelemetry::trace::SpanId;
+use opentelemetry::trace::TraceId;
+use opentelemetry::Key;
+use opentelemetry::Value;
 use prometheus::Encoder;
 use prometheus::Registry;
 use prometheus::TextEncoder;
@@ -19,6 +40,7 @@ use tower_service::Service;
 
 use crate::plugins::telemetry::config::MetricView;
 use crate::plugins::telemetry::config::MetricsCommon;
+use crate::plugins::telemetry::metrics::exemplars;
 use crate::plugins::telemetry::metrics::CustomAggregationSelector;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
@@ -52,9 +74,9 @@ impl Default for Config {
 // Prometheus metrics are special. We want them to persist between restarts if possible.
 // This means reusing the existing registry and meter provider if we can.
 // These statics will keep track of new registry for commit when the telemetry plugin is activated.
-static EXISTING_PROMETHEUS: Lazy<Mutex<Option<(PrometheusConfig, Registry)>>> =
+static EXISTING_PROMETHEUS: Lazy<Mutex<Option<(PrometheusConfig, PrometheusService)>>> =
     Lazy::new(Default::default);
-static NEW_PROMETHEUS: Lazy<Mutex<Option<(PrometheusConfig, Registry)>>> =
+static NEW_PROMETHEUS: Lazy<Mutex<Option<(PrometheusConfig, PrometheusService)>>> =
     Lazy::new(Default::default);
 
 #[derive(PartialEq, Clone)]
@@ -62,6 +84,7 @@ struct PrometheusConfig {
     resource: Resource,
     buckets: Vec<f64>,
     views: Vec<MetricView>,
+    exemplars: bool,
 }
 
 pub(crate) fn commit_prometheus() {
@@ -91,6 +114,7 @@ impl MetricsConfigurator for Config {
             resource: builder.resource.clone(),
             buckets: metrics_config.buckets.clone(),
             views: metrics_config.views.clone(),
+            exemplars: metrics_config.exemplars,
         };
 
         // Check the last registry to see if the resources are the same, if they are we can use it as is.
@@ -98,20 +122,14 @@ impl MetricsConfigurator for Config {
         // Note that during tests the prom registry cannot be reused as we have a different meter provider for each test.
         // Prom reloading IS tested in an integration test.
         #[cfg(not(test))]
-        if let Some((last_config, last_registry)) =
+        if let Some((last_config, last_service)) =
             EXISTING_PROMETHEUS.lock().expect("lock poisoned").clone()
         {
             if prometheus_config == last_config {
                 tracing::debug!("prometheus registry can be reused");
                 builder.custom_endpoints.insert(
                     self.listen.clone(),
-                    Endpoint::from_router_service(
-                        self.path.clone(),
-                        PrometheusService {
-                            registry: last_registry.clone(),
-                        }
-                        .boxed(),
-                    ),
+                    Endpoint::from_router_service(self.path.clone(), last_service.boxed()),
                 );
                 tracing::info!(
                     "Prometheus endpoint exposed at {}{}",
@@ -139,27 +157,43 @@ impl MetricsConfigurator for Config {
         let mut meter_provider_builder = MeterProvider::builder()
             .with_reader(exporter)
             .with_resource(builder.resource.clone());
+        // The prometheus crate cannot encode exemplars, so the OpenMetrics format is encoded from
+        // a second reader of the meter provider, after attaching the exemplars to its histograms
+        let openmetrics = metrics_config.exemplars.then(|| OpenMetricsReader {
+            reader: Arc::new(
+                ManualReader::builder()
+                    .with_aggregation_selector(Box::new(
+                        CustomAggregationSelector::builder()
+                            .boundaries(metrics_config.buckets.clone())
+                            .record_min_max(true)
+                            .build(),
+                    ))
+                    .build(),
+            ),
+            renamed_instruments: Arc::new(exemplars::renamed_instruments(&metrics_config.views)),
+        });
+        if let Some(reader) = &openmetrics {
+            meter_provider_builder = meter_provider_builder.with_reader(reader.clone());
+        }
         for metric_view in metrics_config.views.clone() {
             let view: Box<dyn View> = metric_view.try_into()?;
             meter_provider_builder = meter_provider_builder.with_view(view);
         }
         let meter_provider = meter_provider_builder.build();
+        let service = PrometheusService {
+            registry,
+            openmetrics,
+        };
         builder.custom_endpoints.insert(
             self.listen.clone(),
-            Endpoint::from_router_service(
-                self.path.clone(),
-                PrometheusService {
-                    registry: registry.clone(),
-                }
-                .boxed(),
-            ),
+            Endpoint::from_router_service(self.path.clone(), service.clone().boxed()),
         );
         builder.prometheus_meter_provider = Some(meter_provider.clone());
 
         NEW_PROMETHEUS
             .lock()
             .expect("lock poisoned")
-            .replace((prometheus_config, registry));
+            .replace((prometheus_config, service));
 
         tracing::info!(
             "Prometheus endpoint exposed at {}{}",
@@ -174,6 +208,8 @@ impl MetricsConfigurator for Config {
 #[derive(Clone)]
 pub(crate) struct PrometheusService {
     registry: Registry,
+    /// Set when exemplars are enabled
+    openmetrics: Option<OpenMetricsReader>,
 }
 
 impl Service<router::Request> for PrometheusService {
@@ -186,23 +222,666 @@ impl Service<router::Request> for PrometheusService {
     }
 
     fn call(&mut self, req: router::Request) -> Self::Future {
-        let metric_families = self.registry.gather();
+        // Exemplars can only be exposed in the OpenMetrics format
+        let openmetrics = self.openmetrics.clone().filter(|_| {
+            exemplars::is_enabled()
+                && req
+                    .router_request
+                    .headers()
+                    .get_all(http::header::ACCEPT)
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .any(|value| value.contains(OPENMETRICS_CONTENT_TYPE))
+        });
+        let registry = self.registry.clone();
         Box::pin(async move {
-            let encoder = TextEncoder::new();
-            let mut result = Vec::new();
-            encoder.encode(&metric_families, &mut result)?;
-            // otel 0.19.0 started adding "_total" onto various statistics.
-            // Let's remove any problems they may have created for us.
-            let stats = String::from_utf8_lossy(&result);
-            let modified_stats = stats.replace("_total_total", "_total");
+            let (content_type, stats) = match openmetrics {
+                Some(reader) => (
+                    "application/openmetrics-text; version=1.0.0; charset=utf-8",
+                    reader.encode()?,
+                ),
+                None => {
+                    let metric_families = registry.gather();
+                    let encoder = TextEncoder::new();
+                    let mut result = Vec::new();
+                    encoder.encode(&metric_families, &mut result)?;
+                    // otel 0.19.0 started adding "_total" onto various statistics.
+                    // Let's remove any problems they may have created for us.
+                    let stats = String::from_utf8_lossy(&result);
+                    (
+                        "text/plain; version=0.0.4",
+                        stats.replace("_total_total", "_total"),
+                    )
+                }
+            };
             Ok(router::Response {
                 response: http::Response::builder()
                     .status(StatusCode::OK)
-                    .header(http::header::CONTENT_TYPE, "text/plain; version=0.0.4")
-                    .body::<Body>(modified_stats.into())
+                    .header(http::header::CONTENT_TYPE, content_type)
+                    .body::<Body>(stats.into())
                     .map_err(BoxError::from)?,
                 context: req.context,
             })
         })
     }
 }
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text";
+
+/// A reader of the Prometheus meter provider, shared with the Prometheus service.
+#[derive(Clone, Debug)]
+struct OpenMetricsReader {
+    reader: Arc<ManualReader>,
+    renamed_instruments: Arc<HashMap<String, String>>,
+}
+
+impl TemporalitySelector for OpenMetricsReader {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.reader.temporality(kind)
+    }
+}
+
+impl AggregationSelector for OpenMetricsReader {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.reader.aggregation(kind)
+    }
+}
+
+impl MetricReader for OpenMetricsReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.reader.register_pipeline(pipeline)
+    }
+
+    fn register_producer(&self, producer: Box<dyn MetricProducer>) {
+        self.reader.register_producer(producer)
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> opentelemetry::metrics::Result<()> {
+        self.reader.collect(rm)
+    }
+
+    fn force_flush(&self, cx: &opentelemetry::Context) -> opentelemetry::metrics::Result<()> {
+        self.reader.force_flush(cx)
+    }
+
+    fn shutdown(&self) -> opentelemetry::metrics::Result<()> {
+        self.reader.shutdown()
+    }
+}
+
+impl OpenMetricsReader {
+    /// Collects the metrics and encodes them with the exemplars of their histograms.
+    fn encode(&self) -> Result<String, BoxError> {
+        let mut metrics = ResourceMetrics {
+            resource: Resource::empty(),
+            scope_metrics: Vec::new(),
+        };
+        self.reader.collect(&mut metrics)?;
+        exemplars::attach(&mut metrics, &self.renamed_instruments);
+        Ok(to_openmetrics(&metrics))
+    }
+}
+
+/// A metric family of the OpenMetrics exposition
+struct Family {
+    kind: &'static str,
+    help: String,
+    samples: String,
+}
+
+#[derive(Default)]
+struct Families(BTreeMap<String, Family>);
+
+impl Families {
+    /// The samples of the family, unless the family has another type.
+    fn samples(&mut self, name: String, kind: &'static str, help: &str) -> Option<&mut String> {
+        let family = self.0.entry(name).or_insert_with(|| Family {
+            kind,
+            help: help.to_string(),
+            samples: String::new(),
+        });
+        // like the Prometheus exporter, the instruments conflicting with the type of the family are dropped
+        (family.kind == kind).then_some(&mut family.samples)
+    }
+}
+
+/// Encodes the metrics in the OpenMetrics text format.
+///
+/// The families are named as the Prometheus exporter names them in the Prometheus text format, with
+/// the same labels, so that both formats expose the same series.
+fn to_openmetrics(metrics: &ResourceMetrics) -> String {
+    let mut families = Families::default();
+
+    if let Some(samples) = families.samples("target".to_string(), "info", "Target metadata") {
+        let labels = labels(metrics.resource.iter(), &[]);
+        let _ = writeln!(samples, "target_info{} 1", format_labels(&labels, None));
+    }
+    for scope_metrics in &metrics.scope_metrics {
+        let scope = &scope_metrics.scope;
+        let mut scope_labels = vec![("otel_scope_name".to_string(), scope.name.to_string())];
+        if let Some(version) = &scope.version {
+            scope_labels.push(("otel_scope_version".to_string(), version.to_string()));
+        }
+        if let Some(samples) = families.samples(
+            "otel_scope".to_string(),
+            "info",
+            "Instrumentation Scope metadata",
+        ) {
+            let _ = writeln!(
+                samples,
+                "otel_scope_info{} 1",
+                format_labels(&scope_labels, None)
+            );
+        }
+
+        for metric in &scope_metrics.metrics {
+            let name = family_name(metric);
+            let help = metric.description.as_ref();
+            let aggregation = metric.data.as_any();
+            if let Some(histogram) = aggregation.downcast_ref::<data::Histogram<f64>>() {
+                if let Some(samples) = families.samples(name.clone(), "histogram", help) {
+                    write_histogram(samples, &name, histogram, &scope_labels);
+                }
+            } else if let Some(histogram) = aggregation.downcast_ref::<data::Histogram<u64>>() {
+                if let Some(samples) = families.samples(name.clone(), "histogram", help) {
+                    write_histogram(samples, &name, histogram, &scope_labels);
+                }
+            } else if let Some(histogram) = aggregation.downcast_ref::<data::Histogram<i64>>() {
+                if let Some(samples) = families.samples(name.clone(), "histogram", help) {
+                    write_histogram(samples, &name, histogram, &scope_labels);
+                }
+            } else if let Some(sum) = aggregation.downcast_ref::<data::Sum<f64>>() {
+                write_sum(&mut families, name, help, sum, &scope_labels);
+            } else if let Some(sum) = aggregation.downcast_ref::<data::Sum<u64>>() {
+                write_sum(&mut families, name, help, sum, &scope_labels);
+            } else if let Some(sum) = aggregation.downcast_ref::<data::Sum<i64>>() {
+                write_sum(&mut families, name, help, sum, &scope_labels);
+            } else if let Some(gauge) = aggregation.downcast_ref::<data::Gauge<f64>>() {
+                if let Some(samples) = families.samples(name.clone(), "gauge", help) {
+                    write_points(samples, &name, &gauge.data_points, &scope_labels);
+                }
+            } else if let Some(gauge) = aggregation.downcast_ref::<data::Gauge<u64>>() {
+                if let Some(samples) = families.samples(name.clone(), "gauge", help) {
+                    write_points(samples, &name, &gauge.data_points, &scope_labels);
+                }
+            } else if let Some(gauge) = aggregation.downcast_ref::<data::Gauge<i64>>() {
+                if let Some(samples) = families.samples(name.clone(), "gauge", help) {
+                    write_points(samples, &name, &gauge.data_points, &scope_labels);
+                }
+            }
+        }
+    }
+
+    let mut result = String::new();
+    for (name, family) in families.0 {
+        let _ = writeln!(result, "# HELP {name} {}", escape(&family.help));
+        let _ = writeln!(result, "# TYPE {name} {}", family.kind);
+        result.push_str(&family.samples);
+    }
+    result.push_str("# EOF\n");
+    result
+}
+
+fn write_sum<T: Number>(
+    families: &mut Families,
+    name: String,
+    help: &str,
+    sum: &data::Sum<T>,
+    scope_labels: &[(String, String)],
+) {
+    if sum.is_monotonic {
+        // counter families are named without the `_total` suffix of their samples
+        let name = name.strip_suffix("_total").unwrap_or(&name).to_string();
+        if let Some(samples) = families.samples(name.clone(), "counter", help) {
+            write_points(
+                samples,
+                &format!("{name}_total"),
+                &sum.data_points,
+                scope_labels,
+            );
+        }
+    } else if let Some(samples) = families.samples(name.clone(), "gauge", help) {
+        write_points(samples, &name, &sum.data_points, scope_labels);
+    }
+}
+
+fn write_points<T: Number>(
+    samples: &mut String,
+    name: &str,
+    data_points: &[data::DataPoint<T>],
+    scope_labels: &[(String, String)],
+) {
+    for data_point in data_points {
+        let labels = labels(data_point.attributes.iter(), scope_labels);
+        let _ = writeln!(
+            samples,
+            "{name}{} {}",
+            format_labels(&labels, None),
+            format_number(data_point.value.to_f64())
+        );
+    }
+}
+
+fn write_histogram<T: Number>(
+    samples: &mut String,
+    name: &str,
+    histogram: &data::Histogram<T>,
+    scope_labels: &[(String, String)],
+) {
+    for data_point in &histogram.data_points {
+        let labels = labels(data_point.attributes.iter(), scope_labels);
+        let mut count = 0;
+        let mut lower = f64::NEG_INFINITY;
+        for (bucket, bucket_count) in data_point.bucket_counts.iter().enumerate() {
+            count += bucket_count;
+            let upper = data_point
+                .bounds
+                .get(bucket)
+                .copied()
+                .unwrap_or(f64::INFINITY);
+            let _ = write!(
+                samples,
+                "{name}_bucket{} {count}",
+                format_labels(&labels, Some(upper))
+            );
+            // one exemplar at most was attached for each bucket
+            if let Some(exemplar) = data_point.exemplars.iter().find(|exemplar| {
+                let value = exemplar.value.to_f64();
+                value > lower && value <= upper
+            }) {
+                let _ = write!(
+                    samples,
+                    " # {{trace_id=\"{}\",span_id=\"{}\"}} {} {:.3}",
+                    TraceId::from_bytes(exemplar.trace_id),
+                    SpanId::from_bytes(exemplar.span_id),
+                    format_number(exemplar.value.to_f64()),
+                    exemplar
+                        .time
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs_f64()
+                );
+            }
+            samples.push('\n');
+            lower = upper;
+        }
+        let labels = format_labels(&labels, None);
+        let _ = writeln!(
+            samples,
+            "{name}_sum{labels} {}",
+            format_number(data_point.sum.to_f64())
+        );
+        let _ = writeln!(samples, "{name}_count{labels} {}", data_point.count);
+    }
+}
+
+/// Metric values, Prometheus only handles floats
+trait Number {
+    fn to_f64(&self) -> f64;
+}
+
+impl Number for f64 {
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+}
+
+impl Number for u64 {
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl Number for i64 {
+    fn to_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+/// The name of the metric family, with the unit suffix added by the Prometheus exporter.
+fn family_name(metric: &data::Metric) -> String {
+    let mut name: String = metric
+        .name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    if let Some(suffix) = unit_suffix(metric.unit.as_str()) {
+        name.push('_');
+        name.push_str(&suffix);
+    }
+    name
+}
+
+fn unit_suffix(unit: &str) -> Option<String> {
+    if let Some(unit) = prometheus_unit(unit) {
+        return Some(unit.to_string());
+    }
+    let (unit, per_unit) = unit.split_once('/')?;
+    let per_unit = match per_unit {
+        "s" => "second",
+        "m" => "minute",
+        "h" => "hour",
+        "d" => "day",
+        "w" => "week",
+        "mo" => "month",
+        "y" => "year",
+        _ => return None,
+    };
+    match prometheus_unit(unit) {
+        Some(unit) if !["1", "d", "h", "min", "s", "ms", "us", "ns"].contains(&unit) => {
+            Some(format!("{unit}_per_{per_unit}"))
+        }
+        _ => Some(format!("per_{per_unit}")),
+    }
+}
+
+fn prometheus_unit(unit: &str) -> Option<&'static str> {
+    Some(match unit {
+        "d" => "days",
+        "h" => "hours",
+        "min" => "minutes",
+        "s" => "seconds",
+        "ms" => "milliseconds",
+        "us" => "microseconds",
+        "ns" => "nanoseconds",
+        "By" | "B" => "bytes",
+        "KiBy" => "kibibytes",
+        "MiBy" => "mebibytes",
+        "GiBy" => "gibibytes",
+        "TiBy" => "tibibytes",
+        "KBy" | "KB" => "kilobytes",
+        "MBy" | "MB" => "megabytes",
+        "GBy" | "GB" => "gigabytes",
+        "TBy" | "TB" => "terabytes",
+        "m" => "meters",
+        "V" => "volts",
+        "A" => "amperes",
+        "J" => "joules",
+        "W" => "watts",
+        "g" => "grams",
+        "Cel" => "celsius",
+        "Hz" => "hertz",
+        "1" => "ratio",
+        "%" => "percent",
+        "$" => "dollars",
+        _ => return None,
+    })
+}
+
+/// The labels of a series, sorted, with the values of the attributes having the same sanitized
+/// name joined, as the Prometheus exporter does.
+fn labels<'a>(
+    attributes: impl Iterator<Item = (&'a Key, &'a Value)>,
+    extra: &[(String, String)],
+) -> Vec<(String, String)> {
+    let mut labels: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (key, value) in attributes {
+        let key = key
+            .as_str()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == ':' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        labels.entry(key).or_default().push(value.to_string());
+    }
+    labels
+        .into_iter()
+        .map(|(key, mut values)| {
+            values.sort_unstable();
+            (key, values.join(";"))
+        })
+        .chain(extra.iter().cloned())
+        .collect()
+}
+
+fn format_labels(labels: &[(String, String)], le: Option<f64>) -> String {
+    let mut formatted: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{key}=\"{}\"", escape(value)))
+        .collect();
+    if let Some(le) = le {
+        formatted.push(format!("le=\"{}\"", format_number(le)));
+    }
+    if formatted.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", formatted.join(","))
+    }
+}
+
+fn format_number(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{value:.1}")
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use opentelemetry::metrics::MeterProvider as _;
+    use opentelemetry::KeyValue;
+
+    use super::*;
+
+    fn openmetrics_reader(views: &[MetricView]) -> OpenMetricsReader {
+        OpenMetricsReader {
+            reader: Arc::new(
+                ManualReader::builder()
+                    .with_aggregation_selector(Box::new(
+                        CustomAggregationSelector::builder()
+                            .boundaries(vec![0.1, 1.0])
+                            .record_min_max(true)
+                            .build(),
+                    ))
+                    .build(),
+            ),
+            renamed_instruments: Arc::new(exemplars::renamed_instruments(views)),
+        }
+    }
+
+    /// The samples of a text exposition, by series name and labels, without the exemplars
+    fn series(exposition: &str) -> BTreeMap<(String, BTreeMap<String, String>), f64> {
+        exposition
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                let sample = line.split(" # ").next().unwrap();
+                let (series, value) = sample.rsplit_once(' ').unwrap();
+                let (name, labels) = series.split_once('{').unwrap_or((series, "}"));
+                let labels = labels
+                    .trim_end_matches('}')
+                    .split(',')
+                    .filter(|label| !label.is_empty())
+                    .map(|label| {
+                        let (key, value) = label.split_once('=').unwrap();
+                        let value = value.trim_matches('"');
+                        // the bucket boundaries are floats, formatted differently by each encoder
+                        let value = match key {
+                            "le" => value.parse::<f64>().unwrap().to_string(),
+                            _ => value.to_string(),
+                        };
+                        (key.to_string(), value)
+                    })
+                    .collect();
+                ((name.to_string(), labels), value.parse().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn openmetrics_and_prometheus_expose_the_same_series() {
+        let views: Vec<MetricView> = vec![serde_json::from_value(serde_json::json!({
+            "name": "apollo.router.renamed",
+            "rename": "apollo.router.view"
+        }))
+        .unwrap()];
+        let registry = Registry::new();
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_aggregation_selector(
+                CustomAggregationSelector::builder()
+                    .boundaries(vec![0.1, 1.0])
+                    .record_min_max(true)
+                    .build(),
+            )
+            .with_registry(registry.clone())
+            .build()
+            .unwrap();
+        let reader = openmetrics_reader(&views);
+        let mut meter_provider = MeterProvider::builder()
+            .with_reader(exporter)
+            .with_reader(reader.clone())
+            .with_resource(Resource::new([KeyValue::new("service.name", "router")]));
+        for view in views {
+            let view: Box<dyn View> = view.try_into().unwrap();
+            meter_provider = meter_provider.with_view(view);
+        }
+        let meter_provider = meter_provider.build();
+        let meter = meter_provider.meter("apollo/router");
+
+        let attributes = [
+            KeyValue::new("subgraph.name", "products"),
+            KeyValue::new("http.response.status_code", 200),
+            KeyValue::new("error", "quote \"and\" newline\n"),
+        ];
+        meter
+            .u64_counter("apollo_router_http_requests_total")
+            .init()
+            .add(2, &attributes);
+        meter
+            .f64_counter("apollo.router.operations.sent")
+            .with_unit(opentelemetry::metrics::Unit::new("By"))
+            .init()
+            .add(1.5, &attributes);
+        meter
+            .i64_up_down_counter("apollo.router.pending")
+            .init()
+            .add(-3, &attributes);
+        meter
+            .f64_histogram("apollo.router.request.duration")
+            .with_unit(opentelemetry::metrics::Unit::new("s"))
+            .init()
+            .record(0.5, &attributes);
+        meter
+            .u64_histogram("apollo.router.renamed")
+            .init()
+            .record(7, &attributes);
+        meter
+            .i64_histogram("apollo.router.size")
+            .with_unit(opentelemetry::metrics::Unit::new("KiBy"))
+            .init()
+            .record(3, &[]);
+        let _gauge = meter
+            .f64_observable_gauge("apollo.router.ratio")
+            .with_unit(opentelemetry::metrics::Unit::new("1"))
+            .with_callback(|gauge| gauge.observe(0.25, &[KeyValue::new("a.b", "1")]))
+            .init();
+
+        let mut prometheus = Vec::new();
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut prometheus)
+            .unwrap();
+        let prometheus = String::from_utf8(prometheus)
+            .unwrap()
+            .replace("_total_total", "_total");
+        let openmetrics = reader.encode().unwrap();
+
+        let prometheus_series = series(&prometheus);
+        assert_eq!(prometheus_series.len(), 21);
+        assert_eq!(series(&openmetrics), prometheus_series);
+    }
+
+    #[test]
+    fn openmetrics_exposition() {
+        let reader = openmetrics_reader(&[]);
+        let meter_provider = MeterProvider::builder().with_reader(reader.clone()).build();
+        let meter = meter_provider.meter("apollo/router");
+        meter
+            .u64_counter("apollo_router_http_requests_total")
+            .init()
+            .add(2, &[KeyValue::new("status", "200")]);
+        let histogram = meter
+            .f64_histogram("apollo.router.request.duration")
+            .with_unit(opentelemetry::metrics::Unit::new("s"))
+            .init();
+        histogram.record(0.0625, &[KeyValue::new("subgraph.name", "products")]);
+        histogram.record(0.5, &[KeyValue::new("subgraph.name", "products")]);
+
+        let mut metrics = ResourceMetrics {
+            resource: Resource::empty(),
+            scope_metrics: Vec::new(),
+        };
+        reader.collect(&mut metrics).unwrap();
+        // exemplars are attached to the histograms by the reader
+        for metric in &mut metrics.scope_metrics[0].metrics {
+            if let Some(histogram) = metric.data.as_any().downcast_ref::<data::Histogram<f64>>() {
+                let mut data_points = histogram.data_points.clone();
+                data_points[0].exemplars.push(data::Exemplar {
+                    filtered_attributes: Vec::new(),
+                    time: UNIX_EPOCH + Duration::from_millis(1_700_000_000_500),
+                    value: 0.5,
+                    span_id: SpanId::from(2u64).to_bytes(),
+                    trace_id: TraceId::from(1u128).to_bytes(),
+                });
+                metric.data = Box::new(data::Histogram {
+                    data_points,
+                    temporality: histogram.temporality,
+                });
+            }
+        }
+
+        let exposition = to_openmetrics(&metrics);
+        let lines: Vec<&str> = exposition
+            .lines()
+            .filter(|line| !line.starts_with("target_info"))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "# HELP apollo_router_http_requests ",
+                "# TYPE apollo_router_http_requests counter",
+                "apollo_router_http_requests_total{status=\"200\",otel_scope_name=\"apollo/router\"} 2.0",
+                "# HELP apollo_router_request_duration_seconds ",
+                "# TYPE apollo_router_request_duration_seconds histogram",
+                "apollo_router_request_duration_seconds_bucket{subgraph_name=\"products\",otel_scope_name=\"apollo/router\",le=\"0.1\"} 1",
+                "apollo_router_request_duration_seconds_bucket{subgraph_name=\"products\",otel_scope_name=\"apollo/router\",le=\"1.0\"} 2 # {trace_id=\"00000000000000000000000000000001\",span_id=\"0000000000000002\"} 0.5 1700000000.500",
+                "apollo_router_request_duration_seconds_bucket{subgraph_name=\"products\",otel_scope_name=\"apollo/router\",le=\"+Inf\"} 2",
+                "apollo_router_request_duration_seconds_sum{subgraph_name=\"products\",otel_scope_name=\"apollo/router\"} 0.5625",
+                "apollo_router_request_duration_seconds_count{subgraph_name=\"products\",otel_scope_name=\"apollo/router\"} 2",
+                "# HELP otel_scope Instrumentation Scope metadata",
+                "# TYPE otel_scope info",
+                "otel_scope_info{otel_scope_name=\"apollo/router\"} 1",
+                "# HELP target Target metadata",
+                "# TYPE target info",
+                "# EOF",
+            ]
+        );
+    }
+}
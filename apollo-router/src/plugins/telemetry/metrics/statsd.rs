@@ -0,0 +1,590 @@
+//! StatsD and DogStatsD metrics exporter
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::io;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use opentelemetry::runtime;
+use opentelemetry::sdk::metrics::data::Gauge;
+use opentelemetry::sdk::metrics::data::Histogram;
+use opentelemetry::sdk::metrics::data::Metric;
+use opentelemetry::sdk::metrics::data::ResourceMetrics;
+use opentelemetry::sdk::metrics::data::Sum;
+use opentelemetry::sdk::metrics::data::Temporality;
+use opentelemetry::sdk::metrics::exporter::PushMetricsExporter;
+use opentelemetry::sdk::metrics::reader::AggregationSelector;
+use opentelemetry::sdk::metrics::reader::TemporalitySelector;
+use opentelemetry::sdk::metrics::Aggregation;
+use opentelemetry::sdk::metrics::InstrumentKind;
+use opentelemetry::sdk::metrics::PeriodicReader;
+use opentelemetry::sdk::metrics::View;
+use opentelemetry::sdk::AttributeSet;
+use opentelemetry_api::metrics::MetricsError;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+
+use crate::plugins::telemetry::config::MetricsCommon;
+use crate::plugins::telemetry::metrics::CustomAggregationSelector;
+use crate::plugins::telemetry::metrics::MetricsBuilder;
+use crate::plugins::telemetry::metrics::MetricsConfigurator;
+
+const UNIX_SCHEME: &str = "unix://";
+
+/// StatsD configuration
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Config {
+    /// Set to true to enable
+    pub(crate) enabled: bool,
+    /// The StatsD server, `host:port` for UDP or `unix:///path/to/socket` for a Unix domain
+    /// socket (default: 127.0.0.1:8125)
+    pub(crate) endpoint: String,
+    /// The protocol flavor, `dogstatsd` sends the attributes as tags (default: dogstatsd)
+    pub(crate) flavor: Flavor,
+    /// A prefix added to the metric names
+    pub(crate) prefix: Option<String>,
+    /// Tags added to every metric
+    pub(crate) tags: BTreeMap<String, String>,
+    /// The maximum size of a datagram (default: 1432 bytes)
+    pub(crate) max_packet_size: usize,
+    /// The interval between two exports (default: 10s)
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    pub(crate) interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "127.0.0.1:8125".to_string(),
+            flavor: Flavor::default(),
+            prefix: None,
+            tags: BTreeMap::new(),
+            max_packet_size: 1432,
+            interval: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum Flavor {
+    /// Plain StatsD, without tags
+    Statsd,
+    /// DogStatsD, with the attributes sent as tags
+    #[default]
+    Dogstatsd,
+}
+
+impl MetricsConfigurator for Config {
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn apply(
+        &self,
+        mut builder: MetricsBuilder,
+        metrics_config: &MetricsCommon,
+    ) -> Result<MetricsBuilder, BoxError> {
+        if !self.enabled {
+            return Ok(builder);
+        }
+        tracing::info!("configuring StatsD metrics exporter to {}", self.endpoint);
+        let exporter = StatsdExporter::new(self, metrics_config)?;
+        builder.public_meter_provider_builder = builder.public_meter_provider_builder.with_reader(
+            PeriodicReader::builder(exporter, runtime::Tokio)
+                .with_interval(self.interval)
+                .build(),
+        );
+        for metric_view in metrics_config.views.clone() {
+            let view: Box<dyn View> = metric_view.try_into()?;
+            builder.public_meter_provider_builder =
+                builder.public_meter_provider_builder.with_view(view);
+        }
+        Ok(builder)
+    }
+}
+
+enum Socket {
+    Udp(UdpSocket),
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+}
+
+impl Socket {
+    fn connect(endpoint: &str) -> io::Result<Self> {
+        if let Some(path) = endpoint.strip_prefix(UNIX_SCHEME) {
+            #[cfg(unix)]
+            {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                socket.set_nonblocking(true)?;
+                return Ok(Socket::Unix(socket));
+            }
+            #[cfg(not(unix))]
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("unix sockets are not supported on this platform: {path}"),
+                ));
+            }
+        }
+
+        let address = endpoint.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("could not resolve {endpoint}"),
+            )
+        })?;
+        let socket = if address.is_ipv4() {
+            UdpSocket::bind("0.0.0.0:0")?
+        } else {
+            UdpSocket::bind("[::]:0")?
+        };
+        socket.connect(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Socket::Udp(socket))
+    }
+
+    fn send(&self, packet: &[u8]) -> io::Result<usize> {
+        match self {
+            Socket::Udp(socket) => socket.send(packet),
+            #[cfg(unix)]
+            Socket::Unix(socket) => socket.send(packet),
+        }
+    }
+}
+
+/// Sends the metrics as StatsD lines, batched in datagrams.
+///
+/// Counters and histograms are exported with a delta temporality: counters are sent as StatsD
+/// counters, and histograms as the `.count` and `.sum` counters and the `.min` and `.max` gauges
+/// of the interval. Up down counters and gauges are sent as StatsD gauges.
+struct StatsdExporter {
+    socket: Socket,
+    flavor: Flavor,
+    prefix: Option<String>,
+    tags: Vec<String>,
+    max_packet_size: usize,
+    aggregation_selector: CustomAggregationSelector,
+}
+
+impl StatsdExporter {
+    fn new(config: &Config, metrics_config: &MetricsCommon) -> Result<Self, BoxError> {
+        let socket = Socket::connect(&config.endpoint).map_err(|e| {
+            format!(
+                "could not connect the StatsD exporter to {}: {e}",
+                config.endpoint
+            )
+        })?;
+        Ok(Self {
+            socket,
+            flavor: config.flavor,
+            prefix: config.prefix.clone(),
+            tags: config
+                .tags
+                .iter()
+                .map(|(key, value)| tag(key, value))
+                .collect(),
+            max_packet_size: config.max_packet_size,
+            aggregation_selector: CustomAggregationSelector::builder()
+                .boundaries(metrics_config.buckets.clone())
+                .build(),
+        })
+    }
+
+    fn lines(&self, metrics: &ResourceMetrics) -> Vec<String> {
+        let mut lines = Vec::new();
+        for metric in metrics
+            .scope_metrics
+            .iter()
+            .flat_map(|scope_metrics| scope_metrics.metrics.iter())
+        {
+            let name = self.name(&metric.name);
+            self.metric_lines(&name, metric, &mut lines);
+        }
+        lines
+    }
+
+    fn metric_lines(&self, name: &str, metric: &Metric, lines: &mut Vec<String>) {
+        let data = metric.data.as_any();
+        if let Some(sum) = data.downcast_ref::<Sum<u64>>() {
+            self.sum_lines(name, sum, lines);
+        } else if let Some(sum) = data.downcast_ref::<Sum<i64>>() {
+            self.sum_lines(name, sum, lines);
+        } else if let Some(sum) = data.downcast_ref::<Sum<f64>>() {
+            self.sum_lines(name, sum, lines);
+        } else if let Some(gauge) = data.downcast_ref::<Gauge<u64>>() {
+            self.gauge_lines(name, gauge, lines);
+        } else if let Some(gauge) = data.downcast_ref::<Gauge<i64>>() {
+            self.gauge_lines(name, gauge, lines);
+        } else if let Some(gauge) = data.downcast_ref::<Gauge<f64>>() {
+            self.gauge_lines(name, gauge, lines);
+        } else if let Some(histogram) = data.downcast_ref::<Histogram<u64>>() {
+            self.histogram_lines(name, histogram, lines);
+        } else if let Some(histogram) = data.downcast_ref::<Histogram<i64>>() {
+            self.histogram_lines(name, histogram, lines);
+        } else if let Some(histogram) = data.downcast_ref::<Histogram<f64>>() {
+            self.histogram_lines(name, histogram, lines);
+        }
+    }
+
+    fn sum_lines<T: StatsdValue>(&self, name: &str, sum: &Sum<T>, lines: &mut Vec<String>) {
+        for data_point in &sum.data_points {
+            if sum.is_monotonic && sum.temporality == Temporality::Delta {
+                if !data_point.value.is_zero() {
+                    lines.push(self.line(name, data_point.value, "c", &data_point.attributes));
+                }
+            } else {
+                self.gauge_line(name, data_point.value, &data_point.attributes, lines);
+            }
+        }
+    }
+
+    fn gauge_lines<T: StatsdValue>(&self, name: &str, gauge: &Gauge<T>, lines: &mut Vec<String>) {
+        for data_point in &gauge.data_points {
+            self.gauge_line(name, data_point.value, &data_point.attributes, lines);
+        }
+    }
+
+    fn gauge_line<T: StatsdValue>(
+        &self,
+        name: &str,
+        value: T,
+        attributes: &AttributeSet,
+        lines: &mut Vec<String>,
+    ) {
+        // a signed gauge is a relative change in StatsD, a negative value is set from zero
+        if self.flavor == Flavor::Statsd && value.is_negative() {
+            lines.push(self.line(name, 0, "g", attributes));
+        }
+        lines.push(self.line(name, value, "g", attributes));
+    }
+
+    fn histogram_lines<T: StatsdValue>(
+        &self,
+        name: &str,
+        histogram: &Histogram<T>,
+        lines: &mut Vec<String>,
+    ) {
+        for data_point in &histogram.data_points {
+            if data_point.count == 0 {
+                continue;
+            }
+            let attributes = &data_point.attributes;
+            lines.push(self.line(&format!("{name}.count"), data_point.count, "c", attributes));
+            lines.push(self.line(&format!("{name}.sum"), data_point.sum, "c", attributes));
+            if let Some(min) = data_point.min {
+                self.gauge_line(&format!("{name}.min"), min, attributes, lines);
+            }
+            if let Some(max) = data_point.max {
+                self.gauge_line(&format!("{name}.max"), max, attributes, lines);
+            }
+        }
+    }
+
+    fn name(&self, name: &str) -> String {
+        let name = match &self.prefix {
+            Some(prefix) => format!("{prefix}.{name}"),
+            None => name.to_string(),
+        };
+        name.replace([':', '|', '@', '#', ' ', '\n'], "_")
+    }
+
+    fn line(
+        &self,
+        name: &str,
+        value: impl Display,
+        metric_type: &str,
+        attributes: &AttributeSet,
+    ) -> String {
+        let mut line = format!("{name}:{value}|{metric_type}");
+        if self.flavor == Flavor::Dogstatsd && (!self.tags.is_empty() || !attributes.is_empty()) {
+            line.push_str("|#");
+            let tags = self.tags.iter().cloned().chain(
+                attributes
+                    .iter()
+                    .map(|(key, value)| tag(key.as_str(), &value.as_str())),
+            );
+            for (index, tag) in tags.enumerate() {
+                if index > 0 {
+                    line.push(',');
+                }
+                line.push_str(&tag);
+            }
+        }
+        line
+    }
+
+    /// Batches the lines in datagrams of at most `max_packet_size` bytes
+    fn packets(&self, lines: Vec<String>) -> Vec<String> {
+        let mut packets = Vec::new();
+        let mut packet = String::new();
+        for line in lines {
+            if !packet.is_empty() && packet.len() + line.len() + 1 > self.max_packet_size {
+                packets.push(std::mem::take(&mut packet));
+            }
+            if !packet.is_empty() {
+                packet.push('\n');
+            }
+            packet.push_str(&line);
+        }
+        if !packet.is_empty() {
+            packets.push(packet);
+        }
+        packets
+    }
+}
+
+impl AggregationSelector for StatsdExporter {
+    fn aggregation(&self, kind: InstrumentKind) -> Aggregation {
+        self.aggregation_selector.aggregation(kind)
+    }
+}
+
+impl TemporalitySelector for StatsdExporter {
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        match kind {
+            InstrumentKind::Counter
+            | InstrumentKind::ObservableCounter
+            | InstrumentKind::Histogram => Temporality::Delta,
+            InstrumentKind::UpDownCounter
+            | InstrumentKind::ObservableUpDownCounter
+            | InstrumentKind::ObservableGauge => Temporality::Cumulative,
+        }
+    }
+}
+
+#[async_trait]
+impl PushMetricsExporter for StatsdExporter {
+    async fn export(
+        &self,
+        metrics: &mut ResourceMetrics,
+    ) -> opentelemetry_api::metrics::Result<()> {
+        for packet in self.packets(self.lines(metrics)) {
+            match self.socket.send(packet.as_bytes()) {
+                Ok(_) => {}
+                // the server is not keeping up, the metrics of this interval are dropped
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    return Err(MetricsError::Other(format!(
+                        "could not send metrics to StatsD: {e}"
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn force_flush(&self) -> opentelemetry_api::metrics::Result<()> {
+        Ok(())
+    }
+
+    fn shutdown(&self) -> opentelemetry_api::metrics::Result<()> {
+        Ok(())
+    }
+}
+
+trait StatsdValue: Display + Copy {
+    fn is_zero(&self) -> bool;
+    fn is_negative(&self) -> bool;
+}
+
+impl StatsdValue for u64 {
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn is_negative(&self) -> bool {
+        false
+    }
+}
+
+impl StatsdValue for i64 {
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < 0
+    }
+}
+
+impl StatsdValue for f64 {
+    fn is_zero(&self) -> bool {
+        *self == 0.0
+    }
+
+    fn is_negative(&self) -> bool {
+        *self < 0.0
+    }
+}
+
+fn tag(key: &str, value: &str) -> String {
+    let sanitize = |s: &str| s.replace([',', '|', '#', '\n'], "_");
+    format!("{}:{}", sanitize(key), sanitize(value))
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use opentelemetry::sdk::metrics::data::DataPoint;
+    use opentelemetry::sdk::metrics::data::HistogramDataPoint;
+    use opentelemetry::sdk::metrics::data::ScopeMetrics;
+    use opentelemetry::sdk::Resource;
+    use opentelemetry::KeyValue;
+    use opentelemetry_api::metrics::Unit;
+
+    use super::*;
+
+    fn metric(
+        name: &'static str,
+        data: Box<dyn opentelemetry::sdk::metrics::data::Aggregation>,
+    ) -> Metric {
+        Metric {
+            name: Cow::Borrowed(name),
+            description: Cow::Borrowed(""),
+            unit: Unit::new(""),
+            data,
+        }
+    }
+
+    fn resource_metrics(metrics: Vec<Metric>) -> ResourceMetrics {
+        ResourceMetrics {
+            resource: Resource::empty(),
+            scope_metrics: vec![ScopeMetrics {
+                scope: Default::default(),
+                metrics,
+            }],
+        }
+    }
+
+    fn exporter(flavor: Flavor, endpoint: &str) -> StatsdExporter {
+        StatsdExporter::new(
+            &Config {
+                enabled: true,
+                endpoint: endpoint.to_string(),
+                flavor,
+                prefix: Some("router".to_string()),
+                tags: [("env".to_string(), "prod".to_string())].into(),
+                max_packet_size: 64,
+                ..Default::default()
+            },
+            &MetricsCommon::default(),
+        )
+        .unwrap()
+    }
+
+    fn test_metrics() -> ResourceMetrics {
+        let attributes = AttributeSet::from(&[KeyValue::new("subgraph.name", "products")][..]);
+        resource_metrics(vec![
+            metric(
+                "http.requests",
+                Box::new(Sum {
+                    data_points: vec![DataPoint {
+                        attributes: attributes.clone(),
+                        start_time: None,
+                        time: None,
+                        value: 3u64,
+                        exemplars: Vec::new(),
+                    }],
+                    temporality: Temporality::Delta,
+                    is_monotonic: true,
+                }),
+            ),
+            metric(
+                "connections",
+                Box::new(Sum {
+                    data_points: vec![DataPoint {
+                        attributes: AttributeSet::from(&[] as &[KeyValue]),
+                        start_time: None,
+                        time: None,
+                        value: -2i64,
+                        exemplars: Vec::new(),
+                    }],
+                    temporality: Temporality::Cumulative,
+                    is_monotonic: false,
+                }),
+            ),
+            metric(
+                "http.duration",
+                Box::new(Histogram {
+                    data_points: vec![HistogramDataPoint {
+                        attributes,
+                        start_time: std::time::SystemTime::UNIX_EPOCH,
+                        time: std::time::SystemTime::UNIX_EPOCH,
+                        count: 2,
+                        bounds: vec![1.0],
+                        bucket_counts: vec![1, 1],
+                        min: Some(0.5),
+                        max: Some(1.5),
+                        sum: 2.0,
+                        exemplars: Vec::new(),
+                    }],
+                    temporality: Temporality::Delta,
+                }),
+            ),
+        ])
+    }
+
+    #[test]
+    fn dogstatsd_lines() {
+        let exporter = exporter(Flavor::Dogstatsd, "127.0.0.1:8125");
+        assert_eq!(
+            exporter.lines(&test_metrics()),
+            [
+                "router.http.requests:3|c|#env:prod,subgraph.name:products",
+                "router.connections:-2|g|#env:prod",
+                "router.http.duration.count:2|c|#env:prod,subgraph.name:products",
+                "router.http.duration.sum:2|c|#env:prod,subgraph.name:products",
+                "router.http.duration.min:0.5|g|#env:prod,subgraph.name:products",
+                "router.http.duration.max:1.5|g|#env:prod,subgraph.name:products",
+            ]
+        );
+    }
+
+    #[test]
+    fn statsd_lines() {
+        let exporter = exporter(Flavor::Statsd, "127.0.0.1:8125");
+        assert_eq!(
+            exporter.lines(&test_metrics())[..3],
+            [
+                "router.http.requests:3|c",
+                "router.connections:0|g",
+                "router.connections:-2|g",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_datagrams() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let exporter = exporter(Flavor::Dogstatsd, &server.local_addr().unwrap().to_string());
+
+        exporter.export(&mut test_metrics()).await.unwrap();
+
+        let mut lines = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while lines.len() < 6 {
+            let len = server.recv(&mut buffer).unwrap();
+            assert!(len <= 64);
+            let packet = std::str::from_utf8(&buffer[..len]).unwrap();
+            lines.extend(packet.lines().map(str::to_string));
+        }
+        assert_eq!(
+            lines[0],
+            "router.http.requests:3|c|#env:prod,subgraph.name:products"
+        );
+    }
+}
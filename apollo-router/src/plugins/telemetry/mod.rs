@@ -12,10 +12,12 @@ use axum::headers::HeaderName;
 use config_new::cache::CacheInstruments;
 use config_new::instruments::InstrumentsConfig;
 use config_new::instruments::StaticInstrument;
+use config_new::query_planner::QueryPlannerInstruments;
 use config_new::Selectors;
 use dashmap::DashMap;
 use futures::future::ready;
 use futures::future::BoxFuture;
+use futures::stream::empty;
 use futures::stream::once;
 use futures::StreamExt;
 use http::header;
@@ -56,6 +58,7 @@ use tokio::runtime::Handle;
 use tower::BoxError;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
+use tracing_subscriber::Layer;
 use uuid::Uuid;
 
 use self::apollo::ForwardValues;
@@ -68,9 +71,11 @@ use self::config::Conf;
 use self::config::Sampler;
 use self::config::SamplerOption;
 use self::config::TraceIdFormat;
+use self::config_new::events::ExecutionEvents;
 use self::config_new::events::RouterEvents;
 use self::config_new::events::SubgraphEvents;
 use self::config_new::events::SupergraphEvents;
+use self::config_new::execution::ExecutionInstruments;
 use self::config_new::instruments::Instrumented;
 use self::config_new::instruments::RouterInstruments;
 use self::config_new::instruments::SubgraphInstruments;
@@ -82,6 +87,7 @@ pub(crate) use self::span_factory::SpanMode;
 use self::tracing::apollo_telemetry::APOLLO_PRIVATE_DURATION_NS;
 use self::tracing::apollo_telemetry::CLIENT_NAME_KEY;
 use self::tracing::apollo_telemetry::CLIENT_VERSION_KEY;
+use self::tracing::sampling::TailSamplingSpanProcessor;
 use crate::apollo_studio_interop::ExtendedReferenceStats;
 use crate::apollo_studio_interop::ReferencedEnums;
 use crate::context::CONTAINS_GRAPHQL_ERROR;
@@ -93,8 +99,8 @@ use crate::layers::ServiceBuilderExt;
 use crate::metrics::aggregation::MeterProviderType;
 use crate::metrics::filter::FilterMeterProvider;
 use crate::metrics::meter_provider;
-use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugin::PluginPrivate;
 use crate::plugins::demand_control;
 use crate::plugins::telemetry::apollo::ForwardHeaders;
 use crate::plugins::telemetry::apollo_exporter::proto::reports::trace::node::Id::ResponseName;
@@ -115,6 +121,8 @@ use crate::plugins::telemetry::consts::OTEL_STATUS_CODE_OK;
 use crate::plugins::telemetry::consts::REQUEST_SPAN_NAME;
 use crate::plugins::telemetry::consts::ROUTER_SPAN_NAME;
 use crate::plugins::telemetry::dynamic_attribute::SpanDynAttribute;
+use crate::plugins::telemetry::field_spans::record_field_spans;
+use crate::plugins::telemetry::field_spans::EnableSubgraphFieldSpans;
 use crate::plugins::telemetry::fmt_layer::create_fmt_layer;
 use crate::plugins::telemetry::metrics::apollo::histogram::ListLengthHistogram;
 use crate::plugins::telemetry::metrics::apollo::studio::LocalTypeStat;
@@ -128,21 +136,24 @@ use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
 use crate::plugins::telemetry::otel::OpenTelemetrySpanExt;
 use crate::plugins::telemetry::reload::metrics_layer;
+use crate::plugins::telemetry::reload::LayeredTracer;
 use crate::plugins::telemetry::reload::OPENTELEMETRY_TRACER_HANDLE;
 use crate::plugins::telemetry::tracing::apollo_telemetry::decode_ftv1_trace;
 use crate::plugins::telemetry::tracing::apollo_telemetry::APOLLO_PRIVATE_OPERATION_SIGNATURE;
 use crate::plugins::telemetry::tracing::TracingConfigurator;
 use crate::plugins::telemetry::utils::TracingUtils;
 use crate::query_planner::OperationKind;
-use crate::register_plugin;
+use crate::register_private_plugin;
 use crate::router_factory::Endpoint;
 use crate::services::execution;
+use crate::services::query_planner;
 use crate::services::router;
 use crate::services::subgraph;
 use crate::services::subgraph::Request;
 use crate::services::subgraph::Response;
 use crate::services::supergraph;
 use crate::services::ExecutionRequest;
+use crate::services::ExecutionResponse;
 use crate::services::SubgraphRequest;
 use crate::services::SubgraphResponse;
 use crate::services::SupergraphRequest;
@@ -159,13 +170,14 @@ pub(crate) mod config_new;
 pub(crate) mod consts;
 pub(crate) mod dynamic_attribute;
 mod endpoint;
+mod field_spans;
 mod fmt_layer;
 pub(crate) mod formatters;
-mod logging;
+pub(crate) mod logging;
 pub(crate) mod metrics;
 /// Opentelemetry utils
 pub(crate) mod otel;
-mod otlp;
+pub(crate) mod otlp;
 pub(crate) mod reload;
 mod resource;
 mod span_factory;
@@ -205,8 +217,10 @@ pub(crate) struct Telemetry {
     pub(crate) graphql_custom_instruments: RwLock<Arc<HashMap<String, StaticInstrument>>>,
     router_custom_instruments: RwLock<Arc<HashMap<String, StaticInstrument>>>,
     supergraph_custom_instruments: RwLock<Arc<HashMap<String, StaticInstrument>>>,
+    execution_custom_instruments: RwLock<Arc<HashMap<String, StaticInstrument>>>,
     subgraph_custom_instruments: RwLock<Arc<HashMap<String, StaticInstrument>>>,
     cache_custom_instruments: RwLock<Arc<HashMap<String, StaticInstrument>>>,
+    query_planner_custom_instruments: RwLock<Arc<HashMap<String, StaticInstrument>>>,
     activation: Mutex<TelemetryActivation>,
 }
 
@@ -216,6 +230,7 @@ struct TelemetryActivation {
     public_meter_provider: Option<FilterMeterProvider>,
     public_prometheus_meter_provider: Option<FilterMeterProvider>,
     private_meter_provider: Option<FilterMeterProvider>,
+    fmt_layer: Option<Box<dyn Layer<LayeredTracer> + Send + Sync>>,
     is_active: bool,
 }
 
@@ -264,8 +279,10 @@ struct BuiltinInstruments {
     graphql_custom_instruments: Arc<HashMap<String, StaticInstrument>>,
     router_custom_instruments: Arc<HashMap<String, StaticInstrument>>,
     supergraph_custom_instruments: Arc<HashMap<String, StaticInstrument>>,
+    execution_custom_instruments: Arc<HashMap<String, StaticInstrument>>,
     subgraph_custom_instruments: Arc<HashMap<String, StaticInstrument>>,
     cache_custom_instruments: Arc<HashMap<String, StaticInstrument>>,
+    query_planner_custom_instruments: Arc<HashMap<String, StaticInstrument>>,
 }
 
 fn create_builtin_instruments(config: &InstrumentsConfig) -> BuiltinInstruments {
@@ -273,13 +290,15 @@ fn create_builtin_instruments(config: &InstrumentsConfig) -> BuiltinInstruments
         graphql_custom_instruments: Arc::new(config.new_builtin_graphql_instruments()),
         router_custom_instruments: Arc::new(config.new_builtin_router_instruments()),
         supergraph_custom_instruments: Arc::new(config.new_builtin_supergraph_instruments()),
+        execution_custom_instruments: Arc::new(config.new_builtin_execution_instruments()),
         subgraph_custom_instruments: Arc::new(config.new_builtin_subgraph_instruments()),
         cache_custom_instruments: Arc::new(config.new_builtin_cache_instruments()),
+        query_planner_custom_instruments: Arc::new(config.new_builtin_query_planner_instruments()),
     }
 }
 
 #[async_trait::async_trait]
-impl Plugin for Telemetry {
+impl PluginPrivate for Telemetry {
     type Config = config::Conf;
 
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
@@ -295,7 +314,13 @@ impl Plugin for Telemetry {
             config.calculate_field_level_instrumentation_ratio()?;
         let metrics_builder = Self::create_metrics_builder(&config)?;
 
+        config
+            .exporters
+            .tracing
+            .common
+            .validate_sampling_rules(config.instrumentation.spans.mode)?;
         let (sampling_filter_ratio, tracer_provider) = Self::create_tracer_provider(&config)?;
+        let fmt_layer = create_fmt_layer(&config)?;
 
         if config.instrumentation.spans.mode == SpanMode::Deprecated {
             ::tracing::warn!("telemetry.instrumentation.spans.mode is currently set to 'deprecated', either explicitly or via defaulting. Set telemetry.instrumentation.spans.mode explicitly in your router.yaml to 'spec_compliant' for log and span attributes that follow OpenTelemetry semantic conventions. This option will be defaulted to 'spec_compliant' in a future release and eventually removed altogether");
@@ -305,8 +330,10 @@ impl Plugin for Telemetry {
             graphql_custom_instruments,
             router_custom_instruments,
             supergraph_custom_instruments,
+            execution_custom_instruments,
             subgraph_custom_instruments,
             cache_custom_instruments,
+            query_planner_custom_instruments,
         } = create_builtin_instruments(&config.instrumentation.instruments);
 
         Ok(Telemetry {
@@ -324,13 +351,16 @@ impl Plugin for Telemetry {
                 public_prometheus_meter_provider: metrics_builder
                     .prometheus_meter_provider
                     .map(FilterMeterProvider::public),
+                fmt_layer: Some(fmt_layer),
                 is_active: false,
             }),
             graphql_custom_instruments: RwLock::new(graphql_custom_instruments),
             router_custom_instruments: RwLock::new(router_custom_instruments),
             supergraph_custom_instruments: RwLock::new(supergraph_custom_instruments),
+            execution_custom_instruments: RwLock::new(execution_custom_instruments),
             subgraph_custom_instruments: RwLock::new(subgraph_custom_instruments),
             cache_custom_instruments: RwLock::new(cache_custom_instruments),
+            query_planner_custom_instruments: RwLock::new(query_planner_custom_instruments),
             sampling_filter_ratio,
             config: Arc::new(config),
         })
@@ -692,6 +722,8 @@ impl Plugin for Telemetry {
     }
 
     fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        let config = self.config.clone();
+        let static_execution_instruments = self.execution_custom_instruments.read().clone();
         ServiceBuilder::new()
             .instrument(move |req: &ExecutionRequest| {
                 let operation_kind = req
@@ -720,6 +752,67 @@ impl Plugin for Telemetry {
                     }
                 }
             })
+            .map_future_with_request_data(
+                move |req: &ExecutionRequest| {
+                    let custom_instruments = config
+                        .instrumentation
+                        .instruments
+                        .new_execution_instruments(static_execution_instruments.clone());
+                    custom_instruments.on_request(req);
+                    let custom_events = config.instrumentation.events.new_execution_events();
+                    custom_events.on_request(req);
+
+                    (req.context.clone(), custom_instruments, custom_events)
+                },
+                move |(ctx, custom_instruments, custom_events): (
+                    Context,
+                    ExecutionInstruments,
+                    ExecutionEvents,
+                ),
+                      fut: BoxFuture<'static, Result<ExecutionResponse, BoxError>>| {
+                    async move {
+                        let result = fut.await;
+                        match result {
+                            Ok(resp) => {
+                                custom_events.on_response(&resp);
+                                // The execution ends with the last response of the stream, and the
+                                // primary and deferred responses are only known once it is consumed,
+                                // so the instruments and events live until then. The instruments get
+                                // the status, headers and context of the response when it ends.
+                                let mut end_response = http::Response::new(empty().boxed());
+                                *end_response.status_mut() = resp.response.status();
+                                *end_response.headers_mut() = resp.response.headers().clone();
+                                let end_response = ExecutionResponse {
+                                    response: end_response,
+                                    context: ctx.clone(),
+                                };
+                                let custom_instruments = Arc::new(custom_instruments);
+                                let end_instruments = custom_instruments.clone();
+                                Ok(resp.map(move |stream| {
+                                    stream
+                                        .map(move |gql_response| {
+                                            custom_instruments.on_response_event(&gql_response, &ctx);
+                                            custom_events.on_response_event(&gql_response, &ctx);
+                                            gql_response
+                                        })
+                                        .chain(
+                                            once(async move {
+                                                end_instruments.on_response(&end_response);
+                                            })
+                                            .filter_map(|()| ready(None)),
+                                        )
+                                        .boxed()
+                                }))
+                            }
+                            Err(err) => {
+                                custom_instruments.on_error(&err, &ctx);
+                                custom_events.on_error(&err, &ctx);
+                                Err(err)
+                            }
+                        }
+                    }
+                },
+            )
             .service(service)
             .boxed()
     }
@@ -847,6 +940,58 @@ impl Plugin for Telemetry {
             .boxed()
     }
 
+    fn query_planner_service(
+        &self,
+        service: query_planner::CachingBoxService,
+    ) -> query_planner::CachingBoxService {
+        let config = self.config.clone();
+        let config_response = self.config.clone();
+        let static_query_planner_instruments = self.query_planner_custom_instruments.read().clone();
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                move |req: &query_planner::CachingRequest| {
+                    // the query planning span is created by the supergraph service
+                    Span::current().set_span_dyn_attributes(
+                        config
+                            .instrumentation
+                            .spans
+                            .query_planner
+                            .attributes
+                            .on_request(req),
+                    );
+                    let custom_instruments = config
+                        .instrumentation
+                        .instruments
+                        .new_query_planner_instruments(static_query_planner_instruments.clone());
+                    custom_instruments.on_request(req);
+
+                    (req.context.clone(), custom_instruments)
+                },
+                move |(ctx, custom_instruments): (Context, QueryPlannerInstruments),
+                      fut: BoxFuture<'static, Result<query_planner::Response, BoxError>>| {
+                    let config = config_response.clone();
+                    async move {
+                        let span = Span::current();
+                        let span_attributes = &config.instrumentation.spans.query_planner.attributes;
+                        let result = fut.await;
+                        match &result {
+                            Ok(resp) => {
+                                span.set_span_dyn_attributes(span_attributes.on_response(resp));
+                                custom_instruments.on_response(resp);
+                            }
+                            Err(err) => {
+                                span.set_span_dyn_attributes(span_attributes.on_error(err, &ctx));
+                                custom_instruments.on_error(err, &ctx);
+                            }
+                        }
+                        result
+                    }
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
         self.custom_endpoints.clone()
     }
@@ -889,22 +1034,29 @@ impl Telemetry {
         }
 
         activation.reload_metrics();
+        metrics::exemplars::set_enabled(self.config.exporters.metrics.common.exemplars);
 
         let BuiltinInstruments {
             graphql_custom_instruments,
             router_custom_instruments,
             supergraph_custom_instruments,
+            execution_custom_instruments,
             subgraph_custom_instruments,
             cache_custom_instruments,
+            query_planner_custom_instruments,
         } = create_builtin_instruments(&self.config.instrumentation.instruments);
 
         *self.graphql_custom_instruments.write() = graphql_custom_instruments;
         *self.router_custom_instruments.write() = router_custom_instruments;
         *self.supergraph_custom_instruments.write() = supergraph_custom_instruments;
+        *self.execution_custom_instruments.write() = execution_custom_instruments;
         *self.subgraph_custom_instruments.write() = subgraph_custom_instruments;
         *self.cache_custom_instruments.write() = cache_custom_instruments;
+        *self.query_planner_custom_instruments.write() = query_planner_custom_instruments;
 
-        reload_fmt(create_fmt_layer(&self.config));
+        if let Some(fmt_layer) = activation.fmt_layer.take() {
+            reload_fmt(fmt_layer);
+        }
         activation.is_active = true;
     }
 
@@ -953,9 +1105,15 @@ impl Telemetry {
         let spans_config = &config.instrumentation.spans;
         let mut common = tracing_config.common.clone();
         let mut sampler = common.sampler.clone();
-        // set it to AlwaysOn: it is now done in the SamplingFilter, so whatever is sent to an exporter
-        // should be accepted
-        common.sampler = SamplerOption::Always(Sampler::AlwaysOn);
+        if common.has_sampling_rules() {
+            // the SamplingFilter records the root spans at the highest ratio of the rules, and the
+            // rule sampler keeps the expected share of them
+            sampler = SamplerOption::TraceIdRatioBased(common.recorded_ratio());
+        } else {
+            // set it to AlwaysOn: it is now done in the SamplingFilter, so whatever is sent to an exporter
+            // should be accepted
+            common.sampler = SamplerOption::Always(Sampler::AlwaysOn);
+        }
 
         let mut builder =
             opentelemetry::sdk::trace::TracerProvider::builder().with_config((&common).into());
@@ -975,7 +1133,17 @@ impl Telemetry {
             sampler = SamplerOption::Always(Sampler::AlwaysOff);
         }
 
-        let tracer_provider = builder.build();
+        let mut tracer_provider = builder.build();
+        if common.tail_sampling.enabled {
+            // a single buffer for all the exporters
+            tracer_provider = opentelemetry::sdk::trace::TracerProvider::builder()
+                .with_config((&common).into())
+                .with_span_processor(TailSamplingSpanProcessor::new(
+                    tracer_provider,
+                    &common.tail_sampling,
+                ))
+                .build();
+        }
         Ok((sampler, tracer_provider))
     }
 
@@ -987,6 +1155,7 @@ impl Telemetry {
         builder =
             setup_metrics_exporter(builder, &metrics_config.prometheus, metrics_common_config)?;
         builder = setup_metrics_exporter(builder, &metrics_config.otlp, metrics_common_config)?;
+        builder = setup_metrics_exporter(builder, &metrics_config.statsd, metrics_common_config)?;
         Ok(builder)
     }
 
@@ -1178,6 +1347,14 @@ impl Telemetry {
                 .extensions()
                 .with_lock(|mut lock| lock.insert(EnableSubgraphFtv1));
         }
+        let field_spans = &config.instrumentation.spans.subgraph.field_spans;
+        if field_spans.enabled {
+            context.extensions().with_lock(|mut lock| {
+                lock.insert(EnableSubgraphFieldSpans {
+                    max_spans: field_spans.max_spans,
+                })
+            });
+        }
     }
 
     fn create_subgraph_metrics_conf(&self, name: &str) -> Arc<AttributesForwardConf> {
@@ -1741,6 +1918,7 @@ impl Telemetry {
     fn plugin_metrics(config: &Arc<Conf>) {
         let metrics_prom_used = config.exporters.metrics.prometheus.enabled;
         let metrics_otlp_used = MetricsConfigurator::enabled(&config.exporters.metrics.otlp);
+        let metrics_statsd_used = config.exporters.metrics.statsd.enabled;
         let tracing_otlp_used = TracingConfigurator::enabled(&config.exporters.tracing.otlp);
         let tracing_datadog_used = config.exporters.tracing.datadog.enabled();
         let tracing_jaeger_used = config.exporters.tracing.jaeger.enabled();
@@ -1748,6 +1926,7 @@ impl Telemetry {
 
         if metrics_prom_used
             || metrics_otlp_used
+            || metrics_statsd_used
             || tracing_jaeger_used
             || tracing_otlp_used
             || tracing_zipkin_used
@@ -1757,6 +1936,7 @@ impl Telemetry {
                 monotonic_counter.apollo.router.operations.telemetry = 1u64,
                 telemetry.metrics.otlp = metrics_otlp_used.or_empty(),
                 telemetry.metrics.prometheus = metrics_prom_used.or_empty(),
+                telemetry.metrics.statsd = metrics_statsd_used.or_empty(),
                 telemetry.tracing.otlp = tracing_otlp_used.or_empty(),
                 telemetry.tracing.datadog = tracing_datadog_used.or_empty(),
                 telemetry.tracing.jaeger = tracing_jaeger_used.or_empty(),
@@ -1971,14 +2151,12 @@ fn handle_error_internal<T: Into<opentelemetry::global::Error>>(
     }
 }
 
-register_plugin!("apollo", "telemetry", Telemetry);
+register_private_plugin!("apollo", "telemetry", Telemetry);
 
 fn request_ftv1(mut req: SubgraphRequest) -> SubgraphRequest {
-    if req
-        .context
-        .extensions()
-        .with_lock(|lock| lock.contains_key::<EnableSubgraphFtv1>())
-        && Span::current().context().span().span_context().is_sampled()
+    if req.context.extensions().with_lock(|lock| {
+        lock.contains_key::<EnableSubgraphFtv1>() || lock.contains_key::<EnableSubgraphFieldSpans>()
+    }) && Span::current().context().span().span_context().is_sampled()
     {
         req.subgraph_request
             .headers_mut()
@@ -1988,30 +2166,37 @@ fn request_ftv1(mut req: SubgraphRequest) -> SubgraphRequest {
 }
 
 fn store_ftv1(subgraph_name: &ByteString, resp: SubgraphResponse) -> SubgraphResponse {
-    // Stash the FTV1 data
-    if resp
-        .context
-        .extensions()
-        .with_lock(|lock| lock.contains_key::<EnableSubgraphFtv1>())
-    {
-        if let Some(serde_json_bytes::Value::String(ftv1)) =
-            resp.response.body().extensions.get("ftv1")
-        {
-            // Record the ftv1 trace for processing later
-            Span::current().record("apollo_private.ftv1", ftv1.as_str());
-            resp.context
-                .upsert_json_value(SUBGRAPH_FTV1, move |value: Value| {
-                    let mut vec = match value {
-                        Value::Array(array) => array,
-                        // upsert_json_value populate the entry with null if it was vacant
-                        Value::Null => Vec::new(),
-                        _ => panic!("unexpected JSON value kind"),
-                    };
-                    vec.push(json!([subgraph_name, ftv1]));
-                    Value::Array(vec)
-                })
+    let (enable_ftv1, field_spans) = resp.context.extensions().with_lock(|lock| {
+        (
+            lock.contains_key::<EnableSubgraphFtv1>(),
+            lock.get::<EnableSubgraphFieldSpans>().cloned(),
+        )
+    });
+    let Some(serde_json_bytes::Value::String(ftv1)) = resp.response.body().extensions.get("ftv1")
+    else {
+        return resp;
+    };
+    if let Some(field_spans) = field_spans {
+        if let Some(trace) = decode_ftv1_trace(ftv1.as_str()) {
+            record_field_spans(subgraph_name.as_str(), &trace, field_spans.max_spans);
         }
     }
+    // Stash the FTV1 data
+    if enable_ftv1 {
+        // Record the ftv1 trace for processing later
+        Span::current().record("apollo_private.ftv1", ftv1.as_str());
+        resp.context
+            .upsert_json_value(SUBGRAPH_FTV1, move |value: Value| {
+                let mut vec = match value {
+                    Value::Array(array) => array,
+                    // upsert_json_value populate the entry with null if it was vacant
+                    Value::Null => Vec::new(),
+                    _ => panic!("unexpected JSON value kind"),
+                };
+                vec.push(json!([subgraph_name, ftv1]));
+                Value::Array(vec)
+            })
+    }
     resp
 }
 
@@ -2275,6 +2460,26 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn plugin_creation_fails_if_the_log_file_cannot_be_opened() {
+        let dir = tempfile::tempdir().unwrap();
+        // the log file would be created under a file instead of a directory
+        let not_a_directory = dir.path().join("router.log");
+        std::fs::write(&not_a_directory, "").unwrap();
+        let path = not_a_directory.join("router.log");
+
+        let error = crate::plugin::plugins()
+            .find(|factory| factory.name == "apollo.telemetry")
+            .expect("Plugin not found")
+            .create_instance_without_schema(&serde_json::json!({
+                "exporters": { "logging": { "file": { "enabled": true, "path": path } } }
+            }))
+            .await
+            .err()
+            .expect("the plugin creation should fail");
+        assert!(error.to_string().contains("cannot open the log file"));
+    }
+
     #[tokio::test]
     async fn config_serialization() {
         create_plugin_with_config(include_str!("testdata/config.router.yaml")).await;
@@ -1,3 +1,7 @@
+use std::borrow::Cow;
+use std::time::SystemTime;
+
+use opentelemetry::trace::Event;
 use opentelemetry::trace::SpanContext;
 use opentelemetry::Context;
 use opentelemetry::KeyValue;
@@ -54,6 +58,9 @@ pub(crate) trait OpenTelemetrySpanExt {
     /// [`SpanContext`]: opentelemetry::trace::SpanContext
     fn add_link_with_attributes(&self, cx: SpanContext, attributes: Vec<KeyValue>);
 
+    /// Adds an event with the given name and attributes to the OpenTelemetry span of `self`.
+    fn add_event(&self, name: impl Into<Cow<'static, str>>, attributes: Vec<KeyValue>);
+
     /// Extracts an OpenTelemetry [`Context`] from `self`.
     ///
     /// [`Context`]: opentelemetry::Context
@@ -110,6 +117,22 @@ impl OpenTelemetrySpanExt for tracing::Span {
         }
     }
 
+    fn add_event(&self, name: impl Into<Cow<'static, str>>, attributes: Vec<KeyValue>) {
+        let mut event = Some(Event::new(name, SystemTime::now(), attributes, 0));
+        self.with_subscriber(move |(id, subscriber)| {
+            if let Some(get_context) = subscriber.downcast_ref::<WithContext>() {
+                get_context.with_context(subscriber, id, move |data, _tracer| {
+                    if let Some(event) = event.take() {
+                        data.builder
+                            .events
+                            .get_or_insert_with(|| Vec::with_capacity(1))
+                            .push(event);
+                    }
+                });
+            }
+        });
+    }
+
     fn context(&self) -> Context {
         let mut cx = None;
         self.with_subscriber(|(id, subscriber)| {
@@ -32,6 +32,7 @@ lazy_static! {
 }
 
 const DEFAULT_HTTP_ENDPOINT_PATH: &str = "/v1/traces";
+const DEFAULT_HTTP_LOGS_ENDPOINT_PATH: &str = "/v1/logs";
 
 #[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
 #[serde(deny_unknown_fields)]
@@ -69,6 +70,7 @@ pub(crate) struct Config {
 pub(crate) enum TelemetryDataKind {
     Traces,
     Metrics,
+    Logs,
 }
 
 impl Config {
@@ -121,22 +123,27 @@ fn add_missing_path(
     kind: TelemetryDataKind,
     mut endpoint_parts: Option<Parts>,
 ) -> Result<Option<Uri>, BoxError> {
+    let default_path = match kind {
+        TelemetryDataKind::Traces => Some(DEFAULT_HTTP_ENDPOINT_PATH),
+        TelemetryDataKind::Logs => Some(DEFAULT_HTTP_LOGS_ENDPOINT_PATH),
+        TelemetryDataKind::Metrics => None,
+    };
     if let Some(endpoint_parts) = &mut endpoint_parts {
-        if let TelemetryDataKind::Traces = kind {
+        if let Some(default_path) = default_path {
             match &mut endpoint_parts.path_and_query {
                 Some(path_and_query) => {
-                    if !path_and_query.path().ends_with(DEFAULT_HTTP_ENDPOINT_PATH) {
+                    if !path_and_query.path().ends_with(default_path) {
                         match path_and_query.query() {
                             Some(query) => {
                                 endpoint_parts.path_and_query =
                                     Some(PathAndQuery::from_str(&format!(
-                                        "{}{DEFAULT_HTTP_ENDPOINT_PATH}?{query}",
+                                        "{}{default_path}?{query}",
                                         path_and_query.path().trim_end_matches('/')
                                     ))?);
                             }
                             None => {
                                 *path_and_query = PathAndQuery::from_str(&format!(
-                                    "{}{DEFAULT_HTTP_ENDPOINT_PATH}",
+                                    "{}{default_path}",
                                     path_and_query.path().trim_end_matches('/')
                                 ))?;
                             }
@@ -144,8 +151,7 @@ fn add_missing_path(
                     }
                 }
                 None => {
-                    endpoint_parts.path_and_query =
-                        Some(PathAndQuery::from_static(DEFAULT_HTTP_ENDPOINT_PATH));
+                    endpoint_parts.path_and_query = Some(PathAndQuery::from_static(default_path));
                 }
             }
         }
@@ -27,6 +27,7 @@ pub(crate) mod datadog_exporter;
 pub(crate) mod jaeger;
 pub(crate) mod otlp;
 pub(crate) mod reload;
+pub(crate) mod sampling;
 pub(crate) mod zipkin;
 
 pub(crate) trait TracingConfigurator {
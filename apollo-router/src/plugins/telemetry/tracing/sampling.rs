@@ -0,0 +1,855 @@
+//! Sampling rules, deciding when the traces start, and tail sampling, deciding when they end
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::time::Duration;
+use std::time::Instant;
+
+use indexmap::IndexMap;
+use lru::LruCache;
+use opentelemetry::sdk::export::trace::SpanData;
+use opentelemetry::sdk::trace::ShouldSample;
+use opentelemetry::sdk::trace::Span;
+use opentelemetry::sdk::trace::SpanProcessor;
+use opentelemetry::sdk::trace::TracerProvider;
+use opentelemetry::trace::Link;
+use opentelemetry::trace::OrderMap;
+use opentelemetry::trace::SamplingDecision;
+use opentelemetry::trace::SamplingResult;
+use opentelemetry::trace::SpanContext;
+use opentelemetry::trace::SpanKind;
+use opentelemetry::trace::Status;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::trace::TraceId;
+use opentelemetry::trace::TraceResult;
+use opentelemetry::Context;
+use opentelemetry::Key;
+use opentelemetry::Value;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+
+use crate::plugins::telemetry::config::AttributeValue;
+use crate::plugins::telemetry::config::Sampler;
+use crate::plugins::telemetry::config::SamplerOption;
+use crate::plugins::telemetry::config::TracingCommon;
+use crate::plugins::telemetry::consts::REQUEST_SPAN_NAME;
+use crate::plugins::telemetry::consts::ROUTER_SPAN_NAME;
+use crate::plugins::telemetry::tracing::apollo_telemetry::CLIENT_NAME_KEY;
+use crate::plugins::telemetry::SpanMode;
+use crate::query_planner::subscription::SUBSCRIPTION_EVENT_SPAN_NAME;
+
+/// Number of traces for which the decision is remembered, for the spans ending after their root
+const DECIDED_TRACES: usize = 10_000;
+/// The attributes of the request span when it is created, the root span of the deprecated span mode
+const REQUEST_SPAN_ATTRIBUTES: [&str; 4] = [
+    "http.method",
+    "http.request.method",
+    "http.route",
+    "http.flavor",
+];
+
+/// A sampling rule, setting the sampler of the traces it matches.
+///
+/// The rules are evaluated when a trace starts, on the attributes of its root span. All the
+/// conditions of a rule must match. A rule without conditions matches every trace.
+///
+/// With the deprecated span mode, the root span is the request span, created before the client
+/// name and the router span attributes are known: the rules can only match its HTTP attributes.
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SamplingRule {
+    /// The sampler of the matching traces, always_on, always_off or a decimal between 0.0 and 1.0
+    pub(crate) sampler: SamplerOption,
+    /// Matches the traces of the requests sent by this client
+    #[serde(default)]
+    pub(crate) client_name: Option<String>,
+    /// Matches the traces with a root span having these attribute values, such as request headers
+    /// recorded as router span attributes
+    #[serde(default)]
+    pub(crate) attributes: HashMap<String, AttributeValue>,
+}
+
+impl SamplingRule {
+    fn matches(&self, attributes: &OrderMap<Key, Value>) -> bool {
+        self.client_name.as_deref().map_or(true, |name| {
+            attributes
+                .get(&CLIENT_NAME_KEY)
+                .is_some_and(|value| value.as_str() == name)
+        }) && self.attributes.iter().all(|(key, expected)| {
+            attributes
+                .get(&Key::new(key.to_string()))
+                .is_some_and(|value| *value == Value::from(expected.clone()))
+        })
+    }
+}
+
+/// Tail sampling: the spans of the traces not sampled by the sampling rules or the sampler are
+/// buffered until their trace ends, and the traces matching a policy are kept.
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct TailSampling {
+    /// Enables tail sampling
+    pub(crate) enabled: bool,
+    /// Keeps the traces with a span in error
+    pub(crate) errors: bool,
+    /// Keeps the traces lasting longer than this duration
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "Option<String>")]
+    pub(crate) min_duration: Option<Duration>,
+    /// Keeps the traces with a span having one of these attribute values
+    pub(crate) attributes: HashMap<String, AttributeValue>,
+    /// The maximum number of traces waiting for a decision. When it is reached, the decision is
+    /// taken for the oldest trace with the spans received so far
+    pub(crate) max_traces: usize,
+    /// The maximum duration to wait for the end of a trace before taking the decision
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    pub(crate) decision_wait: Duration,
+}
+
+impl Default for TailSampling {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            errors: true,
+            min_duration: None,
+            attributes: HashMap::new(),
+            max_traces: 10_000,
+            decision_wait: Duration::from_secs(30),
+        }
+    }
+}
+
+impl TailSampling {
+    fn keeps(&self, span: &SpanData) -> bool {
+        (self.errors && matches!(span.status, Status::Error { .. }))
+            || (is_root(span)
+                && self.min_duration.is_some_and(|min_duration| {
+                    span.end_time
+                        .duration_since(span.start_time)
+                        .unwrap_or_default()
+                        >= min_duration
+                }))
+            || self.attributes.iter().any(|(key, expected)| {
+                span.attributes
+                    .get(&Key::new(key.to_string()))
+                    .is_some_and(|value| *value == Value::from(expected.clone()))
+            })
+    }
+}
+
+impl TracingCommon {
+    /// Whether the sampling decision is taken by the [`RuleSampler`] instead of the sampler alone
+    pub(crate) fn has_sampling_rules(&self) -> bool {
+        !self.sampling_rules.is_empty() || self.tail_sampling.enabled
+    }
+
+    /// Rejects the sampling rules which could never match, because their attributes are not set on
+    /// the root span when the sampling decision is taken
+    pub(crate) fn validate_sampling_rules(&self, span_mode: SpanMode) -> Result<(), BoxError> {
+        if span_mode != SpanMode::Deprecated {
+            return Ok(());
+        }
+        for rule in &self.sampling_rules {
+            if rule.client_name.is_some() {
+                return Err("the client_name condition of the sampling rules requires telemetry.instrumentation.spans.mode: spec_compliant".into());
+            }
+            if let Some(key) = rule
+                .attributes
+                .keys()
+                .find(|key| !REQUEST_SPAN_ATTRIBUTES.contains(&key.as_str()))
+            {
+                return Err(format!(
+                    "the attribute '{key}' of the sampling rules is not known when the trace starts, with telemetry.instrumentation.spans.mode: deprecated the rules can only match {}",
+                    REQUEST_SPAN_ATTRIBUTES.join(", ")
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    /// The ratio of the root spans recorded by the tracing layer, before the [`RuleSampler`]
+    /// decides. Tail sampling needs every trace
+    pub(crate) fn recorded_ratio(&self) -> f64 {
+        if self.tail_sampling.enabled {
+            return 1.0;
+        }
+        self.sampling_rules
+            .iter()
+            .map(|rule| ratio(&rule.sampler))
+            .fold(ratio(&self.sampler), f64::max)
+    }
+}
+
+/// Head sampler applying the sampling rules to the root spans. The other spans follow the decision
+/// of their parent.
+///
+/// The tracing layer already recorded the root spans at [`TracingCommon::recorded_ratio`], so the
+/// ratio of the rule is scaled to keep the expected share of the traces. With tail sampling, the
+/// traces that are not sampled are still recorded, for the [`TailSamplingSpanProcessor`].
+#[derive(Clone, Debug)]
+pub(crate) struct RuleSampler {
+    rules: Vec<SamplingRule>,
+    sampler: SamplerOption,
+    recorded_ratio: f64,
+    parent_based: bool,
+    tail_sampling: bool,
+}
+
+impl RuleSampler {
+    pub(crate) fn new(common: &TracingCommon) -> Self {
+        Self {
+            rules: common.sampling_rules.clone(),
+            sampler: common.sampler.clone(),
+            recorded_ratio: common.recorded_ratio(),
+            parent_based: common.parent_based_sampler,
+            tail_sampling: common.tail_sampling.enabled,
+        }
+    }
+}
+
+impl ShouldSample for RuleSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        _name: &str,
+        _span_kind: &SpanKind,
+        attributes: &OrderMap<Key, Value>,
+        _links: &[Link],
+    ) -> SamplingResult {
+        let parent = parent_context
+            .filter(|cx| cx.has_active_span())
+            .map(|cx| cx.span().span_context().clone());
+        let sampled = match &parent {
+            // the spans of the router follow the decision taken for their root span
+            Some(parent) if !parent.is_remote() || self.parent_based => parent.is_sampled(),
+            _ => {
+                let sampler = self
+                    .rules
+                    .iter()
+                    .find(|rule| rule.matches(attributes))
+                    .map_or(&self.sampler, |rule| &rule.sampler);
+                self.recorded_ratio > 0.0
+                    && is_sampled(trace_id, ratio(sampler) / self.recorded_ratio)
+            }
+        };
+        SamplingResult {
+            decision: if sampled {
+                SamplingDecision::RecordAndSample
+            } else if self.tail_sampling {
+                SamplingDecision::RecordOnly
+            } else {
+                SamplingDecision::Drop
+            },
+            attributes: Vec::new(),
+            trace_state: parent
+                .map(|parent| parent.trace_state().clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+struct PendingTrace {
+    spans: Vec<SpanData>,
+    keep: bool,
+    started: Instant,
+}
+
+struct Traces {
+    pending: IndexMap<TraceId, PendingTrace>,
+    decided: LruCache<TraceId, bool>,
+}
+
+/// Sends the sampled spans to the span processors of the exporters, and buffers the spans of the
+/// traces recorded for tail sampling until their root span ended. A single buffer is shared by all
+/// the exporters.
+pub(crate) struct TailSamplingSpanProcessor {
+    exporters: Option<TracerProvider>,
+    tail_sampling: TailSampling,
+    traces: Mutex<Traces>,
+}
+
+impl std::fmt::Debug for TailSamplingSpanProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TailSamplingSpanProcessor")
+            .field("tail_sampling", &self.tail_sampling)
+            .finish()
+    }
+}
+
+impl TailSamplingSpanProcessor {
+    /// The span processors of the exporters are the ones of the `exporters` provider
+    pub(crate) fn new(exporters: TracerProvider, tail_sampling: &TailSampling) -> Self {
+        Self {
+            exporters: Some(exporters),
+            tail_sampling: tail_sampling.clone(),
+            traces: Mutex::new(Traces {
+                pending: IndexMap::new(),
+                decided: LruCache::new(NonZeroUsize::new(DECIDED_TRACES).expect("not zero; qed")),
+            }),
+        }
+    }
+
+    fn export(&self, span: SpanData) {
+        if let Some(exporters) = &self.exporters {
+            let processors = exporters.span_processors();
+            if let Some((last, others)) = processors.split_last() {
+                for processor in others {
+                    processor.on_end(span.clone());
+                }
+                last.on_end(span);
+            }
+        }
+    }
+
+    fn decide_all(
+        &self,
+        traces: &mut Traces,
+        decided: Vec<(TraceId, PendingTrace)>,
+    ) -> Vec<SpanData> {
+        let mut kept = Vec::new();
+        for (trace_id, trace) in decided {
+            traces.decided.put(trace_id, trace.keep);
+            if trace.keep {
+                kept.extend(trace.spans);
+            }
+        }
+        kept
+    }
+}
+
+impl SpanProcessor for TailSamplingSpanProcessor {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        if let Some(exporters) = &self.exporters {
+            for processor in exporters.span_processors() {
+                processor.on_start(span, cx);
+            }
+        }
+    }
+
+    fn on_end(&self, span: SpanData) {
+        // the traces sampled by the rules or the sampler are not buffered
+        if span.span_context.is_sampled() {
+            return self.export(span);
+        }
+        let trace_id = span.span_context.trace_id();
+        let kept = {
+            let mut traces = self.traces.lock();
+            // spans ending after their root follow the decision taken for their trace
+            if let Some(keep) = traces.decided.get(&trace_id).copied() {
+                if keep {
+                    vec![span]
+                } else {
+                    Vec::new()
+                }
+            } else {
+                let root = is_root(&span);
+                let keep = self.tail_sampling.keeps(&span);
+                let trace = traces
+                    .pending
+                    .entry(trace_id)
+                    .or_insert_with(|| PendingTrace {
+                        spans: Vec::new(),
+                        keep: false,
+                        started: Instant::now(),
+                    });
+                trace.keep |= keep;
+                trace.spans.push(span);
+
+                let mut decided = Vec::new();
+                if root {
+                    if let Some(trace) = traces.pending.shift_remove(&trace_id) {
+                        decided.push((trace_id, trace));
+                    }
+                }
+                // the oldest traces are decided with the spans received so far
+                while let Some((_, oldest)) = traces.pending.first() {
+                    if traces.pending.len() <= self.tail_sampling.max_traces
+                        && oldest.started.elapsed() < self.tail_sampling.decision_wait
+                    {
+                        break;
+                    }
+                    if let Some(oldest) = traces.pending.shift_remove_index(0) {
+                        decided.push(oldest);
+                    }
+                }
+                self.decide_all(&mut traces, decided)
+            }
+        };
+
+        for span in kept {
+            self.export(sampled(span));
+        }
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        match &self.exporters {
+            Some(exporters) => exporters.force_flush().into_iter().collect(),
+            None => Ok(()),
+        }
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        let kept = {
+            let mut traces = self.traces.lock();
+            let pending = std::mem::take(&mut traces.pending);
+            self.decide_all(&mut traces, pending.into_iter().collect())
+        };
+        for span in kept {
+            self.export(sampled(span));
+        }
+        // dropping the provider shuts down the span processors of the exporters
+        self.exporters.take();
+        Ok(())
+    }
+}
+
+/// Marks the span of a kept trace as sampled, the span processors of the exporters ignore the
+/// other spans
+fn sampled(mut span: SpanData) -> SpanData {
+    let context = &span.span_context;
+    span.span_context = SpanContext::new(
+        context.trace_id(),
+        context.span_id(),
+        context.trace_flags().with_sampled(true),
+        context.is_remote(),
+        context.trace_state().clone(),
+    );
+    span
+}
+
+fn is_root(span: &SpanData) -> bool {
+    span.parent_span_id == opentelemetry::trace::SpanId::INVALID
+        || [
+            REQUEST_SPAN_NAME,
+            ROUTER_SPAN_NAME,
+            SUBSCRIPTION_EVENT_SPAN_NAME,
+        ]
+        .contains(&span.name.as_ref())
+}
+
+fn ratio(sampler: &SamplerOption) -> f64 {
+    match sampler {
+        SamplerOption::TraceIdRatioBased(ratio) => ratio.clamp(0.0, 1.0),
+        SamplerOption::Always(Sampler::AlwaysOn) => 1.0,
+        SamplerOption::Always(Sampler::AlwaysOff) => 0.0,
+    }
+}
+
+/// The same decision as the trace ID ratio sampler, so that a trace keeps its decision
+fn is_sampled(trace_id: TraceId, ratio: f64) -> bool {
+    if ratio >= 1.0 {
+        return true;
+    }
+    let bytes = trace_id.to_bytes();
+    let mut low = [0u8; 8];
+    low.copy_from_slice(&bytes[8..]);
+    let random = u64::from_be_bytes(low) >> 1;
+    random < (ratio.max(0.0) * (1u64 << 63) as f64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    use opentelemetry::sdk::trace::EvictedHashMap;
+    use opentelemetry::sdk::trace::EvictedQueue;
+    use opentelemetry::sdk::Resource;
+    use opentelemetry::trace::SpanId;
+    use opentelemetry::trace::TraceFlags;
+    use opentelemetry::trace::TraceState;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry::InstrumentationLibrary;
+    use opentelemetry::KeyValue;
+    use tower_http::trace::MakeSpan;
+    use tracing::instrument::WithSubscriber;
+    use tracing::Instrument;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::axum_factory::utils::PropagatingMakeSpan;
+    use crate::plugins::telemetry::otel;
+    use crate::plugins::telemetry::Telemetry;
+    use crate::plugins::test::PluginTestHarness;
+    use crate::services::router;
+
+    #[derive(Debug, Default, Clone)]
+    struct Collect(Arc<Mutex<Vec<SpanData>>>);
+
+    impl SpanProcessor for Collect {
+        fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+        fn on_end(&self, span: SpanData) {
+            self.0.lock().push(span);
+        }
+
+        fn force_flush(&self) -> TraceResult<()> {
+            Ok(())
+        }
+
+        fn shutdown(&mut self) -> TraceResult<()> {
+            Ok(())
+        }
+    }
+
+    fn span(
+        trace_id: u128,
+        name: &'static str,
+        parent_span_id: u64,
+        attributes: Vec<KeyValue>,
+        status: Status,
+    ) -> SpanData {
+        let mut span_attributes = EvictedHashMap::new(16, attributes.len());
+        for attribute in attributes {
+            span_attributes.insert(attribute);
+        }
+        SpanData {
+            span_context: SpanContext::new(
+                TraceId::from_u128(trace_id),
+                SpanId::from_u64(parent_span_id + 1),
+                TraceFlags::default(),
+                false,
+                TraceState::default(),
+            ),
+            parent_span_id: SpanId::from_u64(parent_span_id),
+            span_kind: SpanKind::Internal,
+            name: name.into(),
+            start_time: SystemTime::UNIX_EPOCH,
+            end_time: SystemTime::UNIX_EPOCH + Duration::from_millis(100),
+            attributes: span_attributes,
+            events: EvictedQueue::new(0),
+            links: EvictedQueue::new(0),
+            status,
+            resource: Cow::Owned(Resource::empty()),
+            instrumentation_lib: InstrumentationLibrary::new(
+                "test",
+                None::<&'static str>,
+                None::<&'static str>,
+                None,
+            ),
+        }
+    }
+
+    fn tail_sampling(tail_sampling: TailSampling) -> (Collect, TailSamplingSpanProcessor) {
+        let collect = Collect::default();
+        let exporters = TracerProvider::builder()
+            .with_span_processor(collect.clone())
+            .build();
+        let processor = TailSamplingSpanProcessor::new(
+            exporters,
+            &TailSampling {
+                enabled: true,
+                ..tail_sampling
+            },
+        );
+        (collect, processor)
+    }
+
+    fn decision(
+        sampler: &RuleSampler,
+        parent_context: Option<&Context>,
+        attributes: Vec<KeyValue>,
+    ) -> SamplingDecision {
+        sampler
+            .should_sample(
+                parent_context,
+                TraceId::from_u128(1),
+                ROUTER_SPAN_NAME,
+                &SpanKind::Server,
+                &attributes.into_iter().collect(),
+                &[],
+            )
+            .decision
+    }
+
+    #[test]
+    fn rules_set_the_sampler() {
+        let sampler = RuleSampler::new(&TracingCommon {
+            sampler: SamplerOption::Always(Sampler::AlwaysOff),
+            sampling_rules: vec![
+                SamplingRule {
+                    sampler: SamplerOption::Always(Sampler::AlwaysOn),
+                    client_name: Some("ios".to_string()),
+                    attributes: HashMap::new(),
+                },
+                SamplingRule {
+                    sampler: SamplerOption::TraceIdRatioBased(0.5),
+                    client_name: None,
+                    attributes: [("http.request.header.x-debug".to_string(), "true".into())].into(),
+                },
+            ],
+            ..Default::default()
+        });
+        // the root spans are recorded at the highest ratio of the rules
+        assert_eq!(sampler.recorded_ratio, 1.0);
+
+        assert_eq!(
+            decision(&sampler, None, vec![KeyValue::new("client.name", "ios")]),
+            SamplingDecision::RecordAndSample
+        );
+        assert_eq!(
+            decision(&sampler, None, vec![KeyValue::new("client.name", "web")]),
+            SamplingDecision::Drop
+        );
+        assert_eq!(decision(&sampler, None, Vec::new()), SamplingDecision::Drop);
+
+        // the other spans follow their parent
+        let parent = Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_u128(1),
+            SpanId::from_u64(1),
+            TraceFlags::SAMPLED,
+            false,
+            TraceState::default(),
+        ));
+        assert_eq!(
+            decision(&sampler, Some(&parent), Vec::new()),
+            SamplingDecision::RecordAndSample
+        );
+    }
+
+    #[test]
+    fn rule_ratio_is_scaled_by_the_recorded_ratio() {
+        let sampler = RuleSampler::new(&TracingCommon {
+            sampler: SamplerOption::TraceIdRatioBased(0.1),
+            sampling_rules: vec![SamplingRule {
+                sampler: SamplerOption::TraceIdRatioBased(0.5),
+                client_name: Some("ios".to_string()),
+                attributes: HashMap::new(),
+            }],
+            ..Default::default()
+        });
+        // the tracing layer records half of the root spans, all of them are sampled for the rule
+        assert_eq!(sampler.recorded_ratio, 0.5);
+        assert_eq!(
+            decision(&sampler, None, vec![KeyValue::new("client.name", "ios")]),
+            SamplingDecision::RecordAndSample
+        );
+    }
+
+    #[test]
+    fn traces_are_recorded_for_tail_sampling() {
+        let sampler = RuleSampler::new(&TracingCommon {
+            sampler: SamplerOption::Always(Sampler::AlwaysOff),
+            tail_sampling: TailSampling {
+                enabled: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        assert_eq!(sampler.recorded_ratio, 1.0);
+        assert_eq!(
+            decision(&sampler, None, Vec::new()),
+            SamplingDecision::RecordOnly
+        );
+    }
+
+    #[test]
+    fn traces_with_errors_are_kept() {
+        let (collect, processor) = tail_sampling(TailSampling::default());
+
+        processor.on_end(span(1, "subgraph", 2, Vec::new(), Status::Unset));
+        processor.on_end(span(1, ROUTER_SPAN_NAME, 0, Vec::new(), Status::Unset));
+        processor.on_end(span(
+            2,
+            "subgraph",
+            2,
+            Vec::new(),
+            Status::error("subgraph error"),
+        ));
+        assert!(collect.0.lock().is_empty());
+        processor.on_end(span(2, ROUTER_SPAN_NAME, 0, Vec::new(), Status::Unset));
+        // late spans follow the decision of their trace
+        processor.on_end(span(1, "late", 3, Vec::new(), Status::Unset));
+        processor.on_end(span(2, "late", 3, Vec::new(), Status::Unset));
+
+        let exported = collect.0.lock();
+        assert_eq!(exported.len(), 3);
+        assert!(exported.iter().all(|span| {
+            span.span_context.trace_id() == TraceId::from_u128(2) && span.span_context.is_sampled()
+        }));
+    }
+
+    #[test]
+    fn sampled_traces_are_not_buffered() {
+        let (collect, processor) = tail_sampling(TailSampling::default());
+
+        processor.on_end(sampled(span(1, "subgraph", 2, Vec::new(), Status::Unset)));
+
+        assert_eq!(collect.0.lock().len(), 1);
+    }
+
+    #[test]
+    fn oldest_traces_are_decided_when_the_buffer_is_full() {
+        let (collect, processor) = tail_sampling(TailSampling {
+            attributes: [("graphql.operation.name".to_string(), "Me".into())].into(),
+            max_traces: 1,
+            ..Default::default()
+        });
+
+        processor.on_end(span(
+            1,
+            "supergraph",
+            2,
+            vec![KeyValue::new("graphql.operation.name", "Me")],
+            Status::Unset,
+        ));
+        processor.on_end(span(2, "supergraph", 2, Vec::new(), Status::Unset));
+
+        assert_eq!(collect.0.lock().len(), 1);
+    }
+
+    #[test]
+    fn ratio_is_consistent() {
+        let sampled = (0..1000u128)
+            .filter(|i| {
+                is_sampled(
+                    TraceId::from_u128(i.wrapping_mul(0x9E37_79B9_7F4A_7C15_F39C_C060_5CED_C835)),
+                    0.5,
+                )
+            })
+            .count();
+        assert!((400..600).contains(&sampled));
+        assert_eq!(
+            is_sampled(TraceId::from_u128(42), 0.5),
+            is_sampled(TraceId::from_u128(42), 0.5)
+        );
+        assert!(!is_sampled(TraceId::from_u128(42), 0.0));
+    }
+
+    /// Sends a request with this client name through the telemetry plugin, under a root span
+    /// created like the HTTP server does, and returns the number of exported spans
+    async fn router_spans(
+        span_mode: SpanMode,
+        rule: SamplingRule,
+        client_name: &'static str,
+    ) -> usize {
+        let collect = Collect::default();
+        let provider = TracerProvider::builder()
+            .with_config(
+                opentelemetry::sdk::trace::config().with_sampler(RuleSampler::new(
+                    &TracingCommon {
+                        sampler: SamplerOption::Always(Sampler::AlwaysOff),
+                        sampling_rules: vec![rule],
+                        ..Default::default()
+                    },
+                )),
+            )
+            .with_span_processor(collect.clone())
+            .build();
+        let tracer = provider.versioned_tracer("test", None::<String>, None::<String>, None);
+        let subscriber =
+            tracing_subscriber::registry().with(otel::layer().force_sampling().with_tracer(tracer));
+
+        let mode = match span_mode {
+            SpanMode::Deprecated => "deprecated",
+            SpanMode::SpecCompliant => "spec_compliant",
+        };
+        let harness: PluginTestHarness<Telemetry> = PluginTestHarness::builder()
+            .config(&format!(
+                "telemetry:\n  instrumentation:\n    spans:\n      mode: {mode}\n"
+            ))
+            .build()
+            .await;
+        let request = router::Request::fake_builder()
+            .method(http::Method::POST)
+            .header("apollographql-client-name", client_name)
+            .build()
+            .unwrap();
+
+        async {
+            let root = PropagatingMakeSpan {
+                license: Default::default(),
+                span_mode,
+            }
+            .make_span(&request.router_request);
+            harness
+                .call_router(request, |_| {
+                    // the first child span takes the sampling decision of the trace
+                    tracing::info_span!("supergraph").in_scope(|| {
+                        router::Response::fake_builder()
+                            .build()
+                            .expect("expecting valid response")
+                    })
+                })
+                .instrument(root)
+                .await
+                .unwrap();
+        }
+        .with_subscriber(subscriber)
+        .await;
+
+        let spans = collect.0.lock();
+        spans.len()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn client_name_rules_match_the_router_spans() {
+        let rule = SamplingRule {
+            sampler: SamplerOption::Always(Sampler::AlwaysOn),
+            client_name: Some("ios".to_string()),
+            attributes: HashMap::new(),
+        };
+
+        // the router span and its child
+        assert_eq!(
+            router_spans(SpanMode::SpecCompliant, rule.clone(), "ios").await,
+            2
+        );
+        assert_eq!(router_spans(SpanMode::SpecCompliant, rule, "web").await, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn request_span_attributes_match_with_deprecated_spans() {
+        let rule = SamplingRule {
+            sampler: SamplerOption::Always(Sampler::AlwaysOn),
+            client_name: None,
+            attributes: [("http.request.method".to_string(), "POST".into())].into(),
+        };
+
+        // the request span, the router span and its child
+        assert_eq!(router_spans(SpanMode::Deprecated, rule, "ios").await, 3);
+    }
+
+    #[test]
+    fn rules_unknown_to_the_request_span_are_rejected_with_deprecated_spans() {
+        let common = |rule: SamplingRule| TracingCommon {
+            sampling_rules: vec![rule],
+            ..Default::default()
+        };
+        let client_name = common(SamplingRule {
+            sampler: SamplerOption::Always(Sampler::AlwaysOn),
+            client_name: Some("ios".to_string()),
+            attributes: HashMap::new(),
+        });
+        let header = common(SamplingRule {
+            sampler: SamplerOption::Always(Sampler::AlwaysOn),
+            client_name: None,
+            attributes: [("http.request.header.x-debug".to_string(), "true".into())].into(),
+        });
+        let method = common(SamplingRule {
+            sampler: SamplerOption::Always(Sampler::AlwaysOn),
+            client_name: None,
+            attributes: [("http.request.method".to_string(), "POST".into())].into(),
+        });
+
+        assert!(client_name
+            .validate_sampling_rules(SpanMode::Deprecated)
+            .is_err());
+        assert!(header
+            .validate_sampling_rules(SpanMode::Deprecated)
+            .is_err());
+        assert!(method.validate_sampling_rules(SpanMode::Deprecated).is_ok());
+
+        assert!(client_name
+            .validate_sampling_rules(SpanMode::SpecCompliant)
+            .is_ok());
+        assert!(header
+            .validate_sampling_rules(SpanMode::SpecCompliant)
+            .is_ok());
+    }
+}
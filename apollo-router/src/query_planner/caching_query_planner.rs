@@ -66,6 +66,27 @@ pub(crate) enum ConfigMode {
     Js(Arc<QueryPlannerConfig>),
 }
 
+impl ConfigMode {
+    /// The name of the mode, as configured in `experimental_query_planner_mode`
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ConfigMode::Rust(_) => "new",
+            ConfigMode::Both(_) => "both",
+            ConfigMode::BothBestEffort(_) => "both_best_effort",
+            ConfigMode::Js(_) => "legacy",
+        }
+    }
+}
+
+/// How the query plan of a request was obtained, inserted in the context extensions for telemetry
+#[derive(Clone, Debug)]
+pub(crate) struct PlanningInfo {
+    /// The query plan was found in the cache
+    pub(crate) cache_hit: bool,
+    /// The configured query planner mode
+    pub(crate) mode: &'static str,
+}
+
 /// A query planner wrapper that caches results.
 ///
 /// The query planner performs LRU caching.
@@ -501,6 +522,12 @@ where
                 init_query_plan_from_redis(&self.subgraph_schemas, v)
             })
             .await;
+        context.extensions().with_lock(|mut lock| {
+            lock.insert(PlanningInfo {
+                cache_hit: !entry.is_first(),
+                mode: self.config_mode.name(),
+            })
+        });
         if entry.is_first() {
             let query_planner::CachingRequest {
                 mut query,
@@ -532,6 +559,7 @@ where
                             content,
                             context,
                             errors,
+                            status_code,
                         }) => {
                             if let Some(content) = content.clone() {
                                 let can_cache = match &content {
@@ -556,6 +584,7 @@ where
                                 content,
                                 context,
                                 errors,
+                                status_code,
                             })
                         }
                         Err(error) => {
@@ -303,6 +303,92 @@ impl PlanNode {
         Ok(query_hashes)
     }
 
+    /// The number of nodes in the plan, including both branches of condition nodes
+    pub(crate) fn node_count(&self) -> usize {
+        1 + match self {
+            PlanNode::Sequence { nodes } | PlanNode::Parallel { nodes } => {
+                nodes.iter().map(|n| n.node_count()).sum()
+            }
+            PlanNode::Fetch(_) => 0,
+            PlanNode::Flatten(node) => node.node.node_count(),
+            PlanNode::Defer { primary, deferred } => {
+                primary.node.as_ref().map_or(0, |n| n.node_count())
+                    + deferred
+                        .iter()
+                        .map(|n| n.node.as_ref().map_or(0, |n| n.node_count()))
+                        .sum::<usize>()
+            }
+            PlanNode::Subscription { rest, .. } => rest.as_ref().map_or(0, |n| n.node_count()),
+            PlanNode::Condition {
+                if_clause,
+                else_clause,
+                ..
+            } => {
+                if_clause.as_ref().map_or(0, |n| n.node_count())
+                    + else_clause.as_ref().map_or(0, |n| n.node_count())
+            }
+        }
+    }
+
+    /// The highest number of subgraph fetches that can run at the same time
+    pub(crate) fn parallelism(&self) -> usize {
+        match self {
+            PlanNode::Sequence { nodes } => {
+                nodes.iter().map(|n| n.parallelism()).max().unwrap_or(0)
+            }
+            PlanNode::Parallel { nodes } => nodes.iter().map(|n| n.parallelism()).sum(),
+            PlanNode::Fetch(_) => 1,
+            PlanNode::Flatten(node) => node.node.parallelism(),
+            // deferred parts are executed concurrently with the primary part
+            PlanNode::Defer { primary, deferred } => {
+                primary.node.as_ref().map_or(0, |n| n.parallelism())
+                    + deferred
+                        .iter()
+                        .map(|n| n.node.as_ref().map_or(0, |n| n.parallelism()))
+                        .sum::<usize>()
+            }
+            PlanNode::Subscription { rest, .. } => {
+                std::cmp::max(1, rest.as_ref().map_or(0, |n| n.parallelism()))
+            }
+            PlanNode::Condition {
+                if_clause,
+                else_clause,
+                ..
+            } => std::cmp::max(
+                if_clause.as_ref().map_or(0, |n| n.parallelism()),
+                else_clause.as_ref().map_or(0, |n| n.parallelism()),
+            ),
+        }
+    }
+
+    /// The number of deferred blocks in the plan
+    pub(crate) fn defer_blocks(&self) -> usize {
+        match self {
+            PlanNode::Sequence { nodes } | PlanNode::Parallel { nodes } => {
+                nodes.iter().map(|n| n.defer_blocks()).sum()
+            }
+            PlanNode::Fetch(_) => 0,
+            PlanNode::Flatten(node) => node.node.defer_blocks(),
+            PlanNode::Defer { primary, deferred } => {
+                deferred.len()
+                    + primary.node.as_ref().map_or(0, |n| n.defer_blocks())
+                    + deferred
+                        .iter()
+                        .map(|n| n.node.as_ref().map_or(0, |n| n.defer_blocks()))
+                        .sum::<usize>()
+            }
+            PlanNode::Subscription { rest, .. } => rest.as_ref().map_or(0, |n| n.defer_blocks()),
+            PlanNode::Condition {
+                if_clause,
+                else_clause,
+                ..
+            } => std::cmp::max(
+                if_clause.as_ref().map_or(0, |n| n.defer_blocks()),
+                else_clause.as_ref().map_or(0, |n| n.defer_blocks()),
+            ),
+        }
+    }
+
     pub(crate) fn subgraph_fetches(&self) -> usize {
         match self {
             PlanNode::Sequence { nodes } => nodes.iter().map(|n| n.subgraph_fetches()).sum(),
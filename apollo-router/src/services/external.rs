@@ -42,6 +42,8 @@ pub(crate) enum PipelineStep {
     ExecutionResponse,
     SubgraphRequest,
     SubgraphResponse,
+    QueryPlannerRequest,
+    QueryPlannerResponse,
 }
 
 impl From<PipelineStep> for opentelemetry::Value {
@@ -101,7 +103,7 @@ pub(crate) struct Externalizable<T> {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) has_next: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    query_plan: Option<Arc<QueryPlan>>,
+    pub(crate) query_plan: Option<Arc<QueryPlan>>,
 }
 
 #[buildstructor::buildstructor]
@@ -266,6 +268,75 @@ where
         }
     }
 
+    #[builder(visibility = "pub(crate)")]
+    /// This is the constructor (or builder) to use when constructing a QueryPlanner
+    /// `Externalizable`.
+    ///
+    fn query_planner_new(
+        stage: PipelineStep,
+        control: Option<Control>,
+        id: String,
+        body: Option<T>,
+        context: Option<Context>,
+        sdl: Option<String>,
+        query_plan: Option<Arc<QueryPlan>>,
+    ) -> Result<Self, BoxError> {
+        if !matches!(
+            stage,
+            PipelineStep::QueryPlannerRequest | PipelineStep::QueryPlannerResponse
+        ) {
+            return Err(format!("{stage} is not a query planner stage").into());
+        }
+        Ok(Externalizable {
+            version: EXTERNALIZABLE_VERSION,
+            stage: stage.to_string(),
+            control,
+            id: Some(id),
+            headers: None,
+            body,
+            context,
+            status_code: None,
+            sdl,
+            uri: None,
+            path: None,
+            method: None,
+            service_name: None,
+            has_next: None,
+            query_plan,
+        })
+    }
+
+    /// The coprocessor output that leaves the request or response of the stage unmodified.
+    pub(crate) fn unmodified(stage: PipelineStep, id: Option<String>) -> Self {
+        // the request stages must return a control, while it changes the status of responses
+        let control = matches!(
+            stage,
+            PipelineStep::RouterRequest
+                | PipelineStep::SupergraphRequest
+                | PipelineStep::ExecutionRequest
+                | PipelineStep::SubgraphRequest
+                | PipelineStep::QueryPlannerRequest
+        )
+        .then_some(Control::Continue);
+        Externalizable {
+            version: EXTERNALIZABLE_VERSION,
+            stage: stage.to_string(),
+            control,
+            id,
+            headers: None,
+            body: None,
+            context: None,
+            status_code: None,
+            sdl: None,
+            uri: None,
+            path: None,
+            method: None,
+            service_name: None,
+            has_next: None,
+            query_plan: None,
+        }
+    }
+
     pub(crate) async fn call<C>(self, mut client: C, uri: &str) -> Result<Self, BoxError>
     where
         C: Service<
@@ -380,4 +451,27 @@ mod test {
             .id(String::default())
             .build();
     }
+
+    #[test]
+    fn it_will_build_query_planner_externalizable_correctly() {
+        assert!(Externalizable::<String>::query_planner_builder()
+            .stage(PipelineStep::QueryPlannerRequest)
+            .id(String::default())
+            .build()
+            .is_ok());
+        assert!(Externalizable::<String>::query_planner_builder()
+            .stage(PipelineStep::QueryPlannerResponse)
+            .id(String::default())
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn it_will_not_build_query_planner_externalizable_incorrectly() {
+        assert!(Externalizable::<String>::query_planner_builder()
+            .stage(PipelineStep::SupergraphRequest)
+            .id(String::default())
+            .build()
+            .is_err());
+    }
 }
@@ -10,9 +10,11 @@ use futures::Stream;
 use futures::TryFutureExt;
 use global::get_text_map_propagator;
 use http::header::ACCEPT_ENCODING;
+use http::header::AUTHORIZATION;
 use http::header::CONTENT_ENCODING;
 use http::HeaderValue;
 use http::Request;
+use http::StatusCode;
 use hyper::client::HttpConnector;
 use hyper_rustls::HttpsConnector;
 #[cfg(unix)]
@@ -43,6 +45,7 @@ use crate::plugins::telemetry::reload::prepare_context;
 use crate::plugins::telemetry::LOGGING_DISPLAY_BODY;
 use crate::plugins::telemetry::LOGGING_DISPLAY_HEADERS;
 use crate::plugins::traffic_shaping::Http2Config;
+use crate::services::router::body::get_body_bytes;
 use crate::services::router::body::RouterBody;
 use crate::services::trust_dns_connector::new_async_http_connector;
 use crate::services::trust_dns_connector::AsyncHyperResolver;
@@ -298,12 +301,27 @@ impl tower::Service<HttpRequest> for HttpClientService {
             .with_lock(|lock| lock.get::<Arc<SigningParamsConfig>>().cloned());
 
         Box::pin(async move {
-            let http_request = if let Some(signing_params) = signing_params {
-                signing_params.sign(http_request, &service_name).await?
+            // keep a copy of the request if it can be sent again after refreshing the credentials
+            let (http_request, retry_request) = match &signing_params {
+                Some(signing_params) if signing_params.retry_on_unauthorized() => {
+                    let (http_request, retry_request) = buffer_request(http_request).await?;
+                    (http_request, Some(retry_request))
+                }
+                _ => (http_request, None),
+            };
+
+            let http_request = if let Some(signing_params) = &signing_params {
+                signing_params
+                    .sign(http_request, &service_name, &context)
+                    .await?
             } else {
                 http_request
             };
 
+            let authorization = retry_request
+                .as_ref()
+                .and_then(|_| http_request.headers().get(AUTHORIZATION).cloned());
+
             let display_headers = context.contains_key(LOGGING_DISPLAY_HEADERS);
             let display_body = context.contains_key(LOGGING_DISPLAY_BODY);
 
@@ -315,10 +333,23 @@ impl tower::Service<HttpRequest> for HttpClientService {
                 tracing::info!(http.request.body = ?http_request.body(), apollo.subgraph.name = %service_name, "Request body to subgraph {service_name:?}");
             }
 
-            let http_response = do_fetch(client, &context, &service_name, http_request)
-                .instrument(http_req_span)
+            let mut http_response = do_fetch(client.clone(), &context, &service_name, http_request)
+                .instrument(http_req_span.clone())
                 .await?;
 
+            if let (Some(signing_params), Some(retry_request)) = (signing_params, retry_request) {
+                if http_response.status() == StatusCode::UNAUTHORIZED {
+                    tracing::debug!(apollo.subgraph.name = %service_name, "subgraph {service_name:?} rejected the credentials, retrying with new ones");
+                    signing_params.invalidate(authorization.as_ref()).await;
+                    let retry_request = signing_params
+                        .sign(retry_request, &service_name, &context)
+                        .await?;
+                    http_response = do_fetch(client, &context, &service_name, retry_request)
+                        .instrument(http_req_span)
+                        .await?;
+                }
+            }
+
             // Print out the debug for the response
             if display_headers {
                 tracing::info!(response.headers = ?http_response.headers(), apollo.subgraph.name = %service_name, "Response headers from subgraph {service_name:?}");
@@ -332,6 +363,23 @@ impl tower::Service<HttpRequest> for HttpClientService {
     }
 }
 
+/// Reads the request body, and returns the request along with a copy of it
+async fn buffer_request(
+    request: Request<RouterBody>,
+) -> Result<(Request<RouterBody>, Request<RouterBody>), BoxError> {
+    let (parts, body) = request.into_parts();
+    let body = get_body_bytes(body).await?;
+
+    let mut copy = Request::builder()
+        .method(parts.method.clone())
+        .uri(parts.uri.clone())
+        .version(parts.version)
+        .body(RouterBody::from(body.clone()))?;
+    *copy.headers_mut() = parts.headers.clone();
+
+    Ok((Request::from_parts(parts, RouterBody::from(body)), copy))
+}
+
 async fn do_fetch(
     mut client: MixedClient,
     context: &Context,
@@ -4,9 +4,11 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use derivative::Derivative;
+use http::StatusCode;
 use serde::Deserialize;
 use serde::Serialize;
 use static_assertions::assert_impl_all;
+use tower::BoxError;
 
 use crate::error::QueryPlannerError;
 use crate::graphql;
@@ -73,6 +75,8 @@ pub(crate) struct Response {
     pub(crate) content: Option<QueryPlannerContent>,
     pub(crate) errors: Vec<graphql::Error>,
     pub(crate) context: Context,
+    /// The HTTP status of the errors, when a plugin rejects the operation
+    pub(crate) status_code: Option<StatusCode>,
 }
 
 /// Query, QueryPlan and Introspection data.
@@ -93,11 +97,13 @@ impl Response {
         content: Option<QueryPlannerContent>,
         context: Context,
         errors: Vec<graphql::Error>,
+        status_code: Option<StatusCode>,
     ) -> Response {
         Self {
             content,
             context,
             errors,
+            status_code,
         }
     }
 }
@@ -106,6 +112,8 @@ pub(crate) type BoxService = tower::util::BoxService<Request, Response, QueryPla
 #[allow(dead_code)]
 pub(crate) type BoxCloneService =
     tower::util::BoxCloneService<Request, Response, QueryPlannerError>;
+/// Query planning as seen from the supergraph service, including the query plan cache
+pub(crate) type CachingBoxService = tower::util::BoxService<CachingRequest, Response, BoxError>;
 #[allow(dead_code)]
 pub(crate) type ServiceResult = Result<Response, QueryPlannerError>;
 #[allow(dead_code)]
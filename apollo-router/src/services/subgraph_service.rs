@@ -553,7 +553,7 @@ async fn call_websocket(
 
     let request = if let Some(signing_params) = signing_params {
         signing_params
-            .sign_empty(request, service_name.as_str())
+            .sign_empty(request, service_name.as_str(), &context)
             .await?
     } else {
         request
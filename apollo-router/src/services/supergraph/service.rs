@@ -7,6 +7,7 @@ use std::time::Instant;
 
 use futures::future::BoxFuture;
 use futures::stream::StreamExt;
+use futures::FutureExt;
 use futures::TryFutureExt;
 use http::StatusCode;
 use indexmap::IndexMap;
@@ -17,6 +18,7 @@ use router_bridge::planner::UsageReporting;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::error::SendError;
 use tokio_stream::wrappers::ReceiverStream;
+use tower::buffer::Buffer;
 use tower::BoxError;
 use tower::Layer;
 use tower::ServiceBuilder;
@@ -30,9 +32,11 @@ use crate::batching::BatchQuery;
 use crate::configuration::Batching;
 use crate::context::OPERATION_NAME;
 use crate::error::CacheResolverError;
+use crate::error::QueryPlannerError;
 use crate::graphql;
 use crate::graphql::IntoGraphQLErrors;
 use crate::graphql::Response;
+use crate::layers::ServiceBuilderExt;
 use crate::plugin::DynPlugin;
 use crate::plugins::subscription::SubscriptionConfig;
 use crate::plugins::telemetry::config_new::events::log_event;
@@ -82,11 +86,16 @@ pub(crate) const FIRST_EVENT_CONTEXT_KEY: &str = "apollo_router::supergraph::fir
 /// An [`IndexMap`] of available plugins.
 pub(crate) type Plugins = IndexMap<String, Box<dyn DynPlugin>>;
 
+/// The query planner wrapped by the `query_planner_service` hooks of the plugins, built once for
+/// the supergraph and cloned for each request.
+pub(crate) type QueryPlanningService =
+    Buffer<query_planner::CachingBoxService, query_planner::CachingRequest>;
+
 /// Containing [`Service`] in the request lifecyle.
 #[derive(Clone)]
 pub(crate) struct SupergraphService {
     execution_service_factory: ExecutionServiceFactory,
-    query_planner_service: CachingQueryPlanner<BridgeQueryPlannerPool>,
+    query_planner_service: QueryPlanningService,
     schema: Arc<Schema>,
     notify: Notify<String, graphql::Response>,
 }
@@ -95,7 +104,7 @@ pub(crate) struct SupergraphService {
 impl SupergraphService {
     #[builder]
     pub(crate) fn new(
-        query_planner_service: CachingQueryPlanner<BridgeQueryPlannerPool>,
+        query_planner_service: QueryPlanningService,
         execution_service_factory: ExecutionServiceFactory,
         schema: Arc<Schema>,
         notify: Notify<String, graphql::Response>,
@@ -115,9 +124,7 @@ impl Service<SupergraphRequest> for SupergraphService {
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.query_planner_service
-            .poll_ready(cx)
-            .map_err(|err| err.into())
+        self.query_planner_service.poll_ready(cx)
     }
 
     fn call(&mut self, req: SupergraphRequest) -> Self::Future {
@@ -160,7 +167,7 @@ impl Service<SupergraphRequest> for SupergraphService {
 }
 
 async fn service_call(
-    planning: CachingQueryPlanner<BridgeQueryPlannerPool>,
+    planning: QueryPlanningService,
     execution_service_factory: ExecutionServiceFactory,
     schema: Arc<Schema>,
     req: SupergraphRequest,
@@ -174,6 +181,7 @@ async fn service_call(
         content,
         context,
         errors,
+        status_code,
     } = match plan_query(
         planning,
         body.operation_name.clone(),
@@ -188,15 +196,18 @@ async fn service_call(
     .await
     {
         Ok(resp) => resp,
-        Err(err) => match err.into_graphql_errors() {
-            Ok(gql_errors) => {
-                return Ok(SupergraphResponse::infallible_builder()
-                    .context(context)
-                    .errors(gql_errors)
-                    .status_code(StatusCode::BAD_REQUEST) // If it's a graphql error we return a status code 400
-                    .build());
-            }
-            Err(err) => return Err(err.into()),
+        Err(err) => match err.downcast::<CacheResolverError>() {
+            Ok(err) => match (*err).into_graphql_errors() {
+                Ok(gql_errors) => {
+                    return Ok(SupergraphResponse::infallible_builder()
+                        .context(context)
+                        .errors(gql_errors)
+                        .status_code(StatusCode::BAD_REQUEST) // If it's a graphql error we return a status code 400
+                        .build());
+                }
+                Err(err) => return Err(err.into()),
+            },
+            Err(err) => return Err(err),
         },
     };
 
@@ -204,7 +215,9 @@ async fn service_call(
         return Ok(SupergraphResponse::infallible_builder()
             .context(context)
             .errors(errors)
-            .status_code(StatusCode::BAD_REQUEST) // If it's a graphql error we return a status code 400
+            // If it's a graphql error we return a status code 400, unless a plugin rejected the
+            // operation with another status
+            .status_code(status_code.unwrap_or(StatusCode::BAD_REQUEST))
             .build());
     }
 
@@ -671,12 +684,12 @@ async fn dispatch_event(
 }
 
 async fn plan_query(
-    mut planning: CachingQueryPlanner<BridgeQueryPlannerPool>,
+    planning: QueryPlanningService,
     operation_name: Option<String>,
     context: Context,
     schema: Arc<Schema>,
     query_str: String,
-) -> Result<QueryPlannerResponse, CacheResolverError> {
+) -> Result<QueryPlannerResponse, BoxError> {
     // FIXME: we have about 80 tests creating a supergraph service and crafting a supergraph request for it
     // none of those tests create an executable document to put it in the context, and the document cannot be created
     // from inside the supergraph request fake builder, because it needs a schema matching the query.
@@ -693,27 +706,20 @@ async fn plan_query(
             &schema,
             &Configuration::default(),
         )
-        .map_err(crate::error::QueryPlannerError::from)?;
+        .map_err(|error| CacheResolverError::from(QueryPlannerError::from(error)))?;
         context.extensions().with_lock(|mut lock| {
             lock.insert::<crate::services::layers::query_analysis::ParsedDocument>(doc)
         });
     }
 
-    let qpr = planning
-        .call(
-            query_planner::CachingRequest::builder()
-                .query(query_str)
-                .and_operation_name(operation_name)
-                .context(context.clone())
-                .build(),
-        )
-        .instrument(tracing::info_span!(
-            QUERY_PLANNING_SPAN_NAME,
-            "otel.kind" = "INTERNAL"
-        ))
-        .await?;
+    let request = query_planner::CachingRequest::builder()
+        .query(query_str)
+        .and_operation_name(operation_name)
+        .context(context.clone())
+        .build();
+    let span = tracing::info_span!(QUERY_PLANNING_SPAN_NAME, "otel.kind" = "INTERNAL");
 
-    Ok(qpr)
+    planning.oneshot(request).instrument(span).boxed().await
 }
 
 fn clone_supergraph_request(
@@ -814,6 +820,19 @@ impl PluggableSupergraphServiceBuilder {
             }
         }
 
+        // plugins wrap the query plan cache, so they see every operation, even when its plan was
+        // cached. The hooks are applied after the telemetry activation, which sets up its
+        // instruments.
+        let query_planning_service = ServiceBuilder::new().buffered().service(
+            self.plugins.iter().rev().fold(
+                ServiceBuilder::new()
+                    .map_err(BoxError::from)
+                    .service(query_planner_service.clone())
+                    .boxed(),
+                |acc, (_, plugin)| plugin.query_planner_service(acc),
+            ),
+        );
+
         /*for (_, service) in self.subgraph_services.iter_mut() {
             if let Some(subgraph) =
                 (service as &mut dyn std::any::Any).downcast_mut::<SubgraphService>()
@@ -832,6 +851,7 @@ impl PluggableSupergraphServiceBuilder {
 
         Ok(SupergraphCreator {
             query_planner_service,
+            query_planning_service,
             subgraph_service_factory,
             schema,
             plugins: self.plugins,
@@ -844,6 +864,7 @@ impl PluggableSupergraphServiceBuilder {
 #[derive(Clone)]
 pub(crate) struct SupergraphCreator {
     query_planner_service: CachingQueryPlanner<BridgeQueryPlannerPool>,
+    query_planning_service: QueryPlanningService,
     subgraph_service_factory: Arc<SubgraphServiceFactory>,
     schema: Arc<Schema>,
     config: Arc<Configuration>,
@@ -897,7 +918,7 @@ impl SupergraphCreator {
         Future = BoxFuture<'static, supergraph::ServiceResult>,
     > + Send {
         let supergraph_service = SupergraphService::builder()
-            .query_planner_service(self.query_planner_service.clone())
+            .query_planner_service(self.query_planning_service.clone())
             .execution_service_factory(ExecutionServiceFactory {
                 schema: self.schema.clone(),
                 subgraph_schemas: self.query_planner_service.subgraph_schemas(),
@@ -40,6 +40,9 @@ use crate::uplink::license_enforcement::LicenseState;
 /// Mocks for services the Apollo Router must integrate with.
 pub mod mocks;
 
+/// Unit tests for Rhai scripts.
+pub mod rhai;
+
 #[cfg(test)]
 pub(crate) mod http_client;
 
@@ -0,0 +1,265 @@
+//! Unit tests for Rhai scripts, without a running router.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use tower::util::BoxService;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::plugin::PluginInit;
+use crate::plugin::PluginPrivate;
+use crate::plugins::rhai::Rhai;
+use crate::services::execution;
+use crate::services::router;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+
+/// Runs the hooks of a Rhai script on fixture requests and responses, one stage at a time.
+///
+/// Each call runs a request through the callbacks that the script registered for the stage,
+/// then the fixture response through them, as the router would. The outcome holds the request
+/// passed on to the next stage, with its headers, body and context, and the response returned to
+/// the previous stage, which contains the errors if the script ended the request early.
+///
+/// ```no_run
+/// use apollo_router::services::supergraph;
+/// use apollo_router::test_harness::rhai::RhaiTestHarness;
+///
+/// # #[tokio::main] async fn main() -> Result<(), tower::BoxError> {
+/// let harness = RhaiTestHarness::builder()
+///     .scripts("rhai")
+///     .main("main.rhai")
+///     .build()
+///     .await?;
+///
+/// let request = supergraph::Request::fake_builder()
+///     .header("x-client-id", "checkout")
+///     .build()?;
+/// let response = supergraph::Response::fake_builder().build()?;
+/// let outcome = harness.supergraph_service(request, response).await?;
+///
+/// let request = outcome.request.expect("the script should not reject the request");
+/// assert_eq!(
+///     request.context.get::<_, String>("client")?,
+///     Some("checkout".to_string())
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub struct RhaiTestHarness {
+    plugin: Rhai,
+}
+
+/// The result of running a fixture request and response through the hooks of a stage
+#[non_exhaustive]
+pub struct StageOutcome<Request, Response> {
+    /// The request passed on to the next stage, or `None` if the script ended the request early
+    pub request: Option<Request>,
+    /// The response returned to the previous stage
+    pub response: Response,
+}
+
+#[buildstructor::buildstructor]
+impl RhaiTestHarness {
+    /// Loads the main script, which can import modules from the scripts directory.
+    ///
+    /// The scripts directory defaults to `rhai`, the main script to `main.rhai` and the
+    /// supergraph schema, available to the scripts as `Router.APOLLO_SDL`, to an empty string.
+    #[builder(visibility = "pub")]
+    async fn new(
+        scripts: Option<PathBuf>,
+        main: Option<String>,
+        schema: Option<String>,
+    ) -> Result<Self, BoxError> {
+        let config = serde_json::from_value(serde_json::json!({
+            "scripts": scripts,
+            "main": main,
+        }))?;
+        let plugin = Rhai::new(
+            PluginInit::fake_builder()
+                .config(config)
+                .supergraph_sdl(Arc::new(schema.unwrap_or_default()))
+                .build(),
+        )
+        .await?;
+        Ok(Self { plugin })
+    }
+
+    /// Runs the `router_service` hooks
+    pub async fn router_service(
+        &self,
+        request: router::Request,
+        response: router::Response,
+    ) -> Result<StageOutcome<router::Request, router::Response>, BoxError> {
+        call_stage(
+            |service| self.plugin.router_service(service),
+            request,
+            response,
+            |request| request.context.clone(),
+            |response, context| response.context = context,
+        )
+        .await
+    }
+
+    /// Runs the `supergraph_service` hooks
+    pub async fn supergraph_service(
+        &self,
+        request: supergraph::Request,
+        response: supergraph::Response,
+    ) -> Result<StageOutcome<supergraph::Request, supergraph::Response>, BoxError> {
+        call_stage(
+            |service| self.plugin.supergraph_service(service),
+            request,
+            response,
+            |request| request.context.clone(),
+            |response, context| response.context = context,
+        )
+        .await
+    }
+
+    /// Runs the `execution_service` hooks
+    pub async fn execution_service(
+        &self,
+        request: execution::Request,
+        response: execution::Response,
+    ) -> Result<StageOutcome<execution::Request, execution::Response>, BoxError> {
+        call_stage(
+            |service| self.plugin.execution_service(service),
+            request,
+            response,
+            |request| request.context.clone(),
+            |response, context| response.context = context,
+        )
+        .await
+    }
+
+    /// Runs the `subgraph_service` hooks for a subgraph
+    pub async fn subgraph_service(
+        &self,
+        subgraph_name: &str,
+        request: subgraph::Request,
+        response: subgraph::Response,
+    ) -> Result<StageOutcome<subgraph::Request, subgraph::Response>, BoxError> {
+        call_stage(
+            |service| self.plugin.subgraph_service(subgraph_name, service),
+            request,
+            response,
+            |request| request.context.clone(),
+            |response, context| response.context = context,
+        )
+        .await
+    }
+}
+
+/// Wraps a service returning the fixture response with the hooks of the script, and keeps the
+/// request it receives. The response gets the context of the request, as in the router.
+async fn call_stage<Request, Response>(
+    hooks: impl FnOnce(
+        BoxService<Request, Response, BoxError>,
+    ) -> BoxService<Request, Response, BoxError>,
+    request: Request,
+    response: Response,
+    request_context: fn(&Request) -> Context,
+    set_response_context: fn(&mut Response, Context),
+) -> Result<StageOutcome<Request, Response>, BoxError>
+where
+    Request: Send + 'static,
+    Response: Send + 'static,
+{
+    let passed_request = Arc::new(Mutex::new(None));
+    let mut response = Some(response);
+    let inner = {
+        let passed_request = passed_request.clone();
+        tower::service_fn(move |request: Request| {
+            let response = response.take().map(|mut response| {
+                set_response_context(&mut response, request_context(&request));
+                response
+            });
+            *passed_request.lock().expect("poisoned mutex") = Some(request);
+            async move { response.ok_or_else(|| BoxError::from("the stage was called twice")) }
+        })
+        .boxed()
+    };
+
+    let response = hooks(inner).oneshot(request).await?;
+    let request = passed_request.lock().expect("poisoned mutex").take();
+    Ok(StageOutcome { request, response })
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+
+    use super::*;
+    use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+
+    async fn harness(main: &str) -> RhaiTestHarness {
+        RhaiTestHarness::builder()
+            .scripts("tests/fixtures")
+            .main(main)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_runs_request_callbacks() {
+        let harness = harness("require_authentication.rhai").await;
+
+        let mut outcome = harness
+            .supergraph_service(
+                supergraph::Request::fake_builder().build().unwrap(),
+                supergraph::Response::fake_builder().build().unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(outcome.request.is_none());
+        assert_eq!(outcome.response.response.status(), 401);
+        let body = outcome.response.next_response().await.unwrap();
+        assert_eq!(body.errors[0].message, "The request is not authenticated");
+
+        let context = Context::new();
+        context
+            .insert(
+                APOLLO_AUTHENTICATION_JWT_CLAIMS,
+                serde_json::json!({"sub": "1"}),
+            )
+            .unwrap();
+        let outcome = harness
+            .supergraph_service(
+                supergraph::Request::fake_builder()
+                    .context(context)
+                    .build()
+                    .unwrap(),
+                supergraph::Response::fake_builder().build().unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(outcome.request.is_some());
+    }
+
+    #[tokio::test]
+    async fn it_runs_response_callbacks() {
+        let harness = harness("remove_header.rhai").await;
+
+        let outcome = harness
+            .supergraph_service(
+                supergraph::Request::fake_builder().build().unwrap(),
+                supergraph::Response::fake_builder()
+                    .header("x-custom-header", "CUSTOM_VALUE")
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let request = outcome.request.unwrap();
+        assert!(request.context.contains_key("request_start"));
+        assert_eq!(
+            outcome.response.response.headers().get("x-custom-header"),
+            None::<&HeaderValue>
+        );
+    }
+}
@@ -861,6 +861,65 @@ async fn entity_cache_authorization() -> Result<(), BoxError> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn rhai_kv_counter() -> Result<(), BoxError> {
+    let counter = "rhai_kv_counter";
+
+    let config = RedisConfig::from_url("redis://127.0.0.1:6379").unwrap();
+    let client = RedisClient::new(config, None, None, None);
+    let connection_task = client.connect();
+    client.wait_for_connect().await.unwrap();
+
+    client.del::<String, _>(counter).await.unwrap();
+
+    let supergraph = apollo_router::TestHarness::builder()
+        .configuration_json(json!({
+            "rhai": {
+                "scripts": "tests/fixtures",
+                "main": "kv_counter.rhai",
+                "kv": {
+                    "redis": {
+                        "urls": ["redis://127.0.0.1:6379"]
+                    }
+                }
+            }
+        }))
+        .unwrap()
+        .schema(include_str!("../fixtures/supergraph.graphql"))
+        .build_supergraph()
+        .await
+        .unwrap();
+
+    // concurrent increments are not lost
+    let requests = (0..20).map(|_| {
+        let request = supergraph::Request::fake_builder()
+            .query("{ __typename }")
+            .header("x-counter", counter)
+            .method(Method::POST)
+            .build()
+            .unwrap();
+        tokio::spawn(supergraph.clone().oneshot(request))
+    });
+    let mut counts = Vec::new();
+    for response in futures::future::join_all(requests).await {
+        let response = response??;
+        counts.push(response.context.get::<_, i64>("count")?.unwrap());
+    }
+    counts.sort();
+    assert_eq!(counts, (1..=20).collect::<Vec<i64>>());
+
+    let count: i64 = client.get(counter).await.unwrap();
+    assert_eq!(count, 20);
+    // the expiration is set by the first increment
+    let ttl: i64 = client.ttl(counter).await.unwrap();
+    assert!(ttl > 0 && ttl <= 60, "unexpected ttl {ttl}");
+
+    client.quit().await.unwrap();
+    // calling quit ends the connection and event listener tasks
+    let _ = connection_task.await;
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn connection_failure_blocks_startup() {
     let _ = apollo_router::TestHarness::builder()